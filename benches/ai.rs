@@ -0,0 +1,63 @@
+/*
+
+What the AI spends its time on, for each piece: finding everywhere the piece can go, and dropping it in each of
+those places to see what the well looks like after. With lookahead, the same again for the next piece after
+every one of those, which is what makes big wells slow.
+
+    cargo bench --no-default-features --features tui --bench ai
+
+Each benchmark works on an untidy stack of garbage, in the usual well and in the biggest one there can be.
+
+*/
+
+extern crate criterion;
+extern crate tetris;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use tetris::{GameState, Randomizer, Well, GARBAGE, MAX_HIDDEN_ROWS, MAX_WELL_COLS, MAX_WELL_ROWS};
+use tetris::ai::{best_placement, evaluate, find_placements, Weights};
+
+
+/// Garbage in the bottom quarter, with holes dotted through it.
+fn untidy(mut well: Well) -> Well
+{
+    let rows = well.rows();
+    for row in rows - rows / 4..rows {
+        for col in 0..well.cols() {
+            if (row * 7 + col * 3) % 5 != 0 { well.set(row, col, GARBAGE); }
+        }
+    }
+    well
+}
+
+fn games() -> Vec<(&'static str, GameState)>
+{
+    [("10x20+4", Well::default()), ("40x60+20", Well::new(MAX_WELL_COLS, MAX_WELL_ROWS, MAX_HIDDEN_ROWS))].iter().map(|(name, well)| {
+        let mut game_state = GameState::with_seed(1, Randomizer::Bag);
+        game_state.set_well(well.clone());
+        game_state.well = untidy(game_state.well.clone());
+        (*name, game_state)
+    }).collect()
+}
+
+
+fn placements(c: &mut Criterion)
+{
+    for (name, game_state) in games()
+    {
+        let (ttmo, well, row, col) = (&game_state.curr_ttmo, &game_state.well, game_state.ttmo_row, game_state.ttmo_col);
+        let found = find_placements(ttmo, well, row, col);
+
+        let mut group = c.benchmark_group(format!("ai {}", name));
+        group.bench_function("find_placements", |b| b.iter(|| find_placements(ttmo, black_box(well), row, col)));
+        group.bench_function("evaluate", |b| b.iter(|| found.iter().map(|placement| evaluate(placement, black_box(well)).0[0]).sum::<f64>()));
+        group.bench_function("best_placement", |b| b.iter(|| best_placement(black_box(&game_state), &Weights::default(), false).map(|placement| placement.col)));
+        group.bench_function("lookahead", |b| b.iter(|| best_placement(black_box(&game_state), &Weights::default(), true).map(|placement| placement.col)));
+        group.finish();
+    }
+}
+
+
+criterion_group!(benches, placements);
+criterion_main!(benches);
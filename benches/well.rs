@@ -0,0 +1,169 @@
+/*
+
+The well's bitboard against going square by square, which is how would_collide() and clear_complete_rows() used
+to work. The square-by-square versions are kept here, as they were, to compare with.
+
+    cargo bench --no-default-features --features tui
+
+Each benchmark works on the same wells: an untidy stack of garbage with a few full rows in it, in the usual well
+and in a wide one. Collisions try every piece, every way round, everywhere it could go; landing drops every piece
+from the top in every column; line clears clear the full rows.
+
+*/
+
+extern crate criterion;
+extern crate tetris;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use tetris::{clear_complete_rows, landing_row, rotate_tetrimino, would_collide, Tetrimino, TetriminoKind, Well, GARBAGE, PIECE_SIZE};
+
+
+/// would_collide() and clear_complete_rows() before the bitboard.
+mod cells
+{
+    use tetris::{Tetrimino, Well, PIECE_SIZE};
+
+    pub fn would_collide(ttmo: &Tetrimino, well: &Well, row: &i32, col: &i32) -> bool
+    {
+        for ttmo_row in 0..PIECE_SIZE {
+            for ttmo_col in 0..PIECE_SIZE {
+                if ttmo.shape[ttmo_row][ttmo_col] == 0 { continue; }
+
+                let well_row = ttmo_row as i32 + *row;
+                let well_col = ttmo_col as i32 + *col;
+
+                if well_col < 0 { return true; }
+                if well_col >= well.cols() as i32 { return true; }
+                if well_row >= well.rows() as i32 { return true; }
+                if well_row < 0 { return true; }
+
+                if well[well_row as usize][well_col as usize] != 0 { return true; }
+            }
+        }
+
+        false
+    }
+
+    pub fn landing_row(ttmo: &Tetrimino, well: &Well, row: i32, col: i32) -> i32
+    {
+        let mut landing_row = row;
+        while !would_collide(ttmo, well, &(landing_row + 1), &col) { landing_row += 1; }
+        landing_row
+    }
+
+    pub fn clear_complete_rows(well: &Well) -> Well
+    {
+        let mut new_well = well.empty_like();
+        let mut new_well_row: usize = well.rows() - 1;
+
+        for old_well_row in (0..well.rows()).rev()
+        {
+            let mut pop_count = 0;
+            for square in well[old_well_row].iter() {
+                if *square != 0 { pop_count += 1; }
+            }
+
+            if pop_count == 0 || pop_count == well.cols() { continue; }
+
+            if well[old_well_row].iter().sum::<u8>() > 0
+            {
+                new_well.set_row(new_well_row, &well[old_well_row]);
+                new_well_row -= 1;
+            }
+        }
+
+        new_well
+    }
+}
+
+
+/// Garbage up to about half way, higher on the left, with holes dotted through it and every fourth row full.
+fn untidy(mut well: Well) -> Well
+{
+    let (rows, cols) = (well.rows(), well.cols());
+    for row in rows / 2..rows {
+        for col in 0..cols {
+            let hole = (row * 7 + col * 3) % 5 == 0 && (rows - row) % 4 != 0;
+            let above = row < rows - (rows / 2) * (cols - col) / cols;
+            if !hole && !above { well.set(row, col, GARBAGE); }
+        }
+    }
+    well
+}
+
+fn wells() -> Vec<(&'static str, Well)>
+{
+    vec![("10x20", untidy(Well::default())), ("40x20", untidy(Well::new(40, 20, 4)))]
+}
+
+/// Every standard piece in each of its rotation states.
+fn pieces() -> Vec<Tetrimino>
+{
+    TetriminoKind::STANDARD.iter().flat_map(|kind| {
+        let mut ttmo = Tetrimino::new(*kind);
+        (0..ttmo.rotation_count).map(move |_| { let turned = ttmo; rotate_tetrimino(&mut ttmo, true); turned })
+    }).collect()
+}
+
+/// Everywhere a piece's box could be, and a little way past the walls.
+fn positions(well: &Well) -> Vec<(i32, i32)>
+{
+    let reach = PIECE_SIZE as i32;
+    (-reach..well.rows() as i32).flat_map(|row| (-reach..well.cols() as i32 + 1).map(move |col| (row, col))).collect()
+}
+
+
+fn collisions(c: &mut Criterion)
+{
+    let pieces = pieces();
+    for (name, well) in wells() {
+        let positions = positions(&well);
+        let mut group = c.benchmark_group(format!("collisions {}", name));
+        group.bench_function("cells", |b| b.iter(|| {
+            pieces.iter().flat_map(|ttmo| positions.iter().map(move |(row, col)| (ttmo, row, col)))
+                .filter(|(ttmo, row, col)| cells::would_collide(ttmo, black_box(&well), row, col)).count()
+        }));
+        group.bench_function("bitboard", |b| b.iter(|| {
+            pieces.iter().flat_map(|ttmo| positions.iter().map(move |(row, col)| (ttmo, row, col)))
+                .filter(|(ttmo, row, col)| would_collide(ttmo, black_box(&well), row, col)).count()
+        }));
+        group.finish();
+    }
+}
+
+
+fn landing(c: &mut Criterion)
+{
+    let pieces = pieces();
+    for (name, well) in wells() {
+        let (row, cols) = (well.spawn().0, well.cols() as i32);
+        let mut group = c.benchmark_group(format!("landing {}", name));
+        group.bench_function("cells", |b| b.iter(|| {
+            pieces.iter().flat_map(|ttmo| (-2..cols).map(move |col| (ttmo, col)))
+                .filter(|(ttmo, col)| !cells::would_collide(ttmo, &well, &row, col))
+                .map(|(ttmo, col)| cells::landing_row(ttmo, black_box(&well), row, col)).sum::<i32>()
+        }));
+        group.bench_function("bitboard", |b| b.iter(|| {
+            pieces.iter().flat_map(|ttmo| (-2..cols).map(move |col| (ttmo, col)))
+                .filter(|(ttmo, col)| !would_collide(ttmo, &well, &row, col))
+                .map(|(ttmo, col)| landing_row(ttmo, black_box(&well), row, col)).sum::<i32>()
+        }));
+        group.finish();
+    }
+}
+
+
+fn line_clears(c: &mut Criterion)
+{
+    for (name, well) in wells() {
+        let mut group = c.benchmark_group(format!("line clears {}", name));
+        group.bench_function("cells", |b| b.iter(|| cells::clear_complete_rows(black_box(&well))));
+        group.bench_function("bitboard", |b| b.iter(|| clear_complete_rows(black_box(&well))));
+        group.finish();
+    }
+}
+
+
+criterion_group!(benches, collisions, landing, line_clears);
+criterion_main!(benches);
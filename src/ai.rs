@@ -0,0 +1,445 @@
+/*
+
+A computer player.
+
+For the falling piece, the AI finds every rotation/column it could get to by pressing keys (using the
+same would_collide() the rules use), drops it there on a copy of the well, and scores the result with
+Pierre Dellacherie's features plus a couple of popular extras. With lookahead on, each of those is
+followed by every placement of the next piece too, and the best pair wins.
+
+Then it presses the keys to get there, one key per update, through the same GameState::inputs a person's keys go into.
+So it can play in any frontend, and in rustris-sim. Whenever the piece isn't where the last key should have left it,
+because gravity got to it first, it plans again from wherever the piece is now. At 20G that starts with each
+piece as it appears, already on the stack.
+
+How much each feature matters is up to the Weights, which can be loaded from a profile file:
+
+    # rustris AI profile
+    landing_height -4.5
+    holes -7.9
+    ...
+
+Any weight not mentioned keeps its default.
+
+*/
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{clear_complete_rows, count_complete_rows, freeze_to_well, landing_row, rotate_in_well, would_collide};
+use crate::{game_update, Action, GameState, Randomizer, ShapeMask, Tetrimino, Well, PIECE_SIZE};
+use crate::input::InputSource;
+
+
+/// How much the AI likes (positive) or dislikes (negative) each feature of a well.
+/// The defaults are El-Tetris' tuning of Dellacherie's features (https://imake.ninja/el-tetris-an-improvement-on-pierre-dellacheries-algorithm/).
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Weights
+{
+    pub landing_height: f64,     // How high up the piece ended up.
+    pub eroded_cells: f64,       // Rows cleared times how many of the piece's own squares went with them.
+    pub row_transitions: f64,    // Filled/empty changes along each row. The walls count as filled.
+    pub col_transitions: f64,    // Filled/empty changes down each column. The floor counts as filled.
+    pub holes: f64,              // Empty squares with something above them.
+    pub wells: f64,              // Empty squares with filled squares (or walls) on both sides, deeper ones counting more.
+    pub aggregate_height: f64,   // Sum of the column heights.
+    pub bumpiness: f64           // Sum of the height differences between neighbouring columns.
+}
+
+
+/// Number of features, and the order they're in when Weights is treated as a plain list of numbers.
+pub const NUM_FEATURES: usize = 8;
+
+const FEATURE_NAMES: [&str; NUM_FEATURES] = [ "landing_height", "eroded_cells", "row_transitions", "col_transitions",
+                                              "holes", "wells", "aggregate_height", "bumpiness" ];
+
+
+impl Default for Weights
+{
+    fn default() -> Self
+    {
+        Weights {
+            landing_height: -4.500158825082766,
+            eroded_cells: 3.4181268101392694,
+            row_transitions: -3.2178882868487753,
+            col_transitions: -9.348695305445199,
+            holes: -7.899265427351652,
+            wells: -3.3855972247263626,
+            aggregate_height: 0.0,
+            bumpiness: 0.0
+        }
+    }
+}
+
+
+impl Weights
+{
+    pub fn to_array(&self) -> [f64; NUM_FEATURES]
+    {
+        [ self.landing_height, self.eroded_cells, self.row_transitions, self.col_transitions,
+          self.holes, self.wells, self.aggregate_height, self.bumpiness ]
+    }
+
+    pub fn from_array(values: [f64; NUM_FEATURES]) -> Self
+    {
+        Weights {
+            landing_height: values[0],
+            eroded_cells: values[1],
+            row_transitions: values[2],
+            col_transitions: values[3],
+            holes: values[4],
+            wells: values[5],
+            aggregate_height: values[6],
+            bumpiness: values[7]
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()>
+    {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self>
+    {
+        Weights::from_text(&fs::read_to_string(path)?).map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+
+    pub fn to_text(&self) -> String
+    {
+        let mut text = String::from("# rustris AI profile\n");
+        for (name, value) in FEATURE_NAMES.iter().zip(self.to_array().iter()) {
+            text.push_str(&format!("{} {}\n", name, value));
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String>
+    {
+        let mut values = Weights::default().to_array();
+
+        for (num, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let mut words = line.split_whitespace();
+            let (name, value) = match (words.next(), words.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => return Err(format!("line {}: expected 'name value', got '{}'", num + 1, line))
+            };
+
+            let index = FEATURE_NAMES.iter().position(|known| *known == name)
+                            .ok_or(format!("line {}: unknown feature '{}'", num + 1, name))?;
+            values[index] = value.parse().map_err(|_| format!("line {}: '{}' is not a number", num + 1, value))?;
+        }
+
+        Ok(Weights::from_array(values))
+    }
+}
+
+
+/// Somewhere the falling piece can be dropped, and how to get it there.
+#[derive(Clone)]
+pub struct Placement
+{
+    pub ttmo: Tetrimino,     // Rotated as it will be when it lands.
+    pub row: i32,            // Where it lands.
+    pub col: i32,
+    pub keys: Vec<Action>    // Presses to get there, in order, one per update. Doesn't include the final HardDrop.
+}
+
+
+/// Finds every place the given piece can be hard dropped to from (row, col), by moving and rotating at that row.
+/// Each distinct landing spot is listed once, with the shortest key sequence that reaches it.
+pub fn find_placements(ttmo: &Tetrimino, well: &Well, row: i32, col: i32) -> Vec<Placement>
+{
+    // Breadth first search over where the piece can get to at the row it starts in: its rotation state, and
+    // where its box is. Turns go through rotate_in_well(), so a piece with kicks can end up a row or two lower.
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    let mut placements: Vec<Placement> = Vec::new();
+    let mut landings = HashSet::new();    // The squares each placement so far covers.
+
+    if would_collide(ttmo, well, &row, &col) { return placements; }
+    seen.insert((ttmo.rotation, row, col));
+    queue.push_back((*ttmo, row, col, Vec::new()));
+
+    while let Some((ttmo, row, col, keys)) = queue.pop_front()
+    {
+        // Where would it land from here?
+        let landing_row = landing_row(&ttmo, well, row, col);
+
+        // Several rotation states can cover the same squares (S, Z, I). Only the first (shortest) way there counts.
+        if landings.insert(covered(&ttmo, landing_row, col)) {
+            placements.push(Placement { ttmo, row: landing_row, col, keys: keys.clone() });
+        }
+
+        // Walls stop sideways moves (would_collide() counts everything outside the well as full), so this ends.
+        let shifts = [(Action::MoveLeft, -1), (Action::MoveRight, 1)];
+        let moves = shifts.iter()
+            .filter(|(_, shift)| !would_collide(&ttmo, well, &row, &(col + shift)))
+            .map(|(key, shift)| (*key, (ttmo, row, col + shift)))
+            .chain([(Action::RotateCCW, false), (Action::RotateCW, true)].iter()
+                .filter_map(|(key, clockwise)| rotate_in_well(&ttmo, well, row, col, *clockwise).map(|next| (*key, next))));
+
+        for (key, (next_ttmo, next_row, next_col)) in moves
+        {
+            if !seen.insert((next_ttmo.rotation, next_row, next_col)) { continue; }
+
+            let mut next_keys = keys.clone();
+            next_keys.push(key);
+            queue.push_back((next_ttmo, next_row, next_col, next_keys));
+        }
+    }
+
+    placements
+}
+
+
+/// The squares a piece covers at (row, col), as its rotation state's masks moved up and left to the corner of its box,
+/// and the well row and column that corner ends up at. Rotation states that cover the same squares come out the same.
+fn covered(ttmo: &Tetrimino, row: i32, col: i32) -> (ShapeMask, i32, i32)
+{
+    let masks = ttmo.mask();
+    let top = masks.iter().position(|mask| *mask != 0).unwrap_or(0);
+    let left = masks.iter().filter(|mask| **mask != 0).map(|mask| mask.trailing_zeros()).min().unwrap_or(0);
+
+    let mut cornered = [0; PIECE_SIZE];
+    for (corner, mask) in cornered.iter_mut().zip(masks[top..].iter()) { *corner = mask >> left; }
+    (cornered, row + top as i32, col + left as i32)
+}
+
+
+/// Drops the given placement onto a copy of the well and clears rows.
+/// Returns the features of the result (see Weights), and the well after the drop.
+pub fn evaluate(placement: &Placement, well: &Well) -> ([f64; NUM_FEATURES], Well)
+{
+    let mut new_well = well.clone();
+    freeze_to_well(&placement.ttmo, &mut new_well, &placement.row, &placement.col);
+
+    // Which rows of the piece did it occupy, and how many of its squares were in full rows?
+    let mut top = well.rows();
+    let mut bottom = 0;
+    let mut eroded_squares = 0;
+    for (ttmo_row, shape_row) in placement.ttmo.shape.iter().enumerate()
+    {
+        let squares = shape_row.iter().filter(|square| **square != 0).count();
+        if squares == 0 { continue; }
+
+        let well_row = (placement.row + ttmo_row as i32) as usize;
+        top = top.min(well_row);
+        bottom = bottom.max(well_row);
+        if new_well[well_row].iter().all(|square| *square != 0) { eroded_squares += squares; }
+    }
+
+    let cleared = count_complete_rows(&new_well);
+    if cleared > 0 { new_well = clear_complete_rows(&new_well); }
+
+    let mut features = well_features(&new_well);
+    features[0] = well.rows() as f64 - (top + bottom) as f64 / 2.0 - 0.5;
+    features[1] = (cleared as usize * eroded_squares) as f64;
+
+    (features, new_well)
+}
+
+
+/// How much the given weights like the given features.
+pub fn score(weights: &Weights, features: &[f64; NUM_FEATURES]) -> f64
+{
+    weights.to_array().iter().zip(features.iter()).map(|(weight, feature)| weight * feature).sum()
+}
+
+
+/// Measures the features of a well that don't depend on the last piece placed.
+/// (The first two, landing height and eroded cells, are left at 0 for evaluate() to fill in.)
+/// This runs for every placement tried, so it goes a row at a time by the well's row masks (see Well) rather than
+/// square by square: bit n of a mask is column n, and the walls and floor count as filled.
+pub fn well_features(well: &Well) -> [f64; NUM_FEATURES]
+{
+    let (rows, cols) = (well.rows(), well.cols());
+    let full = well.full_mask();
+    let mask = |row: usize| if row < rows { well.row_mask(row) } else { full };    // Below the last row is the floor.
+
+    let mut heights = vec![0i32; cols];
+    let mut depths = vec![0u32; cols];    // How far down the well each column is in, as of the row above.
+    let mut deep = 0u64;                  // Columns whose depth isn't 0.
+    let mut covered = 0u64;               // Columns with something in them at this row or above.
+
+    let mut row_transitions = 0;
+    let mut col_transitions = 0;
+    let mut holes = 0;
+    let mut wells = 0;
+    let mut aggregate_height = 0;
+
+    // Empty rows above the stack add nothing to any of them, so start at the first row with anything in it.
+    let top = (0..rows).find(|row| well.row_mask(*row) != 0).unwrap_or(rows);
+
+    for row in top..rows
+    {
+        let filled = mask(row);
+
+        // Column heights, measured from the floor, for columns whose top is in this row.
+        let mut tops = filled & !covered;
+        while tops != 0 {
+            heights[tops.trailing_zeros() as usize] = (rows - row) as i32;
+            tops &= tops - 1;
+        }
+        covered |= filled;
+        aggregate_height += covered.count_ones();
+
+        // Rows that are completely empty add nothing but two wall transitions, so skip them as Dellacherie did.
+        // Otherwise it's every change along the row, walls and all: a bit for each column plus one each side.
+        if filled != 0 {
+            let walled = filled << 1 | 1 | 1 << (cols + 1);
+            row_transitions += ((walled ^ walled >> 1) & ((1 << (cols + 1)) - 1)).count_ones();
+        }
+
+        // From the top of each column down, changes down it (the floor counts as filled), and empty squares.
+        col_transitions += (covered & (filled ^ mask(row + 1))).count_ones();
+        holes += (covered & !filled).count_ones();
+
+        // Wells: empty squares with something either side. Each square deeper into a well counts one more.
+        let sides = (filled << 1 | 1) & (filled >> 1 | 1 << (cols - 1));
+        let in_well = !filled & sides & full;
+        let mut out = deep & !in_well;
+        while out != 0 {
+            depths[out.trailing_zeros() as usize] = 0;
+            out &= out - 1;
+        }
+        let mut remaining = in_well;
+        while remaining != 0 {
+            let depth = &mut depths[remaining.trailing_zeros() as usize];
+            *depth += 1;
+            wells += *depth;
+            remaining &= remaining - 1;
+        }
+        deep = in_well;
+    }
+
+    let bumpiness: i32 = heights.windows(2).map(|pair| (pair[0] - pair[1]).abs()).sum();
+
+    [ 0.0, 0.0, f64::from(row_transitions), f64::from(col_transitions), f64::from(holes), f64::from(wells),
+      f64::from(aggregate_height), f64::from(bumpiness) ]
+}
+
+
+/// Picks the best placement for the falling piece. With lookahead, also tries every placement of the next piece after it.
+pub fn best_placement(game_state: &GameState, weights: &Weights, lookahead: bool) -> Option<Placement>
+{
+    let mut best: Option<(f64, Placement)> = None;
+
+    for placement in find_placements(&game_state.curr_ttmo, &game_state.well, game_state.ttmo_row, game_state.ttmo_col)
+    {
+        let (features, well_after) = evaluate(&placement, &game_state.well);
+        let mut total = score(weights, &features);
+
+        if lookahead
+        {
+            // Judge the pair by the well they leave behind, plus how well each piece landed.
+            // The next piece spawns where every piece does. If it can't, this placement ends the game.
+            total = find_placements(&game_state.next_ttmo, &well_after, game_state.spawn_row, game_state.spawn_col).iter()
+                        .map(|next| {
+                            let mut next_features = evaluate(next, &well_after).0;
+                            next_features[0] += features[0];
+                            next_features[1] += features[1];
+                            score(weights, &next_features)
+                        })
+                        .fold(f64::NEG_INFINITY, f64::max);
+        }
+
+        if best.as_ref().is_none_or(|(best_total, _)| total > *best_total) {
+            best = Some((total, placement));
+        }
+    }
+
+    best.map(|(_, placement)| placement)
+}
+
+
+/// Plays by pressing keys, like a person would.
+pub struct AiBot
+{
+    pub weights: Weights,
+    pub lookahead: bool,
+    plan: VecDeque<Action>,          // Keys still to press for the current piece.
+    planned_piece: Option<u32>,      // Value of GameState::pieces when the plan was made.
+    expected: (i32, i32, usize)      // Where the last key pressed should have left the piece: row, column and rotation.
+}
+
+
+impl AiBot
+{
+    pub fn new(weights: Weights, lookahead: bool) -> Self
+    {
+        AiBot { weights, lookahead, plan: VecDeque::new(), planned_piece: None, expected: (0, 0, 0) }
+    }
+}
+
+
+impl InputSource for AiBot
+{
+    fn next_keys(&mut self, game_state: &GameState) -> Vec<Action>
+    {
+        if game_state.game_over.is_some() { return Vec::new(); }
+        if game_state.spawn_delay > 0 { return Vec::new(); }    // Nothing to move until the next piece appears.
+
+        // A new piece has appeared, or this one isn't where the plan left it: gravity pulled it down a row between
+        // keys (or, at 20G, to the ground the moment it appeared), or finesse practice sent it back to the top.
+        // Work out where it should go from where it is now.
+        let here = (game_state.ttmo_row, game_state.ttmo_col, game_state.curr_ttmo.rotation);
+        if self.planned_piece != Some(game_state.pieces) || self.expected != here
+        {
+            self.planned_piece = Some(game_state.pieces);
+            self.plan = match best_placement(game_state, &self.weights, self.lookahead) {
+                Some(placement) => placement.keys.into_iter().chain(std::iter::once(Action::HardDrop)).collect(),    // ...then HardDrop.
+                None => VecDeque::new()
+            };
+        }
+
+        let key = self.plan.pop_front();
+        self.expected = key.map_or(here, |key| moved(game_state, key));
+        key.into_iter().collect()
+    }
+}
+
+
+/// Where the given key will take the falling piece, if nothing else moves it first: its row, column and rotation.
+fn moved(game_state: &GameState, key: Action) -> (i32, i32, usize)
+{
+    let (ttmo, well) = (&game_state.curr_ttmo, &game_state.well);
+    let (row, col) = (game_state.ttmo_row, game_state.ttmo_col);
+    match key
+    {
+        Action::MoveLeft | Action::MoveRight => {
+            let shifted = if key == Action::MoveLeft { col - 1 } else { col + 1 };
+            (row, if would_collide(ttmo, well, &row, &shifted) { col } else { shifted }, ttmo.rotation)
+        }
+        Action::RotateCCW | Action::RotateCW => match rotate_in_well(ttmo, well, row, col, key == Action::RotateCW) {
+            Some((turned, row, col)) => (row, col, turned.rotation),
+            None => (row, col, ttmo.rotation)
+        },
+        Action::HardDrop => (landing_row(ttmo, well, row, col), col, ttmo.rotation),
+        _ => (row, col, ttmo.rotation)
+    }
+}
+
+
+/// Plays one whole game with no delay between updates, and returns how it ended up.
+/// Stops early after max_pieces, since a good AI can go on for a very long time.
+pub fn play_game(weights: &Weights, lookahead: bool, seed: u64, randomizer: Randomizer, max_pieces: u32) -> GameState
+{
+    let mut game_state = GameState::with_seed(seed, randomizer);
+    let mut bot = AiBot::new(*weights, lookahead);
+
+    while game_state.game_over.is_none() && game_state.pieces < max_pieces
+    {
+        game_state.inputs = bot.next_keys(&game_state);
+        game_update(&mut game_state);
+    }
+
+    game_state
+}
@@ -0,0 +1,120 @@
+/*
+
+Battle royale: any number of players, each sending garbage at the others, until only one is left.
+
+Everyone gets the same seed, so the same pieces, and garbage works as in two-player versus (see versus.rs),
+with the same rules. The difference is where an attack goes: at one of the players still in, picked at
+random. Players are placed in the order they top out, last place first, and whoever is left standing at
+the end wins. Players who top out on the same update share a place.
+
+*/
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::{game_update, GameState, Randomizer};
+use crate::modes::{GameOver, Mode};
+use crate::versus::{cancel, count_down, receive, Incoming, Rules};
+
+
+/// A match between any number of players.
+pub struct Battle
+{
+    pub players: Vec<GameState>,
+    pub incoming: Vec<Vec<Incoming>>,    // Garbage on its way to each player, oldest first.
+    pub sent: Vec<u32>,                  // Lines of garbage each player has sent, after cancelling.
+    pub places: Vec<Option<usize>>,      // Where each player finished, 1 being the winner. None while they're still in.
+    pub rules: Rules,
+    pub rng: StdRng                      // Picks targets and garbage holes. Separate from the players', so their pieces stay the same.
+}
+
+
+impl Battle
+{
+    /// A fresh match for the given number of players, whose pieces, targets and garbage holes are all determined by the seed.
+    pub fn with_seed(seed: u64, count: usize, rules: Rules) -> Self
+    {
+        let mut player = GameState::with_seed(seed, Randomizer::Bag);
+        player.set_mode(Mode::Versus);
+
+        Battle {
+            players: vec![player; count],
+            incoming: vec![Vec::new(); count],
+            sent: vec![0; count],
+            places: vec![None; count],
+            rules,
+            rng: StdRng::seed_from_u64(seed.wrapping_add(1))
+        }
+    }
+
+    /// Players still in.
+    pub fn alive(&self) -> Vec<usize>
+    {
+        (0..self.players.len()).filter(|player| self.places[*player].is_none()).collect()
+    }
+
+    /// Over once no more than one player is left.
+    pub fn is_over(&self) -> bool
+    {
+        self.alive().len() <= 1
+    }
+
+    /// The winner, once there is one. A match where the last players topped out together has none: they share first place.
+    pub fn winner(&self) -> Option<usize>
+    {
+        match self.places.iter().filter(|place| **place == Some(1)).count() {
+            1 => self.places.iter().position(|place| *place == Some(1)),
+            _ => None
+        }
+    }
+
+    /// One update of the match: each player's own game_update(), then garbage flying about.
+    /// Returns the players who were knocked out by it.
+    pub fn update(&mut self) -> Vec<usize>
+    {
+        if self.is_over() { return Vec::new(); }
+
+        for player in self.alive()
+        {
+            game_update(&mut self.players[player]);
+            count_down(&mut self.incoming[player]);
+
+            let lock = match self.players[player].last_lock { Some(lock) => lock, None => continue };
+            if lock.cleared > 0 {
+                let attack = cancel(&mut self.incoming[player], self.rules.attack(&lock));
+                let targets: Vec<usize> = self.alive().into_iter().filter(|target| *target != player && self.players[*target].game_over.is_none()).collect();
+
+                if attack > 0 && !targets.is_empty() {
+                    let target = targets[self.rng.gen_range(0, targets.len())];
+                    self.incoming[target].push(Incoming { lines: attack, wait: self.rules.garbage_delay() });
+                    self.sent[player] += attack;
+                }
+            }
+            else { receive(&mut self.players[player], &mut self.incoming[player], &mut self.rng); }
+        }
+
+        let knocked_out: Vec<usize> = self.alive().into_iter().filter(|player| self.players[*player].game_over.is_some()).collect();
+        self.place(&knocked_out);
+        knocked_out
+    }
+
+    /// Takes a player out of the match, say because they left.
+    pub fn forfeit(&mut self, player: usize)
+    {
+        if self.places[player].is_some() { return; }
+
+        self.players[player].game_over = Some(GameOver::TopOut);
+        self.place(&[player]);
+    }
+
+    /// Places players who just went out, behind everyone still in. If that leaves one player, they've won.
+    fn place(&mut self, knocked_out: &[usize])
+    {
+        if knocked_out.is_empty() { return; }
+
+        let remaining = self.alive().len() - knocked_out.len();
+        for player in knocked_out { self.places[*player] = Some(remaining + 1); }
+
+        if let [winner] = self.alive().as_slice() { self.places[*winner] = Some(1); }
+    }
+}
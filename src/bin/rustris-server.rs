@@ -0,0 +1,445 @@
+/*
+
+rustris-server: hosts battle royale matches for any number of players, over TCP.
+
+Clients connect, pick a name, and create or join rooms. Once everyone playing in a room is ready (and there
+are at least two of them), the server starts a match with a fresh seed. From then on the clients just send
+their keys; the server runs every player's game, sends the garbage flying about, places players as they top
+out, and sends the whole match to everyone in the room every update, spectators included. When one player
+is left standing, the room goes back to waiting for everyone to ready up again.
+
+    rustris-server --port 7342 --rules party.rules
+
+The protocol is plain lines of text, described in PROTOCOL.md.
+
+(C) 2021 Ben Cantrick. MIT License, see LICENSE.txt.
+
+*/
+
+extern crate rand;
+extern crate tetris;
+
+use rand::{thread_rng, Rng};
+
+use tetris::{Action, UPDATES_PER_SECOND};
+use tetris::battle::Battle;
+use tetris::lobby::{ClientMessage, PlayerView, ServerMessage, Status, DEFAULT_SERVER_PORT, SERVER_PROTOCOL_VERSION};
+use tetris::net::LineStream;
+use tetris::versus::Rules;
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::Path;
+use std::process;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+const USAGE: &str = "\
+usage: rustris-server [options]
+
+  --port N        port to listen on (default: 7342)
+  --rules FILE    versus rules file for the attack table and garbage delay (default: built-in rules)
+  --speed N       run matches N times faster than normal, for testing with bots (default: 1)
+";
+
+
+/// What the connection threads tell the main thread.
+enum Event
+{
+    Connected(u32, TcpStream),
+    Line(u32, String),
+    Closed(u32)
+}
+
+
+struct Client
+{
+    name: Option<String>,     // None until they've said hello.
+    stream: TcpStream,        // For writing. The connection's own thread does the reading.
+    room: Option<String>,
+    status: Status
+}
+
+
+struct Room
+{
+    members: Vec<u32>,        // Clients in the room, in the order they came in.
+    game: Option<Game>        // The match being played, if there is one.
+}
+
+
+/// A match in progress.
+struct Game
+{
+    battle: Battle,
+    slots: Vec<u32>,          // The client playing each slot. Stays put after they leave, so slots keep their numbers.
+    keys: Vec<Vec<Action>>,   // Keys each slot has pressed since the last update, in the order they arrived.
+    frame: u32                // Updates so far.
+}
+
+
+struct Server
+{
+    clients: BTreeMap<u32, Client>,
+    rooms: BTreeMap<String, Room>,
+    rules: Rules,
+    dead: Vec<u32>            // Clients that couldn't be written to, to be dropped once it's safe.
+}
+
+
+fn main()
+{
+    let mut port = DEFAULT_SERVER_PORT;
+    let mut rules = Rules::default();
+    let mut speed = 1;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next()
+    {
+        let value = args.next();
+        match (arg.as_str(), value)
+        {
+            ("--port", Some(value)) => port = value.parse().unwrap_or_else(|_| fail(&format!("'{}' is not a port", value))),
+            ("--rules", Some(path)) => {
+                rules = Rules::load(Path::new(&path)).unwrap_or_else(|err| fail(&format!("can't read rules {}: {}", path, err)));
+            }
+            ("--speed", Some(value)) => speed = value.parse().ok().filter(|speed| *speed > 0).unwrap_or_else(|| fail(&format!("bad speed '{}'", value))),
+            ("--help", _) | ("-h", _) => {
+                print!("{}", USAGE);
+                return;
+            }
+            _ => fail(&format!("bad option '{}'", arg))
+        }
+    }
+
+    let listener = TcpListener::bind(("0.0.0.0", port)).unwrap_or_else(|err| fail(&format!("can't listen on port {}: {}", port, err)));
+    eprintln!("rustris-server: listening on port {}", port);
+
+    let (events, inbox) = channel();
+    thread::spawn(move || accept_clients(listener, events));
+
+    let mut server = Server { clients: BTreeMap::new(), rooms: BTreeMap::new(), rules, dead: Vec::new() };
+
+    // Matches move on UPDATES_PER_SECOND times a second, whatever the clients are up to in between.
+    let update_period = Duration::from_secs(1) / (UPDATES_PER_SECOND * speed);
+    let mut next_update = Instant::now() + update_period;
+    loop
+    {
+        match inbox.recv_timeout(next_update.saturating_duration_since(Instant::now()))
+        {
+            Ok(Event::Connected(id, stream)) => server.connect(id, stream),
+            Ok(Event::Line(id, line)) => server.handle(id, &line),
+            Ok(Event::Closed(id)) => server.disconnect(id),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => fail("stopped accepting connections")
+        }
+
+        if Instant::now() >= next_update
+        {
+            server.update();
+            next_update += update_period;
+        }
+
+        while let Some(id) = server.dead.pop() { server.disconnect(id); }
+    }
+}
+
+
+fn fail(msg: &str) -> !
+{
+    eprintln!("rustris-server: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+
+/// Gives each new connection an id and a thread of its own to read it.
+fn accept_clients(listener: TcpListener, events: Sender<Event>)
+{
+    for (id, stream) in (1..).zip(listener.incoming())
+    {
+        let stream = match stream { Ok(stream) => stream, Err(_) => continue };
+        let writer = match stream.try_clone() { Ok(writer) => writer, Err(_) => continue };
+        if events.send(Event::Connected(id, writer)).is_err() { return; }
+
+        let events = events.clone();
+        thread::spawn(move || read_client(id, stream, events));
+    }
+}
+
+
+/// Passes a client's lines on to the main thread until they leave.
+fn read_client(id: u32, stream: TcpStream, events: Sender<Event>)
+{
+    let mut lines = match LineStream::new(stream) { Ok(lines) => lines, Err(_) => return };
+    let _ = lines.stream.set_read_timeout(None);    // People can sit in a lobby as long as they like.
+
+    while let Ok(line) = lines.read_line()
+    {
+        if line == "bye" { break; }
+        if events.send(Event::Line(id, line)).is_err() { return; }
+    }
+    let _ = events.send(Event::Closed(id));
+}
+
+
+impl Server
+{
+    fn connect(&mut self, id: u32, stream: TcpStream)
+    {
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));    // Anyone who can't keep up gets dropped, rather than holding up everyone else.
+        self.clients.insert(id, Client { name: None, stream, room: None, status: Status::Waiting });
+    }
+
+    fn disconnect(&mut self, id: u32)
+    {
+        self.leave_room(id);
+        if let Some(client) = self.clients.remove(&id)
+        {
+            if let Some(name) = client.name { eprintln!("rustris-server: {} left", name); }
+            let _ = client.stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn send(&mut self, id: u32, message: &ServerMessage)
+    {
+        let client = match self.clients.get_mut(&id) { Some(client) => client, None => return };
+        if client.stream.write_all(format!("{}\n", message.to_line()).as_bytes()).is_err() { self.dead.push(id); }
+    }
+
+    /// Sends to everyone in a room, players and spectators alike.
+    fn broadcast(&mut self, room: &str, message: &ServerMessage)
+    {
+        let members = self.rooms.get(room).map(|room| room.members.clone()).unwrap_or_default();
+        for id in members { self.send(id, message); }
+    }
+
+    fn error(&mut self, id: u32, msg: &str)
+    {
+        self.send(id, &ServerMessage::Error(msg.to_string()));
+    }
+
+    fn handle(&mut self, id: u32, line: &str)
+    {
+        let message = match ClientMessage::parse(line) {
+            Some(message) => message,
+            None => return self.error(id, &format!("can't make sense of '{}'", line))
+        };
+        let named = self.clients.get(&id).is_some_and(|client| client.name.is_some());
+
+        match message
+        {
+            ClientMessage::Hello { version, name } if !named => {
+                if version != SERVER_PROTOCOL_VERSION {
+                    self.error(id, &format!("this server speaks version {}", SERVER_PROTOCOL_VERSION));
+                    self.dead.push(id);
+                    return;
+                }
+                eprintln!("rustris-server: {} connected", name);
+                if let Some(client) = self.clients.get_mut(&id) { client.name = Some(name); }
+                self.send(id, &ServerMessage::Welcome { version: SERVER_PROTOCOL_VERSION, id });
+            }
+            _ if !named => self.error(id, "say hello first"),
+            ClientMessage::Hello { .. } => self.error(id, "already said hello"),
+
+            ClientMessage::List => {
+                let rooms: Vec<ServerMessage> = self.rooms.iter().map(|(name, room)| {
+                    let spectators = room.members.iter().filter(|member| self.clients[member].status == Status::Spectating).count();
+                    ServerMessage::Room { name: name.clone(), players: room.members.len() - spectators, spectators, playing: room.game.is_some() }
+                }).collect();
+                for room in rooms.iter() { self.send(id, room); }
+                self.send(id, &ServerMessage::EndRooms);
+            }
+
+            ClientMessage::Create(room) => {
+                if self.rooms.contains_key(&room) { return self.error(id, &format!("room {} already exists", room)); }
+                if self.clients[&id].room.is_some() { return self.error(id, "already in a room"); }
+
+                self.rooms.insert(room.clone(), Room { members: Vec::new(), game: None });
+                self.enter_room(id, &room, Status::Waiting);
+            }
+            ClientMessage::Join(room)     => self.join(id, &room, false),
+            ClientMessage::Spectate(room) => self.join(id, &room, true),
+            ClientMessage::Leave => {
+                if self.clients[&id].room.is_none() { return self.error(id, "not in a room"); }
+                self.leave_room(id);
+                self.send(id, &ServerMessage::Left);
+            }
+
+            ClientMessage::Ready | ClientMessage::Unready => {
+                let ready = message == ClientMessage::Ready;
+                let client = &self.clients[&id];
+                let room = match (&client.room, client.status) {
+                    (Some(room), Status::Waiting) | (Some(room), Status::Ready) => room.clone(),
+                    _ => return self.error(id, "nothing to be ready for")
+                };
+
+                self.set_status(id, if ready { Status::Ready } else { Status::Waiting });
+                self.maybe_start(&room);
+            }
+
+            ClientMessage::Keys(keys) => {
+                let client = &self.clients[&id];
+                let game = match (&client.room, client.status) {
+                    (Some(room), Status::Playing) => self.rooms.get_mut(room).and_then(|room| room.game.as_mut()),
+                    _ => None
+                };
+                // Keys that arrive after a player is out, or between matches, don't matter. No need to complain.
+                if let Some(game) = game {
+                    if let Some(slot) = game.slots.iter().position(|slot| *slot == id) {
+                        game.keys[slot].extend(keys);
+                    }
+                }
+            }
+
+            ClientMessage::Bye => self.dead.push(id)
+        }
+    }
+
+    fn join(&mut self, id: u32, room: &str, spectating: bool)
+    {
+        let playing = match self.rooms.get(room) {
+            Some(room) => room.game.is_some(),
+            None => return self.error(id, &format!("no room called {}", room))
+        };
+        if self.clients[&id].room.is_some() { return self.error(id, "already in a room"); }
+        if playing && !spectating { return self.error(id, "match in progress, spectate or wait for it to end"); }
+
+        self.enter_room(id, room, if spectating { Status::Spectating } else { Status::Waiting });
+    }
+
+    /// Puts a client in a room, and tells everyone about it. They get the whole roster, and the match so far if there's one on.
+    fn enter_room(&mut self, id: u32, room: &str, status: Status)
+    {
+        if let Some(client) = self.clients.get_mut(&id)
+        {
+            client.room = Some(room.to_string());
+            client.status = status;
+        }
+        self.rooms.get_mut(room).unwrap().members.push(id);
+        self.send(id, &ServerMessage::Joined { room: room.to_string(), spectating: status == Status::Spectating });
+
+        let members = self.rooms[room].members.clone();
+        for member in members.iter().filter(|member| **member != id)
+        {
+            let message = self.member_message(*member);
+            self.send(id, &message);
+        }
+        let message = self.member_message(id);
+        self.broadcast(room, &message);
+
+        let start = self.rooms[room].game.as_ref().map(|game| self.start_messages(game));
+        for message in start.unwrap_or_default() { self.send(id, &message); }
+    }
+
+    /// Takes a client out of whatever room they're in. Walking out of a match counts as topping out.
+    fn leave_room(&mut self, id: u32)
+    {
+        let name = match self.clients.get_mut(&id).and_then(|client| client.room.take()) { Some(name) => name, None => return };
+        let room = self.rooms.get_mut(&name).unwrap();
+        room.members.retain(|member| *member != id);
+
+        let mut out = None;
+        if let Some(game) = room.game.as_mut() {
+            if let Some(slot) = game.slots.iter().position(|slot| *slot == id) {
+                if game.battle.places[slot].is_none() {
+                    game.battle.forfeit(slot);
+                    out = Some(ServerMessage::Out { slot, place: game.battle.places[slot].unwrap() });
+                }
+            }
+        }
+
+        if room.members.is_empty() {
+            self.rooms.remove(&name);
+            return;
+        }
+        if let Some(out) = out { self.broadcast(&name, &out); }
+        self.broadcast(&name, &ServerMessage::Gone(id));
+        self.maybe_start(&name);    // Maybe everyone left was already ready.
+    }
+
+    fn set_status(&mut self, id: u32, status: Status)
+    {
+        let room = match self.clients.get_mut(&id) {
+            Some(client) => { client.status = status; client.room.clone() }
+            None => return
+        };
+        let message = self.member_message(id);
+        if let Some(room) = room { self.broadcast(&room, &message); }
+    }
+
+    fn member_message(&self, id: u32) -> ServerMessage
+    {
+        let client = &self.clients[&id];
+        ServerMessage::Member { id, name: client.name.clone().unwrap_or_default(), status: client.status }
+    }
+
+    /// What someone needs to hear to follow a match: its seed, and who's playing which slot.
+    fn start_messages(&self, game: &Game) -> Vec<ServerMessage>
+    {
+        let mut messages = vec![ServerMessage::Start { seed: game.battle.players[0].seed, players: game.slots.len() }];
+        for (slot, id) in game.slots.iter().enumerate()
+        {
+            let name = self.clients.get(id).and_then(|client| client.name.clone()).unwrap_or_else(|| "-".to_string());
+            messages.push(ServerMessage::Slot { slot, id: *id, name });
+        }
+        messages
+    }
+
+    /// Starts a match once every player in the room is ready, as long as there are at least two of them.
+    fn maybe_start(&mut self, name: &str)
+    {
+        let room = match self.rooms.get(name) { Some(room) => room, None => return };
+        if room.game.is_some() { return; }
+
+        let players: Vec<u32> = room.members.iter().copied().filter(|member| self.clients[member].status != Status::Spectating).collect();
+        if players.len() < 2 || players.iter().any(|player| self.clients[player].status != Status::Ready) { return; }
+
+        let seed = thread_rng().gen();
+        let game = Game { battle: Battle::with_seed(seed, players.len(), self.rules.clone()), slots: players.clone(), keys: vec![Vec::new(); players.len()], frame: 0 };
+        eprintln!("rustris-server: room {} starts a match for {} players, seed {}", name, players.len(), seed);
+
+        for message in self.start_messages(&game) { self.broadcast(name, &message); }
+        self.rooms.get_mut(name).unwrap().game = Some(game);
+        for player in players { self.set_status(player, Status::Playing); }
+    }
+
+    /// Moves every match on by one update, and sends everyone the result.
+    fn update(&mut self)
+    {
+        let names: Vec<String> = self.rooms.iter().filter(|(_, room)| room.game.is_some()).map(|(name, _)| name.clone()).collect();
+
+        for name in names
+        {
+            let game = self.rooms.get_mut(&name).unwrap().game.as_mut().unwrap();
+
+            for (player, keys) in game.battle.players.iter_mut().zip(game.keys.iter_mut())
+            {
+                player.inputs = std::mem::take(keys);
+            }
+            let knocked_out = game.battle.update();
+            game.frame += 1;
+
+            let mut messages = vec![ServerMessage::Frame(game.frame)];
+            messages.extend((0..game.slots.len()).map(|slot| ServerMessage::State(Box::new(PlayerView::new(&game.battle, slot)))));
+            messages.extend(knocked_out.iter().map(|slot| ServerMessage::Out { slot: *slot, place: game.battle.places[*slot].unwrap() }));
+
+            let over = game.battle.is_over();
+            if over { messages.push(ServerMessage::Winner(game.battle.winner())); }
+
+            for message in messages.iter() { self.broadcast(&name, message); }
+
+            // Back to the lobby, where everyone has to ready up again.
+            if over
+            {
+                let room = self.rooms.get_mut(&name).unwrap();
+                room.game = None;
+                let members = room.members.clone();
+                let players: Vec<u32> = members.into_iter().filter(|member| self.clients[member].status == Status::Playing).collect();
+                for player in players { self.set_status(player, Status::Waiting); }
+            }
+        }
+    }
+}
@@ -0,0 +1,535 @@
+/*
+
+rustris-sim: plays games with no window, no terminal and no sound, as fast as the CPU allows.
+
+The keys come from a bot or a replay file instead of a keyboard, and each game's final stats are printed
+to stdout as one line of JSON, so runs can be collected with a shell loop and compared with jq.
+
+    rustris-sim --seed 42 --randomizer bag --input random --games 100
+    rustris-sim --seed 1 --games 20 --input ai --weights strong.profile --max-pieces 1000
+    rustris-sim --replay last.replay
+
+It can also play one side of a network versus match, which makes it easy to try netplay on one box:
+
+    rustris-sim --host 7341 --input ai &
+    rustris-sim --join 127.0.0.1:7341 --input random
+
+Or be a bot on a rustris-server, playing (or watching) one match in a room:
+
+    rustris-sim --server 127.0.0.1:7342 --room party --input ai
+
+Same rules as the other frontends: everything goes through game_update().
+
+It also solves perfect clears in bulk: one problem per line of stdin, a fumen of the well and the pieces (the
+falling one first, with a held one in brackets before it), or just a fumen with the pieces in a quiz comment.
+Each answer is a line of JSON, with the solution as a fumen of a page per piece.
+
+    echo "v115@vhAAgH [T]IOLJSZTLJ" | rustris-sim --solve-pc 4
+
+(C) 2021 Ben Cantrick. MIT License, see LICENSE.txt.
+
+*/
+
+extern crate rand;
+extern crate serde;
+extern crate serde_json;
+extern crate tetris;
+
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+
+use tetris::*;
+use tetris::ai::{AiBot, Weights};
+use tetris::input::{InputSource, RandomBot};
+use tetris::lobby::{ClientMessage, LobbyClient, ServerMessage, Status};
+use tetris::modes::Mode;
+use tetris::net::{versus_hash, NetVersus, DEFAULT_INPUT_DELAY};
+use tetris::pieces::PieceSet;
+use tetris::fumen;
+use tetris::replay::{Replay, ReplayInput};
+use tetris::solver::{solution_fumen, Problem, Solution, MAX_LINES};
+use tetris::versus::{Outcome, Rules};
+
+use std::collections::HashSet;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+const USAGE: &str = "\
+usage: rustris-sim [options]
+
+  --seed N            seed for the first game (default: random)
+  --randomizer NAME   bag, memoryless or nes (default: bag)
+  --mode NAME         endless, sprint-20, sprint-40, sprint-100, ultra-2, ultra-3, marathon-0 to marathon-19,
+                      master, or cheese-N with optional -mPERCENT messiness and -rSECONDS rising (default: endless)
+  --well SIZE         well size, width x height, optionally + hidden rows: 4x20, 20x30+6 (default: 10x20+4)
+  --pieces NAME       piece set: standard, triominoes, pentominoes, mixed, or one of your own (default: standard)
+  --input NAME        who plays: random or ai (default: random)
+  --weights FILE      AI profile to play with (default: built-in weights)
+  --lookahead         let the AI look at the next piece too
+  --replay FILE       play back a replay file instead; its seed, randomizer and mode are used
+  --games N           number of games to play, with seeds N, N+1, ... (default: 1)
+  --max-frames N      stop a game after this many updates (default: no limit)
+  --max-pieces N      stop a game after this many pieces (default: no limit)
+  --record FILE       save a replay of the game (only with --games 1)
+  --host PORT         play one network versus match as player 1, waiting for player 2 on this port
+  --join ADDRESS      play one network versus match as player 2, joining a host at ADDRESS (host:port)
+  --input-delay N     updates of input delay for a hosted match (default: 4)
+  --server ADDRESS    play one match on a rustris-server at ADDRESS (host:port), in the room given by --room
+  --room NAME         room to join on the server, created if it isn't there (default: sim)
+  --name NAME         name to go by on the server (default: sim-N)
+  --spectate          watch the match in the room instead of playing
+  --players N         on a server, wait for this many players in the room before readying up (default: 2)
+  --solve-pc LINES    solve perfect clears in that many lines or fewer, one problem per line of stdin:
+                      FUMEN [PIECES], the pieces as letters, falling first, held in brackets: [T]IOLJSZ
+  --hold              let the solver use hold even when nothing's held to begin with
+";
+
+
+struct Options
+{
+    seed: u64,
+    randomizer: Randomizer,
+    mode: Mode,
+    well: Well,         // Empty, just its size.
+    pieces: Arc<PieceSet>,
+    input: String,
+    weights: Weights,
+    lookahead: bool,
+    replay: Option<PathBuf>,
+    games: u64,
+    max_frames: Option<u32>,
+    max_pieces: Option<u32>,
+    record: Option<PathBuf>,
+    host: Option<u16>,
+    join: Option<String>,
+    input_delay: u32,
+    server: Option<String>,
+    room: String,
+    name: String,
+    spectate: bool,
+    players: usize,
+    solve_pc: Option<usize>,
+    hold: bool
+}
+
+
+/// What gets printed for each game.
+#[derive(Serialize)]
+struct GameStats
+{
+    seed: u64,
+    randomizer: String,
+    mode: String,
+    input: String,
+    lines: u32,
+    score: u32,
+    pieces: u32,
+    frames: u32,
+    finesse_faults: u32,        // Wasted move/rotate presses. (Only meaningful for people and replays of them.)
+    game_over: &'static str,    // Why the game ended: top_out, goal_reached, time_up, frame_limit, piece_limit or replay_end.
+    elapsed_ms: f64             // Wall clock time spent simulating.
+}
+
+
+/// What gets printed for each perfect clear problem.
+#[derive(Serialize)]
+struct SolveStats
+{
+    problem: usize,             // Which line of the input, from 1.
+    pieces: String,
+    result: &'static str,       // found, impossible or gave_up.
+    solution: Option<String>,   // As a fumen, a page per piece. Pages where hold was pressed first say so.
+    elapsed_ms: f64
+}
+
+
+/// What gets printed for a network match. Both sides should print the same thing, apart from player.
+#[derive(Serialize)]
+struct NetStats
+{
+    player: usize,              // 1 for the host, 2 for the one who joined.
+    seed: u64,
+    frames: u32,
+    outcome: String,            // p1_wins, p2_wins, draw, or the error that ended the match.
+    lines: [u32; 2],
+    sent: [u32; 2],
+    hash: String                // The match's state hash at the end, to check that both sides agree.
+}
+
+
+/// What gets printed for a match on a server.
+#[derive(Serialize)]
+struct ServerStats
+{
+    name: String,
+    room: String,
+    seed: u64,
+    slot: Option<usize>,        // None for a spectator.
+    place: Option<usize>,
+    winner: Option<usize>,      // The winning slot. None for a draw.
+    frames: u32,
+    lines: u32,
+    sent: u32
+}
+
+
+fn main()
+{
+    let options = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("rustris-sim: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Some(lines) = options.solve_pc
+    {
+        for (index, line) in io::stdin().lock().lines().map_while(Result::ok).enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match solve_pc(index + 1, &line, lines, options.hold) {
+                Ok(stats) => println!("{}", serde_json::to_string(&stats).unwrap()),
+                Err(err) => eprintln!("rustris-sim: line {}: {}", index + 1, err)
+            }
+        }
+        return;
+    }
+
+    if options.host.is_some() || options.join.is_some()
+    {
+        let stats = run_net(&options).unwrap_or_else(|err| {
+            eprintln!("rustris-sim: {}", err);
+            process::exit(1);
+        });
+        println!("{}", serde_json::to_string(&stats).unwrap());
+        return;
+    }
+
+    if let Some(address) = &options.server
+    {
+        let stats = run_server(address, &options).unwrap_or_else(|err| {
+            eprintln!("rustris-sim: {}", err);
+            process::exit(1);
+        });
+        println!("{}", serde_json::to_string(&stats).unwrap());
+        return;
+    }
+
+    // A replay brings its own seed, randomizer and mode, and is exactly one game.
+    if let Some(path) = &options.replay
+    {
+        let replay = Replay::load(path).unwrap_or_else(|err| {
+            eprintln!("rustris-sim: can't read replay {}: {}", path.display(), err);
+            process::exit(1);
+        });
+
+        let mut game_state = replay.new_game();
+        let mut input = ReplayInput::new(replay);
+        let stats = run_game(&mut game_state, &mut input, "replay", &options, None);
+        println!("{}", serde_json::to_string(&stats).unwrap());
+        return;
+    }
+
+    for game in 0..options.games
+    {
+        let seed = options.seed.wrapping_add(game);
+        let mut game_state = GameState::with_seed(seed, options.randomizer);
+        game_state.set_mode(options.mode);
+        if !options.well.is_standard() { game_state.set_well(options.well.clone()); }    // Same as the settings do it. (See Settings::apply_rules().)
+        if !options.pieces.is_standard() { game_state.set_piece_set(options.pieces.clone()); }
+
+        let mut input: Box<dyn InputSource> = match options.input.as_str() {
+            "random" => Box::new(RandomBot::new(seed)),
+            "ai"     => Box::new(AiBot::new(options.weights, options.lookahead)),
+            _ => unreachable!()    // parse_args() already checked.
+        };
+
+        let mut replay = options.record.as_ref().map(|_| Replay::new(&game_state));
+        let stats = run_game(&mut game_state, input.as_mut(), &options.input, &options, replay.as_mut());
+        println!("{}", serde_json::to_string(&stats).unwrap());
+
+        if let (Some(replay), Some(path)) = (replay, &options.record) {
+            if let Err(err) = replay.save(path) {
+                eprintln!("rustris-sim: can't write replay {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+
+/// Runs one game to the end, with no delay between updates.
+fn run_game(game_state: &mut GameState, input: &mut dyn InputSource, input_name: &str,
+            options: &Options, mut replay: Option<&mut Replay>) -> GameStats
+{
+    let start = Instant::now();
+
+    let game_over = loop
+    {
+        if let Some(reason) = game_state.game_over { break reason.name(); }
+        if input.finished(game_state) { break "replay_end"; }
+        if options.max_frames.is_some_and(|max| game_state.frames >= max) { break "frame_limit"; }
+        if options.max_pieces.is_some_and(|max| game_state.pieces >= max) { break "piece_limit"; }
+
+        game_state.inputs = input.next_keys(game_state);
+        game_state.repeats = input.repeats();
+        if let Some(replay) = replay.as_mut() { replay.record(game_state.frames, &game_state.inputs, game_state.repeats); }
+
+        game_update(game_state);
+    };
+
+    GameStats {
+        seed: game_state.seed,
+        randomizer: game_state.randomizer.to_string(),
+        mode: game_state.mode.to_string(),
+        input: input_name.to_string(),
+        lines: game_state.lines,
+        score: game_state.score,
+        pieces: game_state.pieces,
+        frames: game_state.frames,
+        finesse_faults: game_state.finesse.faults,
+        game_over,
+        elapsed_ms: start.elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+
+/// Solves one perfect clear problem, given as FUMEN [PIECES].
+fn solve_pc(problem: usize, line: &str, lines: usize, hold: bool) -> Result<SolveStats, String>
+{
+    let mut words = line.split_whitespace();
+    let pages = fumen::decode(words.next().unwrap())?;
+
+    // The pieces, or else the fumen's quiz: #Q=[HELD](FALLING)REST.
+    let pieces = match words.next() {
+        Some(pieces) => pieces.to_string(),
+        None => pages[0].comment.strip_prefix("#Q=").ok_or("no pieces, and the fumen isn't a quiz")?.replace(['(', ')'], "")
+    };
+    let (held, rest) = match pieces.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']').ok_or(format!("'{}' has no closing bracket", pieces))?,
+        None => ("", pieces.as_str())
+    };
+
+    let letters = |text: &str| text.chars().map(|letter| match "IJLOSTZ".find(letter) {
+        Some(index) => Ok(Tetrimino::new(TetriminoKind::STANDARD[index])),
+        None => Err(format!("'{}' isn't a piece (expected I, J, L, O, S, T or Z)", letter))
+    }).collect::<Result<Vec<_>, _>>();
+
+    let mut solver = Problem::new(pages[0].well.clone(), letters(rest)?, lines);
+    solver.hold = letters(held)?.first().copied();
+    solver.can_hold = hold || solver.hold.is_some();
+
+    let start = Instant::now();
+    let (result, solution) = match solver.solve() {
+        Solution::Found(steps) => ("found", Some(solution_fumen(&solver.well, &steps)?)),
+        Solution::Impossible => ("impossible", None),
+        Solution::GaveUp => ("gave_up", None)
+    };
+
+    Ok(SolveStats { problem, pieces, result, solution, elapsed_ms: start.elapsed().as_secs_f64() * 1000.0 })
+}
+
+
+/// Plays one side of a network versus match to the end.
+fn run_net(options: &Options) -> Result<NetStats, String>
+{
+    let mut net = match (options.host, &options.join) {
+        (Some(port), _) => NetVersus::host(port, Rules::default(), options.input_delay),
+        (None, Some(address)) => NetVersus::join(address.as_str()),
+        _ => unreachable!()
+    }.map_err(|err| err.to_string())?;
+
+    let seed = net.versus.players[0].seed;
+    let mut input: Box<dyn InputSource> = match options.input.as_str() {
+        "random" => Box::new(RandomBot::new(seed.wrapping_add(net.local as u64))),
+        "ai"     => Box::new(AiBot::new(options.weights, options.lookahead)),
+        _ => unreachable!()
+    };
+
+    let outcome = loop
+    {
+        match net.versus.outcome {
+            Some(Outcome::Winner(player)) => break format!("p{}_wins", player + 1),
+            Some(Outcome::Draw) => break "draw".to_string(),
+            None => ()
+        }
+        if options.max_frames.is_some_and(|max| net.frame() >= max) { break "frame_limit".to_string(); }
+
+        // Only think about new keys once the last ones have gone.
+        if net.local_keys.is_empty() {
+            let local = net.local;
+            net.local_keys = input.next_keys(&net.versus.players[local]);
+        }
+
+        match net.step() {
+            Ok(true) => (),
+            Ok(false) => thread::sleep(Duration::from_millis(1)),    // Waiting on the other side.
+            Err(err) => break err.to_string()
+        }
+    };
+
+    Ok(NetStats {
+        player: net.local + 1,
+        seed,
+        frames: net.frame(),
+        outcome,
+        lines: [net.versus.players[0].lines, net.versus.players[1].lines],
+        sent: net.versus.sent,
+        hash: format!("{:016x}", versus_hash(&net.versus))
+    })
+}
+
+
+/// Joins (or makes) a room on a server, readies up, and plays one match there. Spectators just watch it.
+fn run_server(address: &str, options: &Options) -> Result<ServerStats, String>
+{
+    let mut client = LobbyClient::connect(address, &options.name).map_err(|err| err.to_string())?;
+    let join = if options.spectate { ClientMessage::Spectate(options.room.clone()) } else { ClientMessage::Join(options.room.clone()) };
+    client.send(&join).map_err(|err| err.to_string())?;
+
+    let mut input: Box<dyn InputSource> = match options.input.as_str() {
+        "random" => Box::new(RandomBot::new(options.seed)),
+        "ai"     => Box::new(AiBot::new(options.weights, options.lookahead)),
+        _ => unreachable!()
+    };
+    let mut stats = ServerStats { name: options.name.clone(), room: options.room.clone(), seed: 0, slot: None, place: None, winner: None,
+                                  frames: 0, lines: 0, sent: 0 };
+
+    let mut players = HashSet::new();
+    let mut ready = false;
+
+    loop
+    {
+        let messages = client.poll().map_err(|err| err.to_string())?;
+        if messages.is_empty() { thread::sleep(Duration::from_millis(1)); }
+
+        for message in messages
+        {
+            match message
+            {
+                // No such room yet? Make it, or for a spectator, wait for it. Somebody else may make it first, so then join that.
+                ServerMessage::Error(msg) if msg.starts_with("no room") && options.spectate => {
+                    thread::sleep(Duration::from_millis(100));
+                    client.send(&join).map_err(|err| err.to_string())?;
+                }
+                ServerMessage::Error(msg) if msg.starts_with("no room") => client.send(&ClientMessage::Create(options.room.clone())).map_err(|err| err.to_string())?,
+                ServerMessage::Error(msg) if msg.ends_with("already exists") => client.send(&join).map_err(|err| err.to_string())?,
+                ServerMessage::Error(msg) => return Err(format!("server says: {}", msg)),
+
+                // Keep count of who's in the room, and ready up once everyone's here.
+                ServerMessage::Member { id, status, .. } => {
+                    if status == Status::Spectating { players.remove(&id); } else { players.insert(id); }
+                    if !ready && !options.spectate && players.len() >= options.players {
+                        client.send(&ClientMessage::Ready).map_err(|err| err.to_string())?;
+                        ready = true;
+                    }
+                }
+                ServerMessage::Gone(id) => { players.remove(&id); }
+
+                ServerMessage::Start { seed, .. } => stats.seed = seed,
+                ServerMessage::Slot { slot, id, .. } if id == client.id => stats.slot = Some(slot),
+                ServerMessage::Frame(frame) => stats.frames = frame,
+
+                ServerMessage::State(view) if Some(view.slot) == stats.slot => {
+                    stats.lines = view.lines;
+                    stats.sent = view.sent;
+                    stats.place = view.place;
+
+                    let keys = input.next_keys(&view.to_game_state());
+                    if !keys.is_empty() { client.send(&ClientMessage::Keys(keys)).map_err(|err| err.to_string())?; }
+                }
+                ServerMessage::Out { slot, place } if Some(slot) == stats.slot => stats.place = Some(place),
+
+                ServerMessage::Winner(winner) => {
+                    if winner.is_some() && winner == stats.slot { stats.place = Some(1); }
+                    stats.winner = winner;
+                    return Ok(stats);
+                }
+                _ => ()
+            }
+        }
+    }
+}
+
+
+fn parse_args(args: Vec<String>) -> Result<Options, String>
+{
+    let mut options = Options {
+        seed: thread_rng().gen(),
+        randomizer: Randomizer::Bag,
+        mode: Mode::Endless,
+        well: Well::default(),
+        pieces: Arc::new(PieceSet::standard()),
+        input: "random".to_string(),
+        weights: Weights::default(),
+        lookahead: false,
+        replay: None,
+        games: 1,
+        max_frames: None,
+        max_pieces: None,
+        record: None,
+        host: None,
+        join: None,
+        input_delay: DEFAULT_INPUT_DELAY,
+        server: None,
+        room: "sim".to_string(),
+        name: format!("sim-{}", thread_rng().gen_range(0, 10000)),
+        spectate: false,
+        players: 2,
+        solve_pc: None,
+        hold: false
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next()
+    {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        let number = |value: String| value.parse::<u64>().map_err(|_| format!("'{}' is not a number", value));
+
+        match arg.as_str()
+        {
+            "--seed"       => options.seed = number(value()?)?,
+            "--randomizer" => options.randomizer = value()?.parse()?,
+            "--mode"       => options.mode = value()?.parse()?,
+            "--well"       => options.well = parse_well_size(&value()?)?,
+            "--pieces"     => options.pieces = Arc::new(PieceSet::load(&value()?)?),
+            "--input"      => options.input = value()?,
+            "--weights"    => {
+                let path = PathBuf::from(value()?);
+                options.weights = Weights::load(&path).map_err(|err| format!("can't read AI profile {}: {}", path.display(), err))?;
+            }
+            "--lookahead"  => options.lookahead = true,
+            "--replay"     => options.replay = Some(PathBuf::from(value()?)),
+            "--games"      => options.games = number(value()?)?,
+            "--max-frames" => options.max_frames = Some(number(value()?)? as u32),
+            "--max-pieces" => options.max_pieces = Some(number(value()?)? as u32),
+            "--record"     => options.record = Some(PathBuf::from(value()?)),
+            "--host"       => options.host = Some(number(value()?)? as u16),
+            "--join"       => options.join = Some(value()?),
+            "--input-delay" => options.input_delay = number(value()?)? as u32,
+            "--server"     => options.server = Some(value()?),
+            "--room"       => options.room = value()?,
+            "--name"       => options.name = value()?,
+            "--spectate"   => options.spectate = true,
+            "--players"    => options.players = number(value()?)? as usize,
+            "--solve-pc"   => options.solve_pc = Some(number(value()?)? as usize),
+            "--hold"       => options.hold = true,
+            "--help" | "-h" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option '{}'", arg))
+        }
+    }
+
+    if options.input != "random" && options.input != "ai" { return Err(format!("unknown input '{}' (expected random or ai)", options.input)); }
+    if options.host.is_some() && options.join.is_some() { return Err("--host and --join don't go together".to_string()); }
+    if options.record.is_some() && options.games != 1 { return Err("--record only works with --games 1".to_string()); }
+    if options.solve_pc.is_some_and(|lines| lines == 0 || lines > MAX_LINES) { return Err(format!("--solve-pc takes 1 to {} lines", MAX_LINES)); }
+
+    Ok(options)
+}
@@ -0,0 +1,321 @@
+/*
+
+rustris-train: evolves AI weights with a genetic algorithm.
+
+Each individual in the population is a set of AI weights. Its fitness is the average number of lines it
+clears over the same fixed set of seeded games, played headless, so every individual faces exactly the
+same pieces and results are reproducible. Games are spread across all CPU cores with rayon.
+
+Each generation keeps the best few individuals as they are, and fills the rest of the population with
+children: two parents picked by tournament selection, crossed over (a fitness-weighted average of the
+two), then mutated a little. Weight vectors are kept at unit length, since only their direction matters
+to which placement the AI picks.
+
+After every generation, the whole population is checkpointed to disk (so a long run can be stopped and
+resumed with --resume), and the best weights so far are written as an AI profile that rustris-sim and
+the game can load.
+
+    rustris-train --population 50 --generations 40 --games 10 --out strong.profile
+    rustris-sim --input ai --weights strong.profile --seed 1000 --games 100
+
+(C) 2021 Ben Cantrick. MIT License, see LICENSE.txt.
+
+*/
+
+extern crate rand;
+extern crate rayon;
+extern crate serde;
+extern crate serde_json;
+extern crate tetris;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use tetris::Randomizer;
+use tetris::ai::{play_game, Weights, NUM_FEATURES};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+
+const USAGE: &str = "\
+usage: rustris-train [options]
+
+  --population N      individuals per generation (default: 50)
+  --generations N     stop after this many generations (default: 30)
+  --games N           games each individual plays per generation (default: 8)
+  --max-pieces N      pieces per game at most (default: 500)
+  --eval-seed N       games are played with seeds N, N+1, ... (default: 1000)
+  --randomizer NAME   bag, memoryless or nes (default: memoryless, which is harder to survive)
+  --lookahead         let the AI look at the next piece too (much slower)
+  --tournament N      individuals per tournament when picking parents (default: 4)
+  --elite N           best individuals copied unchanged to the next generation (default: 2)
+  --mutation-rate X   chance of mutating each weight of a child (default: 0.1)
+  --seed N            seed for the GA's own choices (default: 1)
+  --checkpoint FILE   where to save the population after each generation (default: train-checkpoint.json)
+  --resume FILE       carry on from a checkpoint; its settings replace the ones above,
+                      except --generations, which can be raised to train for longer
+  --out FILE          where to save the best weights as an AI profile (default: best.profile)
+";
+
+
+/// Everything that decides how training goes. Saved in checkpoints, so a resumed run carries on identically.
+#[derive(Clone, Serialize, Deserialize)]
+struct Config
+{
+    population: usize,
+    generations: u32,
+    games: u64,
+    max_pieces: u32,
+    eval_seed: u64,
+    randomizer: String,
+    lookahead: bool,
+    tournament: usize,
+    elite: usize,
+    mutation_rate: f64,
+    seed: u64
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Individual
+{
+    weights: Weights,
+    fitness: Option<f64>    // None until it has played its games.
+}
+
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint
+{
+    version: u32,
+    generation: u32,
+    config: Config,
+    population: Vec<Individual>
+}
+
+
+const CHECKPOINT_VERSION: u32 = 1;
+
+
+fn main()
+{
+    let (config, generations_set, checkpoint_path, resume, out_path) = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(parsed) => parsed,
+        Err(msg) => {
+            eprintln!("rustris-train: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut checkpoint = match resume {
+        Some(path) => {
+            let mut checkpoint = load_checkpoint(&path).unwrap_or_else(|msg| {
+                eprintln!("rustris-train: can't resume from {}: {}", path.display(), msg);
+                process::exit(1);
+            });
+            if generations_set { checkpoint.config.generations = config.generations; }
+            checkpoint
+        }
+        None => Checkpoint { version: CHECKPOINT_VERSION, generation: 0, population: first_generation(&config), config }
+    };
+
+    let randomizer: Randomizer = checkpoint.config.randomizer.parse().unwrap();
+
+    loop
+    {
+        let config = checkpoint.config.clone();
+
+        // Play the games. Individuals carried over from the last generation already know their fitness.
+        checkpoint.population.par_iter_mut()
+            .filter(|individual| individual.fitness.is_none())
+            .for_each(|individual| individual.fitness = Some(fitness(&individual.weights, &config, randomizer)));
+
+        checkpoint.population.sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap());
+
+        let fitnesses: Vec<f64> = checkpoint.population.iter().map(|individual| individual.fitness.unwrap()).collect();
+        eprintln!("generation {:3}: best {:8.1}  mean {:8.1}  worst {:8.1} lines",
+                  checkpoint.generation, fitnesses[0],
+                  fitnesses.iter().sum::<f64>() / fitnesses.len() as f64, fitnesses[fitnesses.len() - 1]);
+
+        let saved = fs::write(&checkpoint_path, serde_json::to_string_pretty(&checkpoint).unwrap())
+                        .and_then(|_| checkpoint.population[0].weights.save(&out_path));
+        if let Err(err) = saved {
+            eprintln!("rustris-train: can't save progress: {}", err);
+            process::exit(1);
+        }
+
+        if checkpoint.generation + 1 >= config.generations { break; }
+
+        checkpoint.population = next_generation(&checkpoint.population, &config, checkpoint.generation);
+        checkpoint.generation += 1;
+    }
+
+    println!("{}", checkpoint.population[0].weights.to_text());
+}
+
+
+/// Average lines cleared over the fixed set of games.
+fn fitness(weights: &Weights, config: &Config, randomizer: Randomizer) -> f64
+{
+    let total: u32 = (0..config.games)
+                        .map(|game| play_game(weights, config.lookahead, config.eval_seed + game, randomizer, config.max_pieces).lines)
+                        .sum();
+    total as f64 / config.games as f64
+}
+
+
+/// Random weights, plus the built-in ones so the population starts with at least one decent player.
+fn first_generation(config: &Config) -> Vec<Individual>
+{
+    let mut rng = StdRng::seed_from_u64(config.seed);
+
+    let mut population = vec![ Individual { weights: normalized(Weights::default().to_array()), fitness: None } ];
+    while population.len() < config.population
+    {
+        let mut values = [0.0; NUM_FEATURES];
+        for value in values.iter_mut() { *value = rng.gen_range(-1.0, 1.0); }
+        population.push(Individual { weights: normalized(values), fitness: None });
+    }
+
+    population
+}
+
+
+/// Breeds the next generation from the current one, which must be sorted best first.
+fn next_generation(population: &[Individual], config: &Config, generation: u32) -> Vec<Individual>
+{
+    // A fresh RNG per generation, so resuming from a checkpoint makes the same choices an unbroken run would have.
+    let mut rng = StdRng::seed_from_u64(config.seed ^ (u64::from(generation) + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+
+    let mut next: Vec<Individual> = population.iter().take(config.elite).cloned().collect();
+
+    while next.len() < config.population
+    {
+        let mom = tournament(population, config.tournament, &mut rng);
+        let dad = tournament(population, config.tournament, &mut rng);
+
+        // Crossover: average the parents, leaning toward the fitter one.
+        let (mom_fitness, dad_fitness) = (mom.fitness.unwrap().max(0.0), dad.fitness.unwrap().max(0.0));
+        let mom_share = if mom_fitness + dad_fitness > 0.0 { mom_fitness / (mom_fitness + dad_fitness) } else { 0.5 };
+
+        let (mom_values, dad_values) = (mom.weights.to_array(), dad.weights.to_array());
+        let mut values = [0.0; NUM_FEATURES];
+        for i in 0..NUM_FEATURES
+        {
+            values[i] = mom_share * mom_values[i] + (1.0 - mom_share) * dad_values[i];
+
+            // Mutation: nudge the occasional weight.
+            if rng.gen::<f64>() < config.mutation_rate { values[i] += rng.gen_range(-0.2, 0.2); }
+        }
+
+        next.push(Individual { weights: normalized(values), fitness: None });
+    }
+
+    next
+}
+
+
+/// Picks a few individuals at random, and returns the fittest of them.
+fn tournament<'a>(population: &'a [Individual], size: usize, rng: &mut StdRng) -> &'a Individual
+{
+    (0..size.max(1))
+        .map(|_| &population[rng.gen_range(0, population.len())])
+        .max_by(|a, b| a.fitness.partial_cmp(&b.fitness).unwrap())
+        .unwrap()
+}
+
+
+/// Scales the weights to unit length.
+fn normalized(values: [f64; NUM_FEATURES]) -> Weights
+{
+    let length = values.iter().map(|value| value * value).sum::<f64>().sqrt();
+    if length == 0.0 { return Weights::from_array(values); }
+
+    let mut scaled = values;
+    for value in scaled.iter_mut() { *value /= length; }
+    Weights::from_array(scaled)
+}
+
+
+fn load_checkpoint(path: &Path) -> Result<Checkpoint, String>
+{
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let checkpoint: Checkpoint = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+
+    if checkpoint.version != CHECKPOINT_VERSION {
+        return Err(format!("checkpoint version {} is not supported (expected {})", checkpoint.version, CHECKPOINT_VERSION));
+    }
+    if checkpoint.population.is_empty() { return Err("checkpoint has an empty population".to_string()); }
+    checkpoint.config.randomizer.parse::<Randomizer>()?;
+
+    Ok(checkpoint)
+}
+
+
+/// Returns the config, whether --generations was given, and the checkpoint, resume and output paths.
+fn parse_args(args: Vec<String>) -> Result<(Config, bool, PathBuf, Option<PathBuf>, PathBuf), String>
+{
+    let mut config = Config {
+        population: 50,
+        generations: 30,
+        games: 8,
+        max_pieces: 500,
+        eval_seed: 1000,
+        randomizer: Randomizer::Memoryless.to_string(),
+        lookahead: false,
+        tournament: 4,
+        elite: 2,
+        mutation_rate: 0.1,
+        seed: 1
+    };
+    let mut generations_set = false;
+    let mut checkpoint = PathBuf::from("train-checkpoint.json");
+    let mut resume = None;
+    let mut out = PathBuf::from("best.profile");
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next()
+    {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        let number = |value: String| value.parse::<u64>().map_err(|_| format!("'{}' is not a number", value));
+
+        match arg.as_str()
+        {
+            "--population"    => config.population = number(value()?)? as usize,
+            "--generations"   => {
+                config.generations = number(value()?)? as u32;
+                generations_set = true;
+            }
+            "--games"         => config.games = number(value()?)?,
+            "--max-pieces"    => config.max_pieces = number(value()?)? as u32,
+            "--eval-seed"     => config.eval_seed = number(value()?)?,
+            "--randomizer"    => config.randomizer = value()?.parse::<Randomizer>()?.to_string(),
+            "--lookahead"     => config.lookahead = true,
+            "--tournament"    => config.tournament = number(value()?)? as usize,
+            "--elite"         => config.elite = number(value()?)? as usize,
+            "--mutation-rate" => {
+                let text = value()?;
+                config.mutation_rate = text.parse().map_err(|_| format!("'{}' is not a number", text))?;
+            }
+            "--seed"          => config.seed = number(value()?)?,
+            "--checkpoint"    => checkpoint = PathBuf::from(value()?),
+            "--resume"        => resume = Some(PathBuf::from(value()?)),
+            "--out"           => out = PathBuf::from(value()?),
+            "--help" | "-h"   => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option '{}'", arg))
+        }
+    }
+
+    if config.population < 2 { return Err("--population must be at least 2".to_string()); }
+    if config.games == 0 { return Err("--games must be at least 1".to_string()); }
+    if config.elite >= config.population { return Err("--elite must be smaller than --population".to_string()); }
+
+    Ok((config, generations_set, checkpoint, resume, out))
+}
@@ -0,0 +1,320 @@
+/*
+
+Rustris, in a terminal.
+
+Plays by the same rules as the Piston build (game_update(), would_collide() and friends all come from
+the tetris library), but draws the well with Unicode half-blocks and ANSI colors via crossterm.
+Nothing here needs a windowing system or a sound card, so it runs fine over SSH and inside tmux.
+
+Start it with e.g. "rustris-tui --mode marathon-18" to play something other than endless.
+
+Each square of the well is two terminal columns wide and half a terminal row tall. The upper half of
+a "▀" character is drawn in the foreground color, the lower half in the background color, so one line
+of text shows two rows of the well, and the squares come out roughly square.
+
+(C) 2021 Ben Cantrick. MIT License, see LICENSE.txt.
+
+*/
+
+extern crate crossterm;
+extern crate tetris;
+
+use crossterm::{cursor, event, execute, queue, style, terminal};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::style::Color;
+
+use tetris::*;
+use tetris::modes::Mode;
+use tetris::settings::Settings;
+use tetris::timestep::{TimedQueue, Timestep};
+
+use std::io::{self, Write};
+use std::time::Instant;
+
+
+// Where the upper-left corner of the well's border goes, in terminal (column, line).
+const WELL_X: u16 = 2;
+const WELL_Y: u16 = 1;
+
+// Columns the next piece, stats and status take up to the right of the well, gap included.
+const SIDE_WIDTH: u16 = 30;
+
+// How bright the ghost piece is, next to the piece itself.
+const GHOST_SHADE: f32 = 0.35;
+
+
+/// Puts the terminal into raw mode on the alternate screen, and puts it back when dropped.
+/// (Dropping also happens on a panic, so a crash doesn't leave the user's shell unusable.)
+struct TerminalGuard;
+
+impl TerminalGuard
+{
+    fn new() -> io::Result<Self>
+    {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All))?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard
+{
+    fn drop(&mut self)
+    {
+        let _ = execute!(io::stdout(), style::ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+
+//
+// ////////// MAIN //////////
+//
+fn main() -> io::Result<()>
+{
+    // Not every terminal (or every tmux config) passes 24-bit color through. Those that do say so here.
+    let truecolor = std::env::var("COLORTERM").map(|v| v == "truecolor" || v == "24bit").unwrap_or(false);
+
+    let args: Vec<String> = std::env::args().collect();
+    let mode: Mode = match args.iter().position(|arg| arg == "--mode").and_then(|i| args.get(i + 1)) {
+        Some(name) => name.parse().unwrap_or_else(|msg| {
+            eprintln!("rustris-tui: {}", msg);
+            std::process::exit(2);
+        }),
+        None => Mode::Endless
+    };
+
+    // The same settings file as the Piston build. Only the ones that make sense in a terminal are used.
+    let mut settings = Settings::load().unwrap_or_else(|msg| {
+        eprintln!("rustris-tui: {}", msg);
+        std::process::exit(2);
+    });
+    if let Err(msg) = settings.override_from_args(&args) {
+        eprintln!("rustris-tui: {}", msg);
+        std::process::exit(2);
+    }
+
+    let _guard = TerminalGuard::new()?;
+    let mut out = io::stdout();
+
+    let mut game_state = GameState::with_mode(mode);
+    settings.apply_rules(&mut game_state);
+    let mut visuals = Visuals { palette: settings.palette(), patterns: settings.visuals.patterns };
+    let mut blink_counter = 0;
+
+    // Same fixed steps as the Piston build, so the game plays at the same speed. (NES Marathon gets the NES's exact 60.0988.)
+    // Keys wait for the step they were pressed during.
+    let mut timestep = Timestep::new(mode.updates_per_second());
+    let mut pending: TimedQueue<KeyEvent> = TimedQueue::new();
+
+    // *****
+    // ***** MAIN LOOP
+    // *****
+    loop
+    {
+        // Wait for keys until it's time for the next update.
+        if event::poll(timestep.due_in(Instant::now()))?
+        {
+            match event::read()?
+            {
+                // Some terminals report releases and repeats too. We only care about presses, same as the Piston build.
+                Event::Key(key) if key.kind == KeyEventKind::Press =>
+                {
+                    if key.code == KeyCode::Char('q') || key.code == KeyCode::Esc { break; }
+                    pending.push(Instant::now(), key);
+                    if key.code == KeyCode::Char('c') { visuals.palette = visuals.palette.next(); }
+                }
+
+                Event::Resize(_, _) => queue!(out, terminal::Clear(terminal::ClearType::All))?,
+
+                _ => ()
+            }
+        }
+
+        // Not time to update yet? Then back to waiting. Running late? Then catch up.
+        let mut stepped = false;
+        while let Some(step_end) = timestep.next_step(Instant::now())
+        {
+            for key in pending.take_before(step_end) { track_keys(&mut game_state.inputs, key); }
+            stepped = true;
+
+            if game_state.game_over.is_some()
+            {
+                if blink_counter == UPDATES_PER_SECOND / 2 {
+                    game_state.well.fill(0);
+                }
+                if blink_counter == UPDATES_PER_SECOND {
+                    game_state.well.fill(1);
+                    blink_counter = 0;
+                }
+                blink_counter += 1;
+            }
+            else {
+                game_update(&mut game_state);
+            }
+        }
+
+        if stepped { render(&mut out, &game_state, &visuals, truecolor)?; }
+    }
+
+    Ok(())
+}
+
+
+/// Queues up the action a key press stands for, if it stands for one. Same keys as the Piston build.
+fn track_keys(inputs: &mut Vec<Action>, key: KeyEvent)
+{
+    let action = match key.code
+    {
+        KeyCode::Left      => Action::MoveLeft,
+        KeyCode::Right     => Action::MoveRight,
+        KeyCode::Up        => Action::RotateCCW,
+        KeyCode::Char('d') => Action::RotateCCW,
+        KeyCode::Char('f') => Action::RotateCW,
+        KeyCode::Down      => Action::SoftDrop,
+        KeyCode::Char(' ') => Action::HardDrop,
+        KeyCode::Char('h') => Action::Hold,
+        _ => return                                 // Ignore all others
+    };
+    inputs.push(action);
+}
+
+
+/// Draws the well, the falling piece and its ghost, and the next and held pieces, then flushes it all to the terminal in one go.
+fn render(out: &mut impl Write, game_state: &GameState, visuals: &Visuals, truecolor: bool) -> io::Result<()>
+{
+    // Flatten the well and the falling piece into one grid of squares, so drawing doesn't have to care which is which.
+    // The ghost, where the piece would land, gets a grid of its own, and only shows where the other has nothing.
+    let mut squares: Well = game_state.well.clone();
+    let mut ghost: Well = game_state.well.empty_like();
+    let (rows, cols) = (squares.rows() as i32, squares.cols() as i32);
+
+    // (The current piece isn't drawn while it's still waiting to appear.)
+    let ghost_row = landing_row(&game_state.curr_ttmo, &game_state.well, game_state.ttmo_row, game_state.ttmo_col);
+    for ttmo_row in 0..PIECE_SIZE {
+        for ttmo_col in 0..PIECE_SIZE {
+            if game_state.curr_ttmo.shape[ttmo_row][ttmo_col] == 0 || game_state.spawn_delay > 0 { continue; }
+
+            let col = game_state.ttmo_col + ttmo_col as i32;
+            for (grid, row) in [(&mut squares, game_state.ttmo_row), (&mut ghost, ghost_row)] {
+                let row = row + ttmo_row as i32;
+                if !(0..rows).contains(&row) || !(0..cols).contains(&col) { continue; }
+                grid.set(row as usize, col as usize, game_state.curr_ttmo.kind.cell());
+            }
+        }
+    }
+    if game_state.game_over.is_some() { ghost.fill(0); }
+
+    // Terminals can't see through anything, so the ghost (and a used hold) is the piece's color darkened toward the black well.
+    let shade = |square: u8, dim: bool| match TetriminoKind::from_cell(square) {
+        Some(kind) => {
+            let color = game_state.piece_set.color(kind, visuals.palette);
+            let scale = if dim { GHOST_SHADE } else { 1.0 };
+            term_color([color[0] * scale, color[1] * scale, color[2] * scale, color[3]], truecolor)
+        }
+        None if square == GARBAGE => term_color(GARBAGE_COLOR, truecolor),
+        None => Color::Black
+    };
+    let color_of = |square: u8| shade(square, false);
+    let color_at = |row: usize, col: usize| if squares[row][col] != 0 { color_of(squares[row][col]) } else { shade(ghost[row][col], true) };
+
+    // Squares are two characters wide, unless that would push the well and what's beside it off the side of the
+    // terminal, as with the widest wells. Then they're one, and come out tall and thin, but all there.
+    let square = if WELL_X + 2 * cols as u16 + 2 + SIDE_WIDTH <= terminal::size()?.0 { 2 } else { 1 };
+    let well_width = square * cols as u16;
+    let side_x = WELL_X + well_width + 5;
+
+    // Border around the well, plus one on each side. An odd number of rows leaves half a line spare at the bottom.
+    let first_visible_row = squares.hidden();
+    let visible_lines = (squares.visible_rows() as u16).div_ceil(2);
+    queue!(out, style::ResetColor, cursor::MoveTo(WELL_X, WELL_Y), style::Print(format!("┌{}┐", "─".repeat(well_width as usize))))?;
+    for line in 0..visible_lines {
+        queue!(out, cursor::MoveTo(WELL_X, WELL_Y + 1 + line), style::Print("│"),
+                    cursor::MoveTo(WELL_X + well_width + 1, WELL_Y + 1 + line), style::Print("│"))?;
+    }
+    queue!(out, cursor::MoveTo(WELL_X, WELL_Y + 1 + visible_lines), style::Print(format!("└{}┘", "─".repeat(well_width as usize))))?;
+
+    // The well itself, two rows of squares per line of text.
+    let half_block = "▀▀"[..3 * square as usize].to_string();
+    for line in 0..visible_lines
+    {
+        let upper_row = first_visible_row + 2 * line as usize;
+        let lower_row = (upper_row + 1 < squares.rows()).then(|| &squares[upper_row + 1]);    // None for the spare half line.
+        queue!(out, cursor::MoveTo(WELL_X + 1, WELL_Y + 1 + line))?;
+
+        for col in 0..cols as usize {
+            let lower = lower_row.map_or(Color::Reset, |_| color_at(upper_row + 1, col));
+            queue!(out, style::SetForegroundColor(color_at(upper_row, col)),
+                        style::SetBackgroundColor(lower),
+                        style::Print(&half_block))?;
+        }
+        queue!(out, style::ResetColor)?;
+    }
+
+    // The next piece, to the right of the well, and the held one under the stats, in modes with hold.
+    queue!(out, cursor::MoveTo(side_x, WELL_Y + 1), style::Print("NEXT"))?;
+    queue_piece(out, (side_x, WELL_Y + 3), Some(&game_state.next_ttmo), &color_of)?;
+    if game_state.has_hold() {
+        let held_color = |square: u8| shade(square, game_state.hold_used);
+        queue!(out, cursor::MoveTo(side_x, WELL_Y + 9), style::Print("HOLD"))?;
+        queue_piece(out, (side_x, WELL_Y + 11), game_state.hold_ttmo.as_ref(), &held_color)?;
+    }
+
+    let stats = format!("LEVEL {}  LINES {}  SCORE {}", game_state.level(), game_state.lines, game_state.score);
+    queue!(out, cursor::MoveTo(side_x, WELL_Y + 6), terminal::Clear(terminal::ClearType::UntilNewLine), style::Print(stats))?;
+
+    let status = match game_state.game_over {
+        Some(_) => "GAME OVER - q to quit",
+        None if game_state.has_hold() => "q: quit  c: colors  h: hold",
+        None => "q: quit  c: colors"
+    };
+    queue!(out, cursor::MoveTo(side_x, WELL_Y + 7), terminal::Clear(terminal::ClearType::UntilNewLine), style::Print(status))?;
+
+    out.flush()
+}
+
+
+/// Draws a piece out of the well, or nothing, with the upper-left corner of its 5x5 box at the given (column, line).
+/// The box takes up 3 lines, the last one half empty. Where the piece has no squares, the terminal's own background
+/// shows, instead of a black box.
+fn queue_piece(out: &mut impl Write, at: (u16, u16), ttmo: Option<&Tetrimino>, color_of: &dyn Fn(u8) -> Color) -> io::Result<()>
+{
+    let square = |row: usize, col: usize| match ttmo.and_then(|ttmo| ttmo.shape.get(row).map(|squares| (ttmo, squares))) {
+        Some((ttmo, squares)) if squares[col] != 0 => ttmo.kind.cell(),
+        _ => 0
+    };
+
+    for line in 0..3
+    {
+        queue!(out, cursor::MoveTo(at.0, at.1 + line as u16))?;
+
+        for col in 0..PIECE_SIZE {
+            let upper = square(2 * line, col);
+            let lower = square(2 * line + 1, col);
+
+            if upper == 0 && lower == 0 {
+                queue!(out, style::ResetColor, style::Print("  "))?;
+                continue;
+            }
+            queue!(out, style::SetForegroundColor(color_of(upper)),
+                        style::SetBackgroundColor(color_of(lower)),
+                        style::Print("▀▀"))?;
+        }
+        queue!(out, style::ResetColor)?;
+    }
+
+    Ok(())
+}
+
+
+/// Converts one of our RGBA colors to something the terminal understands.
+/// Without truecolor support, falls back to the nearest color in the standard 6x6x6 ANSI color cube.
+fn term_color(color: [f32; 4], truecolor: bool) -> Color
+{
+    if truecolor {
+        return Color::Rgb { r: (color[0] * 255.0) as u8, g: (color[1] * 255.0) as u8, b: (color[2] * 255.0) as u8 };
+    }
+
+    let cube = |channel: f32| (channel * 5.0).round() as u8;
+    Color::AnsiValue(16 + 36 * cube(color[0]) + 6 * cube(color[1]) + cube(color[2]))
+}
@@ -0,0 +1,194 @@
+/*
+
+The board editor: paint a well, line up the pieces to play in it and pick a goal, then play it or save it.
+
+What it makes is a puzzle (see puzzles.rs), so that's how it's saved: in edited.puzzles, in the puzzles folder,
+a pack like any other. The puzzle list reads it with the rest, and any puzzle on the list can be opened in the
+editor to change it. Saving one that came from edited.puzzles replaces it there. Anything else, a new board or
+a change to some other pack's puzzle, goes on the end as board-1, board-2 and so on, leaving the original alone.
+
+The first piece in the list is the one that falls first, and the rest are the queue. A piece can go in the hold
+too, to start there: then the puzzle has a hold, and holding swaps the falling piece with what's in it.
+
+Boards also go to and from fumens (see fumen.rs), to share with other Tetris players. The pieces go in as a
+quiz comment, "#Q=[hold](first)rest", which is how fumen's editor gives a queue. Reading a fumen, the board is
+its first page's, and the pieces are the quiz's if it has one, or else the pieces on its pages, in order.
+
+*/
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{TetriminoKind, Well, GARBAGE};
+use crate::fumen::{self, Page};
+use crate::puzzles::{piece_letter, Goal, Puzzle, PIECE_LETTERS};
+
+
+/// The pack the editor saves to, in the puzzles folder.
+pub const EDITED_PACK: &str = "edited.puzzles";
+
+/// The goals the editor offers, in the order Left/Right goes through them. A perfect clear is always in as many
+/// pieces as there are in the list.
+const GOALS: [Goal; 10] = [ Goal::Lines(1), Goal::Lines(2), Goal::Lines(3), Goal::Lines(4), Goal::Tetris,
+                            Goal::TSpin(1), Goal::TSpin(2), Goal::TSpin(3), Goal::ClearAll, Goal::PerfectClear(1) ];
+
+
+pub struct Editor
+{
+    pub well: Well,
+    pub pieces: Vec<TetriminoKind>,   // The first piece to fall, then the queue.
+    pub hold: Option<TetriminoKind>,  // The piece that starts in the hold, if there is one.
+    pub brush: u8,                    // What painting puts in a square: a piece's cell, GARBAGE, or 0 to rub out.
+    goal: usize,                      // Which of GOALS.
+    name: Option<String>,             // The puzzle being changed, if it's one from a pack.
+    title: String,                    // Kept from the puzzle being changed, along with its hint.
+    hint: String
+}
+
+
+impl Editor
+{
+    /// An empty well of the given size, with no pieces yet.
+    pub fn new(mut well: Well) -> Self
+    {
+        well.fill(0);
+        Editor { well, pieces: Vec::new(), hold: None, brush: GARBAGE, goal: 0, name: None, title: String::new(), hint: String::new() }
+    }
+
+    /// A puzzle's well, pieces and goal, to change. Goals the editor doesn't offer become the nearest one it does.
+    pub fn from_puzzle(puzzle: &Puzzle) -> Self
+    {
+        let goal = match puzzle.goal {
+            Goal::Lines(lines) => GOALS.iter().position(|goal| *goal == Goal::Lines(lines.min(4))).unwrap(),
+            Goal::PerfectClear(_) => GOALS.len() - 1,
+            goal => GOALS.iter().position(|offered| *offered == goal).unwrap()
+        };
+
+        Editor { well: puzzle.well.clone(), pieces: puzzle.pieces.clone(), hold: puzzle.hold, brush: GARBAGE, goal, name: Some(puzzle.name.clone()),
+                 title: puzzle.title.clone(), hint: puzzle.hint.clone() }
+    }
+
+    /// A fumen's board and pieces, to change.
+    pub fn from_fumen(text: &str) -> Result<Self, String>
+    {
+        let pages = fumen::decode(text)?;
+        let mut editor = Editor::new(Well::default());
+        editor.well = pages[0].well.clone();
+
+        let letters = |text: &str| -> Vec<TetriminoKind> { text.chars().filter_map(|c| PIECE_LETTERS.find(c)).map(|index| TetriminoKind::STANDARD[index]).collect() };
+        match pages[0].comment.strip_prefix("#Q=") {
+            // The piece in the brackets is the one held.
+            Some(quiz) => {
+                let (hold, rest) = quiz.split_once(']').unwrap_or(("", quiz));
+                editor.pieces = letters(rest);
+                editor.hold = letters(hold).first().copied();
+            }
+            None => editor.pieces = pages.iter().filter_map(|page| page.piece.map(|piece| piece.kind)).collect()
+        }
+        Ok(editor)
+    }
+
+    /// The board as a fumen, with the pieces as a quiz.
+    pub fn to_fumen(&self) -> Result<String, String>
+    {
+        let comment = match self.pieces.split_first() {
+            Some((first, rest)) => format!("#Q=[{}]({}){}", self.hold.map_or(String::new(), |hold| piece_letter(hold).to_string()), piece_letter(*first),
+                                           rest.iter().map(|kind| piece_letter(*kind)).collect::<String>()),
+            None => String::new()
+        };
+        fumen::encode(&[Page { well: self.well.clone(), piece: None, lock: true, comment }])
+    }
+
+    /// Paints a square with the brush, or rubs it out. Squares outside the well, or up in its hidden rows, are left alone.
+    pub fn paint(&mut self, row: i32, col: i32, erase: bool)
+    {
+        if row < self.well.hidden() as i32 || row >= self.well.rows() as i32 || col < 0 || col >= self.well.cols() as i32 { return; }
+        self.well.set(row as usize, col as usize, if erase { 0 } else { self.brush });
+    }
+
+    /// Puts the brush's piece on the end of the list. (Not garbage, or the eraser.)
+    pub fn add_piece(&mut self)
+    {
+        if let Some(kind) = TetriminoKind::from_cell(self.brush).filter(|kind| kind.is_standard()) { self.pieces.push(kind); }
+    }
+
+    /// Puts the brush's piece in the hold, in place of whatever was there. Garbage or the eraser empties it.
+    pub fn set_hold(&mut self)
+    {
+        self.hold = TetriminoKind::from_cell(self.brush).filter(|kind| kind.is_standard());
+    }
+
+    /// Starts again with an empty well, no pieces and a new name, in the same size of well.
+    pub fn clear(&mut self)
+    {
+        *self = Editor { brush: self.brush, ..Editor::new(self.well.clone()) };
+    }
+
+    /// The next goal along, or the one before.
+    pub fn change_goal(&mut self, forward: bool)
+    {
+        self.goal = if forward { (self.goal + 1) % GOALS.len() } else { (self.goal + GOALS.len() - 1) % GOALS.len() };
+    }
+
+    pub fn goal(&self) -> Goal
+    {
+        match GOALS[self.goal] {
+            Goal::PerfectClear(_) => Goal::PerfectClear(self.pieces.len().max(1) as u32),
+            goal => goal
+        }
+    }
+
+    /// The board as a puzzle of the given name, checked the same as one read from a pack would be.
+    pub fn to_puzzle(&self, name: &str) -> Result<Puzzle, String>
+    {
+        let title = if self.title.is_empty() || self.name.as_deref() != Some(name) { name.to_uppercase() } else { self.title.clone() };
+        let puzzle = Puzzle { name: name.to_string(), title, hint: self.hint.clone(), goal: self.goal(), pieces: self.pieces.clone(),
+                              hold: self.hold, well: self.well.clone() };
+
+        // The error messages say which line of the pack, which is no help here.
+        let mut checked = Puzzle::parse_pack(&Puzzle::pack_text(&[puzzle]))
+            .map_err(|err| err.split(": ").last().unwrap_or_default().to_string())?;
+        Ok(checked.remove(0))
+    }
+
+    /// The board as a puzzle to play straight away, under whatever name it has.
+    pub fn to_playable(&self) -> Result<Puzzle, String>
+    {
+        self.to_puzzle(self.name.as_deref().unwrap_or("board"))
+    }
+
+    /// Saves the board to EDITED_PACK in the given folder. `known` is every puzzle loaded, so a new name doesn't clash
+    /// with any of them. Returns the name it was saved under.
+    pub fn save(&mut self, dir: &Path, known: &[Arc<Puzzle>]) -> Result<String, String>
+    {
+        let path = dir.join(EDITED_PACK);
+
+        // A pack that's there but can't be read is left as it is, rather than written over.
+        let mut pack = match fs::read_to_string(&path) {
+            Ok(text) => Puzzle::parse_pack(&text).map_err(|err| format!("can't read {}: {}", path.display(), err))?,
+            Err(_) => Vec::new()
+        };
+
+        let taken = |name: &str| known.iter().any(|puzzle| puzzle.name == name) || pack.iter().any(|puzzle| puzzle.name == name);
+        let name = match &self.name {
+            Some(name) if pack.iter().any(|puzzle| puzzle.name == *name) => name.clone(),
+            _ => (1..).map(|number| format!("board-{}", number)).find(|name| !taken(name)).unwrap()
+        };
+
+        let puzzle = self.to_puzzle(&name)?;
+        match pack.iter().position(|saved| saved.name == name) {
+            Some(index) => pack[index] = puzzle,
+            None => pack.push(puzzle)
+        }
+
+        // As with profiles and high scores: a whole new file, or the old one untouched.
+        let temp = path.with_extension("puzzles.tmp");
+        fs::create_dir_all(dir).and_then(|_| fs::write(&temp, Puzzle::pack_text(&pack))).and_then(|_| fs::rename(&temp, &path))
+            .map_err(|err| err.to_string())?;
+
+        if self.name.as_deref() != Some(name.as_str()) { self.title = name.to_uppercase(); }
+        self.name = Some(name.clone());
+        Ok(name)
+    }
+}
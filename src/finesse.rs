@@ -0,0 +1,157 @@
+/*
+
+Finesse: placing each piece with as few key presses as possible.
+
+When a piece locks, we work out the fewest inputs that would have got it from where it spawned to where it
+ended up, and compare that to how many the player actually pressed. Any extra presses are finesse faults.
+
+An input is what a player does with one press of a key: a tap left or right, a turn either way, or holding
+left or right to let DAS carry the piece all the way to the wall (or whatever stops it first). So only real
+key-downs count: a held key repeating isn't pressing it again (see GameState::repeats). Drops don't count
+either way. Turns go through rotate_in_well(), so pieces with kicks in their piece set get kicked just as
+they would in the game.
+
+At 20G (Master, late on) a piece is on the ground from the moment it appears, and slides along the stack
+rather than over it, so there the search drops the piece after every input, starting from where it lands.
+
+Pieces that end up somewhere a plain drop can't reach (tucked under an overhang, say) aren't judged,
+since there's no fair "optimal" to compare them to.
+
+The search covers every spot the piece could have gone, so it's done once per piece: when finesse practice
+sends a piece back to the top, the same search does for it again.
+
+*/
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{landing_row, rotate_in_well, would_collide, Tetrimino, Well, PIECE_SIZE};
+
+
+/// Running totals for one game.
+#[derive(Clone, Default)]
+pub struct Finesse
+{
+    pub judged_pieces: u32,    // Pieces that had a known optimal, and so could be judged.
+    pub faulty_pieces: u32,    // Of those, how many took more presses than needed.
+    pub faults: u32,           // Total extra presses.
+    pub last_faults: u32,      // Extra presses on the most recently locked piece.
+    pub last_optimal: u32,     // Fewest presses the most recently locked piece needed.
+    searched: Option<Search>   // The last piece's search, for when it comes round again.
+}
+
+
+/// Every spot one piece could be dropped to, and the fewest inputs to each.
+#[derive(Clone)]
+struct Search
+{
+    spawned: (Tetrimino, i32, i32),          // The piece as it appeared, and where.
+    well: Well,
+    sonic: bool,                             // Whether it fell to the ground after every input, at 20G.
+    inputs: HashMap<Vec<(i32, i32)>, u32>    // By the squares the piece covers where it lands.
+}
+
+
+impl Finesse
+{
+    /// Records one locked piece. `spawned` and `locked` are the piece as it appeared and as it locked, with the row
+    /// and column of each. `sonic` is for 20G. Returns how many extra presses it took, or None if it couldn't be judged.
+    pub fn judge(&mut self, spawned: (&Tetrimino, i32, i32), well: &Well, locked: (&Tetrimino, i32, i32), presses: u32, sonic: bool) -> Option<u32>
+    {
+        let (ttmo, row, col) = spawned;
+        let fresh = match &self.searched {
+            Some(search) => search.spawned != (*ttmo, row, col) || search.sonic != sonic || search.well != *well,
+            None => true
+        };
+        if fresh {
+            self.searched = Some(Search { spawned: (*ttmo, row, col), well: well.clone(), sonic, inputs: fewest_inputs(spawned, well, sonic) });
+        }
+
+        let (locked, row, col) = locked;
+        let optimal = *self.searched.as_ref()?.inputs.get(&squares(locked, row, col))?;
+        let faults = presses.saturating_sub(optimal);
+
+        self.judged_pieces += 1;
+        if faults > 0 { self.faulty_pieces += 1; }
+        self.faults += faults;
+        self.last_faults = faults;
+        self.last_optimal = optimal;
+
+        Some(faults)
+    }
+
+    /// Percentage of judged pieces placed with no wasted presses.
+    pub fn accuracy(&self) -> u32
+    {
+        if self.judged_pieces == 0 { return 100; }
+        100 * (self.judged_pieces - self.faulty_pieces) / self.judged_pieces
+    }
+}
+
+
+/// The fewest inputs that take a piece from where it spawned (unrotated, usually at row 2, column 3) to the given
+/// landing spot, by tapping, DASing and turning, and then dropping. None if a drop can't get it there.
+pub fn optimal_presses(spawned: (&Tetrimino, i32, i32), well: &Well, locked: (&Tetrimino, i32, i32), sonic: bool) -> Option<u32>
+{
+    let (locked, row, col) = locked;
+    fewest_inputs(spawned, well, sonic).get(&squares(locked, row, col)).copied()
+}
+
+
+/// Breadth first search over where the piece can get to, an input at a time, noting where it would land from each.
+/// Several rotation states can cover the same squares (S, Z, I), so landing spots go by the squares, not the state.
+fn fewest_inputs(spawned: (&Tetrimino, i32, i32), well: &Well, sonic: bool) -> HashMap<Vec<(i32, i32)>, u32>
+{
+    let settle = |(ttmo, row, col): (Tetrimino, i32, i32)| if sonic { (ttmo, landing_row(&ttmo, well, row, col), col) } else { (ttmo, row, col) };
+    let fits = |ttmo: &Tetrimino, row: i32, col: i32| !would_collide(ttmo, well, &row, &col);
+
+    let mut inputs = HashMap::new();
+    let (ttmo, row, col) = spawned;
+    if !fits(ttmo, row, col) { return inputs; }
+
+    let start = settle((*ttmo, row, col));
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert((start.0.rotation, start.1, start.2));
+    queue.push_back((start, 0));
+
+    while let Some(((ttmo, row, col), count)) = queue.pop_front()
+    {
+        inputs.entry(squares(&ttmo, landing_row(&ttmo, well, row, col), col)).or_insert(count);
+
+        let mut next = Vec::new();
+        for shift in [-1, 1].iter()
+        {
+            // A tap, and DAS: the same move again and again until it won't go (falling as it goes, at 20G).
+            if !fits(&ttmo, row, col + shift) { continue; }
+            next.push((ttmo, row, col + shift));
+
+            let (mut slid_row, mut slid_col) = (row, col);
+            while fits(&ttmo, slid_row, slid_col + shift) {
+                let (_, settled_row, settled_col) = settle((ttmo, slid_row, slid_col + shift));
+                slid_row = settled_row;
+                slid_col = settled_col;
+            }
+            next.push((ttmo, slid_row, slid_col));
+        }
+        for clockwise in [false, true].iter() {
+            if let Some(turned) = rotate_in_well(&ttmo, well, row, col, *clockwise) { next.push(turned); }
+        }
+
+        for (ttmo, row, col) in next.into_iter().map(settle)
+        {
+            if seen.insert((ttmo.rotation, row, col)) { queue.push_back(((ttmo, row, col), count + 1)); }
+        }
+    }
+
+    inputs
+}
+
+
+/// The squares of the well a piece covers, top to bottom and left to right.
+fn squares(ttmo: &Tetrimino, row: i32, col: i32) -> Vec<(i32, i32)>
+{
+    (0..PIECE_SIZE).flat_map(|ttmo_row| (0..PIECE_SIZE).map(move |ttmo_col| (ttmo_row, ttmo_col)))
+        .filter(|(ttmo_row, ttmo_col)| ttmo.shape[*ttmo_row][*ttmo_col] != 0)
+        .map(|(ttmo_row, ttmo_col)| (row + ttmo_row as i32, col + ttmo_col as i32))
+        .collect()
+}
@@ -0,0 +1,476 @@
+/*
+
+Fumen: the board diagrams Tetris players share, as used by harddrop, four.lol and the fumen editor itself.
+Only the current version, v115, is read and written.
+
+A fumen is "v115@" and then numbers in base 64 (A-Z, a-z, 0-9, + and /, the lowest digit first), with a "?"
+after the first 42 digits and every 47 after that, which readers skip. It's one or more pages, each:
+
+    the field, as runs of how much each square changed since the page before     2 digits a run
+      (or, when nothing did, one run and a count of how many pages after it
+      don't change either, and have no field at all)                           1 digit
+    the page's piece, where it is, and flags: whether it locks, whether it       3 digits
+      has a comment, and a few for rising garbage and mirroring
+    the comment, if it has one: its length, then 4 characters at a time         2 digits, then 5 each
+
+The field is 10 wide and 23 tall, with one more row below the floor for garbage waiting to rise. It runs
+left to right from the top, and the squares are 0 empty, 1 to 7 for I L O Z T J S, and 8 for garbage.
+
+A page's piece is drawn on the field but isn't part of it. If the page says it locks, it goes in on the way
+to the next page, and full rows clear, before that page's changes are applied.
+
+Wells go in and out of fumens by their bottom rows: the bottom of the well is the bottom of the field. Wells
+have to be 10 wide, and anything above the field's 23 rows doesn't fit. Fumens come out as the usual well.
+
+*/
+
+use std::convert::TryInto;
+
+use crate::{GameState, TetriminoKind, Well, GARBAGE, PIECE_SIZE};
+
+
+/// What every fumen this reads and writes starts with.
+pub const PREFIX: &str = "v115@";
+
+const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const FIELD_WIDTH: usize = 10;
+const FIELD_TOP: usize = 23;                            // Rows in the field proper.
+const FIELD_BLOCKS: usize = (FIELD_TOP + 1) * FIELD_WIDTH;    // Counting the garbage row.
+
+/// The characters comments can have (once escaped, as JavaScript's escape() does), each worth its place here.
+const COMMENT_CHARS: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Fumen's numbers for the standard seven, in TetriminoKind::STANDARD order: I J L O S T Z.
+const PIECE_CODES: [u8; 7] = [1, 6, 2, 3, 7, 5, 4];
+const GARBAGE_CODE: u8 = 8;
+
+/// The squares of each piece in its spawn orientation, around the square it turns about, x right and y up. By fumen number, from I.
+const SHAPES: [[(i32, i32); 4]; 7] = [
+    [(0, 0), (-1, 0), (1, 0), (2, 0)],     // I
+    [(0, 0), (-1, 0), (1, 0), (1, 1)],     // L
+    [(0, 0), (1, 0), (0, 1), (1, 1)],      // O
+    [(0, 0), (1, 0), (0, 1), (-1, 1)],     // Z
+    [(0, 0), (-1, 0), (1, 0), (0, 1)],     // T
+    [(0, 0), (-1, 0), (1, 0), (-1, 1)],    // J
+    [(0, 0), (-1, 0), (0, 1), (1, 1)]      // S
+];
+
+/// Fumen's numbers for the orientations.
+const REVERSE: u32 = 0;
+const RIGHT: u32 = 1;
+const SPAWN: u32 = 2;
+const LEFT: u32 = 3;
+
+
+/// One page of a fumen.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Page
+{
+    pub well: Well,                  // The board as the page shows it, without its piece.
+    pub piece: Option<Placement>,
+    pub lock: bool,                  // Whether the piece goes in, and full rows clear, on the way to the next page.
+    pub comment: String              // Pages without one of their own keep the one before's.
+}
+
+
+/// A piece on a page: which one, and the well squares it covers, (row, column).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Placement
+{
+    pub kind: TetriminoKind,
+    pub squares: [(i32, i32); 4]
+}
+
+
+/// A fumen's piece: its number, orientation, and the field square it turns about, x from the left and y up from the floor.
+#[derive(Copy, Clone)]
+struct FumenPiece { code: u8, rotation: u32, x: i32, y: i32 }
+
+type Field = [u8; FIELD_BLOCKS];
+
+
+impl FumenPiece
+{
+    /// The field squares it covers, (x, y).
+    fn squares(&self) -> [(i32, i32); 4]
+    {
+        SHAPES[self.code as usize - 1].map(|(x, y)| {
+            let (x, y) = match self.rotation { SPAWN => (x, y), RIGHT => (y, -x), REVERSE => (-x, -y), _ => (-y, x) };
+            (self.x + x, self.y + y)
+        })
+    }
+
+    /// How far the square a fumen gives for a piece is from the one it turns about. (Fumen's own editor
+    /// anchors some of them differently.)
+    fn anchor_offset(code: u8, rotation: u32) -> (i32, i32)
+    {
+        match (code, rotation) {
+            (1, REVERSE) => (1, 0),
+            (1, LEFT)    => (0, -1),
+            (3, SPAWN)   => (0, -1),
+            (3, REVERSE) => (1, 0),
+            (3, LEFT)    => (1, -1),
+            (7, SPAWN)   => (0, -1),
+            (7, RIGHT)   => (-1, 0),
+            (4, SPAWN)   => (0, -1),
+            (4, LEFT)    => (1, 0),
+            _ => (0, 0)
+        }
+    }
+}
+
+
+/// The pages of a fumen. Anything before the "v115@" is skipped, so whole fumen links work too.
+pub fn decode(text: &str) -> Result<Vec<Page>, String>
+{
+    let text = text.trim();
+    let start = text.find("115@").filter(|start| *start > 0 && matches!(&text[start - 1..*start], "v" | "m" | "d"))
+        .ok_or_else(|| "not a v115 fumen (expected v115@ and then the data)".to_string())?;
+
+    let digits: Vec<u32> = text[start + 4..].chars().filter(|c| *c != '?')
+        .map(|c| DIGITS.iter().position(|digit| char::from(*digit) == c).map(|value| value as u32)
+                       .ok_or_else(|| format!("'{}' isn't part of a fumen", c)))
+        .collect::<Result<_, _>>()?;
+    let mut reader = Reader { digits: &digits, at: 0 };
+
+    let mut pages = Vec::new();
+    let mut prev: Field = [0; FIELD_BLOCKS];
+    let mut repeats = 0;
+    let mut comment = String::new();
+
+    while reader.at < digits.len()
+    {
+        // The field: the one before, changed run by run. A page that changes nothing says how many after it don't either.
+        let mut field = prev;
+        if repeats > 0 { repeats -= 1; }
+        else {
+            let mut index = 0;
+            let mut changed = true;
+            while index < FIELD_BLOCKS
+            {
+                let run = reader.poll(2)? as usize;
+                let (diff, count) = (run / FIELD_BLOCKS, run % FIELD_BLOCKS + 1);
+                if diff == 8 && count == FIELD_BLOCKS { changed = false; }
+                if index + count > FIELD_BLOCKS || diff > 16 { return Err("a page's field doesn't add up".to_string()); }
+                for square in field[index..index + count].iter_mut() {
+                    *square = (i32::from(*square) + diff as i32 - 8).clamp(0, i32::from(GARBAGE_CODE)) as u8;
+                }
+                index += count;
+            }
+            if !changed { repeats = reader.poll(1)?; }
+        }
+
+        // The piece and the flags, lowest first.
+        let mut action = reader.poll(3)?;
+        let mut take = |base: u32| { let value = action % base; action /= base; value };
+        let code = take(8) as u8;
+        let rotation = take(4);
+        let position = take(FIELD_BLOCKS as u32) as i32;
+        let rise = take(2) == 1;
+        let mirror = take(2) == 1;
+        let _colorize = take(2);
+        let has_comment = take(2) == 1;
+        let lock = take(2) == 0;
+
+        if has_comment
+        {
+            let length = reader.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut value = reader.poll(5)? as usize;
+                for _ in 0..4 {
+                    escaped.push(COMMENT_CHARS.as_bytes()[value % (COMMENT_CHARS.len() + 1)].into());
+                    value /= COMMENT_CHARS.len() + 1;
+                }
+            }
+            comment = unescape(&escaped[..length.min(escaped.len())]);
+        }
+
+        let piece = match code {
+            0 => None,
+            1..=7 => {
+                let (dx, dy) = FumenPiece::anchor_offset(code, rotation);
+                Some(FumenPiece { code, rotation, x: position % FIELD_WIDTH as i32 + dx, y: FIELD_TOP as i32 - position / FIELD_WIDTH as i32 - 1 + dy })
+            }
+            _ => return Err(format!("piece number {} isn't a piece", code))
+        };
+
+        let well = well_from_field(&field);
+        pages.push(Page { piece: piece.map(|piece| placement(&piece, &well)), well, lock, comment: comment.clone() });
+
+        // What the next page starts from.
+        if lock
+        {
+            if let Some(piece) = piece { put(&mut field, &piece)?; }
+            clear_lines(&mut field);
+            if rise {
+                field.copy_within(FIELD_WIDTH.., 0);
+                for square in field[FIELD_BLOCKS - FIELD_WIDTH..].iter_mut() { *square = 0; }
+            }
+            if mirror {
+                for row in field[..FIELD_BLOCKS - FIELD_WIDTH].chunks_mut(FIELD_WIDTH) { row.reverse(); }
+            }
+        }
+        prev = field;
+    }
+
+    if pages.is_empty() { return Err("the fumen has no pages".to_string()); }
+    Ok(pages)
+}
+
+
+/// A fumen of the given pages. Wells have to be 10 wide and fit in the field, and pieces have to be the standard seven.
+pub fn encode(pages: &[Page]) -> Result<String, String>
+{
+    let mut digits: Vec<u32> = Vec::new();
+    let mut prev: Field = [0; FIELD_BLOCKS];
+    let mut repeat_at: Option<usize> = None;    // Where the count of pages without changes is, while there's a run of them.
+    let mut comment = "";
+
+    for page in pages.iter()
+    {
+        let mut field = field_from_well(&page.well)?;
+
+        // The field, unless it's the same as the one before and there's already a count to add this page to.
+        match repeat_at {
+            Some(at) if field == prev && digits[at] < 63 => digits[at] += 1,
+            _ => {
+                let mut runs: Vec<(u32, u32)> = Vec::new();
+                for (now, before) in field.iter().zip(prev.iter()) {
+                    let diff = u32::from(*now) + 8 - u32::from(*before);
+                    match runs.last_mut() {
+                        Some((last, count)) if *last == diff => *count += 1,
+                        _ => runs.push((diff, 1))
+                    }
+                }
+                for (diff, count) in runs.iter() { push(&mut digits, diff * FIELD_BLOCKS as u32 + count - 1, 2); }
+
+                repeat_at = if field == prev { digits.push(0); Some(digits.len() - 1) } else { None };
+            }
+        }
+
+        let piece = page.piece.as_ref().map(|piece| fumen_piece(piece, page.well.rows())).transpose()?;
+        let has_comment = page.comment != comment;
+
+        let (code, rotation, position) = match piece {
+            Some(piece) => {
+                let (dx, dy) = FumenPiece::anchor_offset(piece.code, piece.rotation);
+                (piece.code, piece.rotation, (FIELD_TOP as i32 - (piece.y - dy) - 1) * FIELD_WIDTH as i32 + piece.x - dx)
+            }
+            None => (0, REVERSE, 0)
+        };
+        if !(0..FIELD_TOP as i32 * FIELD_WIDTH as i32).contains(&position) { return Err("a piece is off the top of the field".to_string()); }
+
+        // Flags first, highest first: not locking, comment, colors (always), mirror, rise.
+        let flags = u32::from(!page.lock) * 16 + u32::from(has_comment) * 8 + 4;
+        push(&mut digits, ((flags * FIELD_BLOCKS as u32 + position as u32) * 4 + rotation) * 8 + u32::from(code), 3);
+
+        if has_comment
+        {
+            let escaped = escape(&page.comment);
+            if escaped.len() >= 64 * 64 { return Err("a comment's too long for a fumen".to_string()); }
+            push(&mut digits, escaped.len() as u32, 2);
+            for chunk in escaped.as_bytes().chunks(4) {
+                let value = chunk.iter().rev().fold(0, |value, c| value * (COMMENT_CHARS.len() as u32 + 1) + COMMENT_CHARS.find(char::from(*c)).unwrap() as u32);
+                push(&mut digits, value, 5);
+            }
+            comment = &page.comment;
+        }
+
+        if page.lock
+        {
+            if let Some(piece) = piece { put(&mut field, &piece)?; }
+            clear_lines(&mut field);
+        }
+        prev = field;
+    }
+
+    // A "?" after the first 42 digits, and then every 47.
+    let mut text = PREFIX.to_string();
+    for (index, digit) in digits.iter().enumerate() {
+        if index >= 42 && (index - 42) % 47 == 0 { text.push('?'); }
+        text.push(DIGITS[*digit as usize].into());
+    }
+    Ok(text)
+}
+
+
+/// A fumen of the game as it is: its well, and the piece falling in it, if there is one and it fits in the field.
+pub fn encode_game(game_state: &GameState) -> Result<String, String>
+{
+    let ttmo = &game_state.curr_ttmo;
+    let mut squares = Vec::new();
+    for row in 0..PIECE_SIZE {
+        for col in 0..PIECE_SIZE {
+            if ttmo.shape[row][col] != 0 { squares.push((game_state.ttmo_row + row as i32, game_state.ttmo_col + col as i32)); }
+        }
+    }
+
+    // Fumens only have tetriminos, so pieces of any other size are left out.
+    let falling = game_state.game_over.is_none() && game_state.spawn_delay == 0;
+    let piece = squares.try_into().ok().map(|squares| Placement { kind: ttmo.kind, squares })
+        .filter(|piece| falling && encode(&[page(&game_state.well, Some(*piece))]).is_ok());
+
+    encode(&[page(&game_state.well, piece)])
+}
+
+
+/// A page with just a well and maybe a piece, and no comment.
+fn page(well: &Well, piece: Option<Placement>) -> Page
+{
+    Page { well: well.clone(), piece, lock: true, comment: String::new() }
+}
+
+
+/// Reads numbers off the digits, a few at a time.
+struct Reader<'a> { digits: &'a [u32], at: usize }
+
+impl Reader<'_>
+{
+    fn poll(&mut self, count: usize) -> Result<u32, String>
+    {
+        let digits = self.digits.get(self.at..self.at + count).ok_or_else(|| "the fumen stops part way through a page".to_string())?;
+        self.at += count;
+        Ok(digits.iter().rev().fold(0, |value, digit| value * 64 + digit))
+    }
+}
+
+
+/// Writes a number as the given count of digits, lowest first.
+fn push(digits: &mut Vec<u32>, mut value: u32, count: usize)
+{
+    for _ in 0..count {
+        digits.push(value % 64);
+        value /= 64;
+    }
+}
+
+
+fn field_from_well(well: &Well) -> Result<Field, String>
+{
+    if well.cols() != FIELD_WIDTH { return Err(format!("fumens are {} wide, and the well's {}", FIELD_WIDTH, well.cols())); }
+
+    let mut field = [0; FIELD_BLOCKS];
+    for (row, squares) in well.iter().rev().enumerate()
+    {
+        if squares.iter().all(|square| *square == 0) { continue; }
+        if row >= FIELD_TOP { return Err(format!("only the bottom {} rows of a well fit in a fumen", FIELD_TOP)); }
+
+        for (col, square) in squares.iter().enumerate() {
+            field[(FIELD_TOP - 1 - row) * FIELD_WIDTH + col] = match TetriminoKind::from_cell(*square) {
+                _ if *square == 0 => 0,
+                Some(kind) if kind.is_standard() => PIECE_CODES[TetriminoKind::STANDARD.iter().position(|standard| *standard == kind).unwrap()],
+                _ => GARBAGE_CODE
+            };
+        }
+    }
+    Ok(field)
+}
+
+
+/// The usual well, with the field in its bottom rows. (The garbage row under the floor isn't in it.)
+fn well_from_field(field: &Field) -> Well
+{
+    let mut well = Well::default();
+    let rows = well.rows();
+    for y in 0..FIELD_TOP.min(rows) {
+        for x in 0..FIELD_WIDTH {
+            well.set(rows - 1 - y, x, match field[(FIELD_TOP - 1 - y) * FIELD_WIDTH + x] {
+                0 => 0,
+                code @ 1..=7 => TetriminoKind::STANDARD[PIECE_CODES.iter().position(|piece| *piece == code).unwrap()].cell(),
+                _ => GARBAGE
+            });
+        }
+    }
+    well
+}
+
+
+/// Where a fumen piece is in a well, laid out as well_from_field() does.
+fn placement(piece: &FumenPiece, well: &Well) -> Placement
+{
+    let kind = TetriminoKind::STANDARD[PIECE_CODES.iter().position(|code| *code == piece.code).unwrap()];
+    Placement { kind, squares: piece.squares().map(|(x, y)| (well.rows() as i32 - 1 - y, x)) }
+}
+
+
+/// The fumen piece covering the same squares as a placement in a well with the given rows. Any orientation that
+/// does will do, since fumen only draws the squares.
+fn fumen_piece(placement: &Placement, rows: usize) -> Result<FumenPiece, String>
+{
+    let code = TetriminoKind::STANDARD.iter().position(|kind| *kind == placement.kind).map(|index| PIECE_CODES[index])
+        .ok_or_else(|| "fumens only have the standard seven pieces".to_string())?;
+    let mut squares = placement.squares.map(|(row, col)| (col, rows as i32 - 1 - row));
+    squares.sort_unstable();
+
+    for rotation in [SPAWN, RIGHT, REVERSE, LEFT] {
+        for (x, y) in squares.iter() {
+            let piece = FumenPiece { code, rotation, x: *x, y: *y };
+            let mut covered = piece.squares();
+            covered.sort_unstable();
+            if covered == squares { return Ok(piece); }
+        }
+    }
+    Err("a piece's squares aren't its shape".to_string())
+}
+
+
+/// Puts a piece's squares in a field.
+fn put(field: &mut Field, piece: &FumenPiece) -> Result<(), String>
+{
+    for (x, y) in piece.squares() {
+        if !(0..FIELD_WIDTH as i32).contains(&x) || !(0..FIELD_TOP as i32).contains(&y) { return Err("a piece is outside the field".to_string()); }
+        field[(FIELD_TOP - 1 - y as usize) * FIELD_WIDTH + x as usize] = piece.code;
+    }
+    Ok(())
+}
+
+
+/// Takes out the field's full rows, moving the rest down. The garbage row under the floor is left alone.
+fn clear_lines(field: &mut Field)
+{
+    let mut rows: Vec<[u8; FIELD_WIDTH]> = field[..FIELD_TOP * FIELD_WIDTH].chunks(FIELD_WIDTH)
+        .filter(|row| row.contains(&0))
+        .map(|row| row.try_into().unwrap())
+        .collect();
+    while rows.len() < FIELD_TOP { rows.insert(0, [0; FIELD_WIDTH]); }
+
+    for (index, row) in rows.iter().enumerate() { field[index * FIELD_WIDTH..(index + 1) * FIELD_WIDTH].copy_from_slice(row); }
+}
+
+
+/// As JavaScript's escape(), which fumen comments go through: letters, digits and @*_+-./ as they are, other
+/// characters as %XX, or %uXXXX past 255.
+fn escape(text: &str) -> String
+{
+    let mut escaped = String::new();
+    for c in text.encode_utf16() {
+        match char::from_u32(u32::from(c)) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => escaped.push(c),
+            _ if c < 256 => escaped.push_str(&format!("%{:02X}", c)),
+            _ => escaped.push_str(&format!("%u{:04X}", c))
+        }
+    }
+    escaped
+}
+
+
+/// Undoes escape(). Anything that isn't a proper escape is left as it is.
+fn unescape(text: &str) -> String
+{
+    let mut units: Vec<u16> = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    let hex = |from: usize, count: usize| chars.get(from..from + count).map(|digits| digits.iter().collect::<String>())
+                                               .and_then(|digits| u16::from_str_radix(&digits, 16).ok());
+    let mut index = 0;
+    while index < chars.len()
+    {
+        match (chars[index], chars.get(index + 1)) {
+            ('%', Some('u')) if hex(index + 2, 4).is_some() => { units.push(hex(index + 2, 4).unwrap()); index += 6; }
+            ('%', _) if hex(index + 1, 2).is_some() => { units.push(hex(index + 1, 2).unwrap()); index += 3; }
+            (c, _) => { units.push(c as u16); index += 1; }
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
@@ -0,0 +1,144 @@
+/*
+
+Garbage: gray rows with a hole in them, pushed up into the well from underneath.
+
+Cheese mode starts the well full of it. There are only ever CHEESE_VISIBLE_ROWS of garbage rows showing at
+once. Each time some get cleared, more come up from below until the mode's whole count has been used, and
+the race is over once the last of them is gone. "Messiness" is the chance, in percent, that a row's hole
+isn't straight under the hole of the row above it: 0 makes a single well to dig down, 100 a fresh hole
+every row. Cheese can also raise an extra garbage row every so many seconds, which has to be dug out too.
+
+*/
+
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::{would_collide, GameState, Well, GARBAGE};
+use crate::modes::{GameOver, Mode};
+
+
+/// The most garbage rows Cheese has in the well at any time. (Fewer in wells less than twice that tall: see cheese_rows().)
+pub const CHEESE_VISIBLE_ROWS: u32 = 10;
+
+
+/// Cheese mode's own bookkeeping, kept in GameState::cheese.
+#[derive(Copy, Clone, Default)]
+pub struct Cheese
+{
+    pub pending: u32,           // Garbage rows still waiting below the well.
+    pub hole: Option<usize>,    // Column of the hole in the last row raised.
+    pub rise_counter: u32       // Updates since garbage last rose on its own.
+}
+
+
+/// A row of garbage for a well the given number of columns wide, full except for the given column.
+pub fn garbage_row(cols: usize, hole: usize) -> Vec<u8>
+{
+    let mut row = vec![GARBAGE; cols];
+    row[hole] = 0;
+    row
+}
+
+
+/// Picks the hole for the next garbage row, in a well `cols` wide. Stays under the previous hole unless messiness
+/// (a percentage) says otherwise.
+pub fn next_hole(rng: &mut StdRng, cols: usize, previous: Option<usize>, messiness: u32) -> usize
+{
+    match previous
+    {
+        Some(hole) if rng.gen_range(0, 100) >= messiness => hole,
+        Some(hole) => (hole + rng.gen_range(1, cols)) % cols,    // Anywhere but where it was.
+        None => rng.gen_range(0, cols)
+    }
+}
+
+
+/// How many rows of the well have garbage in them.
+pub fn garbage_rows(well: &Well) -> u32
+{
+    well.iter().filter(|row| row.contains(&GARBAGE)).count() as u32
+}
+
+
+/// Pushes the given rows up into the bottom of the well, in order, so the last one ends up at the very bottom.
+/// The falling piece gets nudged up out of the way if need be. Tops out if anything gets pushed out the top.
+pub fn raise(game_state: &mut GameState, rows: &[Vec<u8>])
+{
+    for row in rows
+    {
+        let pushed_out = game_state.well.push_bottom(row);
+        if pushed_out.iter().any(|square| *square != 0) { game_state.game_over = Some(GameOver::TopOut); }
+    }
+
+    while would_collide(&game_state.curr_ttmo, &game_state.well, &game_state.ttmo_row, &game_state.ttmo_col)
+    {
+        if game_state.ttmo_row <= 0 {
+            game_state.game_over = Some(GameOver::TopOut);
+            return;
+        }
+        game_state.ttmo_row -= 1;
+    }
+}
+
+
+/// The well a Cheese game starts with.
+pub(crate) fn start_cheese(game_state: &mut GameState)
+{
+    if let Mode::Cheese { rows, .. } = game_state.mode {
+        game_state.cheese = Cheese { pending: rows, hole: None, rise_counter: 0 };
+        refill_cheese(game_state);
+    }
+}
+
+
+/// How many garbage rows Cheese keeps showing in a well: CHEESE_VISIBLE_ROWS, or half the well if that's less.
+pub fn cheese_rows(well: &Well) -> u32
+{
+    CHEESE_VISIBLE_ROWS.min(well.visible_rows() as u32 / 2)
+}
+
+
+/// Tops the well back up to cheese_rows() of garbage, if there's any left to come.
+pub(crate) fn refill_cheese(game_state: &mut GameState)
+{
+    let messiness = match game_state.mode { Mode::Cheese { messiness, .. } => messiness, _ => return };
+
+    let wanted = cheese_rows(&game_state.well).saturating_sub(garbage_rows(&game_state.well)).min(game_state.cheese.pending);
+    game_state.cheese.pending -= wanted;
+
+    let rows = new_rows(game_state, wanted, messiness);
+    raise(game_state, &rows);
+}
+
+
+/// Counts toward the next time garbage rises, and raises one extra row when it's time.
+pub(crate) fn rise_cheese(game_state: &mut GameState)
+{
+    let (messiness, rise) = match game_state.mode { Mode::Cheese { messiness, rise, .. } => (messiness, rise), _ => return };
+    if rise == 0 { return; }
+
+    game_state.cheese.rise_counter += 1;
+    if game_state.cheese.rise_counter < rise * crate::UPDATES_PER_SECOND { return; }
+    game_state.cheese.rise_counter = 0;
+
+    let rows = new_rows(game_state, 1, messiness);
+    raise(game_state, &rows);
+}
+
+
+/// How much garbage is left to dig: what's in the well plus what's still to come up.
+pub fn cheese_left(game_state: &GameState) -> u32
+{
+    garbage_rows(&game_state.well) + game_state.cheese.pending
+}
+
+
+fn new_rows(game_state: &mut GameState, count: u32, messiness: u32) -> Vec<Vec<u8>>
+{
+    let cols = game_state.well.cols();
+    (0..count).map(|_| {
+        let hole = next_hole(&mut game_state.rng, cols, game_state.cheese.hole, messiness);
+        game_state.cheese.hole = Some(hole);
+        garbage_row(cols, hole)
+    }).collect()
+}
@@ -0,0 +1,138 @@
+/*
+
+Input sources: things other than a person at a keyboard that can play a game.
+
+Every source just presses the same Actions the keyboard handlers do (see GameState::inputs), so a game
+played by a bot or a replay goes through exactly the same rules as one played by hand.
+
+*/
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::{Action, GameState};
+
+
+/// Anything that can press keys for a player.
+pub trait InputSource
+{
+    /// Returns the keys to press this update, in the order to press them.
+    /// Called once per update, right before game_update().
+    fn next_keys(&mut self, game_state: &GameState) -> Vec<Action>;
+
+    /// True when the source has nothing more to say, e.g. a replay that has run out.
+    fn finished(&self, _game_state: &GameState) -> bool { false }
+
+    /// How many of the keys next_keys() last returned, at the end, were held keys repeating rather than pressed
+    /// again. Only finesse cares (see GameState::repeats). Bots press everything afresh.
+    fn repeats(&self) -> usize { 0 }
+}
+
+
+/// Mashes keys at random. It's a terrible player, but it's good at poking at corners of the rules.
+pub struct RandomBot
+{
+    rng: StdRng
+}
+
+
+impl RandomBot
+{
+    pub fn new(seed: u64) -> Self
+    {
+        RandomBot { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+
+impl InputSource for RandomBot
+{
+    fn next_keys(&mut self, _game_state: &GameState) -> Vec<Action>
+    {
+        let mut keys = Vec::new();
+
+        // Press something on about one update in four. Hard drops are rarer, or the well fills up in seconds.
+        if self.rng.gen_range(0, 4) == 0 {
+            let key = Action::ALL[self.rng.gen_range(0, Action::ALL.len())];
+            if key != Action::HardDrop || self.rng.gen_range(0, 4) == 0 { keys.push(key); }
+        }
+
+        keys
+    }
+}
+
+
+/// Holding a key down: DAS and ARR for moves, and a soft drop that keeps dropping.
+/// Frontends tell it which actions are pressed and released, and it presses them again in GameState::inputs as they're held,
+/// counting those in GameState::repeats so finesse can tell them from real presses.
+/// Presses it hears about while the action is already held, like the OS's own key repeat, are ignored.
+/// Held time counts from the update the press goes in, so with a DAS of 12 the first repeat is 12 updates after the press.
+pub struct AutoRepeat
+{
+    pub das: u32,                // Updates a move is held before it starts repeating. 0 counts as 1: the update after the press.
+    pub arr: u32,                // Updates between repeats after that. 0 counts as 1: a move an update is as fast as the game goes.
+    held: [Option<u32>; Action::ALL.len()]    // How many updates each action has been held since the one its press went in, if it is.
+}
+
+
+impl AutoRepeat
+{
+    pub fn new(das: u32, arr: u32) -> Self
+    {
+        AutoRepeat { das, arr, held: [None; Action::ALL.len()] }
+    }
+
+    /// An action's key went down. Presses it once, straight away.
+    pub fn press(&mut self, inputs: &mut Vec<Action>, action: Action)
+    {
+        if self.held[action.index()].is_some() { return; }
+
+        self.held[action.index()] = Some(0);
+        inputs.push(action);
+    }
+
+    /// An action's key came back up.
+    pub fn release(&mut self, action: Action)
+    {
+        self.held[action.index()] = None;
+    }
+
+    /// Lets go of everything, say when a new game starts.
+    pub fn clear(&mut self)
+    {
+        self.held = [None; Action::ALL.len()];
+    }
+
+    /// Presses whatever's due to repeat this update, after the presses already waiting.
+    /// Call it once per update, before game_update(). Nothing repeats in the update its press goes in, since the
+    /// press is there already. After that every repeat that's due goes in, whatever else was pressed alongside it.
+    pub fn update(&mut self, game_state: &mut GameState)
+    {
+        game_state.repeats += self.update_keys(&mut game_state.inputs);
+    }
+
+    /// The same, for keys that aren't going straight into a game here, like a network match's on their way to the
+    /// other side. Returns how many repeats it pressed.
+    pub fn update_keys(&mut self, inputs: &mut Vec<Action>) -> usize
+    {
+        let das = self.das.max(1);
+        let mut repeats = 0;
+        for (action, held) in Action::ALL.iter().zip(self.held.iter_mut())
+        {
+            let held = match held.as_mut() { Some(held) => held, None => continue };
+            let since = *held;
+            *held += 1;
+
+            let repeat = match action {
+                Action::MoveLeft | Action::MoveRight => since >= das && (since - das).is_multiple_of(self.arr.max(1)),
+                Action::SoftDrop => since > 0,    // Soft drop drops every update.
+                _ => false                        // Rotations, hard drops and holds only go once per press.
+            };
+            if repeat {
+                inputs.push(*action);
+                repeats += 1;
+            }
+        }
+        repeats
+    }
+}
@@ -0,0 +1,1309 @@
+/*
+
+Rustris core rules: tetriminos, the well, and the per-update game logic.
+
+These don't know anything about windows, keyboards or speakers, so every frontend
+(the Piston window in main.rs, the terminal one in bin/rustris-tui.rs) plays by exactly the same rules.
+
+(C) 2021 Ben Cantrick. MIT License, see LICENSE.txt.
+
+*/
+
+extern crate rand;
+
+pub mod ai;
+pub mod battle;
+pub mod editor;
+pub mod finesse;
+pub mod fumen;
+pub mod garbage;
+pub mod input;
+pub mod lobby;
+pub mod master;
+pub mod modes;
+pub mod net;
+pub mod pieces;
+pub mod profiles;
+pub mod puzzles;
+pub mod records;
+pub mod replay;
+pub mod settings;
+pub mod solver;
+pub mod timestep;
+pub mod versus;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use finesse::Finesse;
+use garbage::Cheese;
+use master::{Master, MASTER_MAX_LEVEL};
+use modes::{nes_frames_per_row, nes_level, GameOver, Mode, NES_LINE_SCORES};
+use pieces::PieceSet;
+use puzzles::Puzzle;
+
+
+/// How many times per second game_update() is called, in every mode. (NES Marathon's is a hair faster. See
+/// Mode::updates_per_second().) All timings in the rules are counted in updates.
+pub const UPDATES_PER_SECOND: u32 = 60;
+
+/// Updates a piece hangs in each row before falling, in Endless, Sprint and the like: 0.7 seconds a row.
+pub const GRAVITY: u32 = 42;
+
+/// The speed pieces usually fall at: 100 percent of their mode's gravity. (See GameState::speed.)
+pub const NORMAL_SPEED: u32 = 100;
+
+/// Where new pieces appear: the top left of their 4x4 box goes here, near the top and middle of the well.
+pub const SPAWN_ROW: i32 = 2;
+pub const SPAWN_COL: i32 = 3;
+
+/// Which piece a piece is. The seven standard tetriminos are I to Z, whatever set they're in, so they keep their
+/// palette colors, glyphs and T-spins everywhere. Pieces of other shapes, from piece sets (see pieces.rs), are
+/// numbered from FIRST_CUSTOM_KIND in the order their set lists them.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
+pub struct TetriminoKind(u8);
+
+/// The first kind number for pieces that aren't one of the standard seven. (8 is GARBAGE.)
+pub const FIRST_CUSTOM_KIND: u8 = 9;
+
+
+impl TetriminoKind
+{
+    pub const I: TetriminoKind = TetriminoKind(1);
+    pub const J: TetriminoKind = TetriminoKind(2);
+    pub const L: TetriminoKind = TetriminoKind(3);
+    pub const O: TetriminoKind = TetriminoKind(4);
+    pub const S: TetriminoKind = TetriminoKind(5);
+    pub const T: TetriminoKind = TetriminoKind(6);
+    pub const Z: TetriminoKind = TetriminoKind(7);
+
+    /// The standard seven, in the order the 7-bag starts from.
+    pub const STANDARD: [TetriminoKind; 7] = [ TetriminoKind::I, TetriminoKind::J, TetriminoKind::L, TetriminoKind::O,
+                                               TetriminoKind::S, TetriminoKind::T, TetriminoKind::Z ];
+
+    /// The kind of the given piece (counting from 0) in a set, among those that aren't the standard seven.
+    /// None past the most there can be.
+    pub fn custom(index: usize) -> Option<Self>
+    {
+        (index + FIRST_CUSTOM_KIND as usize <= u8::MAX as usize).then(|| TetriminoKind(index as u8 + FIRST_CUSTOM_KIND))
+    }
+
+    /// One of the standard seven tetriminos?
+    pub fn is_standard(self) -> bool
+    {
+        (1..=7).contains(&self.0)
+    }
+
+    /// The value a square of this kind is stored as once it's frozen into the well. (0 means empty.)
+    pub const fn cell(self) -> u8
+    {
+        self.0
+    }
+
+    /// The inverse of cell(). Returns None for empty squares, and for garbage.
+    pub fn from_cell(cell: u8) -> Option<Self>
+    {
+        match cell
+        {
+            0 | GARBAGE => None,
+            _ => Some(TetriminoKind(cell))
+        }
+    }
+}
+
+
+/// What a key press does to the falling piece. Presses wait in GameState::inputs for the next update,
+/// which applies them one after another, in the order they happened.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Action { MoveLeft, MoveRight, RotateCCW, RotateCW, SoftDrop, HardDrop, Hold }
+
+
+impl Action
+{
+    /// Every action, in the order profiles list their keys.
+    pub const ALL: [Action; 7] = [Action::MoveLeft, Action::MoveRight, Action::RotateCCW, Action::RotateCW, Action::SoftDrop, Action::HardDrop,
+                                  Action::Hold];
+
+    /// Where the action is in ALL.
+    pub fn index(self) -> usize
+    {
+        self as usize
+    }
+}
+
+
+impl fmt::Display for Action
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.write_str(match self {
+            Action::MoveLeft  => "left",
+            Action::MoveRight => "right",
+            Action::RotateCCW => "ccw",
+            Action::RotateCW  => "cw",
+            Action::SoftDrop  => "soft",
+            Action::HardDrop  => "hard",
+            Action::Hold      => "hold"
+        })
+    }
+}
+
+
+impl FromStr for Action
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        Action::ALL.iter().copied().find(|action| action.to_string() == s)
+            .ok_or_else(|| format!("unknown action '{}' (expected left, right, ccw, cw, soft, hard or hold)", s))
+    }
+}
+
+
+/// "left,left,cw" for two moves left and then a rotation, or "-" for no presses at all. Replay files and the
+/// network protocols write presses like this.
+pub fn format_actions(actions: &[Action]) -> String
+{
+    if actions.is_empty() { return "-".to_string(); }
+    actions.iter().map(|action| action.to_string()).collect::<Vec<_>>().join(",")
+}
+
+
+pub fn parse_actions(text: &str) -> Option<Vec<Action>>
+{
+    if text == "-" { return Some(Vec::new()); }
+    text.split(',').map(|action| action.parse().ok()).collect()
+}
+
+
+/// How big a piece's box is: pieces can be any shape that fits in 5x5. (The standard seven only use the top-left 4x4.)
+pub const PIECE_SIZE: usize = 5;
+
+/// The most kicks a piece can have. See Tetrimino::kicks.
+pub const MAX_KICKS: usize = 8;
+
+/// A piece's squares, in its box: 1 for a square, 0 for none.
+pub type Shape = [[u8; PIECE_SIZE]; PIECE_SIZE];
+
+/// The same squares as bitmasks, one per row of the box, bit 0 for its leftmost column. See Well::row_mask().
+pub type ShapeMask = [u8; PIECE_SIZE];
+
+
+/// A piece, in the rotation state it's in. It carries all its rotation states and kicks with it, so it can be
+/// moved and turned without looking anything up.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Tetrimino {
+    pub kind: TetriminoKind,
+    pub color: [f32; 4],        // R, G, B, A
+    pub shape: Shape,           // Always rotations[rotation].
+    pub rotation: usize,        // 0 as it spawns, then counting clockwise turns.
+    pub rotations: [Shape; 4],  // Its rotation states, clockwise from the one it spawns in. Only the first rotation_count are used.
+    pub rotation_count: usize,  // 1 (it doesn't turn), 2 or 4.
+    pub masks: [ShapeMask; 4],  // rotations[] as bitmasks, worked out once so collisions don't go square by square.
+    pub kicks: [(i32, i32); MAX_KICKS],    // (columns right, rows down) to try in turn when a clockwise turn doesn't fit
+    pub kick_count: usize                  // where it is. Counterclockwise turns try them mirrored.
+}
+
+
+impl Tetrimino
+{
+    /// One of the standard seven, as it spawns. Panics for any other kind: those come from their PieceSet.
+    pub fn new(kind: TetriminoKind) -> Self
+    {
+        let (color, shape) = match kind
+        {
+            TetriminoKind::I => ([ 1.0, 1.0, 1.0, 1.0 ],    // white
+                                 [[0, 0, 1, 0],
+                                  [0, 0, 1, 0],
+                                  [0, 0, 1, 0],
+                                  [0, 0, 1, 0]]),
+
+            TetriminoKind::J => ([ 0.0, 0.0, 1.0, 1.0 ],    // blue
+                                 [[ 1, 0, 0, 0 ],
+                                  [ 1, 1, 1, 0 ],
+                                  [ 0, 0, 0, 0 ],
+                                  [ 0, 0, 0, 0 ]]),
+
+            TetriminoKind::L => ([ 0.0, 1.0, 1.0, 1.0 ],    // cyan
+                                 [[ 0, 0, 1, 0 ],
+                                  [ 1, 1, 1, 0 ],
+                                  [ 0, 0, 0, 0 ],
+                                  [ 0, 0, 0, 0 ]]),
+
+            TetriminoKind::S => ([ 1.0, 0.0, 1.0, 1.0 ],    // magenta
+                                 [[ 0, 1, 1, 0 ],
+                                  [ 1, 1, 0, 0 ],
+                                  [ 0, 0, 0, 0 ],
+                                  [ 0, 0, 0, 0 ]]),
+
+            TetriminoKind::Z => ([ 1.0, 0.0, 0.0, 1.0 ],    // red
+                                 [[ 1, 1, 0, 0 ],
+                                  [ 0, 1, 1, 0 ],
+                                  [ 0, 0, 0, 0 ],
+                                  [ 0, 0, 0, 0 ]]),
+
+            TetriminoKind::O => ([ 0.0, 1.0, 0.0, 1.0 ],    // green
+                                 [[ 0, 0, 0, 0 ],
+                                  [ 0, 0, 0, 0 ],
+                                  [ 0, 1, 1, 0 ],
+                                  [ 0, 1, 1, 0 ]]),
+
+            TetriminoKind::T => ([ 1.0, 1.0, 0.0, 1.0 ],    // yellow
+                                 [[ 0, 1, 0, 0 ],
+                                  [ 1, 1, 1, 0 ],
+                                  [ 0, 0, 0, 0 ],
+                                  [ 0, 0, 0, 0 ]]),
+
+            _ => panic!("{:?} isn't one of the standard seven tetriminos", kind)
+        };
+
+        let mut spawn_shape: Shape = [[0; PIECE_SIZE]; PIECE_SIZE];
+        for (row, squares) in shape.iter().enumerate() { spawn_shape[row][..4].copy_from_slice(squares); }
+
+        // Only the I needs all four rows of its box rotated. The others turn within the top-left 3x3, except O,
+        // which doesn't turn at all. (No kicks: a turn that doesn't fit where the piece is doesn't happen.)
+        let states = match kind {
+            TetriminoKind::I => turns(&spawn_shape, 4, 4),
+            TetriminoKind::O => turns(&spawn_shape, 1, 1),
+            _ => turns(&spawn_shape, 3, 4)
+        };
+        Tetrimino::with_rotations(kind, color, &states, &[])
+    }
+
+    /// A piece with the given rotation states (clockwise from the one it spawns in: 1, 2 or 4 of them) and kicks.
+    pub fn with_rotations(kind: TetriminoKind, color: [f32; 4], states: &[Shape], kicks: &[(i32, i32)]) -> Self
+    {
+        assert!(matches!(states.len(), 1 | 2 | 4) && kicks.len() <= MAX_KICKS);
+
+        let mut ttmo = Tetrimino { kind, color, shape: states[0], rotation: 0, rotations: [states[0]; 4], rotation_count: states.len(),
+                                   masks: [[0; PIECE_SIZE]; 4], kicks: [(0, 0); MAX_KICKS], kick_count: kicks.len() };
+        ttmo.rotations[..states.len()].copy_from_slice(states);
+        for (masks, shape) in ttmo.masks.iter_mut().zip(ttmo.rotations.iter()) {
+            for (mask, squares) in masks.iter_mut().zip(shape.iter()) {
+                *mask = squares.iter().rev().fold(0, |mask, square| mask << 1 | (*square != 0) as u8);
+            }
+        }
+        ttmo.kicks[..kicks.len()].copy_from_slice(kicks);
+        ttmo
+    }
+
+    /// The rotation state it's in, as bitmasks.
+    pub fn mask(&self) -> &ShapeMask
+    {
+        &self.masks[self.rotation]
+    }
+
+    /// The same piece, turned back to how it spawns.
+    pub fn spawned(&self) -> Self
+    {
+        Tetrimino { shape: self.rotations[0], rotation: 0, ..*self }
+    }
+
+    /// The same piece turned to the rotation state with the given shape, if it has one. (For pieces that arrive
+    /// as a kind and a shape, like the server's.)
+    pub fn turned_to(&self, shape: &Shape) -> Option<Self>
+    {
+        let rotation = self.rotations[..self.rotation_count].iter().position(|state| state == shape)?;
+        Some(Tetrimino { shape: *shape, rotation, ..*self })
+    }
+
+    /// Whether it has a square in its box's last row or column, so it needs all of its 5x5 box rather than 4x4.
+    pub fn is_big(&self) -> bool
+    {
+        self.shape.iter().enumerate().any(|(row, squares)| squares[PIECE_SIZE - 1] != 0 || (row == PIECE_SIZE - 1 && squares.contains(&1)))
+    }
+}
+
+
+/// The rotation states a shape goes through turning clockwise within the top-left size x size of its box, starting
+/// with the shape itself: the first `count` of them (1, 2 or 4).
+pub fn turns(shape: &Shape, size: usize, count: usize) -> Vec<Shape>
+{
+    let mut states = vec![*shape];
+    while states.len() < count
+    {
+        // First row becomes last column, and so on.
+        let source = states[states.len() - 1];
+        let mut rotated: Shape = [[0; PIECE_SIZE]; PIECE_SIZE];
+        for row in 0..size {
+            for col in 0..size {
+                rotated[col][(size - 1) - row] = source[row][col];    // size is 1 based, array index is 0 based.
+            }
+        }
+        states.push(rotated);
+    }
+    states
+}
+
+// Several of the classic colors above (red Z, green O, magenta S, cyan L) are hard to tell apart
+// with the common color vision deficiencies. The alternate palettes below are built from the
+// Okabe-Ito set (https://jfly.uni-koeln.de/color/) and tweaked per deficiency so that neighbouring
+// kinds differ in lightness as well as hue. The classic palette is just what Tetrimino::new() says.
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum Palette { Classic, Deuteranopia, Protanopia, Tritanopia }
+
+
+impl Palette
+{
+    /// Returns the color used to draw squares of the given kind, for the standard seven. (Other pieces' colors come
+    /// from their set. See PieceSet::color().)
+    pub fn color(self, kind: TetriminoKind) -> [f32; 4]
+    {
+        if !kind.is_standard() { return GARBAGE_COLOR; }
+
+        match self
+        {
+            Palette::Classic => Tetrimino::new(kind).color,
+
+            // Red-green (green weak). Avoid pairing reds with greens; lean on blue vs orange.
+            Palette::Deuteranopia => match kind
+            {
+                TetriminoKind::I => [ 0.34, 0.71, 0.91, 1.0 ],    // sky blue
+                TetriminoKind::J => [ 0.00, 0.45, 0.70, 1.0 ],    // blue
+                TetriminoKind::L => [ 0.90, 0.62, 0.00, 1.0 ],    // orange
+                TetriminoKind::O => [ 0.94, 0.89, 0.26, 1.0 ],    // yellow
+                TetriminoKind::S => [ 0.80, 0.47, 0.65, 1.0 ],    // reddish purple
+                TetriminoKind::T => [ 1.00, 1.00, 1.00, 1.0 ],    // white
+                TetriminoKind::Z => [ 0.84, 0.37, 0.00, 1.0 ],    // vermillion
+                _ => GARBAGE_COLOR
+            },
+
+            // Red-green (red weak). Reds look dark to protanopes, so the "red" kinds are lightened.
+            Palette::Protanopia => match kind
+            {
+                TetriminoKind::I => [ 0.34, 0.71, 0.91, 1.0 ],    // sky blue
+                TetriminoKind::J => [ 0.00, 0.45, 0.70, 1.0 ],    // blue
+                TetriminoKind::L => [ 0.90, 0.62, 0.00, 1.0 ],    // orange
+                TetriminoKind::O => [ 0.94, 0.89, 0.26, 1.0 ],    // yellow
+                TetriminoKind::S => [ 0.95, 0.72, 0.88, 1.0 ],    // pink (grey would vanish into the background)
+                TetriminoKind::T => [ 1.00, 1.00, 1.00, 1.0 ],    // white
+                TetriminoKind::Z => [ 0.95, 0.55, 0.35, 1.0 ],    // light vermillion
+                _ => GARBAGE_COLOR
+            },
+
+            // Blue-yellow. Keep reds and teals apart, avoid blue next to green and yellow next to white.
+            Palette::Tritanopia => match kind
+            {
+                TetriminoKind::I => [ 0.00, 0.62, 0.45, 1.0 ],    // bluish green
+                TetriminoKind::J => [ 0.10, 0.10, 0.10, 1.0 ],    // near black
+                TetriminoKind::L => [ 0.84, 0.37, 0.00, 1.0 ],    // vermillion
+                TetriminoKind::O => [ 1.00, 1.00, 1.00, 1.0 ],    // white
+                TetriminoKind::S => [ 0.80, 0.47, 0.65, 1.0 ],    // reddish purple
+                TetriminoKind::T => [ 0.55, 0.85, 0.85, 1.0 ],    // pale teal
+                TetriminoKind::Z => [ 0.75, 0.00, 0.10, 1.0 ],    // dark red
+                _ => GARBAGE_COLOR
+            }
+        }
+    }
+
+    /// Cycles through the palettes, wrapping around at the end.
+    pub fn next(self) -> Self
+    {
+        match self
+        {
+            Palette::Classic      => Palette::Deuteranopia,
+            Palette::Deuteranopia => Palette::Protanopia,
+            Palette::Protanopia   => Palette::Tritanopia,
+            Palette::Tritanopia   => Palette::Classic
+        }
+    }
+}
+
+
+/// Palettes are written as "classic", "deuteranopia", "protanopia" or "tritanopia" in the settings file.
+impl fmt::Display for Palette
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.write_str(match self {
+            Palette::Classic      => "classic",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Protanopia   => "protanopia",
+            Palette::Tritanopia   => "tritanopia"
+        })
+    }
+}
+
+
+impl FromStr for Palette
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "classic"      => Ok(Palette::Classic),
+            "deuteranopia" => Ok(Palette::Deuteranopia),
+            "protanopia"   => Ok(Palette::Protanopia),
+            "tritanopia"   => Ok(Palette::Tritanopia),
+            _ => Err(format!("unknown palette '{}' (expected classic, deuteranopia, protanopia or tritanopia)", s))
+        }
+    }
+}
+
+
+/// How blocks get drawn. Changed with the C (colors) and G (glyphs) keys, never by the game itself.
+pub struct Visuals
+{
+    pub palette: Palette,
+    pub patterns: bool    // Draw a per-kind glyph inside each block, so kinds can be told apart without color.
+}
+
+
+// A Tetris playfield is known as a "Well".
+// The usual one is composed of 24 rows, each of which is 10 columns wide.
+// Usually only the bottom 20 rows are fully visible. The 4 above them are the hidden buffer, where pieces appear.
+// If possible, a bit of row 21 should be shown also.
+// (https://tetris.fandom.com/wiki/Tetris_Guideline, and
+// https://en.wikipedia.org/wiki/Tetris)
+//
+// Other sizes can be played too: 4-wide wells, 20-wide "big" ones, tall survival wells. See Well::new().
+// Frontends scale the squares so the whole visible well fits the window, however big it is.
+
+/// Width, visible height and hidden rows of the usual well.
+pub const WELL_COLS: usize = 10;
+pub const WELL_VISIBLE_ROWS: usize = 20;
+pub const WELL_HIDDEN_ROWS: usize = 4;
+
+/// How big or small a well can be.
+pub const MIN_WELL_COLS: usize = 4;
+pub const MAX_WELL_COLS: usize = 40;
+pub const MIN_WELL_ROWS: usize = 4;       // Visible rows.
+pub const MAX_WELL_ROWS: usize = 60;
+pub const MIN_HIDDEN_ROWS: usize = 2;     // Enough for a new piece to appear in.
+pub const MAX_HIDDEN_ROWS: usize = 20;
+
+
+/// The playfield. Each square is 0 for empty, TetriminoKind::cell() for part of a piece, or GARBAGE.
+/// Row 0 is the top of the hidden buffer; the visible well starts at row hidden(). Indexing by row gives that row's
+/// squares, so well[row][col] is one square. Squares are changed with set() and set_row(), though, not through the
+/// index: alongside the squares, which are what gets drawn, each row is kept as a bitmask of which squares are
+/// filled, bit 0 for the leftmost column. That's what collisions and line clears look at, a row at a time.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Well
+{
+    cols: usize,
+    hidden: usize,
+    cells: Vec<u8>,   // Row by row from the top, cols squares each.
+    masks: Vec<u64>   // A row's filled squares, one bit each. Wells are never more than 64 wide.
+}
+
+
+impl Well
+{
+    /// An empty well with the given width, visible height and hidden rows above that.
+    /// Panics outside MIN_ and MAX_WELL_COLS, _ROWS and _HIDDEN_ROWS. (Settings and replays check before it gets here.)
+    pub fn new(cols: usize, visible_rows: usize, hidden: usize) -> Self
+    {
+        assert!((MIN_WELL_COLS..=MAX_WELL_COLS).contains(&cols), "well width {} out of range", cols);
+        assert!((MIN_WELL_ROWS..=MAX_WELL_ROWS).contains(&visible_rows), "well height {} out of range", visible_rows);
+        assert!((MIN_HIDDEN_ROWS..=MAX_HIDDEN_ROWS).contains(&hidden), "hidden rows {} out of range", hidden);
+        Well { cols, hidden, cells: vec![0; cols * (visible_rows + hidden)], masks: vec![0; visible_rows + hidden] }
+    }
+
+    /// An empty well of the same size as this one.
+    pub fn empty_like(&self) -> Self
+    {
+        Well { cols: self.cols, hidden: self.hidden, cells: vec![0; self.cells.len()], masks: vec![0; self.masks.len()] }
+    }
+
+    pub fn cols(&self) -> usize { self.cols }
+
+    /// All the rows, hidden ones included.
+    pub fn rows(&self) -> usize { self.masks.len() }
+
+    pub fn hidden(&self) -> usize { self.hidden }
+
+    pub fn visible_rows(&self) -> usize { self.rows() - self.hidden }
+
+    /// The usual 10 wide, 20 tall (plus 4 hidden) well?
+    pub fn is_standard(&self) -> bool
+    {
+        (self.cols, self.visible_rows(), self.hidden) == (WELL_COLS, WELL_VISIBLE_ROWS, WELL_HIDDEN_ROWS)
+    }
+
+    /// Where a new piece's 4x4 box goes: two rows above the visible well, in the middle. Row 2, column 3 in the usual well.
+    pub fn spawn(&self) -> (i32, i32)
+    {
+        (self.hidden as i32 - 2, (self.cols as i32 - 4) / 2)
+    }
+
+    /// Rows from top to bottom.
+    pub fn iter(&self) -> std::slice::Chunks<'_, u8>
+    {
+        self.cells.chunks(self.cols)
+    }
+
+    /// Sets one square.
+    pub fn set(&mut self, row: usize, col: usize, cell: u8)
+    {
+        self.cells[row * self.cols + col] = cell;
+        if cell == 0 { self.masks[row] &= !(1 << col); } else { self.masks[row] |= 1 << col; }
+    }
+
+    /// Sets a whole row's squares.
+    pub fn set_row(&mut self, row: usize, squares: &[u8])
+    {
+        self.cells[row * self.cols..(row + 1) * self.cols].copy_from_slice(squares);
+        self.masks[row] = row_mask(squares);
+    }
+
+    /// Sets every square to the given value.
+    pub fn fill(&mut self, cell: u8)
+    {
+        for square in self.cells.iter_mut() { *square = cell; }
+        let mask = if cell == 0 { 0 } else { self.full_mask() };
+        for row in self.masks.iter_mut() { *row = mask; }
+    }
+
+    /// Pushes a row in at the bottom, and everything else up one. Returns the top row, which gets pushed out.
+    pub fn push_bottom(&mut self, row: &[u8]) -> Vec<u8>
+    {
+        assert_eq!(row.len(), self.cols);
+        let top = self.cells.drain(..self.cols).collect();
+        self.cells.extend_from_slice(row);
+        self.masks.remove(0);
+        self.masks.push(row_mask(row));
+        top
+    }
+
+    /// Which squares of a row are filled: bit 0 for the leftmost column, and so on.
+    pub fn row_mask(&self, row: usize) -> u64
+    {
+        self.masks[row]
+    }
+
+    /// What row_mask() is for a full row.
+    pub fn full_mask(&self) -> u64
+    {
+        (1 << self.cols) - 1
+    }
+}
+
+
+/// A row of squares as a bitmask, the way Well keeps them.
+fn row_mask(squares: &[u8]) -> u64
+{
+    squares.iter().rev().fold(0, |mask, square| mask << 1 | (*square != 0) as u64)
+}
+
+
+/// A row of a piece's mask (see Tetrimino::mask()) moved over to the given column of a row whose full mask is
+/// `full`, or None if any of it goes off either side.
+pub fn shift_mask(mask: u64, col: i32, full: u64) -> Option<u64>
+{
+    // Squares shifted out past either end of the u64 are off the side too.
+    let shifted = if col >= 0 {
+        if mask.leading_zeros() < col as u32 { return None; }
+        mask.checked_shl(col as u32).unwrap_or(0)
+    } else {
+        if mask.trailing_zeros() < col.unsigned_abs() { return None; }
+        mask.checked_shr(col.unsigned_abs()).unwrap_or(0)
+    };
+    if shifted & !full != 0 { None } else { Some(shifted) }
+}
+
+
+/// A well's size as text: width x visible height + hidden rows, "10x20+4" for the usual one.
+pub fn format_well_size(well: &Well) -> String
+{
+    format!("{}x{}+{}", well.cols(), well.visible_rows(), well.hidden())
+}
+
+/// An empty well from a size like format_well_size() writes. The hidden rows can be left off, for the usual 4: "20x30".
+pub fn parse_well_size(text: &str) -> Result<Well, String>
+{
+    let bad = || format!("bad well size '{}' (expected width x height, like 10x20, optionally + hidden rows)", text);
+    let (size, hidden) = match text.split_once('+') {
+        Some((size, hidden)) => (size, hidden.parse::<usize>().map_err(|_| bad())?),
+        None => (text, WELL_HIDDEN_ROWS)
+    };
+    let (cols, visible_rows) = size.split_once('x').ok_or_else(bad)?;
+    let (cols, visible_rows): (usize, usize) = (cols.parse().map_err(|_| bad())?, visible_rows.parse().map_err(|_| bad())?);
+
+    if !(MIN_WELL_COLS..=MAX_WELL_COLS).contains(&cols) || !(MIN_WELL_ROWS..=MAX_WELL_ROWS).contains(&visible_rows)
+       || !(MIN_HIDDEN_ROWS..=MAX_HIDDEN_ROWS).contains(&hidden) {
+        return Err(format!("well size '{}' is out of range: {} to {} wide, {} to {} tall, {} to {} hidden rows", text,
+                           MIN_WELL_COLS, MAX_WELL_COLS, MIN_WELL_ROWS, MAX_WELL_ROWS, MIN_HIDDEN_ROWS, MAX_HIDDEN_ROWS));
+    }
+    Ok(Well::new(cols, visible_rows, hidden))
+}
+
+
+impl Default for Well
+{
+    fn default() -> Self { Well::new(WELL_COLS, WELL_VISIBLE_ROWS, WELL_HIDDEN_ROWS) }
+}
+
+
+impl std::ops::Index<usize> for Well
+{
+    type Output = [u8];
+
+    fn index(&self, row: usize) -> &[u8]
+    {
+        &self.cells[row * self.cols..(row + 1) * self.cols]
+    }
+}
+
+/// The value a square of garbage is stored as in the well. (Pieces are 1 to 7, see TetriminoKind::cell().)
+pub const GARBAGE: u8 = 8;
+
+/// Garbage is drawn in this gray, whatever the palette.
+pub const GARBAGE_COLOR: [f32; 4] = [0.55, 0.55, 0.55, 1.0];
+
+
+/// What happened when a piece locked, for anything that keeps score its own way (like versus attacks).
+#[derive(Copy, Clone, Debug)]
+pub struct Lock
+{
+    pub cleared: u32,
+    pub tspin: bool,          // A T that rotated into a spot with three of its corners blocked. See is_tspin().
+    pub combo: u32,           // How many pieces in a row have cleared lines, this one included. 0 if this one didn't.
+    pub back_to_back: bool    // A tetris or T-spin clear straight after another one, with no easier clears between.
+}
+
+
+/// How the next tetrimino gets picked.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Randomizer
+{
+    Bag,           // All 7 tetriminos in random order, then all 7 again, and so on. (Modern games.)
+    Memoryless,    // Each tetrimino is an independent 1 in 7 pick. Droughts happen!
+    Nes            // Pick 1 in 7, and if that repeats the previous piece, pick again once. (NES Tetris.)
+}
+
+
+impl fmt::Display for Randomizer
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.write_str(match self {
+            Randomizer::Bag        => "bag",
+            Randomizer::Memoryless => "memoryless",
+            Randomizer::Nes        => "nes"
+        })
+    }
+}
+
+
+impl FromStr for Randomizer
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "bag"        => Ok(Randomizer::Bag),
+            "memoryless" => Ok(Randomizer::Memoryless),
+            "nes"        => Ok(Randomizer::Nes),
+            _ => Err(format!("unknown randomizer '{}' (expected bag, memoryless or nes)", s))
+        }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct GameState
+{
+    pub mode: Mode,
+    pub game_over: Option<GameOver>,
+    pub fall_counter: u32,
+    pub spawn_delay: u32,     // Updates left before the current piece appears and starts moving (ARE). Only Master has any.
+    pub well: Well,
+    pub seed: u64,                   // Everything random in a game comes from this, so a seed + inputs replays exactly.
+    pub rng: StdRng,
+    pub randomizer: Randomizer,
+    pub piece_set: Arc<PieceSet>,    // The pieces the randomizer picks from. The standard seven, unless the settings say otherwise.
+    pub ttmo_bag: Vec<Tetrimino>,    // Randomized bag of every piece in the set. (Only used by Randomizer::Bag.)
+    pub curr_ttmo: Tetrimino,
+    pub next_ttmo: Tetrimino,
+    pub hold_ttmo: Option<Tetrimino>,    // The piece put aside with Action::Hold, as it spawns, if there is one.
+    pub hold_used: bool,      // The current piece came out of hold, or went into it, so it can't be held again until it locks.
+    pub ttmo_row: i32,        // Curr piece's location in the well.
+    pub ttmo_col: i32,
+    pub spawn_row: i32,       // Where new pieces appear. SPAWN_ROW and SPAWN_COL unless the settings say otherwise.
+    pub spawn_col: i32,
+    pub gravity: u32,         // Updates a piece hangs in each row before falling, in modes without speeds of their own. Usually GRAVITY.
+    pub speed: u32,           // Percent of its mode's gravity that pieces fall at, whatever the mode. Usually NORMAL_SPEED.
+    pub inputs: Vec<Action>,  // Presses for the next update to apply, oldest first.
+    pub repeats: usize,       // How many of those, at the end, are held keys repeating rather than pressed again. (See input::AutoRepeat.)
+    pub frames: u32,          // Number of updates so far. The first piece is already falling on update 0, so this is also the game timer.
+    pub splits: Vec<u32>,     // Value of frames when lines reached 10, 20, 30...
+    pub pieces: u32,          // Number of pieces frozen into the well so far.
+    pub lines: u32,
+    pub score: u32,
+    pub clears: [u32; 4],         // Number of singles, doubles, triples and tetrises so far.
+    pub clear_points: [u32; 4],   // Points scored by each of those.
+    pub rotated_last: bool,     // The current piece's last successful move was a rotation. (For spotting T-spins.)
+    pub combo: u32,             // Pieces in a row that have cleared lines.
+    pub back_to_back: bool,     // The last clear was a tetris or a T-spin, so another one now would be back-to-back.
+    pub last_lock: Option<Lock>,    // The piece that locked during the latest update, if one did.
+    pub piece_presses: u32,     // Move and rotate key presses since the current piece spawned. Repeats don't count.
+    pub finesse: Finesse,
+    pub finesse_practice: bool, // When a piece locks with a finesse fault, put it back at the top to try again.
+    pub master: Master,         // Master mode's level, gravity and such. Left alone in other modes.
+    pub cheese: Cheese,         // Cheese mode's garbage still to come. Likewise.
+    pub puzzle: Option<Arc<Puzzle>>    // The puzzle being played, in Puzzle mode. Its pieces come instead of the randomizer's.
+}
+
+
+impl GameState
+{
+    /// A fresh game with a random seed and the 7-bag: empty well, first piece near the top and center.
+    pub fn new() -> Self
+    {
+        GameState::with_seed(thread_rng().gen(), Randomizer::Bag)
+    }
+
+    /// A fresh game of the given mode, with a random seed. NES Marathon gets the NES randomizer, everything else the 7-bag.
+    pub fn with_mode(mode: Mode) -> Self
+    {
+        let randomizer = match mode {
+            Mode::Marathon { .. } => Randomizer::Nes,
+            _ => Randomizer::Bag
+        };
+
+        let mut game_state = GameState::with_seed(thread_rng().gen(), randomizer);
+        game_state.set_mode(mode);
+        game_state
+    }
+
+    /// A fresh endless game whose pieces are completely determined by the given seed and randomizer.
+    pub fn with_seed(seed: u64, randomizer: Randomizer) -> Self
+    {
+        let first = Tetrimino::new(TetriminoKind::I);    // Placeholder, replaced just below. (The NES randomizer rerolls against it once.)
+
+        let mut game_state = GameState {
+            mode: Mode::Endless,
+            game_over: None,
+            fall_counter: 0,
+            spawn_delay: 0,
+            well: Well::default(),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            randomizer,
+            piece_set: Arc::new(PieceSet::standard()),
+            ttmo_bag: Vec::new(),
+            curr_ttmo: first,
+            next_ttmo: first,
+            hold_ttmo: None,
+            hold_used: false,
+            ttmo_row: SPAWN_ROW,
+            ttmo_col: SPAWN_COL,
+            spawn_row: SPAWN_ROW,
+            spawn_col: SPAWN_COL,
+            gravity: GRAVITY,
+            speed: NORMAL_SPEED,
+            inputs: Vec::new(),
+            repeats: 0,
+            frames: 0,
+            splits: Vec::new(),
+            pieces: 0,
+            lines: 0,
+            score: 0,
+            clears: [0; 4],
+            clear_points: [0; 4],
+            rotated_last: false,
+            combo: 0,
+            back_to_back: false,
+            last_lock: None,
+            piece_presses: 0,
+            finesse: Finesse::default(),
+            finesse_practice: false,
+            master: Master::default(),
+            cheese: Cheese::default(),
+            puzzle: None
+        };
+
+        game_state.next_ttmo = game_state.pick_next_ttmo();
+        game_state.curr_ttmo = game_state.next_ttmo;
+        game_state.next_ttmo = game_state.pick_next_ttmo();
+        game_state
+    }
+
+    /// Switches a fresh game over to the given mode, and sets up whatever the mode starts with, like Cheese's garbage.
+    pub fn set_mode(&mut self, mode: Mode)
+    {
+        self.mode = mode;
+        garbage::start_cheese(self);
+    }
+
+    /// Whether this game has a hold: its mode's, or a puzzle that starts with a piece in it. That one can only ever swap,
+    /// never draw a piece from the list early, so the list still comes in order.
+    pub fn has_hold(&self) -> bool
+    {
+        self.mode.has_hold() || self.puzzle.as_ref().is_some_and(|puzzle| puzzle.hold.is_some())
+    }
+
+    /// Swaps in an empty well of another size, moves where pieces appear to the middle of it, and sets the mode
+    /// up again in it (Cheese's garbage has to be in the new well, not the old one). For a fresh game.
+    pub fn set_well(&mut self, well: Well)
+    {
+        let (row, col) = well.spawn();
+        self.well = well.empty_like();
+        self.set_spawn(row, col);
+        garbage::start_cheese(self);
+    }
+
+    /// Swaps in another set of pieces, and picks the first two again from it. For a fresh game.
+    pub fn set_piece_set(&mut self, piece_set: Arc<PieceSet>)
+    {
+        self.piece_set = piece_set;
+        self.ttmo_bag.clear();
+        self.next_ttmo = self.pick_next_ttmo();
+        self.curr_ttmo = self.next_ttmo;
+        self.next_ttmo = self.pick_next_ttmo();
+    }
+
+    /// Moves where pieces appear, the first one included. For a fresh game.
+    pub fn set_spawn(&mut self, row: i32, col: i32)
+    {
+        self.spawn_row = row;
+        self.spawn_col = col;
+        self.ttmo_row = row;
+        self.ttmo_col = col;
+    }
+
+    /// Draws a new piece from this game's set, according to its randomizer. Or the puzzle's next, in a puzzle.
+    fn pick_next_ttmo(&mut self) -> Tetrimino
+    {
+        if let Some(puzzle) = &self.puzzle { return puzzle.tetrimino(self.pieces as usize + 1); }
+        let pieces = &self.piece_set.pieces;
+
+        match self.randomizer
+        {
+            Randomizer::Bag => {
+                if self.ttmo_bag.is_empty() { self.ttmo_bag = create_random_bag(&self.piece_set, &mut self.rng); }
+                self.ttmo_bag.pop().unwrap()
+            }
+
+            Randomizer::Memoryless => pieces.choose(&mut self.rng).unwrap().tetrimino(),
+
+            Randomizer::Nes => {
+                let pick = pieces.choose(&mut self.rng).unwrap();
+                if pick.kind != self.next_ttmo.kind { return pick.tetrimino(); }
+                pieces.choose(&mut self.rng).unwrap().tetrimino()    // Reroll once. It can still repeat.
+            }
+        }
+    }
+
+    /// The pieces that will come after the next one, in order, as the randomizer is going to deal them. (It deals
+    /// them to a copy, so this game's pieces don't change.)
+    pub fn upcoming(&self, count: usize) -> Vec<Tetrimino>
+    {
+        let mut copy = self.clone();
+        (0..count).map(|_| {
+            copy.pieces += 1;    // Puzzles count where they are in their list by it.
+            copy.next_ttmo = copy.pick_next_ttmo();
+            copy.next_ttmo
+        }).collect()
+    }
+
+    /// Guideline-style level: starts at 1, goes up every 10 lines.
+    /// NES Marathon counts the NES way instead: from the level picked at the start, which can be 0.
+    /// Master's 0 to 999 goes up with pieces as well as lines, so it's kept track of separately.
+    pub fn level(&self) -> u32
+    {
+        match self.mode
+        {
+            Mode::Marathon { start_level } => nes_level(start_level, self.lines),
+            Mode::Master => self.master.level,
+            _ => self.lines / 10 + 1
+        }
+    }
+
+    /// How many updates the current piece hangs in each row before falling to the next, at the game's speed.
+    /// (Not Master's: its pieces can fall many rows in one update. See master::internal_gravity().)
+    /// Puzzles have no gravity at all: pieces only come down when they're dropped.
+    pub fn frames_per_row(&self) -> u32
+    {
+        let frames = match self.mode
+        {
+            Mode::Marathon { .. } => nes_frames_per_row(self.level()),
+            Mode::Puzzle => return u32::MAX,
+            _ => self.gravity
+        };
+        (frames * NORMAL_SPEED / self.speed).max(1)
+    }
+}
+
+
+impl Default for GameState
+{
+    fn default() -> Self { GameState::new() }
+}
+
+
+/// Implements the main logic of the game. Pieces fall, full rows disappear, etc.
+pub fn game_update(game_state: &mut GameState)
+{
+    // Out of time? The clock stops at exactly the limit, whatever the piece was doing.
+    if let Some(limit) = game_state.mode.time_limit() {
+        if game_state.frames >= limit {
+            game_state.game_over = Some(GameOver::TimeUp);
+            return;
+        }
+    }
+
+    game_state.frames += 1;
+    game_state.last_lock = None;
+
+    // Master has gravity of its own, with lock delay and ARE. See master.rs.
+    if game_state.mode == Mode::Master {
+        master::update(game_state);
+        return;
+    }
+
+    // Cheese garbage can rise by itself, too.
+    garbage::rise_cheese(game_state);
+    if game_state.game_over.is_some() { return; }
+
+    if game_state.fall_counter + 1 < game_state.frames_per_row() {
+        game_state.fall_counter += 1;    // Not time to fall yet...
+    }
+    else    // Time to fall!
+    {
+        game_state.fall_counter = 0;
+
+        if would_collide(&game_state.curr_ttmo, &game_state.well, &(game_state.ttmo_row + 1), &game_state.ttmo_col)
+        {
+            if !lock_piece(game_state) { return; }
+        }
+          
+        else {
+            game_state.ttmo_row += 1;    // Move curr piece down one row.
+            game_state.rotated_last = false;
+        }
+    }
+
+    apply_keys(game_state);
+}
+
+
+/// Freezes the current piece into the well, scores and clears any rows it completed, and brings on the next piece.
+/// Returns false if there's no next piece to move this update: the game was won, or finesse practice sent the piece back.
+fn lock_piece(game_state: &mut GameState) -> bool
+{
+    let spawned = game_state.curr_ttmo.spawned();
+    let spawned_at = (&spawned, game_state.spawn_row, game_state.spawn_col);
+    let locked_at = (&game_state.curr_ttmo, game_state.ttmo_row, game_state.ttmo_col);
+    let sonic = game_state.mode == Mode::Master && master::twenty_g(game_state);
+    let faults = game_state.finesse.judge(spawned_at, &game_state.well, locked_at, game_state.piece_presses, sonic);
+    game_state.piece_presses = 0;
+
+    // Finesse practice: a sloppy piece doesn't stick. Back to the top with it.
+    if game_state.finesse_practice && faults.unwrap_or(0) > 0
+    {
+        game_state.curr_ttmo = spawned;
+        game_state.ttmo_row = game_state.spawn_row;
+        game_state.ttmo_col = game_state.spawn_col;
+        game_state.inputs.clear();
+        game_state.repeats = 0;
+        return false;
+    }
+
+    let tspin = is_tspin(game_state);
+    freeze_to_well(&game_state.curr_ttmo, &mut game_state.well, &game_state.ttmo_row, &game_state.ttmo_col);
+    game_state.pieces += 1;
+
+    // Score with the level from before the clear. Guideline points: 100/300/500/800 times the level.
+    // NES points: 40/100/300/1200 times one more than the level. Master has its own formula.
+    let level = game_state.level();
+    let cleared = count_complete_rows(&game_state.well);
+    game_state.lines += cleared;
+    game_state.well = clear_complete_rows(&game_state.well);
+
+    let points = match game_state.mode {
+        Mode::Marathon { .. } => NES_LINE_SCORES[cleared as usize] * (level + 1),
+        Mode::Master => master::clear_points(game_state, level, cleared),
+        _ => [0, 100, 300, 500, 800][cleared as usize] * level
+    };
+    if cleared > 0 {
+        game_state.score += points;
+        game_state.clears[cleared as usize - 1] += 1;
+        game_state.clear_points[cleared as usize - 1] += points;
+    }
+
+    // Combos and back-to-backs, for versus attacks.
+    let difficult = cleared == 4 || (tspin && cleared > 0);
+    game_state.combo = if cleared > 0 { game_state.combo + 1 } else { 0 };
+    let back_to_back = difficult && game_state.back_to_back;
+    if cleared > 0 { game_state.back_to_back = difficult; }
+    game_state.last_lock = Some(Lock { cleared, tspin, combo: game_state.combo, back_to_back });
+    game_state.rotated_last = false;
+
+    // Lines move Master's level on too, past section stops and all.
+    if game_state.mode == Mode::Master { master::advance(game_state, (level + cleared).min(MASTER_MAX_LEVEL)); }
+
+    // Dug some cheese out? More comes up from below.
+    garbage::refill_cheese(game_state);
+
+    while (game_state.splits.len() as u32) < game_state.lines / 10 { game_state.splits.push(game_state.frames); }
+
+    // Made it!
+    let goal_reached = match game_state.mode {
+        Mode::Sprint { lines } => game_state.lines >= lines,
+        Mode::Master => game_state.master.level >= MASTER_MAX_LEVEL,
+        Mode::Cheese { .. } => garbage::cheese_left(game_state) == 0,
+        Mode::Puzzle => puzzles::goal_reached(game_state),
+        _ => false
+    };
+    if goal_reached {
+        game_state.game_over = Some(GameOver::GoalReached);
+        return false;
+    }
+    if puzzles::out_of_pieces(game_state) {
+        game_state.game_over = Some(GameOver::OutOfPieces);
+        return false;
+    }
+
+    game_state.curr_ttmo = game_state.next_ttmo;
+    game_state.next_ttmo = game_state.pick_next_ttmo();
+    game_state.hold_used = false;
+
+    game_state.ttmo_row = game_state.spawn_row;    // Place near top...
+    game_state.ttmo_col = game_state.spawn_col;    // ...and near center.
+
+    if game_state.mode == Mode::Master { master::next_piece(game_state, cleared); }
+
+    // THAT'S IT, MAN! GAME OVER, MAN!!
+    if would_collide(&game_state.curr_ttmo, &game_state.well, &game_state.ttmo_row, &game_state.ttmo_col)
+    {
+        game_state.game_over = Some(GameOver::TopOut);
+    }
+
+    true
+}
+
+
+/// Puts the current piece aside and brings on the one held before it, or the next piece if nothing was held yet.
+/// Either way the new piece starts again from the top. Once per piece, and only in modes that have hold.
+fn hold_piece(game_state: &mut GameState)
+{
+    if !game_state.has_hold() || game_state.hold_used { return; }
+
+    let held = game_state.hold_ttmo.replace(game_state.curr_ttmo.spawned());
+    game_state.curr_ttmo = match held {
+        Some(held) => held,
+        None => {
+            let next = game_state.next_ttmo;
+            game_state.next_ttmo = game_state.pick_next_ttmo();
+            next
+        }
+    };
+
+    game_state.hold_used = true;
+    game_state.ttmo_row = game_state.spawn_row;
+    game_state.ttmo_col = game_state.spawn_col;
+    game_state.fall_counter = 0;
+    game_state.rotated_last = false;
+    game_state.piece_presses = 0;    // Finesse starts over with the new piece.
+
+    if would_collide(&game_state.curr_ttmo, &game_state.well, &game_state.ttmo_row, &game_state.ttmo_col)
+    {
+        game_state.game_over = Some(GameOver::TopOut);
+    }
+}
+
+
+/// Moves and rotates the current piece by each press waiting in inputs, in the order they happened, then forgets them.
+fn apply_keys(game_state: &mut GameState)
+{
+    let inputs = std::mem::take(&mut game_state.inputs);
+    let pressed = inputs.len().saturating_sub(std::mem::take(&mut game_state.repeats));
+
+    for (index, action) in inputs.into_iter().enumerate()
+    {
+        let (row, col) = (game_state.ttmo_row, game_state.ttmo_col);
+
+        match action
+        {
+            Action::MoveLeft | Action::MoveRight =>
+            {
+                let col = if action == Action::MoveLeft { col - 1 } else { col + 1 };
+                if !would_collide(&game_state.curr_ttmo, &game_state.well, &row, &col) { game_state.ttmo_col = col; }
+            }
+
+            Action::SoftDrop => if !would_collide(&game_state.curr_ttmo, &game_state.well, &(row + 1), &col) { game_state.ttmo_row += 1; },
+
+            Action::HardDrop =>
+            {
+                game_state.ttmo_row = landing_row(&game_state.curr_ttmo, &game_state.well, game_state.ttmo_row, game_state.ttmo_col);
+
+                // Without gravity, nothing else would ever lock it. So it locks on the next update.
+                if game_state.mode == Mode::Puzzle { game_state.fall_counter = game_state.frames_per_row() - 1; }
+            }
+
+            Action::Hold =>
+            {
+                hold_piece(game_state);
+                if game_state.game_over.is_some() { return; }
+                continue;
+            }
+
+            // A rotation that doesn't fit, even kicked, doesn't happen.
+            Action::RotateCCW | Action::RotateCW =>
+            {
+                if let Some((turned, row, col)) = rotate_in_well(&game_state.curr_ttmo, &game_state.well, row, col, action == Action::RotateCW) {
+                    game_state.curr_ttmo = turned;
+                    game_state.ttmo_row = row;
+                    game_state.ttmo_col = col;
+                    game_state.rotated_last = true;
+                }
+            }
+        }
+
+        // Moves and rotations count toward finesse, unless they're a held key repeating. (Drops don't.) Moving at all,
+        // other than by a kick, means the piece didn't get where it is by rotating.
+        let rotation = matches!(action, Action::RotateCCW | Action::RotateCW);
+        if index < pressed && !matches!(action, Action::SoftDrop | Action::HardDrop) { game_state.piece_presses += 1; }
+        if !rotation && (game_state.ttmo_row, game_state.ttmo_col) != (row, col) { game_state.rotated_last = false; }
+    }
+}
+
+
+/// Three-corner T-spin check, for the current piece where it is now: it's a T, it got there by rotating, and at least
+/// three of the four squares diagonal from its center are filled (or off the side or bottom of the well).
+pub fn is_tspin(game_state: &GameState) -> bool
+{
+    if game_state.curr_ttmo.kind != TetriminoKind::T || !game_state.rotated_last { return false; }
+
+    // T rotates within its top-left 3x3, so its center is always at (1, 1) of its shape.
+    let blocked = [(0, 0), (0, 2), (2, 0), (2, 2)].iter().filter(|(row_offs, col_offs)| {
+        let row = game_state.ttmo_row + row_offs;
+        let col = game_state.ttmo_col + col_offs;
+        let well = &game_state.well;
+        col < 0 || col >= well.cols() as i32 || row >= well.rows() as i32 || (row >= 0 && well[row as usize][col as usize] != 0)
+    }).count();
+
+    blocked >= 3
+}
+
+
+/// Creates and returns a vector containing a randomized ordering of every piece in the given set: the 7 standard
+/// tetriminos, usually.
+pub fn create_random_bag(pieces: &PieceSet, rng: &mut StdRng) -> Vec<Tetrimino>
+{
+    let mut tetrimino_bag: Vec<Tetrimino> = pieces.pieces.iter().map(|piece| piece.tetrimino()).collect();
+    tetrimino_bag.shuffle(rng);
+    tetrimino_bag.shuffle(rng);
+    tetrimino_bag.shuffle(rng);    // One randomize was appearing not very random...
+
+    tetrimino_bag
+}
+
+
+
+/// Rotates the given Tetrimino by 90 degrees, either clockwise or counterCW: on to its next rotation state, or back
+/// to its last one. (Pieces that don't turn, like O, stay as they are.)
+pub fn rotate_tetrimino(ttmo: &mut Tetrimino, clockwise: bool)
+{
+    let count = ttmo.rotation_count;
+    ttmo.rotation = if clockwise { (ttmo.rotation + 1) % count } else { (ttmo.rotation + count - 1) % count };
+    ttmo.shape = ttmo.rotations[ttmo.rotation];
+}
+
+
+/// Turns the given piece at (row, col) in the well, if it fits: where it is, or else at the first of its kicks that
+/// it fits at. Returns the turned piece and where it ended up, or None if it doesn't fit anywhere.
+pub fn rotate_in_well(ttmo: &Tetrimino, well: &Well, row: i32, col: i32, clockwise: bool) -> Option<(Tetrimino, i32, i32)>
+{
+    let mut turned = *ttmo;
+    rotate_tetrimino(&mut turned, clockwise);
+
+    std::iter::once((0, 0)).chain(ttmo.kicks[..ttmo.kick_count].iter().copied())
+        .map(|(right, down)| (row + down, if clockwise { col + right } else { col - right }))
+        .find(|(row, col)| !would_collide(&turned, well, row, col))
+        .map(|(row, col)| (turned, row, col))
+}
+
+
+/// Returns true if the given Tetrimino, placed in the given playfield,
+/// at the given row and col, would collide with something.
+pub fn would_collide(ttmo: &Tetrimino, well: &Well, row: &i32, col: &i32) -> bool
+{
+    // A row of the piece at a time, against the same row of the well.
+    for (ttmo_row, mask) in ttmo.mask().iter().enumerate() {
+
+        // Tetrimino has no squares in this row, collison is not possible.
+        if *mask == 0 { continue; }
+
+        // Collisions with well floor, or off the top, which only a kick can do.
+        let well_row = ttmo_row as i32 + *row;
+        if well_row < 0 || well_row >= well.rows() as i32 { return true; }
+
+        // Collisions with well walls, and with blocks already frozen in the well.
+        match shift_mask(*mask as u64, *col, well.full_mask()) {
+            Some(squares) => if squares & well.row_mask(well_row as usize) != 0 { return true; },
+            None => return true
+        }
+    }
+
+    false
+}
+
+
+/// The lowest row the given Tetrimino can drop to, straight down from the given row and col (where it has to fit).
+pub fn landing_row(ttmo: &Tetrimino, well: &Well, row: i32, col: i32) -> i32
+{
+    // Nothing can stop the piece while its whole box is in empty rows, so it falls straight to the first row where
+    // its box would reach the stack. (The AI drops every piece everywhere, and in a tall well that's a long way.)
+    let top = (0..well.rows()).find(|row| well.row_mask(*row) != 0).unwrap_or(well.rows()) as i32;
+    let start = if row >= -1 { row.max(top - PIECE_SIZE as i32 - 1) } else { row };
+
+    // Down to the floor at most: one row past the last is always in the way, however the piece sits in its box.
+    (start..=well.rows() as i32).find(|row| would_collide(ttmo, well, &(row + 1), &col)).unwrap_or(row)
+}
+
+
+/// Copies the given tetrimino's squares into the given well at the given (well_row, well_col).
+pub fn freeze_to_well(ttmo: &Tetrimino, well: &mut Well, well_row: &i32, well_col: &i32)
+{
+    for row in 0..PIECE_SIZE {
+        for col in 0..PIECE_SIZE {
+            if ttmo.shape[row][col] == 0 { continue; }
+            well.set((*well_row + row as i32) as usize, (*well_col + col as i32) as usize, ttmo.kind.cell());
+        }
+    }
+}
+
+
+/// Returns how many rows of the given well are completely full.
+pub fn count_complete_rows(well: &Well) -> u32
+{
+    (0..well.rows()).filter(|row| well.row_mask(*row) == well.full_mask()).count() as u32
+}
+
+
+/// Clears out complete rows in the given well, and moves the rows above them down.
+pub fn clear_complete_rows(well: &Well) -> Well
+{
+    // Copy partial rows to a new well. Ignore both empty and full rows.
+    let mut new_well = well.empty_like();
+    let mut new_well_row: usize = well.rows() - 1;
+
+    for old_well_row in (0..well.rows()).rev()    // Start at bottom and work upward.
+    {
+        // Totally empty or totally full rows are ignored.
+        let mask = well.row_mask(old_well_row);
+        if mask == 0 || mask == well.full_mask() { continue; }
+
+        // Copy partial row to new well, in lowest row possible.
+        // (With every row partial, the top one goes in row 0 and there's nowhere left to go, nor anything left to copy.)
+        new_well.set_row(new_well_row, &well[old_well_row]);
+        new_well_row = new_well_row.saturating_sub(1);
+    }
+
+    new_well
+}
@@ -0,0 +1,372 @@
+/*
+
+The rustris-server protocol, and a client for it. PROTOCOL.md at the top of the repo describes the protocol
+in full; this is the code side of it.
+
+Unlike two-player network versus (net.rs), matches on a server are run by the server. Clients only send
+their keys, and the server sends everyone back what the whole match looks like, every update. Players see
+their own well that way too, so there's nothing to get out of sync.
+
+*/
+
+use std::net::{TcpStream, ToSocketAddrs};
+
+use crate::{format_actions, freeze_to_well, parse_actions, Action, GameState, Randomizer, Shape, Tetrimino, TetriminoKind, Well, PIECE_SIZE, WELL_COLS};
+use crate::battle::Battle;
+use crate::net::{LineStream, NetError};
+
+
+/// Bumped whenever the protocol changes. A server only talks to clients of its own version.
+pub const SERVER_PROTOCOL_VERSION: u32 = 4;
+
+/// The port the server listens on unless told otherwise.
+pub const DEFAULT_SERVER_PORT: u16 = 7342;
+
+/// How long player and room names can be.
+pub const MAX_NAME_LENGTH: usize = 16;
+
+
+/// Names go in the middle of lines, so they're kept to letters, digits, - and _.
+pub fn valid_name(name: &str) -> bool
+{
+    !name.is_empty() && name.len() <= MAX_NAME_LENGTH && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+
+/// Everything a client can say.
+#[derive(PartialEq, Clone, Debug)]
+pub enum ClientMessage
+{
+    Hello { version: u32, name: String },
+    List,
+    Create(String),
+    Join(String),
+    Spectate(String),
+    Leave,
+    Ready,
+    Unready,
+    Keys(Vec<Action>),
+    Bye
+}
+
+
+/// A room member's part in it.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Status { Waiting, Ready, Playing, Spectating }
+
+
+/// One player's well and numbers, as of some update.
+#[derive(PartialEq, Clone, Debug)]
+pub struct PlayerView
+{
+    pub slot: usize,
+    pub place: Option<usize>,         // Where they finished, once they're out.
+    pub lines: u32,
+    pub sent: u32,
+    pub incoming: u32,                // Lines of garbage on their way to them.
+    pub pieces: u32,                  // Pieces locked so far.
+    pub piece: Option<(Tetrimino, i32, i32)>,    // The falling piece, and its row and column, while there is one.
+    pub next: TetriminoKind,
+    pub well: Well
+}
+
+
+/// Everything the server can say.
+#[derive(PartialEq, Clone, Debug)]
+pub enum ServerMessage
+{
+    Welcome { version: u32, id: u32 },
+    Error(String),
+    Room { name: String, players: usize, spectators: usize, playing: bool },
+    EndRooms,
+    Joined { room: String, spectating: bool },
+    Left,
+    Member { id: u32, name: String, status: Status },
+    Gone(u32),
+    Start { seed: u64, players: usize },
+    Slot { slot: usize, id: u32, name: String },
+    Frame(u32),
+    State(Box<PlayerView>),             // Boxed, being much bigger than the rest.
+    Out { slot: usize, place: usize },
+    Winner(Option<usize>)
+}
+
+
+impl PlayerView
+{
+    /// How a player in a battle looks right now.
+    pub fn new(battle: &Battle, slot: usize) -> Self
+    {
+        let game_state = &battle.players[slot];
+        let falling = game_state.spawn_delay == 0 && game_state.game_over.is_none();
+
+        PlayerView {
+            slot,
+            place: battle.places[slot],
+            lines: game_state.lines,
+            sent: battle.sent[slot],
+            incoming: battle.incoming[slot].iter().map(|incoming| incoming.lines).sum(),
+            pieces: game_state.pieces,
+            piece: if falling { Some((game_state.curr_ttmo, game_state.ttmo_row, game_state.ttmo_col)) } else { None },
+            next: game_state.next_ttmo.kind,
+            well: game_state.well.clone()
+        }
+    }
+
+    /// The well with the falling piece drawn into it, for showing.
+    pub fn painted_well(&self) -> Well
+    {
+        let mut well = self.well.clone();
+        if let Some((piece, row, col)) = &self.piece { freeze_to_well(piece, &mut well, row, col); }
+        well
+    }
+
+    /// Enough of a game to hand to a bot, so it can play from what the server says.
+    pub fn to_game_state(&self) -> GameState
+    {
+        let mut game_state = GameState::with_seed(0, Randomizer::Bag);
+
+        game_state.well = self.well.clone();
+        game_state.pieces = self.pieces;
+        game_state.lines = self.lines;
+        game_state.next_ttmo = Tetrimino::new(self.next);
+        match self.piece {
+            Some((piece, row, col)) => {
+                game_state.curr_ttmo = piece;
+                game_state.ttmo_row = row;
+                game_state.ttmo_col = col;
+            }
+            None => game_state.spawn_delay = 1
+        }
+        if self.place.is_some() { game_state.game_over = Some(crate::modes::GameOver::TopOut); }
+
+        game_state
+    }
+}
+
+
+impl Status
+{
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Status::Waiting    => "waiting",
+            Status::Ready      => "ready",
+            Status::Playing    => "playing",
+            Status::Spectating => "spectating"
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self>
+    {
+        match name
+        {
+            "waiting"    => Some(Status::Waiting),
+            "ready"      => Some(Status::Ready),
+            "playing"    => Some(Status::Playing),
+            "spectating" => Some(Status::Spectating),
+            _ => None
+        }
+    }
+}
+
+
+impl ClientMessage
+{
+    pub fn to_line(&self) -> String
+    {
+        match self
+        {
+            ClientMessage::Hello { version, name } => format!("hello {} {}", version, name),
+            ClientMessage::List           => "list".to_string(),
+            ClientMessage::Create(room)   => format!("create {}", room),
+            ClientMessage::Join(room)     => format!("join {}", room),
+            ClientMessage::Spectate(room) => format!("spectate {}", room),
+            ClientMessage::Leave          => "leave".to_string(),
+            ClientMessage::Ready          => "ready".to_string(),
+            ClientMessage::Unready        => "unready".to_string(),
+            ClientMessage::Keys(keys)     => format!("keys {}", format_actions(keys)),
+            ClientMessage::Bye            => "bye".to_string()
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Self>
+    {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let name = |name: &str| if valid_name(name) { Some(name.to_string()) } else { None };
+
+        match words.as_slice()
+        {
+            ["hello", version, player] => Some(ClientMessage::Hello { version: version.parse().ok()?, name: name(player)? }),
+            ["list"]           => Some(ClientMessage::List),
+            ["create", room]   => Some(ClientMessage::Create(name(room)?)),
+            ["join", room]     => Some(ClientMessage::Join(name(room)?)),
+            ["spectate", room] => Some(ClientMessage::Spectate(name(room)?)),
+            ["leave"]          => Some(ClientMessage::Leave),
+            ["ready"]          => Some(ClientMessage::Ready),
+            ["unready"]        => Some(ClientMessage::Unready),
+            ["keys", keys]     => Some(ClientMessage::Keys(parse_actions(keys)?)),
+            ["bye"]            => Some(ClientMessage::Bye),
+            _ => None
+        }
+    }
+}
+
+
+impl ServerMessage
+{
+    pub fn to_line(&self) -> String
+    {
+        match self
+        {
+            ServerMessage::Welcome { version, id } => format!("welcome {} {}", version, id),
+            ServerMessage::Error(msg) => format!("error {}", msg),
+            ServerMessage::Room { name, players, spectators, playing } =>
+                format!("room {} {} {} {}", name, players, spectators, if *playing { "playing" } else { "lobby" }),
+            ServerMessage::EndRooms => "end-rooms".to_string(),
+            ServerMessage::Joined { room, spectating } => format!("joined {} {}", room, if *spectating { "spectator" } else { "player" }),
+            ServerMessage::Left => "left".to_string(),
+            ServerMessage::Member { id, name, status } => format!("member {} {} {}", id, name, status.name()),
+            ServerMessage::Gone(id) => format!("gone {}", id),
+            ServerMessage::Start { seed, players } => format!("start {} {}", seed, players),
+            ServerMessage::Slot { slot, id, name } => format!("slot {} {} {}", slot, id, name),
+            ServerMessage::Frame(frame) => format!("frame {}", frame),
+            ServerMessage::State(view) => {
+                let place = view.place.map_or("-".to_string(), |place| place.to_string());
+                let piece = match &view.piece {
+                    Some((piece, row, col)) => {
+                        let shape: String = piece.shape[..4].iter().map(|row| format!("{:x}", row[..4].iter().fold(0, |bits, square| bits << 1 | (*square != 0) as u8))).collect();
+                        format!("{} {} {} {}", piece.kind.cell(), row, col, shape)
+                    }
+                    None => "0 0 0 0000".to_string()
+                };
+                let well: String = view.well.iter().flat_map(|row| row.iter()).map(|cell| char::from(b'0' + cell)).collect();
+                format!("state {} {} {} {} {} {} {} {} {}", view.slot, place, view.lines, view.sent, view.incoming, view.pieces, piece, view.next.cell(), well)
+            }
+            ServerMessage::Out { slot, place } => format!("out {} {}", slot, place),
+            ServerMessage::Winner(slot) => format!("winner {}", slot.map_or("none".to_string(), |slot| slot.to_string()))
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Self>
+    {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.as_slice()
+        {
+            ["welcome", version, id] => Some(ServerMessage::Welcome { version: version.parse().ok()?, id: id.parse().ok()? }),
+            ["error", ..] => Some(ServerMessage::Error(words[1..].join(" "))),
+            ["room", name, players, spectators, state] => Some(ServerMessage::Room {
+                name: name.to_string(), players: players.parse().ok()?, spectators: spectators.parse().ok()?,
+                playing: match *state { "playing" => true, "lobby" => false, _ => return None }
+            }),
+            ["end-rooms"] => Some(ServerMessage::EndRooms),
+            ["joined", room, part] => Some(ServerMessage::Joined {
+                room: room.to_string(),
+                spectating: match *part { "spectator" => true, "player" => false, _ => return None }
+            }),
+            ["left"] => Some(ServerMessage::Left),
+            ["member", id, name, status] => Some(ServerMessage::Member { id: id.parse().ok()?, name: name.to_string(), status: Status::from_name(status)? }),
+            ["gone", id] => Some(ServerMessage::Gone(id.parse().ok()?)),
+            ["start", seed, players] => Some(ServerMessage::Start { seed: seed.parse().ok()?, players: players.parse().ok()? }),
+            ["slot", slot, id, name] => Some(ServerMessage::Slot { slot: slot.parse().ok()?, id: id.parse().ok()?, name: name.to_string() }),
+            ["frame", frame] => Some(ServerMessage::Frame(frame.parse().ok()?)),
+            ["state", slot, place, lines, sent, incoming, pieces, kind, row, col, shape, next, well] => {
+                let cells: Vec<u8> = well.bytes().map(|byte| byte.wrapping_sub(b'0')).collect();
+                if cells.len() != 240 || cells.iter().any(|cell| *cell > crate::GARBAGE) { return None; }
+
+                let piece = match kind.parse().ok()? {
+                    0 => None,
+                    cell => {
+                        // Battles are always played with the usual seven, which all fit in 4x4.
+                        let piece = Tetrimino::new(TetriminoKind::from_cell(cell).filter(|kind| kind.is_standard())?);
+                        let bits = u16::from_str_radix(shape, 16).ok().filter(|_| shape.len() == 4)?;
+                        let mut squares: Shape = [[0; PIECE_SIZE]; PIECE_SIZE];
+                        for index in 0..16 {
+                            squares[index / 4][index % 4] = (bits >> (15 - index) & 1) as u8;
+                        }
+                        Some((piece.turned_to(&squares)?, row.parse().ok()?, col.parse().ok()?))
+                    }
+                };
+
+                let mut view = PlayerView {
+                    slot: slot.parse().ok()?,
+                    place: if *place == "-" { None } else { Some(place.parse().ok()?) },
+                    lines: lines.parse().ok()?,
+                    sent: sent.parse().ok()?,
+                    incoming: incoming.parse().ok()?,
+                    pieces: pieces.parse().ok()?,
+                    piece,
+                    next: TetriminoKind::from_cell(next.parse().ok()?).filter(|kind| kind.is_standard())?,
+                    well: Well::default()    // Battles are always played in the usual well.
+                };
+                for (row, chunk) in cells.chunks(WELL_COLS).enumerate() { view.well.set_row(row, chunk); }
+                Some(ServerMessage::State(Box::new(view)))
+            }
+            ["out", slot, place] => Some(ServerMessage::Out { slot: slot.parse().ok()?, place: place.parse().ok()? }),
+            ["winner", "none"] => Some(ServerMessage::Winner(None)),
+            ["winner", slot] => Some(ServerMessage::Winner(Some(slot.parse().ok()?))),
+            _ => None
+        }
+    }
+}
+
+
+/// A connection to a rustris-server.
+pub struct LobbyClient
+{
+    pub id: u32,    // The server's name for this client, as used in member and slot messages.
+    lines: LineStream
+}
+
+
+impl LobbyClient
+{
+    /// Connects and says hello. Everything after that happens through send() and poll().
+    pub fn connect<A: ToSocketAddrs>(address: A, name: &str) -> Result<Self, NetError>
+    {
+        let mut lines = LineStream::new(TcpStream::connect(address)?)?;
+        lines.send(&ClientMessage::Hello { version: SERVER_PROTOCOL_VERSION, name: name.to_string() }.to_line())?;
+
+        let reply = lines.read_line()?;
+        let id = match ServerMessage::parse(&reply) {
+            Some(ServerMessage::Welcome { version, id }) if version == SERVER_PROTOCOL_VERSION => id,
+            Some(ServerMessage::Error(msg)) => return Err(NetError::Protocol(msg)),
+            _ => return Err(NetError::Version(reply))
+        };
+
+        lines.stream.set_nonblocking(true)?;
+        Ok(LobbyClient { id, lines })
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) -> Result<(), NetError>
+    {
+        self.lines.send(&message.to_line())
+    }
+
+    /// Everything the server has said since last time, without waiting for more.
+    pub fn poll(&mut self) -> Result<Vec<ServerMessage>, NetError>
+    {
+        self.lines.fill()?;
+
+        let mut messages = Vec::new();
+        while let Some(line) = self.lines.take_line()
+        {
+            if line == "bye" { self.lines.closed = true; continue; }
+            messages.push(ServerMessage::parse(&line).ok_or(NetError::Protocol(line))?);
+        }
+        if messages.is_empty() && self.lines.closed { return Err(NetError::Disconnected); }
+
+        Ok(messages)
+    }
+}
+
+
+impl Drop for LobbyClient
+{
+    fn drop(&mut self)
+    {
+        self.lines.goodbye();
+    }
+}
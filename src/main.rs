@@ -36,112 +36,97 @@ Classic NES Tetris Type 3 background music is composed by Hirokazu Tanaka and is
 */
 
 extern crate piston_window;
-extern crate rand;
+extern crate tetris;
+
+mod text;
 
 use piston_window::*;
 
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+use tetris::*;
+use tetris::ai::{AiBot, Weights};
+use tetris::editor::Editor;
+use tetris::fumen::encode_game;
+use tetris::input::{AutoRepeat, InputSource};
+use tetris::master;
+use tetris::garbage::cheese_left;
+use tetris::net::{NetVersus, DEFAULT_INPUT_DELAY, DEFAULT_PORT};
+use tetris::modes::{format_delta, format_time, format_time_at, GameOver, Mode, CHEESE_MESSINESS, MARATHON_MAX_START};
+use tetris::profiles::{last_profile, load_profiles, set_last_profile, valid_profile_name, Profile, Tally, MAX_PROFILE_NAME};
+use tetris::records::{data_dir, default_name, format_date, HighScores, Ranking, ScoreEntry};
+use tetris::replay::{Replay, ReplayInput};
+use tetris::pieces::PieceSet;
+use tetris::puzzles::{load_puzzles, pieces_to_come, puzzles_dir, start_puzzle, Puzzle, Undo};
+use tetris::settings::{Settings, RESTART_SETTINGS, SETTING_NAMES};
+use tetris::solver::{Problem, Solution, DEFAULT_LINES};
+use tetris::timestep::{TimedQueue, Timestep};
+use tetris::versus::{Outcome, Rules, Versus};
+
+use text::{draw_text, text_width};
 
 use std::io::BufReader;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Instant;
 
+/// What the window is showing.
 #[derive(PartialEq, Copy, Clone)]
-enum TetriminoKind { I, J, L, O, S, T, Z }
+enum Screen { Title, LevelSelect, Puzzles, Editor, Playing, Versus, Scores, Profile, NewProfile, Options }
 
 
-#[derive(Copy, Clone)]
-struct Tetrimino {
-    kind: TetriminoKind,
-    color: [f32; 4],    // R, G, B, A
-    shape: [[u8; 4]; 4]
-}
+/// The modes offered on the title screen, top to bottom.
+const MENU: [Mode; 13] = [ Mode::Endless, Mode::Sprint { lines: 20 }, Mode::Sprint { lines: 40 }, Mode::Sprint { lines: 100 },
+                           Mode::Ultra { minutes: 2 }, Mode::Ultra { minutes: 3 }, Mode::Marathon { start_level: 0 }, Mode::Master,
+                           Mode::Cheese { rows: 10, messiness: CHEESE_MESSINESS, rise: 0 },
+                           Mode::Cheese { rows: 18, messiness: CHEESE_MESSINESS, rise: 0 },
+                           Mode::Cheese { rows: 100, messiness: CHEESE_MESSINESS, rise: 0 }, Mode::Puzzle, Mode::Versus ];
 
 
-impl Tetrimino
-{
-    const fn new(kind: TetriminoKind) -> Self
-    {
-        match kind
-        {
-            TetriminoKind::I => Tetrimino { kind: TetriminoKind::I,
-                                            color: [ 1.0, 1.0, 1.0, 1.0 ],    // white
-                                            shape: [[0, 0, 1, 0],
-                                                    [0, 0, 1, 0],
-                                                    [0, 0, 1, 0],
-                                                    [0, 0, 1, 0]] },
-
-            TetriminoKind::J => Tetrimino { kind: TetriminoKind::J,
-                                            color: [ 0.0, 0.0, 1.0, 1.0 ],    // blue
-                                            shape: [[ 1, 0, 0, 0 ],
-                                                    [ 1, 1, 1, 0 ],
-                                                    [ 0, 0, 0, 0 ],
-                                                    [ 0, 0, 0, 0 ]] },
-
-            TetriminoKind::L => Tetrimino { kind: TetriminoKind::L,
-                                            color: [ 0.0, 1.0, 1.0, 1.0 ],    // cyan
-                                            shape: [[ 0, 0, 1, 0 ],
-                                                    [ 1, 1, 1, 0 ],
-                                                    [ 0, 0, 0, 0 ],
-                                                    [ 0, 0, 0, 0 ]] },
-
-            TetriminoKind::S => Tetrimino { kind: TetriminoKind::S,
-                                            color: [ 1.0, 0.0, 1.0, 1.0 ],    // magenta
-                                            shape: [[ 0, 1, 1, 0 ],
-                                                    [ 1, 1, 0, 0 ],
-                                                    [ 0, 0, 0, 0 ],
-                                                    [ 0, 0, 0, 0 ]] },
-
-            TetriminoKind::Z => Tetrimino { kind: TetriminoKind::Z,
-                                            color: [ 1.0, 0.0, 0.0, 1.0 ],    // red
-                                            shape: [[ 1, 1, 0, 0 ],
-                                                    [ 0, 1, 1, 0 ],
-                                                    [ 0, 0, 0, 0 ],
-                                                    [ 0, 0, 0, 0 ]] },
-
-            TetriminoKind::O => Tetrimino { kind: TetriminoKind::O,
-                                            color: [ 0.0, 1.0, 0.0, 1.0 ],    // green
-                                            shape: [[ 0, 0, 0, 0 ],
-                                                    [ 0, 0, 0, 0 ],
-                                                    [ 0, 1, 1, 0 ],
-                                                    [ 0, 1, 1, 0 ]] },
-
-            TetriminoKind::T => Tetrimino { kind: TetriminoKind::T,
-                                            color: [ 1.0, 1.0, 0.0, 1.0 ],    // yellow
-                                            shape: [[ 0, 1, 0, 0 ],
-                                                    [ 1, 1, 1, 0 ],
-                                                    [ 0, 0, 0, 0 ],
-                                                    [ 0, 0, 0, 0 ]] }
-        }
-    }
-}
+/// The keys a puzzle takes while it's being played, besides the game's own: undo, retry, and back to the list.
+const PUZZLE_KEYS: [Button; 3] = [ Button::Keyboard(Key::U), Button::Keyboard(Key::R), Button::Keyboard(Key::Backspace) ];
 
-// A Tetris playfield is known as a "Well".
-// It is composed of 24 rows, each of which is 10 columns wide.
-// Usually only the bottom 20 rows are fully visible.
-// If possible, a bit of row 21 should be shown also.
-// (https://tetris.fandom.com/wiki/Tetris_Guideline, and
-// https://en.wikipedia.org/wiki/Tetris)
-//
-// Our window is 1280 x 720, but let's only use 700 pixels, to leave some margin.
-// 700 pixels / 20 visible rows = 35, so each row will be 35 pixels tall.
-// The well is 10 rows wide * 35 pixels/row = 350 pixels in width.
 
-type Well = [[u8; 10]; 24];
+/// The board editor's brushes, in the order they're shown and the digit keys pick them: the eraser, the standard
+/// seven, and garbage.
+const BRUSHES: [u8; 9] = [ 0, TetriminoKind::STANDARD[0].cell(), TetriminoKind::STANDARD[1].cell(), TetriminoKind::STANDARD[2].cell(),
+                           TetriminoKind::STANDARD[3].cell(), TetriminoKind::STANDARD[4].cell(), TetriminoKind::STANDARD[5].cell(),
+                           TetriminoKind::STANDARD[6].cell(), GARBAGE ];
+const BRUSH_KEYS: [Key; 9] = [ Key::D0, Key::D1, Key::D2, Key::D3, Key::D4, Key::D5, Key::D6, Key::D7, Key::D8 ];
 
+/// Where the editor's brushes are: the first one's [x, y], and how far along each one after it is.
+const BRUSHES_AT: [f64; 2] = [60.0, 200.0];
+const BRUSH_SPACING: f64 = 40.0;
 
-struct GameState
-{
-    game_over: bool,
-    fall_counter: u32,
-    well: Well,
-    ttmo_bag: Vec<Tetrimino>,    // Randomized bag of all 7 tetriminos.
-    curr_ttmo: Tetrimino,
-    next_ttmo: Tetrimino,
-    ttmo_row: i32,        // Curr piece's location in the well.
-    ttmo_col: i32,
-    key_map: [bool; 6]    // MoveLeft, MoveRight, RotateCCW, RotateCW, SoftDrop, HardDrop
-}    
+
+/// The room the well gets, [x, y, width, height] in pixels: the usual well at SQUARE pixels a square. Versus has two
+/// wells, one either side of the window instead. Wells of other sizes get smaller squares if that's what it takes to
+/// fit, and sit in the middle at the bottom.
+const WELL_AREA: [f64; 4] = [465.0, 0.0, 350.0, 700.0];
+const VERSUS_WELL_AREA: [[f64; 4]; 2] = [[100.0, 0.0, 350.0, 700.0], [830.0, 0.0, 350.0, 700.0]];
+
+/// How big a square is, in pixels, when there's room.
+const SQUARE: f64 = 35.0;
+
+/// How solid the ghost piece is, under the falling one.
+const GHOST_ALPHA: f32 = 0.3;
+
+
+/// How often Piston wakes the main loop to see whether a game step is due. Well above any mode's rate,
+/// so a step never starts more than a few milliseconds late. (The game's own rate is up to the Timestep.)
+const EVENT_UPS: u64 = 240;
+
+
+/// Where the falling piece was before the latest step (row, column, and which piece it was), and how far
+/// real time has got toward the next step. Drawing the piece part way between makes it glide rather than jump.
+#[derive(Copy, Clone)]
+struct Glide { from: (i32, i32, u32), alpha: f64 }
+
+
+/// Where a well is drawn: its visible top-left corner, and the size of its squares.
+#[derive(Copy, Clone)]
+struct WellLayout { left: f64, top: f64, square: f64, hidden: i32 }
 
 
 //
@@ -149,41 +134,134 @@ struct GameState
 //
 fn main()
 {
+    let args: Vec<String> = std::env::args().collect();
+
+    // Settings come from the settings file, and then "--set name=value" on the command line, which isn't saved.
+    let mut settings = Settings::load().and_then(|mut settings| settings.override_from_args(&args).map(|_| settings))
+        .unwrap_or_else(|msg| {
+            eprintln!("Bad settings: {}", msg);
+            std::process::exit(2);
+        });
+
+    // Network versus: "tetris --host [PORT]" waits for someone to join, and "tetris --join ADDRESS:PORT" joins them.
+    // Either way, the game goes straight into the match. This happens before the window opens, since waiting would freeze it.
+    let arg_value = |name: &str| args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1));
+    let mut net = if args.iter().any(|arg| arg == "--host") {
+        let port = arg_value("--host").and_then(|port| port.parse().ok()).unwrap_or(DEFAULT_PORT);
+        let input_delay = arg_value("--input-delay").map(|delay| delay.parse().expect("--input-delay needs a number")).unwrap_or(DEFAULT_INPUT_DELAY);
+        let rules = arg_value("--versus-rules").map(|path| Rules::load(Path::new(path)).unwrap_or_else(|err| panic!("Can't read versus rules {}: {}", path, err)));
+
+        println!("Waiting for player 2 on port {}...", port);
+        Some(NetVersus::host(port, rules.unwrap_or_default(), input_delay).unwrap_or_else(|err| panic!("Can't host: {}", err)))
+    }
+    else if let Some(address) = arg_value("--join") {
+        println!("Joining {}...", address);
+        Some(NetVersus::join(address.as_str()).unwrap_or_else(|err| panic!("Can't join {}: {}", address, err)))
+    }
+    else { None };
+    let mut net_error: Option<String> = None;    // What ended a network match early.
+
     // Obviously we're going to need a window if we want to display anything.
     let mut window: PistonWindow =
-        WindowSettings::new("Rustris", [1280, 720])    // Window title, size.
+        WindowSettings::new("Rustris", [settings.visuals.width, settings.visuals.height])    // Window title, size.
         .exit_on_esc(true)
-        .vsync(true)
+        .vsync(settings.visuals.vsync)
         .build().unwrap();
 
-    // By default, Piston sends 120 update events per second. Lower that to 30/sec.
-    // (Yes, multiple renderings will happen between each update. Code accordingly!)
-    window.events.set_ups(30);
+    // Piston's update events just wake us up often enough. The game steps at its own fixed rate, 60/sec (a hair more
+    // in NES Marathon), however many update and render events happen in between. The speed setting changes how fast
+    // pieces fall, never how fast the game runs.
+    window.events.set_ups(EVENT_UPS);
+    let mut timestep = Timestep::new(f64::from(UPDATES_PER_SECOND));
+    let mut pending: TimedQueue<(Button, ButtonState)> = TimedQueue::new();    // Keys pressed during a game or match, waiting for their step.
+    let mut glide_from = (0, 0, 0);
 
     // Set up the music playing infrastructure. Will be started/repeated/stopped in main loop.
     let (_stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
     let music_sink = rodio::Sink::try_new(&stream_handle).unwrap();
-    music_sink.set_volume(0.1);    // Should really make the audio file quieter instead.
+    music_sink.set_volume(settings.audio.volume);
     // The sound plays in a separate audio thread, so the main thread needs to stay alive.
 
     // Actual state init.
     let mut blink_counter = 0;
 
-    let mut starter_bag = create_random_bag();
-    let starter_first_ttmo = starter_bag.pop().unwrap();
-    let starter_second_ttmo = starter_bag.pop().unwrap();
-
-    let mut game_state = GameState {
-        game_over: false,
-        fall_counter: 0,
-        well: [[0u8 ; 10]; 24],
-        ttmo_bag: starter_bag,
-        curr_ttmo: starter_first_ttmo,
-        next_ttmo: starter_second_ttmo,
-        ttmo_row: 2,
-        ttmo_col: 3,
-        key_map: [false; 6]
+    let mut visuals = Visuals { palette: settings.palette(), patterns: settings.visuals.patterns };
+
+    let mut screen = if net.is_some() { Screen::Versus } else { Screen::Title };
+    let mut menu_choice = 0;
+    let mut level_choice = 0;    // Starting level for NES Marathon.
+
+    // Puzzles: the built-in ones, then the puzzles folder's, or "tetris --puzzles DIR"'s instead.
+    let puzzle_dir = arg_value("--puzzles").map(PathBuf::from).or_else(puzzles_dir);
+    let mut puzzles = load_puzzles(puzzle_dir.as_deref());
+    let mut puzzle_choice = 0;
+    let mut undo: Option<Undo> = None;          // Pieces to take back, while playing a puzzle.
+    let mut puzzle_return = Screen::Puzzles;    // Where a puzzle goes back to: the list, or the editor it was made in.
+
+    // The board editor keeps its board between visits. The mouse paints it.
+    let mut editor = Editor::new(Well::default());
+    let mut editor_message = String::new();     // How the last save or play went.
+    let mut cursor = [0.0; 2];
+    let mut painting: Option<bool> = None;      // While a mouse button's down in the editor: whether it's rubbing out.
+
+    // Boards go to and from the clipboard as fumens. "tetris --fumen FUMEN" starts in the editor with one.
+    let mut clipboard = arboard::Clipboard::new().map_err(|err| eprintln!("No clipboard: {}", err)).ok();
+    if let (Some(text), None) = (arg_value("--fumen"), &net) {
+        match Editor::from_fumen(text) {
+            Ok(fumen_editor) => { editor = fumen_editor; screen = Screen::Editor; }
+            Err(err) => eprintln!("Can't read that fumen: {}", err)
+        }
+    }
+
+    let mut game_state = GameState::new();
+    let mut replay = Replay::new(&game_state);
+
+    // The high-score tables, and the top entry for the mode being played now (from before this game started).
+    let mut scores = HighScores::load();
+    let mut best: Option<ScoreEntry> = None;
+    let mut placed: Option<usize> = None;    // Where the game just finished went in its table.
+
+    // Who's playing: "tetris --name NAME", or whoever played last, or a new profile named after the login name.
+    // Their profile has their keys, and gets the stats of every game they play.
+    let mut profiles = load_profiles();
+    let wanted = arg_value("--name").map(|name| name.to_uppercase()).or_else(last_profile).unwrap_or_else(default_name);
+    if !valid_profile_name(&wanted) { panic!("Profile names are 1 to {} letters and digits, not '{}'", MAX_PROFILE_NAME, wanted); }
+    let mut profile = match profiles.iter().position(|profile| profile.name == wanted) {
+        Some(index) => index,
+        None => { profiles.push(new_profile(&wanted, &settings)); profiles.len() - 1 }
+    };
+    let mut bindings = key_bindings(&profiles[profile]);
+    let mut repeat = AutoRepeat::new(profiles[profile].das, profiles[profile].arr);
+    let mut versus_repeat = versus_repeaters(&profiles[profile]);    // Both players on this keyboard get this profile's DAS and ARR.
+    let mut tally = Tally::default();
+    let mut new_name = String::new();    // Being typed in on the new profile screen.
+
+    // The options menu edits a copy of the settings, which only replaces them when saved.
+    let mut editing = settings.clone();
+    let mut option_choice = 0;
+
+    // The high-score browser: which mode's table, and which entry in it.
+    let mut score_page = 0;
+    let mut score_choice = 0;
+    let mut watching: Option<ReplayInput> = None;    // Playing back a high score's replay.
+
+    // Demo mode: the AI plays instead of the keyboard. Toggled with A, anywhere but in a puzzle.
+    // Start with "tetris --ai-weights FILE" to watch a particular AI profile play.
+    let ai_weights = match args.iter().position(|arg| arg == "--ai-weights").and_then(|i| args.get(i + 1)) {
+        Some(path) => Weights::load(Path::new(path)).unwrap_or_else(|err| panic!("Can't read AI profile {}: {}", path, err)),
+        None => Weights::default()
+    };
+    let mut demo: Option<AiBot> = None;
+    let mut hint: Option<Hint> = None;    // The perfect clear hint, while it's on. Toggled with H.
+    let mut hinted = false;               // Whether the hint's been on at all this game. It sees past the preview, so those games don't count.
+    let mut demoed = false;               // Whether the AI's played any of this game. Nor do those.
+
+    // Two players, one keyboard. Start with "tetris --versus-rules FILE" to change the attack table or garbage delay.
+    let versus_rules = match args.iter().position(|arg| arg == "--versus-rules").and_then(|i| args.get(i + 1)) {
+        Some(path) => Rules::load(Path::new(path)).unwrap_or_else(|err| panic!("Can't read versus rules {}: {}", path, err)),
+        None => Rules::default()
     };
+    let mut versus = Versus::new(versus_rules.clone());
 
     // *****
     // ***** MAIN LOOP
@@ -194,54 +272,506 @@ fn main()
         {
             // Because vsync is on, render events should happen every screen refresh. (Usually 60 times per second.)
             Event::Loop(Loop::Render(_args_not_used)) => {
-                render(&mut window, &event,
-                       &game_state.ttmo_row, &game_state.ttmo_col, &game_state.curr_ttmo,
-                       &game_state.next_ttmo, &mut game_state.well);
+                match screen {
+                    Screen::Title       => draw_title(&mut window, &event, menu_choice, &scores, &profiles[profile], &puzzles),
+                    Screen::LevelSelect => draw_level_select(&mut window, &event, level_choice),
+                    Screen::Puzzles     => draw_puzzles(&mut window, &event, &puzzles, &profiles[profile], puzzle_choice),
+                    Screen::Editor      => draw_editor(&mut window, &event, &editor, &visuals, &editor_message),
+                    Screen::Playing     => {
+                        let glide = if settings.visuals.smooth { Some(Glide { from: glide_from, alpha: timestep.alpha(Instant::now()) }) } else { None };
+                        let standing = Standing { best: best.as_ref(), table: scores.table(game_state.mode), placed };
+                        render(&mut window, &event, &game_state, &visuals, glide, standing, hint.as_ref())
+                    }
+                    Screen::Scores      => draw_scores(&mut window, &event, &scores, &score_pages(&scores), score_page, score_choice),
+                    Screen::Profile     => draw_profile(&mut window, &event, &profiles[profile]),
+                    Screen::NewProfile  => draw_new_profile(&mut window, &event, &new_name),
+                    Screen::Options     => draw_options(&mut window, &event, &editing, &settings, option_choice),
+                    Screen::Versus      => match &net {
+                        Some(net) => draw_versus(&mut window, &event, &net.versus, &visuals, Some(net.local), net_error.as_deref()),
+                        None      => draw_versus(&mut window, &event, &versus, &visuals, None, None)
+                    }
+                }
             }
 
-            // Update events are received here. Update the game state accordingly.
-            Event::Loop(Loop::Update(_args_also_not_used)) =>
+            // Update events are received here. Run however many game steps are due, each with the keys pressed during it.
+            Event::Loop(Loop::Update(_args_also_not_used)) if screen == Screen::Playing =>
             {
-                if game_state.game_over
-                {                    
-                    if blink_counter == 15 {
-                        game_state.well = [[0u8; 10]; 24];
+                while let Some(step_end) = timestep.next_step(Instant::now())
+                {
+                    for (button, state) in pending.take_before(step_end) {
+                        if let Some(action) = bound_action(&bindings, button) {
+                            if state == ButtonState::Press { repeat.press(&mut game_state.inputs, action); } else { repeat.release(action); }
+                        }
                     }
-                    if blink_counter == 30 {
-                        game_state.well = [[1u8; 10]; 24];
-                        blink_counter = 0;
+
+                    if game_state.game_over.is_some()
+                    {
+                        // Only a top out gets the blinking. A finished Sprint or Ultra leaves the well alone, for admiring.
+                        if game_state.game_over == Some(GameOver::TopOut)
+                        {
+                            if blink_counter == UPDATES_PER_SECOND / 2 {
+                                game_state.well.fill(0);
+                            }
+                            if blink_counter == UPDATES_PER_SECOND {
+                                game_state.well.fill(1);
+                                blink_counter = 0;
+
+                                // Attract mode never ends. Blink once, then the AI starts over.
+                                if demo.is_some() {
+                                    game_state = GameState::with_mode(game_state.mode);
+                                    settings.apply_rules(&mut game_state);
+                                    demo = Some(AiBot::new(ai_weights, true));
+                                }
+                            }
+                            blink_counter += 1;
+                        }
+                    }
+                    else {
+
+                        // In demo mode, the AI's keys replace whatever was pressed. Likewise a replay's.
+                        if let Some(bot) = demo.as_mut() { game_state.inputs = bot.next_keys(&game_state); }
+                        if let Some(watch) = watching.as_mut() {
+                            game_state.inputs = watch.next_keys(&game_state);
+                            game_state.repeats = watch.repeats();
+                        }
+                        if demo.is_none() && watching.is_none() { repeat.update(&mut game_state); }
+
+                        replay.record(game_state.frames, &game_state.inputs, game_state.repeats);
+                        glide_from = (game_state.ttmo_row, game_state.ttmo_col, game_state.pieces);
+                        game_update(&mut game_state);
+                        tally.watch(&game_state);
+                        if let Some(undo) = undo.as_mut() { undo.watch(&game_state); }
+
+                        if game_state.game_over.is_some() {
+                            music_sink.stop();
+
+                            // A puzzle isn't a game as far as profiles and high scores go. Solving it just gets it ticked off.
+                            // (Boards straight from the editor aren't on the list to be ticked off.)
+                            if let Some(puzzle) = game_state.puzzle.as_ref() {
+                                let listed = puzzles.iter().any(|listed| Arc::ptr_eq(listed, puzzle));
+                                if listed && game_state.game_over == Some(GameOver::GoalReached) && profiles[profile].mark_solved(&puzzle.name) {
+                                    if let Err(err) = profiles[profile].save() { eprintln!("Couldn't save profile: {}", err); }
+                                }
+                            }
+
+                            // Played it all by yourself? It goes in your profile, and it might be a high score.
+                            else if demo.is_none() && watching.is_none() && !hinted && !demoed {
+                                profiles[profile].record_game(&game_state, &tally);
+                                if let Err(err) = profiles[profile].save() { eprintln!("Couldn't save profile: {}", err); }
+
+                                match scores.add(&game_state, &replay, &profiles[profile].name) {
+                                    Ok(place) => placed = place,
+                                    Err(err) => eprintln!("Couldn't save high score: {}", err)
+                                }
+                            }
+                        }
+                        else if settings.audio.music { keep_music_playing(&music_sink); }
                     }
-                    blink_counter += 1;
                 }
-                else {
-
-                    game_update(&mut game_state);
-
-                    if game_state.game_over {
-                       music_sink.stop();
-                    } else {
-                        if music_sink.empty() {
-                           let music_file = File::open("NESTetrisMusic3.ogg").unwrap();    // Path relative to Cargo.toml
-                           let music_source = rodio::Decoder::new(BufReader::new(music_file)).unwrap();
-                           music_sink.append(music_source);
-                           music_sink.play();
-                       }
-                   }
+
+                if let Some(hint) = hint.as_mut() { hint.update(&game_state); }
+            }
+
+            // Versus keys go in at the step they were pressed during too, each player's through their own auto repeat.
+            Event::Loop(Loop::Update(_args_also_not_used)) if screen == Screen::Versus =>
+            {
+                while net_error.is_none()
+                {
+                    let step_end = match timestep.next_step(Instant::now()) { Some(step_end) => step_end, None => break };
+
+                    // Over the network there's only one player on this side, so either set of versus keys will do.
+                    for (button, state) in pending.take_before(step_end) {
+                        let (player, action) = match versus_action(button) { Some(pressed) => pressed, None => continue };
+                        let (player, inputs) = match net.as_mut() {
+                            Some(net) => (net.local, &mut net.local_keys),
+                            None      => (player, &mut versus.players[player].inputs)
+                        };
+                        if state == ButtonState::Press { versus_repeat[player].press(inputs, action); } else { versus_repeat[player].release(action); }
+                    }
+                    match net.as_mut() {
+                        Some(net) => { versus_repeat[net.local].update_keys(&mut net.local_keys); }
+                        None      => for (player, game_state) in versus.players.iter_mut().enumerate() { versus_repeat[player].update(game_state); }
+                    }
+
+                    // Over the network, the match only moves on once the other side's keys have arrived.
+                    let (outcome_before, outcome) = match net.as_mut() {
+                        Some(net) => {
+                            let before = net.versus.outcome;
+                            if let Err(err) = net.step() {
+                                net_error = Some(err.to_string());
+                                music_sink.stop();
+                                break;
+                            }
+                            (before, net.versus.outcome)
+                        }
+                        None => {
+                            let before = versus.outcome;
+                            versus.update();
+                            (before, versus.outcome)
+                        }
+                    };
+
+                    if outcome.is_none() && settings.audio.music { keep_music_playing(&music_sink); }
+                    else if outcome_before.is_none() { music_sink.stop(); }
                 }
             }
 
             // Keyboard press/release events.
-            Event::Input(Input::Button(button_args), _time_stamp) =>
-            {                
-                if button_args.state == ButtonState::Press {    // We only care about presses, not releases (or others?!).
-                    track_keys(&mut game_state.key_map, button_args);
+            // We only care about presses, not releases (or others?!).
+            Event::Input(Input::Button(button_args), _time_stamp) if button_args.state == ButtonState::Press =>
+            {
+                let pressed_at = Instant::now();    // Piston's own time stamps are often missing, so make one.
+                if screen != Screen::NewProfile { track_visual_keys(&mut visuals, button_args); }
+                let mut start: Option<Mode> = None;
+                let mut chosen: Option<Arc<Puzzle>> = None;    // The puzzle to start, when it's Mode::Puzzle.
+
+                match screen
+                {
+                    Screen::Title => match button_args.button
+                    {
+                        Button::Keyboard(Key::Up)   => menu_choice = (menu_choice + MENU.len() - 1) % MENU.len(),
+                        Button::Keyboard(Key::Down) => menu_choice = (menu_choice + 1) % MENU.len(),
+
+                        Button::Keyboard(Key::H) => {
+                            score_page = 0;
+                            score_choice = 0;
+                            screen = Screen::Scores;
+                        }
+
+                        // Left/Right picks a profile, N makes a new one, and P shows the one picked.
+                        Button::Keyboard(Key::Left) | Button::Keyboard(Key::Right) => {
+                            profile = if button_args.button == Button::Keyboard(Key::Left) { (profile + profiles.len() - 1) % profiles.len() }
+                                      else { (profile + 1) % profiles.len() };
+                            bindings = key_bindings(&profiles[profile]);
+                            repeat = AutoRepeat::new(profiles[profile].das, profiles[profile].arr);
+                            if let Err(err) = set_last_profile(&profiles[profile].name) { eprintln!("Couldn't remember the profile: {}", err); }
+                        }
+                        Button::Keyboard(Key::N) => {
+                            new_name.clear();
+                            screen = Screen::NewProfile;
+                        }
+                        Button::Keyboard(Key::P) => screen = Screen::Profile,
+                        Button::Keyboard(Key::E) => {
+                            editor_message.clear();
+                            screen = Screen::Editor;
+                        }
+                        Button::Keyboard(Key::O) => {
+                            editing = settings.clone();
+                            option_choice = 0;
+                            screen = Screen::Options;
+                        }
+
+                        // NES Marathon asks for a starting level first.
+                        Button::Keyboard(Key::Return) | Button::Keyboard(Key::Space) => match MENU[menu_choice] {
+                            Mode::Marathon { .. } => screen = Screen::LevelSelect,
+                            Mode::Puzzle => screen = Screen::Puzzles,
+                            Mode::Versus => {
+                                versus = Versus::new(versus_rules.clone());
+                                versus_repeat = versus_repeaters(&profiles[profile]);
+                                pending.clear();
+                                timestep.set_rate(f64::from(UPDATES_PER_SECOND));
+                                screen = Screen::Versus;
+                            }
+                            mode => start = Some(mode)
+                        },
+                        _ => ()
+                    },
+
+                    // Levels are laid out like the NES's: 0-9 on top, 10-19 underneath.
+                    Screen::LevelSelect => match button_args.button
+                    {
+                        Button::Keyboard(Key::Left)  => level_choice = (level_choice + 19) % 20,
+                        Button::Keyboard(Key::Right) => level_choice = (level_choice + 1) % 20,
+                        Button::Keyboard(Key::Up) | Button::Keyboard(Key::Down) => level_choice = (level_choice + 10) % 20,
+                        Button::Keyboard(Key::Backspace) => screen = Screen::Title,
+
+                        Button::Keyboard(Key::Return) | Button::Keyboard(Key::Space) => start = Some(Mode::Marathon { start_level: level_choice }),
+                        _ => ()
+                    },
+
+                    // Up/Down picks a puzzle, Enter plays it, E opens it in the editor.
+                    Screen::Puzzles => match button_args.button
+                    {
+                        Button::Keyboard(Key::Up)   => puzzle_choice = (puzzle_choice + puzzles.len() - 1) % puzzles.len(),
+                        Button::Keyboard(Key::Down) => puzzle_choice = (puzzle_choice + 1) % puzzles.len(),
+                        Button::Keyboard(Key::Backspace) => screen = Screen::Title,
+                        Button::Keyboard(Key::E) => {
+                            editor = Editor::from_puzzle(&puzzles[puzzle_choice]);
+                            editor_message.clear();
+                            screen = Screen::Editor;
+                        }
+
+                        Button::Keyboard(Key::Return) | Button::Keyboard(Key::Space) => {
+                            chosen = Some(Arc::clone(&puzzles[puzzle_choice]));
+                            puzzle_return = Screen::Puzzles;
+                            start = Some(Mode::Puzzle);
+                        }
+                        _ => ()
+                    },
+
+                    // Digits pick the brush. Q puts the brush's piece on the end of the list, X takes the last one off, H
+                    // puts it in the hold (or, with garbage or the eraser, empties the hold), and Left/Right picks the goal. Enter plays the board, S saves it, Delete starts a new one. B copies it as
+                    // a fumen and V pastes one.
+                    Screen::Editor => match button_args.button
+                    {
+                        Button::Keyboard(key) if BRUSH_KEYS.contains(&key) => editor.brush = BRUSHES[BRUSH_KEYS.iter().position(|brush| *brush == key).unwrap()],
+                        Button::Keyboard(Key::Q) => editor.add_piece(),
+                        Button::Keyboard(Key::X) => { editor.pieces.pop(); }
+                        Button::Keyboard(Key::H) => editor.set_hold(),
+                        Button::Keyboard(Key::Left)  => editor.change_goal(false),
+                        Button::Keyboard(Key::Right) => editor.change_goal(true),
+                        Button::Keyboard(Key::Delete) => editor.clear(),
+                        Button::Keyboard(Key::Backspace) => screen = Screen::Title,
+
+                        Button::Keyboard(Key::B) => editor_message = match editor.to_fumen() {
+                            Ok(text) => if copy_text(&mut clipboard, text) { "COPIED AS A FUMEN".to_string() } else { "NO CLIPBOARD".to_string() },
+                            Err(err) => err.to_uppercase()
+                        },
+                        Button::Keyboard(Key::V) => editor_message = match clipboard.as_mut().map(|clipboard| clipboard.get_text()) {
+                            Some(Ok(text)) => match Editor::from_fumen(&text) {
+                                Ok(pasted) => { editor = pasted; "PASTED".to_string() }
+                                Err(err) => err.to_uppercase()
+                            },
+                            Some(Err(_)) => "NOTHING TO PASTE".to_string(),
+                            None => "NO CLIPBOARD".to_string()
+                        },
+
+                        Button::Keyboard(Key::S) => editor_message = match puzzle_dir.as_deref() {
+                            Some(dir) => match editor.save(dir, &puzzles) {
+                                Ok(name) => {
+                                    puzzles = load_puzzles(Some(dir));
+                                    puzzle_choice = puzzle_choice.min(puzzles.len() - 1);
+                                    format!("SAVED AS {}", name.to_uppercase())
+                                }
+                                Err(err) => err.to_uppercase()
+                            },
+                            None => "NOWHERE TO SAVE ON THIS PLATFORM".to_string()
+                        },
+                        Button::Keyboard(Key::Return) => match editor.to_playable() {
+                            Ok(puzzle) => {
+                                chosen = Some(Arc::new(puzzle));
+                                puzzle_return = Screen::Editor;
+                                editor_message.clear();
+                                start = Some(Mode::Puzzle);
+                            }
+                            Err(err) => editor_message = err.to_uppercase()
+                        },
+
+                        // A click on a brush picks it. Anywhere else, the left button paints and the others rub out.
+                        Button::Mouse(button) => match brush_at(cursor) {
+                            Some(index) => editor.brush = BRUSHES[index],
+                            None => {
+                                painting = Some(button != MouseButton::Left);
+                                paint_at(&mut editor, cursor, button != MouseButton::Left);
+                            }
+                        },
+                        _ => ()
+                    },
+
+                    Screen::Profile => if let Button::Keyboard(Key::Backspace) | Button::Keyboard(Key::Return) = button_args.button { screen = Screen::Title; },
+
+                    // Letters and digits arrive as text events. Enter makes the profile, or with no name, goes back.
+                    Screen::NewProfile => match button_args.button
+                    {
+                        Button::Keyboard(Key::Backspace) => { new_name.pop(); }
+                        Button::Keyboard(Key::Return) => {
+                            if !new_name.is_empty() {
+                                profile = match profiles.iter().position(|existing| existing.name == new_name) {
+                                    Some(index) => index,
+                                    None => {
+                                        profiles.push(new_profile(&new_name, &settings));
+                                        if let Err(err) = profiles.last().unwrap().save() { eprintln!("Couldn't save profile: {}", err); }
+                                        profiles.len() - 1
+                                    }
+                                };
+                                bindings = key_bindings(&profiles[profile]);
+                                repeat = AutoRepeat::new(profiles[profile].das, profiles[profile].arr);
+                                if let Err(err) = set_last_profile(&profiles[profile].name) { eprintln!("Couldn't remember the profile: {}", err); }
+                            }
+                            screen = Screen::Title;
+                        }
+                        _ => ()
+                    },
+
+                    // Up/Down picks a setting, Left/Right changes it. Enter saves them all, Backspace forgets the changes.
+                    Screen::Options => match button_args.button
+                    {
+                        Button::Keyboard(Key::Up)    => option_choice = (option_choice + SETTING_NAMES.len() - 1) % SETTING_NAMES.len(),
+                        Button::Keyboard(Key::Down)  => option_choice = (option_choice + 1) % SETTING_NAMES.len(),
+                        Button::Keyboard(Key::Left)  => editing.step(SETTING_NAMES[option_choice], false),
+                        Button::Keyboard(Key::Right) => editing.step(SETTING_NAMES[option_choice], true),
+                        Button::Keyboard(Key::Backspace) => screen = Screen::Title,
+
+                        Button::Keyboard(Key::Return) => {
+                            settings = editing.clone();
+                            if let Err(err) = settings.save() { eprintln!("Couldn't save settings: {}", err); }
+
+                            // Everything but the window takes effect straight away.
+                            music_sink.set_volume(settings.audio.volume);
+                            if !settings.audio.music { music_sink.stop(); }
+                            visuals = Visuals { palette: settings.palette(), patterns: settings.visuals.patterns };
+                            screen = Screen::Title;
+                        }
+                        _ => ()
+                    },
+
+                    // Up/Down picks an entry, Left/Right a mode. Enter watches the entry's replay.
+                    Screen::Scores => {
+                        let pages = score_pages(&scores);
+                        let entries = scores.table(pages[score_page]).len();
+                        match button_args.button
+                        {
+                            Button::Keyboard(Key::Left)  => { score_page = (score_page + pages.len() - 1) % pages.len(); score_choice = 0; }
+                            Button::Keyboard(Key::Right) => { score_page = (score_page + 1) % pages.len(); score_choice = 0; }
+                            Button::Keyboard(Key::Up)    => score_choice = score_choice.saturating_sub(1),
+                            Button::Keyboard(Key::Down)  => score_choice = (score_choice + 1).min(entries.saturating_sub(1)),
+                            Button::Keyboard(Key::Backspace) => screen = Screen::Title,
+
+                            Button::Keyboard(Key::Return) | Button::Keyboard(Key::Space) => {
+                                let file = scores.table(pages[score_page]).get(score_choice).and_then(|entry| entry.replay.clone());
+                                match file.and_then(|file| data_dir().map(|dir| dir.join("replays").join(file))).map(|path| Replay::load(&path)) {
+                                    Some(Ok(recorded)) => {
+                                        game_state = recorded.new_game();
+                                        best = scores.best(game_state.mode).cloned();
+                                        placed = None;
+                                        demo = None;
+                                        blink_counter = 0;
+                                        timestep.set_rate(recorded.mode.updates_per_second());
+                                        undo = None;
+                                        watching = Some(ReplayInput::new(recorded));
+                                        screen = Screen::Playing;
+                                    }
+                                    Some(Err(err)) => eprintln!("Can't play that replay: {}", err),
+                                    None => ()
+                                }
+                            }
+                            _ => ()
+                        }
+                    }
+
+                    // A puzzle can be taken back a piece at a time (U) or started again (R), over or not. Backspace, or
+                    // Enter once it's over, goes back to the list, or the editor. Unless the profile plays with those keys.
+                    Screen::Playing if undo.is_some() && (game_state.game_over.is_some()
+                        || (PUZZLE_KEYS.contains(&button_args.button) && bound_action(&bindings, button_args.button).is_none())) => {
+                        let undo = undo.as_mut().unwrap();
+                        match button_args.button
+                        {
+                            Button::Keyboard(Key::U) => game_state = undo.undo(&game_state),
+                            Button::Keyboard(Key::R) => game_state = undo.retry(),
+                            Button::Keyboard(Key::Backspace) | Button::Keyboard(Key::Return) => screen = puzzle_return,
+                            _ => ()
+                        }
+                        repeat.clear();
+                        pending.clear();
+                        blink_counter = 0;
+                        glide_from = (game_state.ttmo_row, game_state.ttmo_col, game_state.pieces);
+                    }
+
+                    // Once the game is over, Enter goes back to the title screen. Or to the high scores, after watching one.
+                    Screen::Playing if game_state.game_over.is_some() && demo.is_none() => {
+                        if button_args.button == Button::Keyboard(Key::Return) {
+                            screen = if watching.take().is_some() { Screen::Scores } else { Screen::Title };
+                        }
+                    }
+
+                    // Hands off a replay. Backspace stops watching it.
+                    Screen::Playing if watching.is_some() => {
+                        if button_args.button == Button::Keyboard(Key::Backspace) {
+                            watching = None;
+                            screen = Screen::Scores;
+                        }
+                    }
+                    // Leaving a network match hangs up, and the rest is local play.
+                    Screen::Versus if versus_over(&versus, &net, &net_error) => {
+                        if button_args.button == Button::Keyboard(Key::Return) {
+                            net = None;
+                            net_error = None;
+                            screen = Screen::Title;
+                        }
+                    }
+
+                    Screen::Versus => pending.push(pressed_at, (button_args.button, ButtonState::Press)),
+
+                    // Game keys go in at the step they were pressed during, not whenever the next one happens to run.
+                    Screen::Playing => {
+                        pending.push(pressed_at, (button_args.button, ButtonState::Press));
+
+                        // The hotkeys below are only hotkeys while the profile hasn't bound them to something else.
+                        let hotkey = |key: Key| button_args.button == Button::Keyboard(key) && bound_action(&bindings, button_args.button).is_none();
+                        if hotkey(Key::A) && undo.is_none() {
+                            demo = if demo.is_some() { None } else { Some(AiBot::new(ai_weights, true)) };
+                            demoed |= demo.is_some();
+                        }
+                        if hotkey(Key::P) {
+                            game_state.finesse_practice = !game_state.finesse_practice;    // Finesse practice mode
+                        }
+                        if hotkey(Key::H) {
+                            hint = if hint.is_some() { None } else { Some(Hint::start(&game_state)) };
+                            hinted |= hint.is_some();
+                        }
+                        if hotkey(Key::B) {
+                            match encode_game(&game_state) {
+                                Ok(text) => { copy_text(&mut clipboard, text); }
+                                Err(err) => eprintln!("Can't copy the board: {}", err)
+                            }
+                        }
+                    }
+                }
+
+                if let Some(mode) = start
+                {
+                    // Puzzles come with their own well and pieces, so the settings' rules don't apply.
+                    game_state = GameState::with_mode(mode);
+                    if let Some(puzzle) = chosen {
+                        start_puzzle(&mut game_state, puzzle);
+                        undo = Some(Undo::new(&game_state));
+                    }
+                    else {
+                        settings.apply_rules(&mut game_state);
+                        undo = None;
+                    }
+                    replay = Replay::new(&game_state);
+                    best = scores.best(mode).cloned();
+                    placed = None;
+                    demo = None;
+                    watching = None;
+                    if hint.is_some() { hint = Some(Hint::start(&game_state)); }    // The last one's for the last game.
+                    hinted = hint.is_some();
+                    demoed = false;
+                    tally = Tally::default();
+                    repeat.clear();
+                    pending.clear();
+                    blink_counter = 0;
+                    glide_from = (game_state.ttmo_row, game_state.ttmo_col, game_state.pieces);
+                    screen = Screen::Playing;
+
+                    // NES Marathon gets its exact 60.0988, which Piston's whole updates per second couldn't do.
+                    timestep.set_rate(mode.updates_per_second());
                 }
             }
 
+            // Letting go of a key stops it repeating. In a game or match, from the step it was let go during.
+            Event::Input(Input::Button(button_args), _time_stamp) if button_args.state == ButtonState::Release => {
+                painting = None;
+                if screen == Screen::Playing || screen == Screen::Versus { pending.push(Instant::now(), (button_args.button, ButtonState::Release)); }
+                else if let Some(action) = bound_action(&bindings, button_args.button) { repeat.release(action); }
+            }
+
+            // Typing a new profile's name.
+            Event::Input(Input::Text(text), _time_stamp) if screen == Screen::NewProfile => {
+                new_name.extend(text.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()));
+                new_name.truncate(MAX_PROFILE_NAME);
+            }
+
+            // Dragging the mouse in the editor with a button down paints everything it passes over.
+            Event::Input(Input::Move(Motion::MouseCursor(position)), _time_stamp) => {
+                cursor = position;
+                if let (Screen::Editor, Some(erase)) = (screen, painting) { paint_at(&mut editor, cursor, erase); }
+            }
+
             // Rust forces you to consider all possible Event types. This "discard all other events" clause satisfies that requirement.
             _ => {
                 // println!("Other event: {:?}", event);    // Super spammy!
-                ()
             }
         }    // match
     }    // while
@@ -250,299 +780,1133 @@ fn main()
 
 
 
-/// Sets bool flags in key_map when a key event notifies us that a key has been pressed.
-fn track_keys(key_map: &mut [bool; 6], btn_info: ButtonArgs)
+/// The keys a profile binds to each action, in Action::ALL order: MoveLeft, MoveRight, RotateCCW, RotateCW, SoftDrop, HardDrop, Hold.
+/// Names Piston doesn't know are left out, with a warning.
+fn key_bindings(profile: &Profile) -> [Vec<Key>; Action::ALL.len()]
+{
+    let mut bindings: [Vec<Key>; Action::ALL.len()] = Default::default();
+    for (action, names) in profile.keys.iter().enumerate() {
+        for name in names.iter() {
+            match key_from_name(name) {
+                Some(key) => bindings[action].push(key),
+                None => eprintln!("Profile {} binds a key called '{}', which isn't one.", profile.name, name)
+            }
+        }
+    }
+    bindings
+}
+
+
+/// Looks a key up by its Piston name, like "Left", "D" or "Space".
+fn key_from_name(name: &str) -> Option<Key>
+{
+    // Piston numbers keys like SDL does: ASCII for the printable ones, and from 0x40000000 up for the rest.
+    (0..0x80).chain(0x4000_0000..0x4000_0120).map(Key::from).find(|key| *key != Key::Unknown && format!("{:?}", key) == name)
+}
+
+
+/// A new profile, with the handling from the settings.
+fn new_profile(name: &str, settings: &Settings) -> Profile
+{
+    let mut profile = Profile::new(name);
+    profile.das = settings.handling.das;
+    profile.arr = settings.handling.arr;
+    profile
+}
+
+
+/// Which action, if any, a button is bound to.
+fn bound_action(bindings: &[Vec<Key>; Action::ALL.len()], button: Button) -> Option<Action>
 {
-    match btn_info.button    // We only care about a few keys, all others are ignored.
+    match button {
+        Button::Keyboard(key) => bindings.iter().position(|keys| keys.contains(&key)).map(|index| Action::ALL[index]),
+        _ => None
+    }
+}
+
+
+/// Versus key bindings. Player 1 is on the left of the keyboard, player 2 on the right.
+const VERSUS_KEYS: [[Key; Action::ALL.len()]; 2] = [
+    //  MoveLeft    MoveRight    RotateCCW     RotateCW   SoftDrop    HardDrop     Hold
+    [ Key::A,    Key::D,     Key::Q,      Key::W,   Key::S,    Key::Space,  Key::E      ],
+    [ Key::Left, Key::Right, Key::Slash,  Key::Up,  Key::Down, Key::Return, Key::RShift ]
+];
+
+
+/// Which player's versus key a button is, if it's one, and the action it's for.
+fn versus_action(button: Button) -> Option<(usize, Action)>
+{
+    VERSUS_KEYS.iter().enumerate().find_map(|(player, keys)| {
+        keys.iter().position(|key| button == Button::Keyboard(*key)).map(|index| (player, Action::ALL[index]))
+    })
+}
+
+
+/// Auto repeat for both versus players.
+fn versus_repeaters(profile: &Profile) -> [AutoRepeat; 2]
+{
+    [AutoRepeat::new(profile.das, profile.arr), AutoRepeat::new(profile.das, profile.arr)]
+}
+
+
+/// Whether the versus match on screen, local or over the network, has finished one way or another.
+fn versus_over(versus: &Versus, net: &Option<NetVersus>, net_error: &Option<String>) -> bool
+{
+    match net {
+        Some(net) => net.versus.outcome.is_some() || net_error.is_some(),
+        None      => versus.outcome.is_some()
+    }
+}
+
+
+/// Starts the music again whenever it runs out.
+fn keep_music_playing(music_sink: &rodio::Sink)
+{
+    if music_sink.empty() {
+        let music_file = File::open("NESTetrisMusic3.ogg").unwrap();    // Path relative to Cargo.toml
+        let music_source = rodio::Decoder::new(BufReader::new(music_file)).unwrap();
+        music_sink.append(music_source);
+        music_sink.play();
+    }
+}
+
+
+/// Puts text on the clipboard. Without one, or if that doesn't work, the text's printed instead, to copy from there.
+fn copy_text(clipboard: &mut Option<arboard::Clipboard>, text: String) -> bool
+{
+    match clipboard.as_mut().map(|clipboard| clipboard.set_text(text.clone())) {
+        Some(Ok(())) => true,
+        _ => { println!("{}", text); false }
+    }
+}
+
+
+/// Handles the keys that change how things look. These work even after the game is over.
+fn track_visual_keys(visuals: &mut Visuals, btn_info: ButtonArgs)
+{
+    match btn_info.button
     {
-        Button::Keyboard(Key::Left)  => key_map[0] = true,    // MoveLeft
-        Button::Keyboard(Key::Right) => key_map[1] = true,    // MoveRight
-        Button::Keyboard(Key::Up)    => key_map[2] = true,    // RotateCCW
-        Button::Keyboard(Key::D)     => key_map[2] = true,    // RotateCCW
-        Button::Keyboard(Key::F)     => key_map[3] = true,    // RotateCW
-        Button::Keyboard(Key::Down)  => key_map[4] = true,    // SoftDrop
-        Button::Keyboard(Key::Space) => key_map[5] = true,    // HardDrop
-        _ => ()                                               // Ignore all others
+        Button::Keyboard(Key::C) => visuals.palette = visuals.palette.next(),    // Cycle color palettes
+        Button::Keyboard(Key::G) => visuals.patterns = !visuals.patterns,        // Toggle glyph overlays
+        _ => ()
     }
 }
 
 
-/// Implements the main logic of the game. Pieces fall, full rows disappear, etc.
-fn game_update(game_state: &mut GameState)
+
+/// The perfect clear hint: a way to clear the well completely from the piece falling now, worked out on another thread so
+/// the game carries on while it looks. It's worked out again for each new piece, once the last search is done.
+struct Hint
 {
-    // Pieces fall fairly slowly: 30 ups per sec / 20 ups per fall = 0.66 (repeating, of course) secs per fall.
+    piece: (u32, bool),                // Which piece it's for: GameState::pieces and hold_used when the search started.
+    solution: Option<Solution>,        // None while it's still looking.
+    working: Option<Receiver<Solution>>
+}
+
+
+impl Hint
+{
+    fn start(game_state: &GameState) -> Self
+    {
+        let problem = Problem::from_game(game_state, DEFAULT_LINES);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || { let _ = sender.send(problem.solve()); });
+        Hint { piece: (game_state.pieces, game_state.hold_used), solution: None, working: Some(receiver) }
+    }
 
-    if game_state.fall_counter < 20 {
-        game_state.fall_counter += 1;    // Not time to fall yet...
+    /// Picks up the solution once it's ready, and starts looking again when there's a new piece.
+    fn update(&mut self, game_state: &GameState)
+    {
+        if let Some(Ok(solution)) = self.working.as_ref().map(|working| working.try_recv()) {
+            self.solution = Some(solution);
+            self.working = None;
+        }
+        if self.working.is_none() && !self.is_for(game_state) { *self = Hint::start(game_state); }
     }
-    else    // Time to fall!
+
+    /// Whether it's for the piece falling now. Holding brings on another piece, without one locking.
+    fn is_for(&self, game_state: &GameState) -> bool
     {
-        game_state.fall_counter = 0;
+        self.piece == (game_state.pieces, game_state.hold_used)
+    }
+}
 
-        if would_collide(&game_state.curr_ttmo, &game_state.well, &(game_state.ttmo_row + 1), &game_state.ttmo_col)
-        {
-            freeze_to_well(&game_state.curr_ttmo, &mut game_state.well, &game_state.ttmo_row, &game_state.ttmo_col);
-            game_state.well = clear_complete_rows(game_state.well);
 
-            if game_state.ttmo_bag.is_empty() { game_state.ttmo_bag = create_random_bag(); }
-            game_state.curr_ttmo = game_state.next_ttmo;
-            game_state.next_ttmo = game_state.ttmo_bag.pop().unwrap();
+/// How the game on screen stands against its mode's high scores: the best from before it started,
+/// the table as it is now, and where the game went in it once it's over.
+struct Standing<'a> { best: Option<&'a ScoreEntry>, table: &'a [ScoreEntry], placed: Option<usize> }
 
-            game_state.ttmo_row = 2;    // Place near top...
-            game_state.ttmo_col = 3;    // ...and near center.
 
-            // THAT'S IT, MAN! GAME OVER, MAN!!
-            if would_collide(&game_state.curr_ttmo, &game_state.well, &game_state.ttmo_row, &game_state.ttmo_col)
-            {
-                game_state.game_over = true;
+fn render(win: &mut PistonWindow, re: &Event, game_state: &GameState, visuals: &Visuals, glide: Option<Glide>, standing: Standing, hint: Option<&Hint>)
+{
+    // "Clear" window by drawing all pixels grey.
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+
+    let layout = WellLayout::fit(&game_state.well, WELL_AREA);
+    draw_well(win, re, game_state, visuals, glide, layout);
+    draw_next(win, re, 320.0, game_state, visuals);    // Draw the next tetrimino, always at the same place.
+    draw_hold(win, re, [120.0, 115.0], game_state, visuals);
+    if let Some(hint) = hint { draw_hint(win, re, game_state, visuals, hint, layout); }
+
+    draw_finesse_hud(win, re, game_state);
+    match game_state.mode {
+        Mode::Sprint { lines }  => draw_sprint_hud(win, re, game_state, lines, standing.best),
+        Mode::Ultra { .. }      => draw_ultra_hud(win, re, game_state, standing.table),
+        Mode::Marathon { .. }   => draw_marathon_hud(win, re, game_state),
+        Mode::Master            => draw_master_hud(win, re, game_state),
+        Mode::Cheese { .. }     => draw_cheese_hud(win, re, game_state),
+        Mode::Puzzle            => draw_puzzle_hud(win, re, game_state),
+        Mode::Endless | Mode::Versus => ()
+    }
+    match game_state.game_over {
+        Some(_) if game_state.mode == Mode::Puzzle => draw_puzzle_result(win, re, game_state),
+        Some(_) => draw_summary(win, re, game_state, standing.best, standing.placed),
+        None => ()
+    }
+}
+
+
+/// The well with its outline, its contents and the falling piece, laid out as given.
+/// With a glide, the piece is drawn part way from where it was before the latest step.
+fn draw_well(win: &mut PistonWindow, re: &Event, game_state: &GameState, visuals: &Visuals, glide: Option<Glide>, layout: WellLayout)
+{
+    draw_well_outline(win, re, &game_state.well, layout);
+    draw_well_blocks(win, re, &game_state.well, &game_state.piece_set, visuals, layout);    // Draw the contents of the playfield.
+
+    // Draw the currently falling tetrimino. (Unless it's still waiting to appear.)
+    // Only a step of one square glides: falling a row, or moving a column. Hard drops, kicks and new pieces just appear.
+    // Its ghost goes first, see-through, where it would land.
+    if game_state.spawn_delay == 0 && game_state.game_over.is_none() {
+        let ghost_row = landing_row(&game_state.curr_ttmo, &game_state.well, game_state.ttmo_row, game_state.ttmo_col);
+        let (x, y) = layout.pixel(ghost_row, game_state.ttmo_col);
+        draw_tetrimino_in_well(win, re, [x, y], game_state, visuals, layout, GHOST_ALPHA);
+    }
+    if game_state.spawn_delay == 0 {
+        match glide {
+            Some(Glide { from: (row, col, pieces), alpha }) if pieces == game_state.pieces &&
+                (row - game_state.ttmo_row).abs() <= 1 && (col - game_state.ttmo_col).abs() <= 1 => {
+                let (from_x, from_y) = layout.pixel(row, col);
+                let (to_x, to_y) = layout.pixel(game_state.ttmo_row, game_state.ttmo_col);
+                draw_tetrimino_in_well(win, re, [from_x + (to_x - from_x) * alpha, from_y + (to_y - from_y) * alpha], game_state, visuals, layout, 1.0);
+            }
+            _ => {
+                let (x, y) = layout.pixel(game_state.ttmo_row, game_state.ttmo_col);
+                draw_tetrimino_in_well(win, re, [x, y], game_state, visuals, layout, 1.0);
             }
         }
-          
-        else { game_state.ttmo_row += 1; }    // Move curr piece down one row.
+    }
+}
+
+
+/// The outline of a well, with a 2 pixel gap all round. (The top one's off screen for the usual well.)
+fn draw_well_outline(win: &mut PistonWindow, re: &Event, well: &Well, layout: WellLayout)
+{
+    let (width, height) = (layout.square * well.cols() as f64, layout.square * well.visible_rows() as f64);
+    win.draw_2d(re, |context, graphics, _device| {
+        rectangle([0.0, 0.0, 0.0, 1.0], [layout.left - 2.0, layout.top - 2.0, width + 4.0, height + 4.0], context.transform, graphics);
+    });
+}
+
+
+/// Versus: a well either side, each with its next and held pieces toward the middle and its incoming garbage meter on the outside.
+/// Over the network, `local` says which player is on this side, and `error` is whatever ended the match early.
+fn draw_versus(win: &mut PistonWindow, re: &Event, versus: &Versus, visuals: &Visuals, local: Option<usize>, error: Option<&str>)
+{
+    let white = [1.0; 4];
+
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+
+    for (player, game_state) in versus.players.iter().enumerate()
+    {
+        let layout = WellLayout::fit(&game_state.well, VERSUS_WELL_AREA[player]);
+        let left = VERSUS_WELL_AREA[player][0];
+        let (next_x, meter_x, text_x) = if player == 0 { (left + 370.0, left - 30.0, left + 370.0) }
+                                        else { (left - 160.0, left + 362.0, left - 160.0) };
+
+        draw_well(win, re, game_state, visuals, None, layout);
+        draw_next(win, re, next_x, game_state, visuals);
+        draw_hold(win, re, [next_x, 540.0], game_state, visuals);
+
+        // The meter: garbage that will rise on the next lock in red, garbage still on its way in orange, stacked up from the floor.
+        let (ready, waiting) = versus.incoming_lines(player);
+        win.draw_2d(re, |context, graphics, _device| {
+            rectangle([0.0, 0.0, 0.0, 1.0], [meter_x, 0.0, 18.0, 700.0], context.transform, graphics);
+            let ready_height = (layout.square * ready as f64).min(700.0);
+            let waiting_height = (layout.square * waiting as f64).min(700.0 - ready_height);
+            rectangle([0.9, 0.1, 0.1, 1.0], [meter_x + 2.0, 700.0 - ready_height, 14.0, ready_height], context.transform, graphics);
+            rectangle([1.0, 0.6, 0.0, 1.0], [meter_x + 2.0, 700.0 - ready_height - waiting_height, 14.0, waiting_height], context.transform, graphics);
+        });
+
+        let name = if local == Some(player) { format!("P{} YOU", player + 1) } else { format!("P{}", player + 1) };
+        draw_text(win, re, text_x, 300.0, 4.0, white, &name);
+        draw_text(win, re, text_x, 350.0, 3.0, white, &format!("SENT  {}", versus.sent[player]));
+        draw_text(win, re, text_x, 380.0, 3.0, white, &format!("LINES {}", game_state.lines));
+        if game_state.combo > 1 { draw_text(win, re, text_x, 420.0, 3.0, white, &format!("{} COMBO", game_state.combo - 1)); }
+        if game_state.back_to_back { draw_text(win, re, text_x, 450.0, 3.0, white, "B2B"); }
     }
 
-    // Keys are checked every update.
+    let (result, detail) = match (versus.outcome, error, local) {
+        (Some(Outcome::Winner(player)), _, Some(local)) => (if player == local { "YOU WIN" } else { "YOU LOSE" }.to_string(), None),
+        (Some(Outcome::Winner(player)), _, None) => (format!("P{} WINS", player + 1), None),
+        (Some(Outcome::Draw), _, _) => ("DRAW".to_string(), None),
+        (None, Some(error), _) => ("NO CONTEST".to_string(), Some(error.to_uppercase())),
+        (None, None, _) => return
+    };
+    win.draw_2d(re, |context, graphics, _device| { rectangle([0.0, 0.0, 0.0, 0.85], [340.0, 260.0, 600.0, 160.0], context.transform, graphics); } );
+    draw_text(win, re, 640.0 - text_width(&result, 6.0) / 2.0, 280.0, 6.0, white, &result);
+    if let Some(detail) = detail { draw_text(win, re, 640.0 - text_width(&detail, 3.0) / 2.0, 340.0, 3.0, white, &detail); }
+    draw_text(win, re, 640.0 - text_width("ENTER: MENU", 3.0) / 2.0, 380.0, 3.0, white, "ENTER: MENU");
+}
+
+
+/// The title screen: pick a mode with Up/Down, start with Enter.
+fn draw_title(win: &mut PistonWindow, re: &Event, menu_choice: usize, scores: &HighScores, profile: &Profile, puzzles: &[Arc<Puzzle>])
+{
+    let white = [1.0; 4];
+    let yellow = [1.0, 1.0, 0.0, 1.0];
+
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+    draw_text(win, re, 640.0 - text_width("RUSTRIS", 12.0) / 2.0, 80.0, 12.0, white, "RUSTRIS");
 
-    // MoveLeft
-    if game_state.key_map[0] && !would_collide(&game_state.curr_ttmo, &game_state.well, &game_state.ttmo_row, &(game_state.ttmo_col - 1))
-        { game_state.ttmo_col -= 1; }
+    for (index, mode) in MENU.iter().enumerate()
+    {
+        let y = 170.0 + 35.0 * index as f64;
+        let color = if index == menu_choice { yellow } else { white };
+
+        if index == menu_choice { draw_text(win, re, 380.0, y, 4.0, yellow, ">"); }
+        draw_text(win, re, 430.0, y, 4.0, color, &mode.title());
+
+        // Marathon has a table per starting level, so no one best. Versus has none at all, and puzzles are solved or not.
+        if let Mode::Puzzle = mode {
+            draw_text(win, re, 760.0, y + 4.0, 3.0, color, &format!("SOLVED {}/{}", solved_count(profile, puzzles), puzzles.len()));
+        }
+        if let Mode::Marathon { .. } | Mode::Puzzle | Mode::Versus = mode { continue; }
+        let best = scores.best(*mode).map(|entry| format_entry_result(*mode, entry));
+        draw_text(win, re, 760.0, y + 4.0, 3.0, color, &format!("BEST {}", best.unwrap_or_else(|| "--".to_string())));
+    }
 
-    // MoveRight
-    if game_state.key_map[1] && !would_collide(&game_state.curr_ttmo, &game_state.well, &game_state.ttmo_row, &(game_state.ttmo_col + 1))
-        { game_state.ttmo_col += 1; }
+    let player = format!("< {} >", profile.name);
+    draw_text(win, re, 640.0 - text_width(&player, 4.0) / 2.0, 635.0, 4.0, yellow, &player);
+    for (index, help) in ["UP/DOWN: MODE  LEFT/RIGHT: PLAYER  ENTER: PLAY", "H: HIGH SCORES  P: STATS  N: NEW PLAYER  O: OPTIONS  E: EDITOR"].iter().enumerate() {
+        draw_text(win, re, 640.0 - text_width(help, 2.0) / 2.0, 665.0 + 20.0 * index as f64, 2.0, white, help);
+    }
+}
 
-    // SoftDrop
-    if game_state.key_map[4] && !would_collide(&game_state.curr_ttmo, &game_state.well, &(game_state.ttmo_row + 1), &game_state.ttmo_col)
-        { game_state.ttmo_row += 1; }
 
-    // HardDrop
-    if game_state.key_map[5]
+/// The modes the high-score browser has pages for, in title screen order. Marathon gets one per starting level
+/// that's been played, and any other table (messier Cheese from the command line, say) goes on the end.
+fn score_pages(scores: &HighScores) -> Vec<Mode>
+{
+    let mut pages = Vec::new();
+    for mode in MENU.iter()
     {
-        for row in game_state.ttmo_row..24 {
-            if would_collide(&game_state.curr_ttmo, &game_state.well, &row, &game_state.ttmo_col) {
-                game_state.ttmo_row = row - 1;
-                break;
+        match mode
+        {
+            Mode::Versus | Mode::Puzzle => (),
+            Mode::Marathon { .. } => {
+                let played: Vec<Mode> = (0..=MARATHON_MAX_START).map(|start_level| Mode::Marathon { start_level })
+                    .filter(|marathon| !scores.table(*marathon).is_empty()).collect();
+                if played.is_empty() { pages.push(*mode); } else { pages.extend(played); }
             }
+            _ => pages.push(*mode)
         }
     }
 
-    // RotateCCW
-    if game_state.key_map[2] {
-        rotate_tetrimino(&mut game_state.curr_ttmo, false);
-        if would_collide(&game_state.curr_ttmo, &game_state.well, &game_state.ttmo_row, &game_state.ttmo_col) {
-            rotate_tetrimino(&mut game_state.curr_ttmo, true);
+    let others: Vec<Mode> = scores.tables.keys().filter_map(|name| name.parse().ok()).filter(|mode| !pages.contains(mode)).collect();
+    pages.extend(others);
+    pages
+}
+
+
+/// What an entry is ranked on: its time in a race, its score otherwise.
+fn format_entry_result(mode: Mode, entry: &ScoreEntry) -> String
+{
+    match Ranking::of(mode) {
+        Ranking::Time  => format_time_at(entry.frames, mode.updates_per_second()),
+        Ranking::Score => entry.score.to_string()
+    }
+}
+
+
+/// The high-score browser: one mode's table at a time.
+fn draw_scores(win: &mut PistonWindow, re: &Event, scores: &HighScores, pages: &[Mode], page: usize, choice: usize)
+{
+    let white = [1.0; 4];
+    let yellow = [1.0, 1.0, 0.0, 1.0];
+    let mode = pages[page];
+
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+    draw_text(win, re, 640.0 - text_width("HIGH SCORES", 8.0) / 2.0, 50.0, 8.0, white, "HIGH SCORES");
+
+    let title = match mode {
+        Mode::Marathon { start_level } => format!("< {} FROM {} >", mode.title(), start_level),
+        _ => format!("< {} >", mode.title())
+    };
+    draw_text(win, re, 640.0 - text_width(&title, 5.0) / 2.0, 150.0, 5.0, white, &title);
+
+    let table = scores.table(mode);
+    let result = if Ranking::of(mode) == Ranking::Time { "TIME" } else { "SCORE" };
+    draw_text(win, re, 80.0, 240.0, 3.0, white, &format!("{:2} {:8} {:>9} {:>5} {:>5} {:>9} {:10}", "#", "NAME", result, "LINES", "LEVEL", "TIME", "DATE"));
+
+    for (index, entry) in table.iter().enumerate()
+    {
+        let color = if index == choice { yellow } else { white };
+        let time = format_time_at(entry.frames, mode.updates_per_second());
+        let name: String = entry.name.chars().take(8).collect();
+        let row = format!("{:2} {:8} {:>9} {:>5} {:>5} {:>9} {:10}", index + 1, name, format_entry_result(mode, entry),
+                          entry.lines, entry.level, time, format_date(entry.date));
+
+        let y = 280.0 + 30.0 * index as f64;
+        if index == choice { draw_text(win, re, 40.0, y, 3.0, yellow, ">"); }
+        draw_text(win, re, 80.0, y, 3.0, color, &row);
+        if entry.replay.is_some() { draw_text(win, re, 1080.0, y, 3.0, color, "REPLAY"); }
+    }
+    if table.is_empty() { draw_text(win, re, 640.0 - text_width("NOTHING YET", 4.0) / 2.0, 400.0, 4.0, white, "NOTHING YET"); }
+
+    let help = "LEFT/RIGHT: MODE  UP/DOWN: PICK  ENTER: WATCH  BACKSPACE: MENU";
+    draw_text(win, re, 640.0 - text_width(help, 3.0) / 2.0, 640.0, 3.0, white, help);
+}
+
+
+/// A profile's lifetime stats on the left, its best game of each mode in the middle, and how it's been getting on on the right.
+fn draw_profile(win: &mut PistonWindow, re: &Event, profile: &Profile)
+{
+    let white = [1.0; 4];
+    let stats = &profile.stats;
+
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+    draw_text(win, re, 640.0 - text_width(&profile.name, 8.0) / 2.0, 30.0, 8.0, white, &profile.name);
+
+    let seconds = stats.millis / 1000;
+    let lifetime = [
+        format!("GAMES     {}", stats.games),
+        format!("TIME      {}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60),
+        format!("PIECES    {}", stats.pieces),
+        format!("LINES     {}", stats.lines),
+        format!("SINGLES   {}", stats.clears[0]),
+        format!("DOUBLES   {}", stats.clears[1]),
+        format!("TRIPLES   {}", stats.clears[2]),
+        format!("TETRISES  {}", stats.clears[3]),
+        format!("T-SPINS   {}", stats.tspins),
+        format!("MAX COMBO {}", stats.max_combo),
+        String::new(),
+        format!("PPS  {:.2}  BEST {:.2}", profile.average_pps(), stats.best_pps),
+        format!("APM  {:.1}  BEST {:.1}", profile.average_apm(), stats.best_apm),
+        String::new(),
+        format!("DAS {}  ARR {}", profile.das, profile.arr)
+    ];
+    for (index, line) in lifetime.iter().enumerate() {
+        draw_text(win, re, 40.0, 130.0 + 30.0 * index as f64, 3.0, white, line);
+    }
+
+    draw_text(win, re, 480.0, 130.0, 3.0, white, "RECORDS");
+    for (index, game) in profile.mode_records().iter().enumerate()
+    {
+        let result = match Ranking::of(game.mode) {
+            Ranking::Time if game.finished => format!("{}:{:02}.{:03}", game.millis / 60_000, game.millis / 1000 % 60, game.millis % 1000),
+            Ranking::Time => "--".to_string(),
+            Ranking::Score => game.score.to_string()
+        };
+        draw_text(win, re, 480.0, 170.0 + 30.0 * index as f64, 3.0, white, &format!("{:15} {:>9}", game.mode.title(), result));
+    }
+
+    let recent = &profile.history[profile.history.len().saturating_sub(GRAPH_GAMES)..];
+    draw_graph(win, re, [880.0, 150.0, 360.0, 180.0], "PPS", &recent.iter().map(|game| game.pps()).collect::<Vec<f64>>());
+    draw_graph(win, re, [880.0, 400.0, 360.0, 180.0], "APM", &recent.iter().map(|game| game.apm()).collect::<Vec<f64>>());
+
+    let help = "BACKSPACE: MENU";
+    draw_text(win, re, 640.0 - text_width(help, 3.0) / 2.0, 660.0, 3.0, white, help);
+}
+
+
+/// How many of the latest games the profile screen's graphs go back.
+const GRAPH_GAMES: usize = 40;
+
+
+/// A line graph of some numbers, oldest on the left, in the given [x, y, width, height] box. The top is the biggest one.
+fn draw_graph(win: &mut PistonWindow, re: &Event, area: [f64; 4], label: &str, values: &[f64])
+{
+    let white = [1.0; 4];
+    let [x, y, width, height] = area;
+    let top = values.iter().cloned().fold(0.0, f64::max);
+
+    draw_text(win, re, x, y - 30.0, 3.0, white, &format!("{} OVER {} GAMES", label, values.len()));
+    win.draw_2d(re, |context, graphics, _device| { rectangle([0.0, 0.0, 0.0, 1.0], area, context.transform, graphics); } );
+    if values.len() < 2 || top <= 0.0 {
+        draw_text(win, re, x + 20.0, y + height / 2.0 - 10.0, 3.0, white, "PLAY SOME MORE");
+        return;
+    }
+    draw_text(win, re, x + width + 8.0, y, 2.0, white, &format!("{:.1}", top));
+
+    let point = |index: usize| [x + width * index as f64 / (values.len() - 1) as f64, y + height - height * values[index] / top];
+    win.draw_2d(re, |context, graphics, _device| {
+        for index in 1..values.len() {
+            line_from_to([1.0, 1.0, 0.0, 1.0], 1.5, point(index - 1), point(index), context.transform, graphics);
+        }
+    });
+}
+
+
+/// Typing in the name of a new profile.
+fn draw_new_profile(win: &mut PistonWindow, re: &Event, new_name: &str)
+{
+    let white = [1.0; 4];
+
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+    draw_text(win, re, 640.0 - text_width("NEW PLAYER", 8.0) / 2.0, 80.0, 8.0, white, "NEW PLAYER");
+
+    let typed = format!("{}_", new_name);
+    draw_text(win, re, 640.0 - text_width(&typed, 6.0) / 2.0, 320.0, 6.0, [1.0, 1.0, 0.0, 1.0], &typed);
+
+    let help = format!("UP TO {} LETTERS AND DIGITS, ENTER WHEN DONE", MAX_PROFILE_NAME);
+    draw_text(win, re, 640.0 - text_width(&help, 3.0) / 2.0, 640.0, 3.0, white, &help);
+}
+
+
+/// The options menu: every setting, with the one being changed in yellow. `saved` is what's in the settings file now.
+fn draw_options(win: &mut PistonWindow, re: &Event, editing: &Settings, saved: &Settings, choice: usize)
+{
+    let white = [1.0; 4];
+    let yellow = [1.0, 1.0, 0.0, 1.0];
+
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+    draw_text(win, re, 640.0 - text_width("OPTIONS", 8.0) / 2.0, 40.0, 8.0, white, "OPTIONS");
+
+    for (index, name) in SETTING_NAMES.iter().enumerate()
+    {
+        let y = 115.0 + 29.0 * index as f64;
+        let color = if index == choice { yellow } else { white };
+        let value = editing.get(name).unwrap_or_default();
+
+        if index == choice { draw_text(win, re, 260.0, y, 3.0, yellow, ">"); }
+        draw_text(win, re, 300.0, y, 3.0, color, &name.replace('_', " "));
+        draw_text(win, re, 700.0, y, 3.0, color, &format!("< {} >", value));
+
+        // Window settings can't change with the window open.
+        if RESTART_SETTINGS.contains(name) && editing.get(name) != saved.get(name) {
+            draw_text(win, re, 960.0, y + 4.0, 2.0, color, "AFTER A RESTART");
         }
     }
 
-    // RotateCW
-    if game_state.key_map[3] {
-        rotate_tetrimino(&mut game_state.curr_ttmo, true);
-        if would_collide(&game_state.curr_ttmo, &game_state.well, &game_state.ttmo_row, &game_state.ttmo_col) {
-            rotate_tetrimino(&mut game_state.curr_ttmo, false);
+    let help = "UP/DOWN: PICK  LEFT/RIGHT: CHANGE  ENTER: SAVE  BACKSPACE: CANCEL";
+    draw_text(win, re, 640.0 - text_width(help, 3.0) / 2.0, 640.0, 3.0, white, help);
+}
+
+
+/// NES Marathon's level select: 0-9 on top, 10-19 underneath.
+fn draw_level_select(win: &mut PistonWindow, re: &Event, level_choice: u32)
+{
+    let white = [1.0; 4];
+
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+    draw_text(win, re, 640.0 - text_width("NES MARATHON", 8.0) / 2.0, 80.0, 8.0, white, "NES MARATHON");
+    draw_text(win, re, 640.0 - text_width("LEVEL", 5.0) / 2.0, 220.0, 5.0, white, "LEVEL");
+
+    for level in 0..=MARATHON_MAX_START
+    {
+        let x = 340.0 + 60.0 * (level % 10) as f64;
+        let y = 320.0 + 80.0 * (level / 10) as f64;
+
+        if level == level_choice {
+            win.draw_2d(re, |context, graphics, _device| { rectangle([1.0, 1.0, 0.0, 1.0], [x - 8.0, y - 12.0, 56.0, 56.0], context.transform, graphics); } );
         }
+        let label = level.to_string();
+        draw_text(win, re, x + 20.0 - text_width(&label, 4.0) / 2.0, y, 4.0, if level == level_choice { [0.0, 0.0, 0.0, 1.0] } else { white }, &label);
     }
 
-    game_state.key_map = [false; 6];                // all keys now unpressed
+    draw_text(win, re, 640.0 - text_width("ARROWS TO CHOOSE, ENTER TO PLAY, BACKSPACE FOR MENU", 3.0) / 2.0, 640.0, 3.0, white,
+              "ARROWS TO CHOOSE, ENTER TO PLAY, BACKSPACE FOR MENU");
 }
 
 
-/// Creates and returns a vector containing a randomized ordering of the 7 standard tetriminos.
-fn create_random_bag() -> Vec<Tetrimino>
+/// How many of the puzzles the profile has solved.
+fn solved_count(profile: &Profile, puzzles: &[Arc<Puzzle>]) -> usize
 {
-    let mut tetrimino_bag: Vec<Tetrimino> = vec![ Tetrimino::new(TetriminoKind::I),
-                                                  Tetrimino::new(TetriminoKind::J),
-                                                  Tetrimino::new(TetriminoKind::L),
-                                                  Tetrimino::new(TetriminoKind::O),
-                                                  Tetrimino::new(TetriminoKind::S),
-                                                  Tetrimino::new(TetriminoKind::T),
-                                                  Tetrimino::new(TetriminoKind::Z)  ];
-    tetrimino_bag.shuffle(&mut thread_rng());
-    tetrimino_bag.shuffle(&mut thread_rng());
-    tetrimino_bag.shuffle(&mut thread_rng());    // One randomize was appearing not very random...
+    puzzles.iter().filter(|puzzle| profile.solved.contains(&puzzle.name)).count()
+}
+
+
+/// The puzzle list: each one's title and goal, with the ones solved ticked off. A long list scrolls to keep the
+/// one picked on screen.
+fn draw_puzzles(win: &mut PistonWindow, re: &Event, puzzles: &[Arc<Puzzle>], profile: &Profile, choice: usize)
+{
+    const SHOWN: usize = 14;
+    let white = [1.0; 4];
+    let yellow = [1.0, 1.0, 0.0, 1.0];
+    let green = [0.0, 0.7, 0.0, 1.0];
+
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+    draw_text(win, re, 640.0 - text_width("PUZZLES", 8.0) / 2.0, 40.0, 8.0, white, "PUZZLES");
 
-    tetrimino_bag
+    let solved = format!("SOLVED {}/{}", solved_count(profile, puzzles), puzzles.len());
+    draw_text(win, re, 640.0 - text_width(&solved, 3.0) / 2.0, 120.0, 3.0, white, &solved);
+
+    let first = choice.saturating_sub(SHOWN / 2).min(puzzles.len().saturating_sub(SHOWN));
+    for (index, puzzle) in puzzles.iter().enumerate().skip(first).take(SHOWN)
+    {
+        let y = 170.0 + 30.0 * (index - first) as f64;
+        let color = if index == choice { yellow } else { white };
+
+        if index == choice { draw_text(win, re, 140.0, y, 3.0, yellow, ">"); }
+        draw_text(win, re, 180.0, y, 3.0, color, &puzzle.title);
+        draw_text(win, re, 660.0, y, 3.0, color, &puzzle.goal.describe());
+        if profile.solved.contains(&puzzle.name) { draw_text(win, re, 1060.0, y, 3.0, green, "SOLVED"); }
+    }
+
+    let help = "UP/DOWN: PICK  ENTER: PLAY  E: EDIT  BACKSPACE: MENU";
+    draw_text(win, re, 640.0 - text_width(help, 3.0) / 2.0, 640.0, 3.0, white, help);
 }
 
 
+/// The puzzle being played, on the right of the well: its goal, the pieces still to come and its hint, with the keys
+/// for undo and retry.
+fn draw_puzzle_hud(win: &mut PistonWindow, re: &Event, game_state: &GameState)
+{
+    let white = [1.0; 4];
+    let puzzle = match game_state.puzzle.as_ref() { Some(puzzle) => puzzle, None => return };
+
+    draw_text(win, re, 860.0, 60.0, 4.0, white, &puzzle.title);
+    draw_text(win, re, 860.0, 110.0, 3.0, white, &puzzle.goal.describe());
+
+    // The next piece is drawn as usual. These are the ones after it.
+    let after_next = puzzle.letters(game_state.pieces as usize + 2);
+    draw_text(win, re, 860.0, 150.0, 3.0, white, &format!("PIECES LEFT {}", pieces_to_come(game_state)));
+    if !after_next.is_empty() { draw_text(win, re, 860.0, 180.0, 3.0, white, &format!("THEN {}", after_next)); }
+
+    // The hint, a word at a time, wrapped to fit between the well and the edge of the window.
+    let mut lines: Vec<String> = Vec::new();
+    for word in puzzle.hint.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if text_width(line, 2.0) + text_width(word, 2.0) + text_width(" ", 2.0) < 400.0 => { line.push(' '); line.push_str(word); }
+            _ => lines.push(word.to_string())
+        }
+    }
+    for (index, line) in lines.iter().enumerate() {
+        draw_text(win, re, 860.0, 240.0 + 22.0 * index as f64, 2.0, white, line);
+    }
+
+    draw_text(win, re, 860.0, 620.0, 3.0, white, "U: UNDO  R: RETRY");
+}
+
+
+/// How a puzzle went, in a box over the middle of the well.
+fn draw_puzzle_result(win: &mut PistonWindow, re: &Event, game_state: &GameState)
+{
+    let white = [1.0; 4];
+
+    let result = match game_state.game_over {
+        Some(GameOver::GoalReached) => "SOLVED!",
+        Some(GameOver::OutOfPieces) => "OUT OF PIECES",
+        _ => "GAME OVER"
+    };
+
+    win.draw_2d(re, |context, graphics, _device| { rectangle([0.0, 0.0, 0.0, 0.85], [478.0, 250.0, 324.0, 200.0], context.transform, graphics); } );
+    draw_text(win, re, 640.0 - text_width(result, 5.0) / 2.0, 275.0, 5.0, white, result);
+    for (index, line) in ["U: UNDO", "R: RETRY", "ENTER: PUZZLES"].iter().enumerate() {
+        draw_text(win, re, 640.0 - text_width(line, 3.0) / 2.0, 340.0 + 30.0 * index as f64, 3.0, white, line);
+    }
+}
+
 
-/// Rotates the given Tetrimino by 90 degrees, either clockwise or counterCW.
-fn rotate_tetrimino(ttmo: &mut Tetrimino, clockwise: bool)
+/// The board editor: the brushes and the keys on the left, the well in the middle, and the goal and the pieces
+/// on the right, with how the last save or play went underneath.
+fn draw_editor(win: &mut PistonWindow, re: &Event, editor: &Editor, visuals: &Visuals, message: &str)
 {
-    // Rotating the O Tetrimino is pointless.
-    if ttmo.kind == TetriminoKind::O { return; }
+    let white = [1.0; 4];
+    let yellow = [1.0, 1.0, 0.0, 1.0];
+    let pieces = PieceSet::standard();
 
-    let source = ttmo.shape;
-    let mut rotated: [[u8; 4]; 4] = [[0; 4]; 4];
+    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
 
-    // Only TetriminoKind::I needs all four rows of .shape rotated.
-    // The others can be done by only rotating the top-left 3x3 submatrix.
-    let matrix_size: usize;
-    if ttmo.kind == TetriminoKind::I { matrix_size = 4; } else { matrix_size = 3; }
+    let layout = WellLayout::fit(&editor.well, WELL_AREA);
+    draw_well_outline(win, re, &editor.well, layout);
+    draw_well_blocks(win, re, &editor.well, &pieces, visuals, layout);
 
-    for row in 0..matrix_size
+    draw_text(win, re, BRUSHES_AT[0], 60.0, 4.0, white, "EDITOR");
+    draw_text(win, re, BRUSHES_AT[0], 150.0, 3.0, white, "BRUSH");
+    for (index, brush) in BRUSHES.iter().enumerate()
     {
-        // First row becomes last column, and so on.
-        if clockwise {
-            for col in 0..matrix_size {
-                rotated[col][(matrix_size - 1) - row] = source[row][col];    // matrix_size is 1 based, array index is 0 based.
-            }
+        let (x, y) = (BRUSHES_AT[0] + BRUSH_SPACING * index as f64, BRUSHES_AT[1]);
+        let outline = if *brush == editor.brush { yellow } else { [0.0, 0.0, 0.0, 1.0] };
+        win.draw_2d(re, |context, graphics, _device| { rectangle(outline, [x - 3.0, y - 3.0, SQUARE + 6.0, SQUARE + 6.0], context.transform, graphics); } );
+
+        // The eraser is an empty square, black like the well.
+        match TetriminoKind::from_cell(*brush) {
+            Some(kind) => draw_block(win, re, [x, y, SQUARE], kind, &pieces, visuals, 1.0),
+            None if *brush == GARBAGE => draw_garbage_block(win, re, x, y, SQUARE),
+            None => ()
         }
+        draw_text(win, re, x + 12.0, y + 50.0, 2.0, white, &index.to_string());
+    }
 
-        // First row becomes first column, but upside down.
-        else {
-            for col in 0..matrix_size {
-                rotated[(matrix_size - 1) - col][row] = source[row][col];
+    let help = ["LEFT MOUSE: PAINT", "RIGHT MOUSE: RUB OUT", "0-8: BRUSH", "Q: ADD THE BRUSH'S PIECE", "X: TAKE THE LAST ONE OFF",
+                "H: HOLD THE BRUSH'S PIECE", "LEFT/RIGHT: GOAL", "ENTER: PLAY", "S: SAVE", "B: COPY AS A FUMEN", "V: PASTE A FUMEN", "DELETE: START AGAIN",
+                "BACKSPACE: MENU"];
+    for (index, line) in help.iter().enumerate() {
+        draw_text(win, re, BRUSHES_AT[0], 320.0 + 28.0 * index as f64, 2.0, white, line);
+    }
+
+    draw_text(win, re, 860.0, 60.0, 4.0, white, "GOAL");
+    draw_text(win, re, 860.0, 110.0, 3.0, white, &editor.goal().describe());
+
+    // The pieces in little squares, six to a row, the first one to fall picked out. The hold goes under them.
+    let draw_small = |win: &mut PistonWindow, x: f64, y: f64, kind: TetriminoKind| {
+        let shape = Tetrimino::new(kind).shape;
+        for (row, squares) in shape.iter().enumerate() {
+            for (col, square) in squares.iter().enumerate() {
+                if *square != 0 { draw_block(win, re, [x + 10.0 * col as f64, y + 10.0 * row as f64, 10.0], kind, &pieces, visuals, 1.0); }
             }
         }
+    };
+
+    draw_text(win, re, 860.0, 170.0, 4.0, white, "PIECES");
+    if editor.pieces.is_empty() { draw_text(win, re, 860.0, 220.0, 3.0, white, "NONE YET"); }
+    for (index, kind) in editor.pieces.iter().enumerate()
+    {
+        let (x, y) = (860.0 + 56.0 * (index % 6) as f64, 220.0 + 56.0 * (index / 6) as f64);
+        if index == 0 {
+            win.draw_2d(re, |context, graphics, _device| { rectangle(yellow, [x - 4.0, y - 4.0, 48.0, 48.0], context.transform, graphics); } );
+            win.draw_2d(re, |context, graphics, _device| { rectangle([0.5; 4], [x - 2.0, y - 2.0, 44.0, 44.0], context.transform, graphics); } );
+        }
+        draw_small(win, x, y, *kind);
+    }
+
+    let hold_y = 220.0 + 56.0 * editor.pieces.len().max(1).div_ceil(6) as f64 + 30.0;
+    draw_text(win, re, 860.0, hold_y, 4.0, white, "HOLD");
+    match editor.hold {
+        Some(kind) => draw_small(win, 860.0, hold_y + 50.0, kind),
+        None => draw_text(win, re, 860.0, hold_y + 50.0, 3.0, white, "NO HOLD")
     }
 
-    ttmo.shape = rotated;    
+    draw_text(win, re, 860.0, 560.0, 2.0, yellow, message);
 }
 
 
-/// Returns true if the given Tetrimino, placed in the given playfield,
-/// at the given row and col, would collide with something.
-fn would_collide(ttmo: &Tetrimino, well: &Well, row: &i32, col: &i32) -> bool
+/// The well square under the mouse in the editor, if there is one, painted with the brush or rubbed out.
+fn paint_at(editor: &mut Editor, cursor: [f64; 2], erase: bool)
 {
-    let mut well_row: i32;
-    let mut well_col: i32;
+    let layout = WellLayout::fit(&editor.well, WELL_AREA);
+    let col = ((cursor[0] - layout.left) / layout.square).floor() as i32;
+    let row = ((cursor[1] - layout.top) / layout.square).floor() as i32 + layout.hidden;
+    editor.paint(row, col, erase);
+}
 
-    for ttmo_row in 0..4 {
-        for ttmo_col in 0..4 {
 
-            // Tetrimino has no square here, collison is not possible.
-            if ttmo.shape[ttmo_row][ttmo_col] == 0 { continue; }
+/// Which of the editor's brushes the mouse is over, if any.
+fn brush_at(cursor: [f64; 2]) -> Option<usize>
+{
+    (0..BRUSHES.len()).find(|index| {
+        let (x, y) = (BRUSHES_AT[0] + BRUSH_SPACING * *index as f64, BRUSHES_AT[1]);
+        (x..x + SQUARE).contains(&cursor[0]) && (y..y + SQUARE).contains(&cursor[1])
+    })
+}
+
+
+/// NES Marathon's level, lines and score, on the right of the well.
+fn draw_marathon_hud(win: &mut PistonWindow, re: &Event, game_state: &GameState)
+{
+    let white = [1.0; 4];
+
+    draw_text(win, re, 860.0, 60.0, 4.0, white, &game_state.mode.title());
+    draw_text(win, re, 860.0, 110.0, 3.0, white, &format!("LEVEL {}", game_state.level()));
+    draw_text(win, re, 860.0, 140.0, 3.0, white, &format!("LINES {}", game_state.lines));
+    draw_text(win, re, 860.0, 170.0, 3.0, white, &format!("SCORE {}", game_state.score));
+}
 
-            // Compute well coords of ttmo square.
-            well_row = ttmo_row as i32 + *row;
-            well_col = ttmo_col as i32 + *col;
 
-            // Collisions with well walls, floor.
-            if well_col < 0 { return true; }
-            if well_col > 9 { return true; }
-            if well_row > 23 { return true; }
-    
-            // Collision with a block already frozen in the well.
-            if well[well_row as usize][well_col as usize] != 0 { return true; }
+/// Master's grade, level and timer, on the right of the well. The level shows the section stop it's heading for, TGM style.
+fn draw_master_hud(win: &mut PistonWindow, re: &Event, game_state: &GameState)
+{
+    let white = [1.0; 4];
+    let level = game_state.level();
+
+    draw_text(win, re, 860.0, 60.0, 4.0, white, &game_state.mode.title());
+    draw_text(win, re, 860.0, 110.0, 3.0, white, "GRADE");
+    draw_text(win, re, 860.0, 140.0, 6.0, white, master::grade(game_state));
+    draw_text(win, re, 860.0, 210.0, 3.0, white, "LEVEL");
+    draw_text(win, re, 860.0, 240.0, 4.0, white, &format!("{:3}", level));
+    draw_text(win, re, 860.0, 280.0, 4.0, white, &format!("{:3}", master::section_stop(level) + 1));
+    draw_text(win, re, 860.0, 330.0, 3.0, white, &format!("SCORE {}", game_state.score));
+    draw_text(win, re, 860.0, 370.0, 5.0, white, &format_time(game_state.frames));
+}
+
+
+/// Cheese's garbage left to dig, and the timer, on the right of the well.
+fn draw_cheese_hud(win: &mut PistonWindow, re: &Event, game_state: &GameState)
+{
+    let white = [1.0; 4];
+
+    draw_text(win, re, 860.0, 60.0, 4.0, white, &game_state.mode.title());
+    draw_text(win, re, 860.0, 110.0, 3.0, white, &format!("LEFT {}", cheese_left(game_state)));
+    draw_text(win, re, 860.0, 150.0, 5.0, white, &format_time(game_state.frames));
+    draw_text(win, re, 860.0, 200.0, 3.0, white, &format!("PIECES {}", game_state.pieces));
+}
+
+
+/// Sprint progress, on the right of the well: lines to go, the timer, and each 10-line split against the best run.
+fn draw_sprint_hud(win: &mut PistonWindow, re: &Event, game_state: &GameState, goal: u32, best: Option<&ScoreEntry>)
+{
+    let white = [1.0; 4];
+
+    draw_text(win, re, 860.0, 60.0, 4.0, white, &format!("SPRINT {}", goal));
+    draw_text(win, re, 860.0, 110.0, 3.0, white, &format!("LINES {}/{}", game_state.lines.min(goal), goal));
+    draw_text(win, re, 860.0, 150.0, 5.0, white, &format_time(game_state.frames));
+
+    let best_time = best.map_or("--".to_string(), |record| format_time(record.frames));
+    draw_text(win, re, 860.0, 200.0, 3.0, white, &format!("BEST {}", best_time));
+
+    // Splits, with how far ahead (green) or behind (red) of the best run they were.
+    for (index, split) in game_state.splits.iter().enumerate().take((goal / 10) as usize)
+    {
+        let y = 250.0 + 28.0 * index as f64;
+        draw_text(win, re, 860.0, y, 3.0, white, &format!("{:3} {}", (index + 1) * 10, format_time(*split)));
+
+        if let Some(best_split) = best.and_then(|record| record.splits.get(index)) {
+            let delta = i64::from(*split) - i64::from(*best_split);
+            let color = if delta < 0 { [0.0, 0.7, 0.0, 1.0] } else { [0.8, 0.0, 0.0, 1.0] };
+            draw_text(win, re, 1100.0, y, 3.0, color, &format_delta(delta));
         }
     }
+}
+
 
-    false
+/// Finesse faults for the last piece and the whole game, on the left of the well.
+fn draw_finesse_hud(win: &mut PistonWindow, re: &Event, game_state: &GameState)
+{
+    let white = [1.0; 4];
+    let finesse = &game_state.finesse;
+
+    draw_text(win, re, 120.0, 320.0, 4.0, white, "FINESSE");
+
+    // Last piece in red if it wasted presses.
+    let last_color = if finesse.last_faults > 0 { [0.8, 0.0, 0.0, 1.0] } else { white };
+    draw_text(win, re, 120.0, 370.0, 3.0, last_color, &format!("LAST   +{}", finesse.last_faults));
+    draw_text(win, re, 120.0, 400.0, 3.0, white, &format!("FAULTS {}", finesse.faults));
+    draw_text(win, re, 120.0, 430.0, 3.0, white, &format!("CLEAN  {}%", finesse.accuracy()));
+
+    if game_state.finesse_practice { draw_text(win, re, 120.0, 480.0, 3.0, [1.0, 1.0, 0.0, 1.0], "PRACTICE (P)"); }
 }
 
 
-/// Copies the given tetrimino's squares into the given well at the given (well_row, well_col).
-fn freeze_to_well(ttmo: &Tetrimino, well: &mut Well, well_row: &i32, well_col: &i32)
+/// The perfect clear hint: where each piece goes, see-through and numbered in the order they come, over the well,
+/// and how the search went under the finesse numbers. Only while it's for the piece falling now.
+fn draw_hint(win: &mut PistonWindow, re: &Event, game_state: &GameState, visuals: &Visuals, hint: &Hint, layout: WellLayout)
 {
-    for row in 0..4 {
-        for col in 0..4 {
-            if ttmo.shape[row][col] == 0 { continue; }
-            // println!("well[{}][{}] = 1", (*well_row + row as i32) as usize, (*well_col + col as i32) as usize);
-            well[(*well_row + row as i32) as usize][(*well_col + col as i32) as usize] = ttmo.shape[row][col];
+    let yellow = [1.0, 1.0, 0.0, 1.0];
+    draw_text(win, re, 120.0, 530.0, 3.0, yellow, "PC HINT (H)");
+    if game_state.game_over.is_some() { return; }
+
+    let status = match &hint.solution {
+        Some(_) if !hint.is_for(game_state) => "LOOKING...".to_string(),
+        None => "LOOKING...".to_string(),
+        Some(Solution::Impossible) => format!("NONE IN {} LINES", DEFAULT_LINES),
+        Some(Solution::GaveUp) => "TOO HARD TO TELL".to_string(),
+        Some(Solution::Found(steps)) => {
+            for (index, step) in steps.iter().enumerate()
+            {
+                let mut color = game_state.piece_set.color(step.ttmo.kind, visuals.palette);
+                color[3] = if index == 0 { 0.6 } else { 0.3 };
+
+                for (row, col) in step.squares.iter().filter(|(row, _)| *row >= game_state.well.hidden() as i32) {
+                    let (x, y) = layout.pixel(*row, *col);
+                    win.draw_2d(re, |context, graphics, _device| { rectangle(color, [x + 1.0, y + 1.0, layout.square - 2.0, layout.square - 2.0], context.transform, graphics); } );
+                }
+                if let Some((row, col)) = step.squares.iter().min() {
+                    let (x, y) = layout.pixel(*row, *col);
+                    // An H after the number: press hold first.
+                    let label = format!("{}{}", index + 1, if step.hold { "H" } else { "" });
+                    draw_text(win, re, x + layout.square * 0.3, y + layout.square * 0.3, 2.0, [1.0; 4], &label);
+                }
+            }
+            format!("{} PIECES", steps.len())
         }
+    };
+    draw_text(win, re, 120.0, 560.0, 3.0, yellow, &status);
+}
+
+
+/// Ultra's countdown and score, on the right of the well. Once time is up, the high-score table goes there instead.
+fn draw_ultra_hud(win: &mut PistonWindow, re: &Event, game_state: &GameState, table: &[ScoreEntry])
+{
+    let white = [1.0; 4];
+    let red = [0.8, 0.0, 0.0, 1.0];
+    let limit = game_state.mode.time_limit().unwrap_or(0);
+    let left = limit.saturating_sub(game_state.frames);
+
+    draw_text(win, re, 860.0, 60.0, 4.0, white, &game_state.mode.title());
+
+    // The last ten seconds count down in red.
+    let countdown_color = if left <= 10 * UPDATES_PER_SECOND { red } else { white };
+    draw_text(win, re, 860.0, 110.0, 5.0, countdown_color, &format_time(left));
+    draw_text(win, re, 860.0, 170.0, 3.0, white, &format!("SCORE {}", game_state.score));
+    draw_text(win, re, 860.0, 200.0, 3.0, white, &format!("LINES {}", game_state.lines));
+
+    if game_state.game_over.is_none() { return; }
+
+    draw_text(win, re, 860.0, 260.0, 3.0, white, "HIGH SCORES");
+    for (index, record) in table.iter().enumerate()
+    {
+        // This game's entry, if it made the table, is picked out in yellow.
+        let this_game = record.seed == game_state.seed && record.score == game_state.score;
+        let color = if this_game { [1.0, 1.0, 0.0, 1.0] } else { white };
+        draw_text(win, re, 860.0, 295.0 + 28.0 * index as f64, 3.0, color,
+                  &format!("{:2} {:7} {:3}L", index + 1, record.score, record.lines));
     }
 }
 
 
-/// Clears out complete rows in the given well, and moves the rows above them down.
-fn clear_complete_rows(well: Well) -> Well
+/// The end of game stats, in a box over the middle of the well.
+/// `placed` is where it went in the high-score table, if it made it.
+fn draw_summary(win: &mut PistonWindow, re: &Event, game_state: &GameState, best: Option<&ScoreEntry>, placed: Option<usize>)
 {
-    // Copy partial rows to a new well. Ignore both empty and full rows.
-    let mut new_well: Well = [[0; 10]; 24];
-    let mut new_well_row: usize = 23;
+    let white = [1.0; 4];
+    let finesse = &game_state.finesse;
+
+    win.draw_2d(re, |context, graphics, _device| { rectangle([0.0, 0.0, 0.0, 0.85], [478.0, 110.0, 324.0, 560.0], context.transform, graphics); } );
 
-    for old_well_row in (0..24).rev()    // Start at bottom and work upward.
+    let mut lines = Vec::new();
+    match game_state.game_over
     {
-        // The number of non-empty columns in a row is its "population count".
-        let mut pop_count = 0;
-        for col in 0..10 {
-            if well[old_well_row][col] != 0 { pop_count += 1; }    // Take your fold() and shove it!
+        // A finished Sprint gets its time up top, and how it compares to the best run before it.
+        Some(GameOver::GoalReached) if game_state.mode == Mode::Master => lines.push("FINISHED".to_string()),
+        Some(GameOver::GoalReached) => {
+            lines.push("FINISHED".to_string());
+            lines.push(format!("TIME    {}", format_time(game_state.frames)));
+            if let Mode::Sprint { .. } = game_state.mode {
+                lines.push(match best {
+                    Some(record) if game_state.frames >= record.frames =>
+                        format!("BEST {}", format_delta(i64::from(game_state.frames) - i64::from(record.frames))),
+                    _ => "NEW BEST!".to_string()
+                });
+            }
         }
+        Some(GameOver::TimeUp) => lines.push("TIME UP".to_string()),
+        _ => lines.push("GAME OVER".to_string())
+    }
+    lines.push(String::new());
+
+    // Master is all about the grade, and how long it took to get.
+    if game_state.mode == Mode::Master {
+        lines.push(format!("GRADE   {}", master::grade(game_state)));
+        lines.push(format!("LEVEL   {}", game_state.level()));
+        lines.push(format!("TIME    {}", format_time(game_state.frames)));
+        lines.push(String::new());
+    }
 
-        // Totally empty or totally full rows are ignored.
-        if pop_count == 0 || pop_count == 10 { continue; }
-
-        // Copy partial row to new well, in lowest row possible.
-        if well[old_well_row].iter().sum::<u8>() > 0    // if well row contains blocks
-        {    
-            new_well[new_well_row] = well[old_well_row];    // Copy row to new well.
-            new_well_row -= 1;
+    // Ultra is all about the score, so show where it came from.
+    if let Mode::Ultra { .. } = game_state.mode {
+        for (index, name) in ["SINGLE", "DOUBLE", "TRIPLE", "TETRIS"].iter().enumerate() {
+            lines.push(format!("{:7}{:3} {:6}", name, game_state.clears[index], game_state.clear_points[index]));
         }
+        lines.push(String::new());
     }
 
-    new_well
+    lines.push(format!("PIECES  {}", game_state.pieces));
+    lines.push(format!("LINES   {}", game_state.lines));
+    lines.push(format!("SCORE   {}", game_state.score));
+    lines.push(String::new());
+    if let Some(place) = placed {
+        lines.push(format!("HIGH SCORE #{}", place + 1));
+        lines.push(String::new());
+    }
+    lines.push(format!("FAULTS  {}", finesse.faults));
+    lines.push(format!("SLOPPY  {}", finesse.faulty_pieces));
+    lines.push(format!("CLEAN   {}%", finesse.accuracy()));
+    lines.push(String::new());
+    lines.push("ENTER: MENU".to_string());
+
+    for (index, line) in lines.iter().enumerate() {
+        draw_text(win, re, 500.0, 130.0 + 30.0 * index as f64, 3.0, white, line);
+    }
 }
 
 
-fn render(win: &mut PistonWindow, re: &Event, row: &i32, col: &i32, curr: &Tetrimino, next: &Tetrimino, well: &Well)
+/// Renders the falling piece with its box's upper-left corner at the given pixel coordinates, in a well laid out as
+/// given. Squares still up in the hidden rows (more than half of one above the well's top) aren't drawn.
+fn draw_tetrimino_in_well(win: &mut PistonWindow, e: &Event, at: [f64; 2], game_state: &GameState, visuals: &Visuals, layout: WellLayout,
+                          alpha: f32)
 {
-    // "Clear" window by drawing all pixels grey.
-    win.draw_2d(re, |_context, graphics, _device| { clear([0.5; 4], graphics); } );
+    let ttmo = &game_state.curr_ttmo;
+    for ttmo_row in 0..PIECE_SIZE {
+        for ttmo_col in 0..PIECE_SIZE {
+
+            if ttmo.shape[ttmo_row][ttmo_col] == 0 { continue; }    // No square to be drawn here.
+
+            let x_offs = at[0] + layout.square * ttmo_col as f64;
+            let y_offs = at[1] + layout.square * ttmo_row as f64;     // Pixel Y coords increase downward.
+            if y_offs < layout.top - layout.square / 2.0 { continue; }
+
+            draw_block(win, e, [x_offs, y_offs, layout.square], ttmo.kind, &game_state.piece_set, visuals, alpha);
+        }
+    }
+}
 
-    // Draw the outline of the playfield. 350 wide + 2 pixel gap on left and right => 354 pixels wide.
-    win.draw_2d(re, |context, graphics, _device| { rectangle([0.0, 0.0, 0.0, 1.0], [463.0, -140.0, 354.0, 842.0], context.transform, graphics); } );
+/// Renders the next piece with its box's upper-left corner at the given x, at the usual height.
+fn draw_next(win: &mut PistonWindow, e: &Event, px: f64, game_state: &GameState, visuals: &Visuals)
+{
+    if game_state.puzzle.is_some() && pieces_to_come(game_state) == 0 { return; }    // The puzzle's last piece is falling.
 
-    draw_well_blocks(win, re, well);                      // Draw the contents of the playfield.
-    draw_tetrimino_well(win, re, row, col, curr);         // Draw the currently falling tetrimino.
-    draw_tetrimino_pixel(win, re, 320.0, 115.0, next);    // Draw the next tetrimino, always at the same place.
+    draw_boxed_piece(win, e, [px, 115.0], &game_state.next_ttmo, &game_state.piece_set, visuals, 1.0);
 }
 
 
-/// Renders the given Tetrimino at the given well coordinates.
-fn draw_tetrimino_well(win: &mut PistonWindow, re: &Event, well_row: &i32, well_col: &i32, ttmo: &Tetrimino)
+/// Renders the held piece, if there is one, under HOLD, with its box's upper-left corner at [x, y]. Faded once it's
+/// been used, until the piece falling now locks.
+fn draw_hold(win: &mut PistonWindow, e: &Event, at: [f64; 2], game_state: &GameState, visuals: &Visuals)
 {
-    let (x, y) = well_to_pixel(*well_row, *well_col);
-    draw_tetrimino_pixel(win, re, x, y, ttmo);
+    if !game_state.has_hold() { return; }
+
+    draw_text(win, e, at[0], at[1] - 40.0, 3.0, [1.0; 4], "HOLD");
+    if let Some(held) = &game_state.hold_ttmo {
+        let alpha = if game_state.hold_used { 0.4 } else { 1.0 };
+        draw_boxed_piece(win, e, at, held, &game_state.piece_set, visuals, alpha);
+    }
 }
 
-/// Renders the given Tetrimino at the given pixel coordinates.
-fn draw_tetrimino_pixel(win: &mut PistonWindow, e: &Event, px: f64, py: f64, ttmo: &Tetrimino)
+
+/// Renders a piece out of the well with its box's upper-left corner at [x, y]. A piece that needs all of its 5x5 box
+/// is drawn with smaller squares, so it takes up the same room as a tetrimino.
+fn draw_boxed_piece(win: &mut PistonWindow, e: &Event, at: [f64; 2], ttmo: &Tetrimino, pieces: &PieceSet, visuals: &Visuals, alpha: f32)
 {
+    let square = if ttmo.is_big() { SQUARE * 4.0 / PIECE_SIZE as f64 } else { SQUARE };
+
     // DEBUG ONLY: Draw transparent grey bounding box around tetrimino.
-    // win.draw_2d(e, |context, graphics, _device| { rectangle([0.5; 4], [px, py, 140.0, 140.0], context.transform, graphics); } );
+    // win.draw_2d(e, |context, graphics, _device| { rectangle([0.5; 4], [at[0], at[1], 4.0 * SQUARE, 4.0 * SQUARE], context.transform, graphics); } );
 
-    for ttmo_row in 0..4 {
-        for ttmo_col in 0..4 {
+    for ttmo_row in 0..PIECE_SIZE {
+        for ttmo_col in 0..PIECE_SIZE {
             
             if ttmo.shape[ttmo_row][ttmo_col] == 0 { continue; }    // No square to be drawn here.
 
-            let x_offs = px + 35.0 * ttmo_col as f64;    // Each square in the Tetrimino is 35x35 pixels.
-            let y_offs = py + 35.0 * ttmo_row as f64;    // Pixel Y coords increase downward.
+            let x_offs = at[0] + square * ttmo_col as f64;
+            let y_offs = at[1] + square * ttmo_row as f64;    // Pixel Y coords increase downward.
 
-            win.draw_2d(e,
-                |context, graphics, _device| {
-                    // Draw 33x33 square inside 35x35 space.
-                    rectangle(ttmo.color, [x_offs + 1.0, y_offs + 1.0, 33.0, 33.0], context.transform, graphics);
-                }
-            );
+            draw_block(win, e, [x_offs, y_offs, square], ttmo.kind, pieces, visuals, alpha);
         }
     }
 }
 
 
-/// Renders the squares of the given playfield.
-fn draw_well_blocks(win: &mut PistonWindow, e: &Event, well: &Well)
+/// Renders the squares of the given playfield that can be seen. (Not the hidden rows above it.)
+fn draw_well_blocks(win: &mut PistonWindow, e: &Event, well: &Well, pieces: &PieceSet, visuals: &Visuals, layout: WellLayout)
 {
-    for row in 0..24 {
-        for col in 0..10 {
-            
-            if well[row][col] == 0 { continue; }    // No square to be drawn here.
+    for (row, well_row) in well.iter().enumerate().skip(well.hidden()) {
+        for (col, square) in well_row.iter().enumerate() {
+
+            let (x_offs, y_offs) = layout.pixel(row as i32, col as i32);
+            if *square == GARBAGE { draw_garbage_block(win, e, x_offs, y_offs, layout.square); }
+
+            // No square to be drawn here.
+            let kind = match TetriminoKind::from_cell(*square) { Some(kind) => kind, None => continue };
+
+            draw_block(win, e, [x_offs, y_offs, layout.square], kind, pieces, visuals, 1.0);
+        }
+    }
+}
+
+
+/// Renders one square of garbage: gray, with a darker square inset so it can't be mistaken for any piece.
+fn draw_garbage_block(win: &mut PistonWindow, e: &Event, px: f64, py: f64, size: f64)
+{
+    win.draw_2d(e,
+        |context, graphics, _device| {
+            let t = context.transform.trans(px, py).scale(size / SQUARE, size / SQUARE);    // Drawn as if 35x35, then scaled.
+            rectangle(GARBAGE_COLOR, [1.0, 1.0, 33.0, 33.0], t, graphics);
+            rectangle([0.4, 0.4, 0.4, 1.0], [9.0, 9.0, 17.0, 17.0], t, graphics);
+        }
+    );
+}
+
+
+/// Renders one square of the given kind from the given piece set, at [x, y, size]: its upper-left corner and size in pixels.
+/// Every block on screen (well, falling piece, ghost, preview, hold) goes through here, so palettes and glyphs apply
+/// everywhere. Alpha below 1 makes it see-through, glyph and all: the ghost, and a hold that's been used.
+fn draw_block(win: &mut PistonWindow, e: &Event, at: [f64; 3], kind: TetriminoKind, pieces: &PieceSet, visuals: &Visuals, alpha: f32)
+{
+    let [px, py, size] = at;
+    let mut color = pieces.color(kind, visuals.palette);
+    color[3] *= alpha;
+    let ink = [0.0, 0.0, 0.0, 0.6 * alpha];
+
+    win.draw_2d(e,
+        |context, graphics, _device| {
+            // Everything's drawn as if the square were 35x35, then scaled to its size.
+            let t = context.transform.trans(px, py).scale(size / SQUARE, size / SQUARE);
+
+            // Draw 33x33 square inside 35x35 space.
+            rectangle(color, [1.0, 1.0, 33.0, 33.0], t, graphics);
+
+            if !visuals.patterns { return; }
+
+            // Glyphs are drawn in translucent black so they read on both light and dark colors.
+            // Coordinates below are relative to the 33x33 square, which starts at (1, 1).
+            let (x, y) = (1.0, 1.0);
+
+            match kind
+            {
+                // I: one long vertical bar.
+                TetriminoKind::I => line(ink, 2.0, [x + 16.5, y + 6.0, x + 16.5, y + 27.0], t, graphics),
+
+                // J: a hook opening to the right, like the piece itself.
+                TetriminoKind::J => {
+                    line(ink, 2.0, [x + 10.0, y + 8.0, x + 10.0, y + 25.0], t, graphics);
+                    line(ink, 2.0, [x + 10.0, y + 25.0, x + 23.0, y + 25.0], t, graphics);
+                }
 
-            let (x_offs, y_offs) = well_to_pixel(row as i32, col as i32);
-            win.draw_2d(e,
-                |context, graphics, _device| {
-                    // Draw 33x33 square inside 35x35 space.
-                    rectangle( [1.0, 1.0, 1.0, 1.0], [x_offs + 1.0, y_offs + 1.0, 33.0, 33.0], context.transform, graphics);
+                // L: the mirror image of J.
+                TetriminoKind::L => {
+                    line(ink, 2.0, [x + 23.0, y + 8.0, x + 23.0, y + 25.0], t, graphics);
+                    line(ink, 2.0, [x + 10.0, y + 25.0, x + 23.0, y + 25.0], t, graphics);
                 }
-            );
+
+                // O: a ring.
+                TetriminoKind::O => Ellipse::new_border(ink, 2.0).draw([x + 8.0, y + 8.0, 17.0, 17.0], &context.draw_state, t, graphics),
+
+                // S and Z: diagonal hatching, leaning in opposite directions.
+                TetriminoKind::S => for offs in [0.0, 10.0, 20.0].iter() {
+                    line(ink, 1.5, [x + 3.0 + offs, y + 30.0, x + 13.0 + offs, y + 3.0], t, graphics);
+                },
+                TetriminoKind::Z => for offs in [0.0, 10.0, 20.0].iter() {
+                    line(ink, 1.5, [x + 3.0 + offs, y + 3.0, x + 13.0 + offs, y + 30.0], t, graphics);
+                },
+
+                // T: a little T.
+                TetriminoKind::T => {
+                    line(ink, 2.0, [x + 8.0, y + 9.0, x + 25.0, y + 9.0], t, graphics);
+                    line(ink, 2.0, [x + 16.5, y + 9.0, x + 16.5, y + 25.0], t, graphics);
+                }
+
+                // Anything else is from a piece set, and gets the first letter of its name, below.
+                _ => ()
+            }
         }
+    );
+
+    // The letter's 5x7 pixels at 3 to the pixel, in the middle of the 35x35 square, scaled like the rest.
+    if visuals.patterns && !kind.is_standard() {
+        let letter: String = pieces.name(kind).chars().take(1).collect();
+        let scale = size / SQUARE;
+        draw_text(win, e, px + 10.0 * scale, py + 7.0 * scale, 3.0 * scale, ink, &letter);
     }
 }
 
 
-/// Takes a well coordinate (row, column) and converts it to a pixel value (x, y).
-/// The pixel value is the upper-left-most pixel of the square at the given well coordinate.
-fn well_to_pixel(row: i32, col: i32) -> (f64, f64)
+impl WellLayout
 {
-    ( (col as f64) * 35.0 + 465.0, (row as f64) * 35.0 - 140.0 )
+    /// Lays a well out in the given [x, y, width, height] area: SQUARE pixels a square, or smaller if it won't fit,
+    /// in the middle of the area at the bottom.
+    fn fit(well: &Well, area: [f64; 4]) -> Self
+    {
+        let (cols, rows) = (well.cols() as f64, well.visible_rows() as f64);
+        let square = SQUARE.min(area[2] / cols).min(area[3] / rows);
+
+        WellLayout { left: area[0] + (area[2] - square * cols) / 2.0, top: area[1] + area[3] - square * rows, square, hidden: well.hidden() as i32 }
+    }
+
+    /// Takes a well coordinate (row, column) and converts it to a pixel value (x, y).
+    /// The pixel value is the upper-left-most pixel of the square at the given well coordinate. Hidden rows are above the top.
+    fn pixel(&self, row: i32, col: i32) -> (f64, f64)
+    {
+        ( self.left + (col as f64) * self.square, self.top + ((row - self.hidden) as f64) * self.square )
+    }
 }
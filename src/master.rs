@@ -0,0 +1,216 @@
+/*
+
+Master mode, after the arcade Tetris: The Grand Master games.
+
+Levels run from 0 to 999, and reaching 999 wins. Each new piece adds one level and each cleared line adds
+one, except that a new piece can't take the level past the end of a section (99, 199, ... or 998). Only
+clearing lines gets you over those.
+
+Gravity is counted the way TGM counts it internally, in 1/256ths of a row per update. It starts out slower
+than the other modes, reaches 1G (a row every update) at level 251, and is 20G from level 500 on. At 20G a
+piece falls the whole height of the well in a single update, so it appears already sitting on the stack.
+
+Landed pieces don't lock straight away. Each gets a lock delay to slide or rotate into place, which starts
+over whenever the piece falls a row. (Soft dropping a landed piece locks it at once.) After a lock comes
+ARE, a short wait before the next piece appears, which is longer when lines were cleared. Both get shorter
+in the later sections, following TGM2's Master timings. TGM runs at 60 frames a second, same as the game
+does everywhere, so those carry over frame for frame, as updates.
+
+Grades are the first TGM's: 9 up to S9 by score, or GM for reaching 999 while keeping up the pace at
+levels 300 and 500 along the way.
+
+*/
+
+use crate::{apply_keys, lock_piece, would_collide, Action, GameState, NORMAL_SPEED, UPDATES_PER_SECOND};
+
+
+/// The level that ends the game.
+pub const MASTER_MAX_LEVEL: u32 = 999;
+
+/// 20G, the fastest gravity there is: 20 rows per update, or the whole visible well.
+pub const TWENTY_G: u32 = 20 * 256;
+
+/// Internal gravity, in 1/256 rows per update, from each level on. (Yes, it slows right down again at 200.)
+const GRAVITY: [(u32, u32); 30] = [ (0, 4), (30, 6), (35, 8), (40, 10), (50, 12), (60, 16), (70, 32), (80, 48), (90, 64), (100, 80),
+                                    (120, 96), (140, 112), (160, 128), (170, 144), (200, 4), (220, 32), (230, 64), (233, 96), (236, 128), (239, 160),
+                                    (243, 192), (247, 224), (251, 256), (300, 512), (330, 768), (360, 1024), (400, 1280), (420, 1024), (450, 768), (500, TWENTY_G) ];
+
+/// The score needed for each grade, lowest first.
+const GRADES: [(u32, &str); 18] = [ (0, "9"), (400, "8"), (800, "7"), (1400, "6"), (2000, "5"), (3500, "4"), (5500, "3"), (8000, "2"), (12000, "1"),
+                                    (16000, "S1"), (22000, "S2"), (30000, "S3"), (40000, "S4"), (52000, "S5"), (66000, "S6"), (82000, "S7"),
+                                    (100000, "S8"), (120000, "S9") ];
+
+/// To still be in the running for GM on reaching each of these levels: at least this score, within this many updates.
+const GM_CHECKPOINTS: [(u32, u32, u32); 3] = [ (300, 12000, (4 * 60 + 15) * UPDATES_PER_SECOND),
+                                               (500, 40000, (7 * 60 + 30) * UPDATES_PER_SECOND),
+                                               (999, 126000, (13 * 60 + 30) * UPDATES_PER_SECOND) ];
+
+
+/// Master mode's own bookkeeping, kept in GameState::master.
+#[derive(Copy, Clone)]
+pub struct Master
+{
+    pub level: u32,         // 0 to 999.
+    pub gravity: u32,       // How far the current piece has fallen toward the next row, in 1/256ths.
+    pub lock_frames: u32,   // Updates the current piece has spent on the ground since it last fell a row.
+    pub soft_rows: u32,     // Rows the current piece has been soft dropped. Worth points when it clears lines.
+    pub combo: u32,         // Score multiplier for clearing lines with piece after piece. 1 when there's no combo going.
+    pub missed_gm: bool     // Fell short at one of the GM checkpoints.
+}
+
+
+impl Default for Master
+{
+    fn default() -> Self
+    {
+        Master { level: 0, gravity: 0, lock_frames: 0, soft_rows: 0, combo: 1, missed_gm: false }
+    }
+}
+
+
+/// Frames of ARE after a lock, ARE after a lock that cleared lines (including the line clear delay), and lock delay.
+pub struct Timings
+{
+    pub are: u32,
+    pub line_are: u32,
+    pub lock_delay: u32
+}
+
+
+/// Internal gravity at the given level, in 1/256 rows per update.
+pub fn internal_gravity(level: u32) -> u32
+{
+    GRAVITY.iter().rev().find(|(from, _)| level >= *from).map(|(_, gravity)| *gravity).unwrap()
+}
+
+
+/// Whether pieces fall at 20G: all the way down in the update they appear, so they're always on the ground.
+pub fn twenty_g(game_state: &GameState) -> bool
+{
+    internal_gravity(game_state.master.level) * game_state.speed / NORMAL_SPEED >= TWENTY_G
+}
+
+
+/// ARE and lock delay at the given level.
+pub fn timings(level: u32) -> Timings
+{
+    match level
+    {
+        0..=499   => Timings { are: 25, line_are: 25 + 40, lock_delay: 30 },
+        500..=599 => Timings { are: 25, line_are: 25 + 25, lock_delay: 30 },
+        600..=699 => Timings { are: 25, line_are: 16 + 16, lock_delay: 30 },
+        700..=799 => Timings { are: 16, line_are: 12 + 12, lock_delay: 30 },
+        800..=899 => Timings { are: 12, line_are: 6 + 6,   lock_delay: 30 },
+        _         => Timings { are: 12, line_are: 6 + 6,   lock_delay: 17 }
+    }
+}
+
+
+/// The grade a game has earned so far.
+pub fn grade(game_state: &GameState) -> &'static str
+{
+    if game_state.master.level >= MASTER_MAX_LEVEL && !game_state.master.missed_gm { return "GM"; }
+
+    GRADES.iter().rev().find(|(score, _)| game_state.score >= *score).map(|(_, name)| *name).unwrap()
+}
+
+
+/// The level a new piece can't take you past: the end of the current section.
+pub fn section_stop(level: u32) -> u32
+{
+    (level / 100 * 100 + 99).min(MASTER_MAX_LEVEL - 1)
+}
+
+
+/// One update of Master mode: ARE, then the keys, then gravity, then maybe locking.
+pub(crate) fn update(game_state: &mut GameState)
+{
+    // ARE. The next piece hasn't appeared yet, so there's nothing to move.
+    if game_state.spawn_delay > 0
+    {
+        game_state.spawn_delay -= 1;
+        game_state.inputs.clear();
+        game_state.repeats = 0;
+        return;
+    }
+
+    let row_before = game_state.ttmo_row;
+    let grounded = |game_state: &GameState|
+        would_collide(&game_state.curr_ttmo, &game_state.well, &(game_state.ttmo_row + 1), &game_state.ttmo_col);
+
+    // Down on a landed piece locks it right away. On a falling one, it's a soft drop, worth a point per row.
+    let soft_drop = game_state.inputs.contains(&Action::SoftDrop);
+    let soft_lock = soft_drop && grounded(game_state);
+    if soft_drop && !soft_lock { game_state.master.soft_rows += 1; }
+
+    // Keys before gravity, so a piece slid off a ledge at 20G drops in the same update.
+    apply_keys(game_state);
+
+    game_state.master.gravity += internal_gravity(game_state.master.level) * game_state.speed / NORMAL_SPEED;
+    while game_state.master.gravity >= 256 && !grounded(game_state)
+    {
+        game_state.master.gravity -= 256;
+        game_state.ttmo_row += 1;
+        game_state.rotated_last = false;
+    }
+
+    if !grounded(game_state) { return; }
+
+    // Falling a row, by gravity or by a drop, starts the lock delay over.
+    game_state.master.gravity = 0;
+    if game_state.ttmo_row > row_before { game_state.master.lock_frames = 0; }
+    game_state.master.lock_frames += 1;
+
+    if soft_lock || game_state.master.lock_frames >= timings(game_state.master.level).lock_delay
+    {
+        game_state.master.lock_frames = 0;
+        lock_piece(game_state);
+    }
+}
+
+
+/// TGM scoring for a piece that just locked, clearing the given number of lines at the given level.
+pub(crate) fn clear_points(game_state: &mut GameState, level: u32, cleared: u32) -> u32
+{
+    let master = &mut game_state.master;
+    let soft_rows = master.soft_rows;
+    master.soft_rows = 0;
+
+    if cleared == 0
+    {
+        master.combo = 1;
+        return 0;
+    }
+
+    master.combo += 2 * cleared - 2;
+
+    // Clearing the whole well (a "bravo") is worth four times as much.
+    let bravo = if game_state.well.iter().all(|row| row.iter().all(|square| *square == 0)) { 4 } else { 1 };
+    ((level + cleared).div_ceil(4) + soft_rows) * cleared * master.combo * bravo
+}
+
+
+/// The next piece is in place: start the ARE before it appears, and move the level on by one, unless it's at a section stop.
+pub(crate) fn next_piece(game_state: &mut GameState, cleared: u32)
+{
+    let level = game_state.master.level;
+    let timings = timings(level);
+
+    game_state.spawn_delay = if cleared > 0 { timings.line_are } else { timings.are };
+    game_state.master.gravity = 0;
+
+    if level < section_stop(level) { advance(game_state, level + 1); }
+}
+
+
+/// Moves the level up, checking the pace for GM at any checkpoints passed on the way.
+pub(crate) fn advance(game_state: &mut GameState, level: u32)
+{
+    for (checkpoint, score, frames) in GM_CHECKPOINTS.iter()
+    {
+        let passed = game_state.master.level < *checkpoint && level >= *checkpoint;
+        if passed && (game_state.score < *score || game_state.frames > *frames) { game_state.master.missed_gm = true; }
+    }
+
+    game_state.master.level = level;
+}
@@ -0,0 +1,271 @@
+/*
+
+Game modes. Each one is the same game with a different goal, or a different way for it to end.
+
+*/
+
+use std::fmt;
+use std::str::FromStr;
+
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Mode
+{
+    Endless,                        // The original: play until you top out.
+    Sprint { lines: u32 },          // Clear this many lines as fast as possible.
+    Ultra { minutes: u32 },         // Score as much as possible before the clock runs out.
+    Marathon { start_level: u32 },  // NES rules: NES speeds, NES scoring, play until you top out.
+    Master,                         // TGM rules: levels 0 to 999, up to 20G, graded. See master.rs.
+    Cheese { rows: u32, messiness: u32, rise: u32 },   // Dig out this many rows of garbage as fast as possible. See garbage.rs.
+    Versus,                         // One side of a two-player match: endless rules, plus garbage. See versus.rs.
+    Puzzle                          // A set-up well, set pieces and a goal. Which puzzle is in GameState::puzzle. See puzzles.rs.
+}
+
+
+/// The line goals offered for Sprint.
+pub const SPRINT_GOALS: [u32; 3] = [20, 40, 100];
+
+/// The time limits offered for Ultra.
+pub const ULTRA_MINUTES: [u32; 2] = [2, 3];
+
+/// The amounts of garbage offered for Cheese. (Any amount works from the command line.)
+pub const CHEESE_ROWS: [u32; 3] = [10, 18, 100];
+
+/// Cheese's usual messiness: every hole somewhere new.
+pub const CHEESE_MESSINESS: u32 = 100;
+
+/// Marathon can start on any level from 0 up to this one, same as the NES level select (with A held).
+pub const MARATHON_MAX_START: u32 = 19;
+
+/// The NTSC NES runs the game once per video frame, which is a little faster than 60 Hz.
+pub const NES_UPDATES_PER_SECOND: f64 = 60.0988;
+
+/// NES points for clearing 0, 1, 2, 3 or 4 lines at once, before multiplying by (level + 1).
+pub const NES_LINE_SCORES: [u32; 5] = [0, 40, 100, 300, 1200];
+
+
+impl Mode
+{
+    /// Name to show on screen.
+    pub fn title(&self) -> String
+    {
+        match self
+        {
+            Mode::Endless           => "ENDLESS".to_string(),
+            Mode::Sprint { lines }  => format!("SPRINT {}", lines),
+            Mode::Ultra { minutes } => format!("ULTRA {} MIN", minutes),
+            Mode::Marathon { .. }   => "NES MARATHON".to_string(),
+            Mode::Master            => "MASTER".to_string(),
+            Mode::Cheese { rows, .. } => format!("CHEESE {}", rows),
+            Mode::Versus            => "VERSUS".to_string(),
+            Mode::Puzzle            => "PUZZLES".to_string()
+        }
+    }
+
+    /// How many times per second game_update() should be called for this mode.
+    pub fn updates_per_second(&self) -> f64
+    {
+        match self
+        {
+            Mode::Marathon { .. } => NES_UPDATES_PER_SECOND,
+            _ => f64::from(crate::UPDATES_PER_SECOND)
+        }
+    }
+
+    /// How many updates there are now for each one there was before every mode ran at 60 a second: 2 for the modes
+    /// that ran at 30, 1 for NES Marathon and Master, which always ran at their own 60. For bringing replays, high
+    /// scores and such from then up to date.
+    pub fn updates_per_old_update(&self) -> u32
+    {
+        match self
+        {
+            Mode::Marathon { .. } | Mode::Master => 1,
+            _ => 2
+        }
+    }
+
+    /// Whether pieces can be put aside with Action::Hold. NES and TGM had no hold, so Marathon and Master don't either,
+    /// and puzzles deal exactly the pieces they were set with. (Except one that starts with a piece in the hold: see
+    /// GameState::has_hold().)
+    pub fn has_hold(&self) -> bool
+    {
+        !matches!(self, Mode::Marathon { .. } | Mode::Master | Mode::Puzzle)
+    }
+
+    /// How many updates a game of this mode lasts, if it's on the clock.
+    pub fn time_limit(&self) -> Option<u32>
+    {
+        match self
+        {
+            Mode::Ultra { minutes } => Some(minutes * 60 * crate::UPDATES_PER_SECOND),
+            _ => None
+        }
+    }
+}
+
+
+/// Modes are written as e.g. "endless", "sprint-40", "ultra-3", "marathon-18" or "master".
+/// Cheese is "cheese-18", optionally followed by a messiness percentage and seconds between rising rows: "cheese-18-m30-r5". in replay files and on command lines.
+impl fmt::Display for Mode
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Mode::Endless           => write!(f, "endless"),
+            Mode::Sprint { lines }  => write!(f, "sprint-{}", lines),
+            Mode::Ultra { minutes } => write!(f, "ultra-{}", minutes),
+            Mode::Marathon { start_level } => write!(f, "marathon-{}", start_level),
+            Mode::Master            => write!(f, "master"),
+
+            // Cheese options only get written when they're not the usual ones.
+            Mode::Cheese { rows, messiness, rise } => {
+                write!(f, "cheese-{}", rows)?;
+                if *messiness != CHEESE_MESSINESS { write!(f, "-m{}", messiness)?; }
+                if *rise != 0 { write!(f, "-r{}", rise)?; }
+                Ok(())
+            }
+
+            Mode::Versus            => write!(f, "versus"),
+            Mode::Puzzle            => write!(f, "puzzle")
+        }
+    }
+}
+
+
+impl FromStr for Mode
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let bad = || format!("unknown mode '{}' (expected endless, sprint-20, sprint-40, sprint-100, ultra-2, ultra-3, marathon-0 to marathon-19, master, cheese-N[-mPERCENT][-rSECONDS], or versus)", s);
+
+        match s.split_once('-')
+        {
+            None if s == "endless" => Ok(Mode::Endless),
+            None if s == "master"  => Ok(Mode::Master),
+            None if s == "versus"  => Ok(Mode::Versus),
+            Some(("sprint", lines)) => {
+                let lines = lines.parse().map_err(|_| bad())?;
+                if !SPRINT_GOALS.contains(&lines) { return Err(bad()); }
+                Ok(Mode::Sprint { lines })
+            }
+            Some(("ultra", minutes)) => {
+                let minutes = minutes.parse().map_err(|_| bad())?;
+                if !ULTRA_MINUTES.contains(&minutes) { return Err(bad()); }
+                Ok(Mode::Ultra { minutes })
+            }
+            Some(("marathon", start_level)) => {
+                let start_level = start_level.parse().map_err(|_| bad())?;
+                if start_level > MARATHON_MAX_START { return Err(bad()); }
+                Ok(Mode::Marathon { start_level })
+            }
+            Some(("cheese", options)) => {
+                let mut options = options.split('-');
+                let rows = options.next().and_then(|rows| rows.parse().ok()).ok_or_else(bad)?;
+                let (mut messiness, mut rise) = (CHEESE_MESSINESS, 0);
+
+                for option in options
+                {
+                    let value = option.get(1..).and_then(|value| value.parse().ok()).ok_or_else(bad)?;
+                    match option.chars().next() {
+                        Some('m') if value <= 100 => messiness = value,
+                        Some('r') => rise = value,
+                        _ => return Err(bad())
+                    }
+                }
+                if rows == 0 { return Err(bad()); }
+                Ok(Mode::Cheese { rows, messiness, rise })
+            }
+            _ => Err(bad())
+        }
+    }
+}
+
+
+/// Why a game ended.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum GameOver
+{
+    TopOut,         // A new piece had nowhere to go.
+    GoalReached,    // The mode's goal was met, e.g. 40 lines in Sprint.
+    TimeUp,         // The clock ran out, in Ultra.
+    OutOfPieces     // A puzzle's pieces were all used up without reaching its goal.
+}
+
+
+impl GameOver
+{
+    /// Short name, as used in rustris-sim's output.
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            GameOver::TopOut      => "top_out",
+            GameOver::GoalReached => "goal_reached",
+            GameOver::TimeUp      => "time_up",
+            GameOver::OutOfPieces => "out_of_pieces"
+        }
+    }
+}
+
+
+/// NES gravity: how many frames a piece waits before falling one row, on each level.
+pub fn nes_frames_per_row(level: u32) -> u32
+{
+    const SLOW_LEVELS: [u32; 9] = [48, 43, 38, 33, 28, 23, 18, 13, 8];
+
+    match level
+    {
+        0..=8   => SLOW_LEVELS[level as usize],
+        9       => 6,
+        10..=12 => 5,
+        13..=15 => 4,
+        16..=18 => 3,
+        19..=28 => 2,
+        _       => 1    // The "kill screen" speed.
+    }
+}
+
+
+/// The NES level after clearing the given number of lines, having started on start_level.
+///
+/// The first level up doesn't come after 10 lines when starting above level 0. It comes after
+/// (start_level * 10 + 10) lines, or max(100, start_level * 10 - 50) if that's fewer. Starting on 18
+/// means 130 lines before level 19, for instance. After that it's every 10 lines, as usual.
+pub fn nes_level(start_level: u32, lines: u32) -> u32
+{
+    let first_level_up = (start_level * 10 + 10).min((start_level * 10).saturating_sub(50).max(100));
+
+    if lines < first_level_up { start_level }
+    else { start_level + 1 + (lines - first_level_up) / 10 }
+}
+
+
+/// Turns a number of updates into "m:ss.mmm".
+pub fn format_time(frames: u32) -> String
+{
+    format_millis(u64::from(frames) * 1000 / u64::from(crate::UPDATES_PER_SECOND))
+}
+
+
+/// Same as format_time(), for a mode that doesn't run at the usual UPDATES_PER_SECOND, like NES Marathon.
+pub fn format_time_at(frames: u32, updates_per_second: f64) -> String
+{
+    format_millis((f64::from(frames) * 1000.0 / updates_per_second) as u64)
+}
+
+
+fn format_millis(millis: u64) -> String
+{
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+
+
+/// Turns a difference in updates into "+s.mmm" or "-s.mmm".
+pub fn format_delta(frames: i64) -> String
+{
+    let millis = frames.abs() * 1000 / i64::from(crate::UPDATES_PER_SECOND);
+    format!("{}{}.{:03}", if frames < 0 { '-' } else { '+' }, millis / 1000, millis % 1000)
+}
@@ -0,0 +1,450 @@
+/*
+
+Network versus: two copies of rustris playing one Versus match over TCP, in deterministic lockstep.
+
+Nothing about the match itself goes over the wire, just keys. Both sides build the same Versus from the
+same seed and rules, feed it the same keys on the same updates, and so see the same match. Each update,
+a side sends its own keys for a few updates ahead (the input delay), and only moves the match on once it
+has both players' keys for the update it's on. A longer delay hides more network lag, but makes the keys
+feel slower. On a LAN, a few updates (of the 60 a second) is plenty.
+
+Every so often both sides hash the whole match and send the hash over. If the hashes ever differ, the two
+matches have drifted apart (a bug, or two different builds), and the match stops with an out of sync error
+instead of carrying on with each side seeing something different.
+
+The protocol is lines of text, so it can be watched with netcat:
+
+    rustris-net 4                    both sides, first thing: protocol version
+    rule single 0                    host: the versus rules, one per line, as in a rules file
+    start 1234 4                     host: seed and input delay. The match starts.
+    keys 4 left,cw                   the sender's keys for update 4, in the order pressed ("-" for none)
+    hash 120 9f3c0d2e8a71b455        the sender's match hash after 120 updates, in hex
+    bye                              the sender is leaving
+
+The host is player 1 and the one who joins is player 2. A side that hears nothing for NET_TIMEOUT, or has
+the connection drop, treats it as the other player leaving.
+
+*/
+
+use rand::{thread_rng, Rng};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::{format_actions, parse_actions, Action, GameState};
+use crate::versus::{Rules, Versus};
+
+
+/// Bumped whenever the protocol changes, so mismatched builds refuse each other instead of drifting apart.
+pub const NET_PROTOCOL_VERSION: u32 = 4;
+
+/// The port hosts listen on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 7341;
+
+/// Updates between pressing a key and it taking effect, unless the host says otherwise.
+pub const DEFAULT_INPUT_DELAY: u32 = 4;
+
+/// How long to wait to hear from the other side before giving up on them.
+pub const NET_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Updates between state hashes: every two seconds.
+const HASH_INTERVAL: u32 = 120;
+
+
+#[derive(Debug)]
+pub enum NetError
+{
+    Io(io::Error),
+    Version(String),     // The other side speaks some other protocol, or version of it.
+    Protocol(String),    // The other side sent something that makes no sense.
+    Disconnected,        // The other side left, or went quiet for too long.
+    Desync(u32)          // The two matches disagreed at this update.
+}
+
+
+impl fmt::Display for NetError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            NetError::Io(err)        => write!(f, "{}", err),
+            NetError::Version(hello) => write!(f, "other side isn't rustris-net {}: '{}'", NET_PROTOCOL_VERSION, hello),
+            NetError::Protocol(msg)  => write!(f, "bad message: {}", msg),
+            NetError::Disconnected   => write!(f, "other player left"),
+            NetError::Desync(frame)  => write!(f, "out of sync at update {}", frame)
+        }
+    }
+}
+
+
+impl From<io::Error> for NetError
+{
+    fn from(err: io::Error) -> Self { NetError::Io(err) }
+}
+
+
+/// A Versus match with the other player on the far end of a TCP connection.
+pub struct NetVersus
+{
+    pub versus: Versus,
+    pub local: usize,                  // Which player is on this side: 0 when hosting, 1 when joined.
+    pub input_delay: u32,
+    pub local_keys: Vec<Action>,       // Keys pressed on this side since they were last sent, in order.
+    frame: u32,                        // The next update to run.
+    sent_frame: u32,                   // The next update to send local keys for.
+    remote_frame: u32,                 // The next update to expect remote keys for.
+    inputs: [VecDeque<Vec<Action>>; 2],    // Each player's keys, from update `frame` on.
+    local_hashes: VecDeque<(u32, u64)>,
+    remote_hashes: VecDeque<(u32, u64)>,
+    lines: LineStream
+}
+
+
+impl NetVersus
+{
+    /// Waits for someone to join on the given port, then starts a match with a fresh seed and these rules.
+    pub fn host(port: u16, rules: Rules, input_delay: u32) -> Result<Self, NetError>
+    {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let (stream, _address) = listener.accept()?;
+
+        let seed = thread_rng().gen();
+        let mut net = NetVersus::new(stream, Versus::with_seed(seed, rules.clone()), 0, input_delay)?;
+
+        net.lines.send(&format!("rustris-net {}", NET_PROTOCOL_VERSION))?;
+        net.check_hello()?;
+        for line in rules.to_text().lines().filter(|line| !line.starts_with('#')) { net.lines.send(&format!("rule {}", line))?; }
+        net.lines.send(&format!("start {} {}", seed, input_delay))?;
+
+        net.lines.stream.set_nonblocking(true)?;
+        Ok(net)
+    }
+
+    /// Joins a host at the given address ("192.168.1.5:7341"), and plays by its seed and rules.
+    pub fn join<A: ToSocketAddrs>(address: A) -> Result<Self, NetError>
+    {
+        let stream = TcpStream::connect(address)?;
+        let mut net = NetVersus::new(stream, Versus::with_seed(0, Rules::default()), 1, 0)?;
+
+        net.lines.send(&format!("rustris-net {}", NET_PROTOCOL_VERSION))?;
+        net.check_hello()?;
+
+        let mut rules_text = String::new();
+        loop
+        {
+            let line = net.lines.read_line()?;
+            let mut words = line.split_whitespace();
+            match words.next()
+            {
+                Some("rule") => {
+                    rules_text.push_str(&words.collect::<Vec<&str>>().join(" "));
+                    rules_text.push('\n');
+                }
+                Some("start") => {
+                    let numbers: Vec<u64> = words.filter_map(|word| word.parse().ok()).collect();
+                    let (seed, input_delay) = match numbers.as_slice() {
+                        [seed, input_delay] => (*seed, *input_delay as u32),
+                        _ => return Err(NetError::Protocol(line))
+                    };
+                    let rules = Rules::from_text(&rules_text).map_err(NetError::Protocol)?;
+
+                    net.versus = Versus::with_seed(seed, rules);
+                    net.set_input_delay(input_delay);
+                    break;
+                }
+                _ => return Err(NetError::Protocol(line))
+            }
+        }
+
+        net.lines.stream.set_nonblocking(true)?;
+        Ok(net)
+    }
+
+    fn new(stream: TcpStream, versus: Versus, local: usize, input_delay: u32) -> Result<Self, NetError>
+    {
+        let mut net = NetVersus {
+            versus, local, input_delay: 0,
+            local_keys: Vec::new(),
+            frame: 0, sent_frame: 0, remote_frame: 0,
+            inputs: [VecDeque::new(), VecDeque::new()],
+            local_hashes: VecDeque::new(),
+            remote_hashes: VecDeque::new(),
+            lines: LineStream::new(stream)?
+        };
+        net.set_input_delay(input_delay);
+        Ok(net)
+    }
+
+    /// Nobody pressed anything during the first few updates, before the first keys could arrive.
+    fn set_input_delay(&mut self, input_delay: u32)
+    {
+        self.input_delay = input_delay;
+        self.sent_frame = input_delay;
+        self.remote_frame = input_delay;
+        self.inputs = [VecDeque::from(vec![Vec::new(); input_delay as usize]), VecDeque::from(vec![Vec::new(); input_delay as usize])];
+    }
+
+    /// Call once per update. Sends this side's keys, and runs the match on by one update if the other side's
+    /// keys for it have arrived. Returns whether it did.
+    pub fn step(&mut self) -> Result<bool, NetError>
+    {
+        if self.versus.outcome.is_some() { return Ok(false); }
+
+        // Never get more than the input delay ahead of the match, or keys would pile up while the other side is slow.
+        if self.sent_frame <= self.frame + self.input_delay
+        {
+            let keys = std::mem::take(&mut self.local_keys);
+            self.lines.send(&format!("keys {} {}", self.sent_frame, format_actions(&keys)))?;
+            self.inputs[self.local].push_back(keys);
+            self.sent_frame += 1;
+        }
+
+        self.receive()?;
+
+        let remote = 1 - self.local;
+        if self.inputs[remote].is_empty()
+        {
+            if self.lines.closed || self.lines.last_heard.elapsed() > NET_TIMEOUT { return Err(NetError::Disconnected); }
+            return Ok(false);
+        }
+
+        for player in 0..2 { self.versus.players[player].inputs = self.inputs[player].pop_front().unwrap(); }
+        self.versus.update();
+        self.frame += 1;
+
+        if self.frame.is_multiple_of(HASH_INTERVAL)
+        {
+            let hash = versus_hash(&self.versus);
+            self.lines.send(&format!("hash {} {:016x}", self.frame, hash))?;
+            self.local_hashes.push_back((self.frame, hash));
+        }
+        self.check_hashes()?;
+
+        Ok(true)
+    }
+
+    /// Updates the match has run so far.
+    pub fn frame(&self) -> u32 { self.frame }
+
+    /// True while the match is held up waiting for the other side's keys.
+    pub fn waiting(&self) -> bool { self.inputs[1 - self.local].is_empty() }
+
+    fn check_hashes(&mut self) -> Result<(), NetError>
+    {
+        while let (Some(local), Some(remote)) = (self.local_hashes.front(), self.remote_hashes.front())
+        {
+            if local != remote { return Err(NetError::Desync(local.0)); }
+            self.local_hashes.pop_front();
+            self.remote_hashes.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Handles everything the other side has sent so far, without waiting for more.
+    fn receive(&mut self) -> Result<(), NetError>
+    {
+        self.lines.fill()?;
+
+        while let Some(line) = self.lines.take_line()
+        {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next())
+            {
+                (Some("keys"), Some(frame), Some(flags)) => {
+                    let keys = parse_actions(flags).ok_or_else(|| NetError::Protocol(line.clone()))?;
+                    if frame.parse::<u32>().ok() != Some(self.remote_frame) { return Err(NetError::Protocol(line)); }
+
+                    self.inputs[1 - self.local].push_back(keys);
+                    self.remote_frame += 1;
+                }
+                (Some("hash"), Some(frame), Some(hash)) => {
+                    let frame = frame.parse().map_err(|_| NetError::Protocol(line.clone()))?;
+                    let hash = u64::from_str_radix(hash, 16).map_err(|_| NetError::Protocol(line.clone()))?;
+                    self.remote_hashes.push_back((frame, hash));
+                }
+                (Some("bye"), None, None) => {
+                    // Nothing more to send them either. Closing our half lets them know we got it, so they can stop waiting in goodbye().
+                    self.lines.closed = true;
+                    let _ = self.lines.stream.shutdown(Shutdown::Write);
+                }
+                _ => return Err(NetError::Protocol(line))
+            }
+        }
+        Ok(())
+    }
+
+    fn check_hello(&mut self) -> Result<(), NetError>
+    {
+        let hello = self.lines.read_line()?;
+        if hello != format!("rustris-net {}", NET_PROTOCOL_VERSION) { return Err(NetError::Version(hello)); }
+        Ok(())
+    }
+}
+
+
+impl Drop for NetVersus
+{
+    fn drop(&mut self)
+    {
+        self.lines.goodbye();
+    }
+}
+
+
+/// A TCP connection carrying lines of text, which is all the network protocols here are.
+pub struct LineStream
+{
+    pub stream: TcpStream,
+    received: Vec<u8>,          // Bytes read but not yet made into whole lines.
+    pub last_heard: Instant,
+    pub closed: bool            // The other side said bye or closed the connection. Lines already read may still need handling.
+}
+
+
+impl LineStream
+{
+    pub fn new(stream: TcpStream) -> io::Result<Self>
+    {
+        stream.set_nodelay(true)?;                      // Lines are tiny and late ones stall things, so don't let them sit in a buffer.
+        stream.set_read_timeout(Some(NET_TIMEOUT))?;    // Only matters while the stream blocks. Once it doesn't, reads never wait.
+
+        Ok(LineStream { stream, received: Vec::new(), last_heard: Instant::now(), closed: false })
+    }
+
+    /// Waits for a whole line. Only for while the stream still blocks, like during a handshake.
+    pub fn read_line(&mut self) -> Result<String, NetError>
+    {
+        loop
+        {
+            if let Some(line) = self.take_line() { return Ok(line); }
+            if self.closed { return Err(NetError::Disconnected); }
+            self.fill()?;
+        }
+    }
+
+    /// Reads whatever has arrived. (When the stream blocks, waits for at least something.)
+    pub fn fill(&mut self) -> Result<(), NetError>
+    {
+        let mut buffer = [0u8; 4096];
+        loop
+        {
+            match self.stream.read(&mut buffer)
+            {
+                Ok(0) => {
+                    self.closed = true;
+                    return Ok(());
+                }
+                Ok(count) => {
+                    self.received.extend_from_slice(&buffer[..count]);
+                    self.last_heard = Instant::now();
+                    if count < buffer.len() { return Ok(()); }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => return Err(NetError::Disconnected),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) if err.kind() == io::ErrorKind::ConnectionReset => return Err(NetError::Disconnected),
+                Err(err) => return Err(err.into())
+            }
+        }
+    }
+
+    /// The next whole line already read, if there is one.
+    pub fn take_line(&mut self) -> Option<String>
+    {
+        let end = self.received.iter().position(|byte| *byte == b'\n')?;
+        let line: Vec<u8> = self.received.drain(..=end).collect();
+        Some(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    pub fn send(&mut self, line: &str) -> Result<(), NetError>
+    {
+        if self.closed { return Ok(()); }
+
+        match self.stream.write_all(format!("{}\n", line).as_bytes())
+        {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe || err.kind() == io::ErrorKind::ConnectionReset => Err(NetError::Disconnected),
+            Err(err) => Err(err.into())
+        }
+    }
+
+    /// Says "bye", so the other side knows this one left on purpose and didn't just go quiet.
+    ///
+    /// Then waits (briefly) for the other side to close its end too. Closing a socket with their lines still unread
+    /// makes the OS reset the connection, which throws away anything still on its way to them, including the keys
+    /// they need to play out the last few updates of a match.
+    pub fn goodbye(&mut self)
+    {
+        let _ = self.stream.set_nonblocking(false);
+        if !self.closed { let _ = self.stream.write_all(b"bye\n"); }
+        let _ = self.stream.shutdown(Shutdown::Write);
+
+        let _ = self.stream.set_read_timeout(Some(Duration::from_secs(2)));
+        let mut buffer = [0u8; 4096];
+        while let Ok(count) = self.stream.read(&mut buffer) {
+            if count == 0 { break; }
+        }
+        self.closed = true;
+    }
+}
+
+
+/// A hash of everything in a game that could tell two copies of it apart. FNV-1a, so it's the same on every build and platform.
+pub fn state_hash(game_state: &GameState) -> u64
+{
+    let mut hash = Fnv::new();
+
+    for row in game_state.well.iter() { hash.bytes(row); }
+    hash.u32(game_state.ttmo_row as u32);
+    hash.u32(game_state.ttmo_col as u32);
+    hash.bytes(&[game_state.curr_ttmo.kind.cell(), game_state.next_ttmo.kind.cell()]);
+    for row in game_state.curr_ttmo.shape.iter() { hash.bytes(row); }
+    for value in [game_state.frames, game_state.pieces, game_state.lines, game_state.score, game_state.combo, game_state.spawn_delay].iter() {
+        hash.u32(*value);
+    }
+    hash.bytes(&[game_state.back_to_back as u8, game_state.game_over.is_some() as u8]);
+    hash.bytes(&[game_state.hold_ttmo.map_or(0, |ttmo| ttmo.kind.cell()), game_state.hold_used as u8]);
+
+    hash.0
+}
+
+
+/// Both players' games, plus the garbage on its way to each.
+pub fn versus_hash(versus: &Versus) -> u64
+{
+    let mut hash = Fnv::new();
+
+    for player in 0..2
+    {
+        hash.u64(state_hash(&versus.players[player]));
+        for incoming in versus.incoming[player].iter() { hash.u32(incoming.lines); hash.u32(incoming.wait); }
+        hash.u32(versus.sent[player]);
+    }
+
+    hash.0
+}
+
+
+struct Fnv(u64);
+
+impl Fnv
+{
+    fn new() -> Self { Fnv(0xcbf2_9ce4_8422_2325) }
+
+    fn bytes(&mut self, bytes: &[u8])
+    {
+        for byte in bytes
+        {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn u32(&mut self, value: u32) { self.bytes(&value.to_le_bytes()); }
+
+    fn u64(&mut self, value: u64) { self.bytes(&value.to_le_bytes()); }
+}
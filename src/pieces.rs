@@ -0,0 +1,523 @@
+/*
+
+Piece sets: which pieces a game's randomizer picks from, and what they look like.
+
+The usual set is the seven tetriminos. Others can be any polyominoes that fit in a 5x5 box: there are built-in
+sets of triominoes, the 18 pentominoes, and a mix, and more can be made as text files in rustris' folder in the
+platform's config directory (e.g. ~/.config/rustris/pieces/mine.pieces on Linux). A set is picked by name, with
+the game.pieces setting.
+
+A set file looks like this:
+
+    rustris-pieces 1
+    name mine
+
+    // Lines starting with // are comments.
+    piece T standard           one of the standard seven, with its usual shape, color, and T-spins
+
+    piece U 0.9 0.5 0.1        a name, then a color: red, green and blue, from 0 to 1
+    #.#
+    ###
+    ...
+    kicks 1,0 -1,0 0,-1        optional: where else to try a turn that doesn't fit, in (columns right, rows down)
+
+    piece X 0.8 0.8 0.8
+    .#.
+    ###
+    .#.
+    rotations 1                optional: how many rotation states it has, 1, 2 or 4 (4 if left out)
+
+    piece S2 0.2 0.6 0.9
+    .##
+    ##.
+    ...
+    state                      or list the rotation states, clockwise from the one it spawns in
+    #..
+    ##.
+    .#.
+
+A shape is a square grid, 1x1 to 5x5, of # for a square and . for none. It's the piece as it spawns, in the top
+left of the piece's box, and unless it says otherwise it turns within that square: a 3x3 shape turns about its
+middle square, and so on. Kicks are tried in order after a turn where the piece is fails, and mirrored (columns
+left) for counterclockwise turns. No kicks, like the standard seven, means a turn that doesn't fit doesn't happen.
+
+*/
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::{turns, Palette, Shape, Tetrimino, TetriminoKind, MAX_KICKS, PIECE_SIZE};
+
+
+const HEADER: &str = "rustris-pieces 1";
+
+/// The sets that come with the game, as set files. (The standard one is made in code. See PieceSet::standard().)
+pub const BUILTIN_SETS: [&str; 4] = ["standard", "triominoes", "pentominoes", "mixed"];
+
+
+/// One piece of a set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PieceDef
+{
+    pub kind: TetriminoKind,
+    pub name: String,
+    pub color: [f32; 4],
+    pub rotations: Vec<Shape>,    // Clockwise from the one it spawns in.
+    pub kicks: Vec<(i32, i32)>
+}
+
+
+impl PieceDef
+{
+    /// The piece, as it spawns.
+    pub fn tetrimino(&self) -> Tetrimino
+    {
+        Tetrimino::with_rotations(self.kind, self.color, &self.rotations, &self.kicks)
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PieceSet
+{
+    pub name: String,
+    pub pieces: Vec<PieceDef>    // In the order the 7-bag starts from.
+}
+
+
+impl PieceSet
+{
+    /// The seven tetriminos, I J L O S T Z.
+    pub fn standard() -> Self
+    {
+        let pieces = TetriminoKind::STANDARD.iter().map(|kind| standard_piece(*kind)).collect();
+        PieceSet { name: "standard".to_string(), pieces }
+    }
+
+    pub fn is_standard(&self) -> bool
+    {
+        self.name == "standard"
+    }
+
+    /// A set by name: one of BUILTIN_SETS, or a .pieces file of that name in the pieces folder.
+    pub fn load(name: &str) -> Result<Self, String>
+    {
+        let text = match name
+        {
+            "standard"    => return Ok(PieceSet::standard()),
+            "triominoes"  => TRIOMINOES.to_string(),
+            "pentominoes" => PENTOMINOES.to_string(),
+            "mixed"       => MIXED.to_string(),
+            _ => {
+                let path = PieceSet::path(name).ok_or_else(|| format!("no piece set called '{}' (there's no config directory to look in)", name))?;
+                match fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound =>
+                        return Err(format!("no piece set called '{}' (built in: {}; or make {})", name, BUILTIN_SETS.join(", "), path.display())),
+                    Err(err) => return Err(format!("{}: {}", path.display(), err))
+                }
+            }
+        };
+
+        let set = PieceSet::from_text(&text)?;
+        if set.name != name { return Err(format!("the piece set in {}.pieces is called '{}'", name, set.name)); }
+        Ok(set)
+    }
+
+    /// Where a set that isn't built in lives. None if the platform doesn't have a config directory.
+    pub fn path(name: &str) -> Option<PathBuf>
+    {
+        dirs::config_dir().map(|dir| dir.join("rustris").join("pieces").join(format!("{}.pieces", name)))
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String>
+    {
+        let mut lines = text.lines().enumerate().map(|(num, line)| (num + 1, line.trim()))
+                            .filter(|(_, line)| !line.is_empty() && !line.starts_with("//"));
+
+        match lines.next() {
+            Some((_, line)) if line == HEADER => (),
+            _ => return Err(format!("not a piece set (expected '{}' on the first line)", HEADER))
+        }
+
+        let mut set = PieceSet { name: String::new(), pieces: Vec::new() };
+        let mut building: Option<Building> = None;
+        let mut custom = 0;
+
+        for (num, line) in lines
+        {
+            let err = |msg: String| format!("line {}: {}", num, msg);
+            let mut words = line.split_whitespace();
+            let first = words.next().unwrap_or("");
+
+            // Grid rows, kicks, rotations and states belong to the piece being built.
+            if line.chars().all(|c| c == '#' || c == '.') || ["kicks", "rotations", "state"].contains(&first)
+            {
+                let piece = building.as_mut().ok_or_else(|| err(format!("'{}' before any piece", line)))?;
+                piece.add(line).map_err(err)?;
+                continue;
+            }
+
+            match first
+            {
+                "name" => set.name = words.next().ok_or_else(|| err("the set needs a name".to_string()))?.to_string(),
+
+                "piece" => {
+                    if let Some(piece) = building.take() { set.pieces.push(piece.finish()?); }
+
+                    let name = words.next().ok_or_else(|| err("a piece needs a name".to_string()))?.to_string();
+                    if set.pieces.iter().any(|piece| piece.name == name) { return Err(err(format!("there's already a piece called '{}'", name))); }
+                    let rest: Vec<&str> = words.collect();
+
+                    if rest == ["standard"] {
+                        let kind = TetriminoKind::STANDARD.iter().copied().find(|kind| standard_name(*kind) == name)
+                            .ok_or_else(|| err(format!("'{}' isn't one of the standard seven (I, J, L, O, S, T or Z)", name)))?;
+                        if set.pieces.iter().any(|piece| piece.kind == kind) { return Err(err(format!("{} is in the set twice", name))); }
+                        set.pieces.push(standard_piece(kind));
+                        continue;
+                    }
+
+                    let color = match rest.iter().map(|word| word.parse::<f32>()).collect::<Result<Vec<f32>, _>>() {
+                        Ok(rgb) if rgb.len() == 3 && rgb.iter().all(|value| (0.0..=1.0).contains(value)) => [rgb[0], rgb[1], rgb[2], 1.0],
+                        _ => return Err(err(format!("piece {} needs a color, three numbers from 0 to 1 (or 'standard')", name)))
+                    };
+                    let kind = TetriminoKind::custom(custom).ok_or_else(|| err("too many pieces".to_string()))?;
+                    custom += 1;
+                    building = Some(Building { num, kind, name, color, grids: vec![Vec::new()], rotations: None, kicks: Vec::new() });
+                }
+
+                _ => return Err(err(format!("expected name, piece, a row of # and ., kicks, rotations or state, not '{}'", line)))
+            }
+        }
+
+        if let Some(piece) = building.take() { set.pieces.push(piece.finish()?); }
+        if set.name.is_empty() || set.name.contains('/') { return Err("the set needs a name line, without any / in it".to_string()); }
+        if set.pieces.is_empty() { return Err("the set has no pieces".to_string()); }
+        Ok(set)
+    }
+
+    /// The piece of the given kind, if it's in the set.
+    pub fn get(&self, kind: TetriminoKind) -> Option<&PieceDef>
+    {
+        self.pieces.iter().find(|piece| piece.kind == kind)
+    }
+
+    /// The color to draw squares of the given kind in. The standard seven go by the palette; the rest are their set's
+    /// own color. (Gray for a kind the set hasn't got.)
+    pub fn color(&self, kind: TetriminoKind, palette: Palette) -> [f32; 4]
+    {
+        match self.get(kind) {
+            _ if kind.is_standard() => palette.color(kind),
+            Some(piece) => piece.color,
+            None => palette.color(kind)
+        }
+    }
+
+    /// A kind's name in this set, for drawing on its squares. "?" for a kind the set hasn't got.
+    pub fn name(&self, kind: TetriminoKind) -> &str
+    {
+        self.get(kind).map_or("?", |piece| piece.name.as_str())
+    }
+}
+
+
+/// A piece partway through being read from a set file.
+struct Building
+{
+    num: usize,                      // The line it started on.
+    kind: TetriminoKind,
+    name: String,
+    color: [f32; 4],
+    grids: Vec<Vec<Vec<u8>>>,        // Its shape, and any more rotation states, a row at a time.
+    rotations: Option<usize>,
+    kicks: Vec<(i32, i32)>
+}
+
+
+impl Building
+{
+    fn add(&mut self, line: &str) -> Result<(), String>
+    {
+        let mut words = line.split_whitespace();
+
+        match words.next()
+        {
+            Some("state") => self.grids.push(Vec::new()),
+
+            Some("rotations") => {
+                match words.next().and_then(|count| count.parse().ok()) {
+                    Some(count @ 1) | Some(count @ 2) | Some(count @ 4) => self.rotations = Some(count),
+                    _ => return Err(format!("piece {}: rotations has to be 1, 2 or 4", self.name))
+                }
+            }
+
+            Some("kicks") => {
+                for kick in words {
+                    let offsets = kick.split_once(',').and_then(|(right, down)| Some((right.parse::<i32>().ok()?, down.parse::<i32>().ok()?)));
+                    match offsets {
+                        Some((right, down)) if right.abs() <= 2 && down.abs() <= 2 => self.kicks.push((right, down)),
+                        _ => return Err(format!("piece {}: bad kick '{}' (expected columns right,rows down, each -2 to 2)", self.name, kick))
+                    }
+                }
+                if self.kicks.len() > MAX_KICKS { return Err(format!("piece {}: at most {} kicks", self.name, MAX_KICKS)); }
+            }
+
+            _ => self.grids.last_mut().unwrap().push(line.chars().map(|c| (c == '#') as u8).collect())
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<PieceDef, String>
+    {
+        let err = |msg: &str| format!("piece {} (line {}): {}", self.name, self.num, msg);
+
+        let size = self.grids[0].len();
+        let mut shapes = Vec::new();
+        for grid in self.grids.iter()
+        {
+            if grid.len() != size || grid.iter().any(|row| row.len() != size) || !(1..=PIECE_SIZE).contains(&size) {
+                return Err(err("every shape has to be a square, the same size, from 1x1 to 5x5"));
+            }
+            let mut shape: Shape = [[0; PIECE_SIZE]; PIECE_SIZE];
+            for (row, squares) in grid.iter().enumerate() { shape[row][..size].copy_from_slice(squares); }
+            shapes.push(shape);
+        }
+
+        let squares = |shape: &Shape| shape.iter().flatten().filter(|square| **square != 0).count();
+        if squares(&shapes[0]) == 0 || shapes.iter().any(|shape| squares(shape) != squares(&shapes[0])) {
+            return Err(err("every rotation state has to have the same number of squares, and at least one"));
+        }
+
+        let rotations = match (shapes.len(), self.rotations) {
+            (1, count) => turns(&shapes[0], size, count.unwrap_or(4)),
+            (2, None) | (4, None) => shapes,
+            (_, None) => return Err(err("a piece lists 1, 2 or 4 rotation states")),
+            (_, Some(_)) => return Err(err("rotations goes with one shape, not with states listed out"))
+        };
+
+        Ok(PieceDef { kind: self.kind, name: self.name, color: self.color, rotations, kicks: self.kicks })
+    }
+}
+
+
+/// The name the standard seven go by, in set files and on their squares.
+fn standard_name(kind: TetriminoKind) -> &'static str
+{
+    match kind
+    {
+        TetriminoKind::I => "I",
+        TetriminoKind::J => "J",
+        TetriminoKind::L => "L",
+        TetriminoKind::O => "O",
+        TetriminoKind::S => "S",
+        TetriminoKind::T => "T",
+        _ => "Z"
+    }
+}
+
+
+fn standard_piece(kind: TetriminoKind) -> PieceDef
+{
+    let ttmo = Tetrimino::new(kind);
+    PieceDef { kind, name: standard_name(kind).to_string(), color: ttmo.color, rotations: ttmo.rotations[..ttmo.rotation_count].to_vec(), kicks: Vec::new() }
+}
+
+
+// The built-in sets. Pieces that aren't the standard seven get kicks one right, one left and one up, so they can be
+// turned against the walls: some of these shapes are awkward enough as it is.
+
+const TRIOMINOES: &str = "\
+rustris-pieces 1
+name triominoes
+
+piece I3 0.95 0.95 0.95
+...
+###
+...
+kicks 1,0 -1,0 0,-1
+
+piece L3 0.3 0.6 1.0
+#.
+##
+kicks 1,0 -1,0 0,-1
+";
+
+
+/// The 18 one-sided pentominoes: the 12 free ones and the mirror images of the six that aren't symmetric (F, L, N, P,
+/// Y and Z, whose mirrors are primed).
+const PENTOMINOES: &str = "\
+rustris-pieces 1
+name pentominoes
+
+piece F 0.95 0.55 0.1
+.##
+##.
+.#.
+kicks 1,0 -1,0 0,-1
+
+piece F' 0.85 0.4 0.0
+##.
+.##
+.#.
+kicks 1,0 -1,0 0,-1
+
+piece I 1.0 1.0 1.0
+.....
+.....
+#####
+.....
+.....
+rotations 2
+kicks 1,0 -1,0 0,-1
+
+piece L 0.0 0.9 0.9
+...#
+####
+....
+....
+kicks 1,0 -1,0 0,-1
+
+piece L' 0.1 0.2 1.0
+#...
+####
+....
+....
+kicks 1,0 -1,0 0,-1
+
+piece N 0.6 0.9 0.2
+##..
+.###
+....
+....
+kicks 1,0 -1,0 0,-1
+
+piece N' 0.2 0.6 0.2
+..##
+###.
+....
+....
+kicks 1,0 -1,0 0,-1
+
+piece P 1.0 0.4 0.7
+##.
+##.
+#..
+kicks 1,0 -1,0 0,-1
+
+piece P' 0.8 0.2 0.5
+##.
+##.
+.#.
+kicks 1,0 -1,0 0,-1
+
+piece T 1.0 1.0 0.0
+###
+.#.
+.#.
+kicks 1,0 -1,0 0,-1
+
+piece U 0.6 0.4 0.2
+#.#
+###
+...
+kicks 1,0 -1,0 0,-1
+
+piece V 0.5 0.3 0.9
+#..
+#..
+###
+kicks 1,0 -1,0 0,-1
+
+piece W 0.3 0.8 0.6
+#..
+##.
+.##
+kicks 1,0 -1,0 0,-1
+
+piece X 0.9 0.1 0.1
+.#.
+###
+.#.
+rotations 1
+
+piece Y 0.4 0.7 1.0
+..#.
+####
+....
+....
+kicks 1,0 -1,0 0,-1
+
+piece Y' 0.2 0.4 0.7
+.#..
+####
+....
+....
+kicks 1,0 -1,0 0,-1
+
+piece Z 1.0 0.0 1.0
+##.
+.#.
+.##
+kicks 1,0 -1,0 0,-1
+
+piece Z' 0.7 0.0 0.7
+.##
+.#.
+##.
+kicks 1,0 -1,0 0,-1
+";
+
+
+/// The standard seven, the two triominoes, and the pentominoes that are easiest to place. The pentominoes have a 5
+/// on their names, so they aren't mistaken for the tetriminos of the same letter.
+const MIXED: &str = "\
+rustris-pieces 1
+name mixed
+
+piece I standard
+piece J standard
+piece L standard
+piece O standard
+piece S standard
+piece T standard
+piece Z standard
+
+piece I3 0.95 0.95 0.95
+...
+###
+...
+kicks 1,0 -1,0 0,-1
+
+piece L3 0.3 0.6 1.0
+#.
+##
+kicks 1,0 -1,0 0,-1
+
+piece I5 0.8 0.8 0.8
+.....
+.....
+#####
+.....
+.....
+rotations 2
+kicks 1,0 -1,0 0,-1
+
+piece P5 1.0 0.4 0.7
+##.
+##.
+#..
+kicks 1,0 -1,0 0,-1
+
+piece U5 0.6 0.4 0.2
+#.#
+###
+...
+kicks 1,0 -1,0 0,-1
+
+piece X5 0.9 0.1 0.1
+.#.
+###
+.#.
+rotations 1
+";
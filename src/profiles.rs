@@ -0,0 +1,396 @@
+/*
+
+Player profiles: who's playing, how they like their keys, and everything they've done so far.
+
+Each profile is a text file in the profiles folder of rustris' data directory (see records.rs), named after
+the player, one "name value" per line like versus rules and AI profiles:
+
+    rustris-profile 2
+    name BEN
+    das 12
+    arr 4
+    key move_left Left
+    key move_right Right
+    key rotate_ccw Up D
+    key rotate_cw F
+    key soft_drop Down
+    key hard_drop Space
+    games 2
+    lines 52
+    clears 4 2 1 10
+    tspins 1
+    max_combo 3
+    pieces 190
+    attack 48
+    millis 176033
+    best_pps 1.19
+    best_apm 17.8
+    game 1792361985 sprint-40 1200 40 102 20 86033 finished
+    game 1792362211 ultra-3 8000 12 88 28 90000 -
+    solved tetris-1
+    solved tsd-1
+
+Keys are the Piston window's key names, as many per action as you like, and a key bound here is no longer a
+hotkey in the game (A, P, H and B, and U, R and Backspace in puzzles). DAS and ARR are in updates, 60 a second: how
+long a move or soft drop key has to be held before it starts repeating, and how often it repeats after that.
+Version 1 profiles are from when the game ran at 30 updates a second; their DAS and ARR are doubled when they're read.
+
+The lifetime totals come first. Then there's a "game" line for every game played, oldest first: when it
+finished, the mode, score, lines, pieces, attack and how long it took in milliseconds, then whether it reached
+the mode's goal. Those are what the progress graphs and per-mode records are drawn from. Last, a "solved" line
+for every puzzle solved (see puzzles.rs), by name. Puzzles don't count as games.
+
+Attack is counted with the default versus rules, whatever the mode, so it means the same thing everywhere.
+Demo and replay games don't count toward anything.
+
+*/
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::GameState;
+use crate::modes::{GameOver, Mode};
+use crate::records::{data_dir, Ranking};
+use crate::versus::Rules;
+
+
+const HEADER: &str = "rustris-profile 2";
+
+/// What profiles said before the game ran at 60 updates a second.
+const OLD_HEADER: &str = "rustris-profile 1";
+
+/// The longest name a profile can have. Names are letters and digits, shown in capitals.
+pub const MAX_PROFILE_NAME: usize = 8;
+
+/// Games shorter than this many pieces don't count toward the best PPS and APM. One quick hard drop isn't a record.
+pub const MIN_PIECES_FOR_BEST: u32 = 20;
+
+/// The actions keys can be bound to, in Action::ALL order, as written in profile files.
+pub const ACTIONS: [&str; 7] = ["move_left", "move_right", "rotate_ccw", "rotate_cw", "soft_drop", "hard_drop", "hold"];
+
+
+/// Lifetime totals.
+#[derive(Clone, Default, Debug)]
+pub struct Stats
+{
+    pub games: u32,
+    pub lines: u32,
+    pub clears: [u32; 4],     // Singles, doubles, triples and tetrises.
+    pub tspins: u32,          // T-spins that cleared lines.
+    pub max_combo: u32,       // Most clears in a row, in any one game.
+    pub pieces: u32,
+    pub attack: u32,
+    pub millis: u64,          // Time played.
+    pub best_pps: f64,        // Pieces per second, in the best game of at least MIN_PIECES_FOR_BEST pieces.
+    pub best_apm: f64         // Attack per minute, likewise.
+}
+
+
+/// One game, as remembered by the profile that played it.
+#[derive(Clone, Debug)]
+pub struct GameRecord
+{
+    pub date: u64,            // Seconds since 1970.
+    pub mode: Mode,
+    pub score: u32,
+    pub lines: u32,
+    pub pieces: u32,
+    pub attack: u32,
+    pub millis: u64,
+    pub finished: bool        // Reached the mode's goal, like Sprint's lines.
+}
+
+
+impl GameRecord
+{
+    pub fn pps(&self) -> f64
+    {
+        if self.millis == 0 { 0.0 } else { f64::from(self.pieces) * 1000.0 / self.millis as f64 }
+    }
+
+    pub fn apm(&self) -> f64
+    {
+        if self.millis == 0 { 0.0 } else { f64::from(self.attack) * 60_000.0 / self.millis as f64 }
+    }
+}
+
+
+/// What happens during a game that GameState doesn't keep count of. Fed each update by watch().
+#[derive(Clone, Default, Debug)]
+pub struct Tally
+{
+    pub tspins: u32,
+    pub max_combo: u32,
+    pub attack: u32
+}
+
+
+impl Tally
+{
+    /// Counts whatever the latest update locked.
+    pub fn watch(&mut self, game_state: &GameState)
+    {
+        let lock = match game_state.last_lock { Some(lock) => lock, None => return };
+
+        if lock.tspin && lock.cleared > 0 { self.tspins += 1; }
+        self.max_combo = self.max_combo.max(lock.combo);
+        self.attack += Rules::default().attack(&lock);
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Profile
+{
+    pub name: String,
+    pub das: u32,                 // Updates a move or soft drop key is held before it repeats.
+    pub arr: u32,                 // Updates between repeats after that.
+    pub keys: [Vec<String>; 7],   // Names of the keys bound to each action, in ACTIONS order.
+    pub stats: Stats,
+    pub history: Vec<GameRecord>, // Every game played, oldest first.
+    pub solved: Vec<String>       // Names of the puzzles solved, in the order they were.
+}
+
+
+impl Profile
+{
+    /// A new profile, with the keys rustris has always had and a fifth of a second of DAS.
+    pub fn new(name: &str) -> Self
+    {
+        let keys = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+        Profile {
+            name: name.to_uppercase(),
+            das: 12,
+            arr: 4,
+            keys: [keys(&["Left"]), keys(&["Right"]), keys(&["Up", "D"]), keys(&["F"]), keys(&["Down"]), keys(&["Space"]),
+                   keys(&["LShift"])],
+            stats: Stats::default(),
+            history: Vec::new(),
+            solved: Vec::new()
+        }
+    }
+
+    /// Adds a finished game to the totals and the history.
+    pub fn record_game(&mut self, game_state: &GameState, tally: &Tally)
+    {
+        let millis = (f64::from(game_state.frames) * 1000.0 / game_state.mode.updates_per_second()) as u64;
+        let record = GameRecord {
+            date: SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0),
+            mode: game_state.mode,
+            score: game_state.score,
+            lines: game_state.lines,
+            pieces: game_state.pieces,
+            attack: tally.attack,
+            millis,
+            finished: game_state.game_over == Some(GameOver::GoalReached)
+        };
+
+        let stats = &mut self.stats;
+        stats.games += 1;
+        stats.lines += game_state.lines;
+        for (total, clears) in stats.clears.iter_mut().zip(game_state.clears.iter()) { *total += clears; }
+        stats.tspins += tally.tspins;
+        stats.max_combo = stats.max_combo.max(tally.max_combo);
+        stats.pieces += game_state.pieces;
+        stats.attack += tally.attack;
+        stats.millis += millis;
+        if record.pieces >= MIN_PIECES_FOR_BEST {
+            stats.best_pps = stats.best_pps.max(record.pps());
+            stats.best_apm = stats.best_apm.max(record.apm());
+        }
+
+        self.history.push(record);
+    }
+
+    /// Ticks a puzzle off as solved. Returns false if it already was.
+    pub fn mark_solved(&mut self, puzzle: &str) -> bool
+    {
+        if self.solved.iter().any(|name| name == puzzle) { return false; }
+        self.solved.push(puzzle.to_string());
+        true
+    }
+
+    /// Pieces per second, over everything played.
+    pub fn average_pps(&self) -> f64
+    {
+        if self.stats.millis == 0 { 0.0 } else { f64::from(self.stats.pieces) * 1000.0 / self.stats.millis as f64 }
+    }
+
+    /// Attack per minute, over everything played.
+    pub fn average_apm(&self) -> f64
+    {
+        if self.stats.millis == 0 { 0.0 } else { f64::from(self.stats.attack) * 60_000.0 / self.stats.millis as f64 }
+    }
+
+    /// The best game of each mode played, ranked the same way as the high-score tables. Modes in the order first played.
+    pub fn mode_records(&self) -> Vec<&GameRecord>
+    {
+        let mut records: Vec<&GameRecord> = Vec::new();
+        for game in self.history.iter()
+        {
+            let better = |best: &GameRecord| match Ranking::of(game.mode) {
+                Ranking::Time  => game.finished && (!best.finished || game.millis < best.millis),
+                Ranking::Score => game.score > best.score
+            };
+            match records.iter().position(|best| best.mode == game.mode) {
+                Some(index) => if better(records[index]) { records[index] = game; },
+                None => records.push(game)
+            }
+        }
+        records
+    }
+
+    /// Where this profile's file goes.
+    pub fn path(&self) -> Option<PathBuf>
+    {
+        profiles_dir().map(|dir| dir.join(format!("{}.profile", self.name.to_lowercase())))
+    }
+
+    /// Saves the profile to its file in the profiles folder.
+    pub fn save(&self) -> io::Result<()>
+    {
+        let path = self.path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory on this platform"))?;
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        // Same as the high scores: a whole new file, or the old one untouched.
+        let temp = path.with_extension("profile.tmp");
+        fs::write(&temp, self.to_text())?;
+        fs::rename(&temp, &path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self>
+    {
+        Profile::from_text(&fs::read_to_string(path)?).map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+
+    pub fn to_text(&self) -> String
+    {
+        let stats = &self.stats;
+        let mut text = format!("{}\nname {}\ndas {}\narr {}\n", HEADER, self.name, self.das, self.arr);
+
+        for (action, keys) in ACTIONS.iter().zip(self.keys.iter()) {
+            text.push_str(&format!("key {} {}\n", action, keys.join(" ")));
+        }
+
+        text.push_str(&format!("games {}\nlines {}\nclears {} {} {} {}\ntspins {}\nmax_combo {}\npieces {}\nattack {}\nmillis {}\n\
+                                best_pps {:.2}\nbest_apm {:.1}\n",
+                               stats.games, stats.lines, stats.clears[0], stats.clears[1], stats.clears[2], stats.clears[3],
+                               stats.tspins, stats.max_combo, stats.pieces, stats.attack, stats.millis, stats.best_pps, stats.best_apm));
+
+        for game in self.history.iter() {
+            text.push_str(&format!("game {} {} {} {} {} {} {} {}\n", game.date, game.mode, game.score, game.lines, game.pieces,
+                                   game.attack, game.millis, if game.finished { "finished" } else { "-" }));
+        }
+
+        for puzzle in self.solved.iter() { text.push_str(&format!("solved {}\n", puzzle)); }
+
+        text
+    }
+
+    /// Anything the text leaves out keeps its default, as in Profile::new(). Lines starting with # are comments.
+    pub fn from_text(text: &str) -> Result<Self, String>
+    {
+        let mut lines = text.lines().enumerate().map(|(num, line)| (num + 1, line.trim()))
+                            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let scale = match lines.next() {
+            Some((_, line)) if line == HEADER => 1,
+            Some((_, line)) if line == OLD_HEADER => 2,
+            _ => return Err(format!("not a profile (expected '{}' on the first line)", HEADER))
+        };
+
+        let mut profile = Profile::new("");
+
+        for (num, line) in lines
+        {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let bad = || format!("line {}: can't make sense of '{}'", num, line);
+            let count = |index: usize| words.get(index).and_then(|word| word.parse::<u32>().ok()).ok_or_else(bad);
+            let number = |index: usize| words.get(index).and_then(|word| word.parse::<u64>().ok()).ok_or_else(bad);
+            let decimal = |index: usize| words.get(index).and_then(|word| word.parse::<f64>().ok()).ok_or_else(bad);
+
+            match words[0]
+            {
+                "name"      => profile.name = words.get(1).filter(|name| valid_profile_name(name)).ok_or_else(bad)?.to_uppercase(),
+                "das"       => profile.das = count(1)?.checked_mul(scale).ok_or_else(bad)?,
+                "arr"       => profile.arr = count(1)?.checked_mul(scale).ok_or_else(bad)?,
+                "key"       => {
+                    let action = ACTIONS.iter().position(|action| Some(action) == words.get(1)).ok_or_else(bad)?;
+                    profile.keys[action] = words[2..].iter().map(|key| key.to_string()).collect();
+                }
+                "games"     => profile.stats.games = count(1)?,
+                "lines"     => profile.stats.lines = count(1)?,
+                "clears"    => for (index, clears) in profile.stats.clears.iter_mut().enumerate() { *clears = count(index + 1)?; },
+                "tspins"    => profile.stats.tspins = count(1)?,
+                "max_combo" => profile.stats.max_combo = count(1)?,
+                "pieces"    => profile.stats.pieces = count(1)?,
+                "attack"    => profile.stats.attack = count(1)?,
+                "millis"    => profile.stats.millis = number(1)?,
+                "best_pps"  => profile.stats.best_pps = decimal(1)?,
+                "best_apm"  => profile.stats.best_apm = decimal(1)?,
+                "game"      => profile.history.push(GameRecord {
+                    date: number(1)?,
+                    mode: words.get(2).and_then(|mode| mode.parse().ok()).ok_or_else(bad)?,
+                    score: count(3)?,
+                    lines: count(4)?,
+                    pieces: count(5)?,
+                    attack: count(6)?,
+                    millis: number(7)?,
+                    finished: words.get(8) == Some(&"finished")
+                }),
+                "solved"    => { profile.mark_solved(words.get(1).ok_or_else(bad)?); }
+                _ => return Err(bad())
+            }
+        }
+
+        if profile.name.is_empty() { return Err("profile has no name".to_string()); }
+        Ok(profile)
+    }
+}
+
+
+/// Whether a name will do for a profile: 1 to MAX_PROFILE_NAME letters and digits. It's a file name, too.
+pub fn valid_profile_name(name: &str) -> bool
+{
+    !name.is_empty() && name.len() <= MAX_PROFILE_NAME && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+
+/// Where profiles live.
+pub fn profiles_dir() -> Option<PathBuf>
+{
+    data_dir().map(|dir| dir.join("profiles"))
+}
+
+
+/// Every profile in the profiles folder, in name order. Ones that can't be read are skipped, with a warning.
+pub fn load_profiles() -> Vec<Profile>
+{
+    let entries = match profiles_dir().and_then(|dir| fs::read_dir(dir).ok()) { Some(entries) => entries, None => return Vec::new() };
+
+    let mut profiles: Vec<Profile> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "profile"))
+        .filter_map(|path| Profile::load(&path).map_err(|err| eprintln!("Skipping profile {}: {}", path.display(), err)).ok())
+        .collect();
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    profiles
+}
+
+
+/// The name of the profile played last, so it can be picked again next time.
+pub fn last_profile() -> Option<String>
+{
+    profiles_dir().and_then(|dir| fs::read_to_string(dir.join("last")).ok()).map(|name| name.trim().to_uppercase())
+}
+
+
+pub fn set_last_profile(name: &str) -> io::Result<()>
+{
+    let dir = profiles_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory on this platform"))?;
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("last"), format!("{}\n", name))
+}
@@ -0,0 +1,503 @@
+/*
+
+Puzzle mode: a well that's already set up, a fixed list of pieces, and a goal to reach with them.
+
+Puzzles come in packs, text files like the others:
+
+    rustris-puzzles 1
+
+    // Lines starting with // are comments.
+    puzzle tsd-1                   a name, unique among all the puzzles: it's what gets ticked off when it's solved
+    title FIRST T-SPIN DOUBLE      optional: what the menu calls it (the name, in capitals, if left out)
+    goal tspin 2
+    pieces T                       the pieces, in the order they come: I J L O S T Z
+    hold O                         optional: a piece that starts in the hold (no hold at all if left out)
+    hint STAND THE T UP IN THE SLOT, THEN TURN IT.
+    well 10x20+4                   optional: the well's size, as in parse_well_size() (the usual 10x20+4 if left out)
+    XXXX......
+    XXX...XXXX
+    XXXX.XXXXX
+
+The rows at the end are the bottom of the well, top to bottom, as wide as the well: . for an empty square,
+I J L O S T Z for a square of that piece, X for garbage. Anything above them starts empty.
+
+The goals are:
+
+    lines N              clear N lines, all told
+    tetris               clear four lines with one piece
+    tspin N              a T-spin that clears N lines: 1, 2 or 3
+    clear-all            leave the well completely empty
+    perfect-clear N      the same, with N pieces or fewer
+
+Puzzles have no gravity: a piece stays where it's put until it's hard dropped, so there's time to think. The
+puzzle's over, one way or the other, once it's solved or the pieces run out. A puzzle with a piece in the hold
+can swap the falling piece for it, once per piece, and the pieces run out with one of them still left over. Undo takes back the last piece,
+and retry starts again. Which puzzles a player has solved is kept in their profile (see profiles.rs).
+
+The built-in pack is below. More packs go in the puzzles folder in rustris' folder in the platform's config
+directory, as NAME.puzzles (e.g. ~/.config/rustris/puzzles/mine.puzzles on Linux), or anywhere else with
+"tetris --puzzles DIR". They come after the built-in ones, in file name order.
+
+*/
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::{format_well_size, parse_well_size, GameState, Tetrimino, TetriminoKind, Well, GARBAGE};
+use crate::modes::Mode;
+
+
+const HEADER: &str = "rustris-puzzles 1";
+
+/// The letters puzzles use for the standard seven, in TetriminoKind::STANDARD order.
+pub(crate) const PIECE_LETTERS: &str = "IJLOSTZ";
+
+
+/// What a puzzle asks for.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Goal
+{
+    Lines(u32),           // Clear this many lines.
+    Tetris,               // Clear four at once.
+    TSpin(u32),           // A T-spin that clears this many lines.
+    ClearAll,             // Empty the well.
+    PerfectClear(u32)     // Empty the well within this many pieces.
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Puzzle
+{
+    pub name: String,
+    pub title: String,
+    pub hint: String,
+    pub goal: Goal,
+    pub pieces: Vec<TetriminoKind>,
+    pub hold: Option<TetriminoKind>,    // What's in the hold to start with. Puzzles without one have no hold.
+    pub well: Well                      // How it starts.
+}
+
+
+impl Goal
+{
+    /// What to aim for, on screen.
+    pub fn describe(&self) -> String
+    {
+        match self
+        {
+            Goal::Lines(1)          => "CLEAR A LINE".to_string(),
+            Goal::Lines(lines)      => format!("CLEAR {} LINES", lines),
+            Goal::Tetris            => "GET A TETRIS".to_string(),
+            Goal::TSpin(lines)      => format!("T-SPIN {}", ["SINGLE", "DOUBLE", "TRIPLE"][*lines as usize - 1]),
+            Goal::ClearAll          => "EMPTY THE WELL".to_string(),
+            Goal::PerfectClear(1)   => "PERFECT CLEAR IN 1 PIECE".to_string(),
+            Goal::PerfectClear(pieces) => format!("PERFECT CLEAR IN {} PIECES", pieces)
+        }
+    }
+}
+
+
+impl fmt::Display for Goal
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Goal::Lines(lines)         => write!(f, "lines {}", lines),
+            Goal::Tetris               => write!(f, "tetris"),
+            Goal::TSpin(lines)         => write!(f, "tspin {}", lines),
+            Goal::ClearAll             => write!(f, "clear-all"),
+            Goal::PerfectClear(pieces) => write!(f, "perfect-clear {}", pieces)
+        }
+    }
+}
+
+
+impl FromStr for Goal
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let bad = || format!("unknown goal '{}' (expected lines N, tetris, tspin 1 to 3, clear-all or perfect-clear N)", s);
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let number = |word: &str| word.parse::<u32>().ok().filter(|number| *number > 0).ok_or_else(bad);
+
+        match words.as_slice()
+        {
+            ["lines", lines]          => Ok(Goal::Lines(number(lines)?)),
+            ["tetris"]                => Ok(Goal::Tetris),
+            ["tspin", lines]          => Ok(Goal::TSpin(number(lines).ok().filter(|lines| *lines <= 3).ok_or_else(bad)?)),
+            ["clear-all"]             => Ok(Goal::ClearAll),
+            ["perfect-clear", pieces] => Ok(Goal::PerfectClear(number(pieces)?)),
+            _ => Err(bad())
+        }
+    }
+}
+
+
+impl Puzzle
+{
+    /// The piece that comes at the given place in the list, counting from 0. Past the end there's nothing more to
+    /// come, but GameState always has a next piece, so it's the last one again. (It's never played, or shown.)
+    pub fn tetrimino(&self, index: usize) -> Tetrimino
+    {
+        Tetrimino::new(self.pieces[index.min(self.pieces.len() - 1)])
+    }
+
+    /// The pieces from the given place in the list on, in the letters packs write them with.
+    pub fn letters(&self, from: usize) -> String
+    {
+        self.pieces.iter().skip(from).map(|kind| piece_letter(*kind)).collect()
+    }
+
+    /// The puzzle as a pack has it, from its "puzzle" line to its last row. Only the well's rows from the highest
+    /// one with anything in it are written, and the title and well only if they aren't what they'd be left out.
+    pub fn to_text(&self) -> String
+    {
+        let mut text = format!("puzzle {}\n", self.name);
+        if self.title != self.name.to_uppercase() { text.push_str(&format!("title {}\n", self.title)); }
+        text.push_str(&format!("goal {}\npieces {}\n", self.goal, self.letters(0)));
+        if let Some(hold) = self.hold { text.push_str(&format!("hold {}\n", piece_letter(hold))); }
+        if !self.hint.is_empty() { text.push_str(&format!("hint {}\n", self.hint)); }
+        if !self.well.is_standard() { text.push_str(&format!("well {}\n", format_well_size(&self.well))); }
+
+        for row in self.well.iter().skip_while(|row| row.iter().all(|square| *square == 0))
+        {
+            let squares: String = row.iter().map(|square| match (*square, TetriminoKind::from_cell(*square)) {
+                (0, _) => '.',
+                (_, Some(kind)) if kind.is_standard() => piece_letter(kind),
+                _ => 'X'
+            }).collect();
+            text.push_str(&squares);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// A whole pack: the header, then the puzzles, a blank line between each.
+    pub fn pack_text(puzzles: &[Puzzle]) -> String
+    {
+        let texts: Vec<String> = puzzles.iter().map(Puzzle::to_text).collect();
+        format!("{}\n\n{}", HEADER, texts.join("\n"))
+    }
+
+    /// Every puzzle in a pack's text, in order.
+    pub fn parse_pack(text: &str) -> Result<Vec<Puzzle>, String>
+    {
+        let mut lines = text.lines().enumerate().map(|(num, line)| (num + 1, line.trim()))
+                            .filter(|(_, line)| !line.is_empty() && !line.starts_with("//"));
+
+        match lines.next() {
+            Some((_, line)) if line == HEADER => (),
+            _ => return Err(format!("not a puzzle pack (expected '{}' on the first line)", HEADER))
+        }
+
+        let mut puzzles = Vec::new();
+        let mut building: Option<(usize, Puzzle, Vec<&str>)> = None;    // The line it started on, and its rows so far.
+
+        for (num, line) in lines
+        {
+            let err = |msg: String| format!("line {}: {}", num, msg);
+            let (first, rest) = line.split_once(' ').map_or((line, ""), |(first, rest)| (first, rest.trim()));
+
+            if first == "puzzle" {
+                if let Some(puzzle) = building.take() { puzzles.push(finish(puzzle)?); }
+                if rest.is_empty() || rest.contains(char::is_whitespace) { return Err(err("a puzzle needs a one-word name".to_string())); }
+                if puzzles.iter().any(|puzzle: &Puzzle| puzzle.name == rest) { return Err(err(format!("there's already a puzzle called '{}'", rest))); }
+
+                let puzzle = Puzzle { name: rest.to_string(), title: rest.to_uppercase(), hint: String::new(), goal: Goal::ClearAll,
+                                      pieces: Vec::new(), hold: None, well: Well::default() };
+                building = Some((num, puzzle, Vec::new()));
+                continue;
+            }
+
+            let (_, puzzle, rows) = building.as_mut().ok_or_else(|| err(format!("'{}' before any puzzle", line)))?;
+
+            if line.chars().all(|c| c == '.' || c == 'X' || PIECE_LETTERS.contains(c)) {
+                rows.push(line);
+                continue;
+            }
+            if !rows.is_empty() { return Err(err(format!("'{}' after the well's rows, which come last", line))); }
+
+            match first
+            {
+                "title"  => puzzle.title = rest.to_uppercase(),
+                "hint"   => puzzle.hint = rest.to_uppercase(),
+                "goal"   => puzzle.goal = rest.parse().map_err(err)?,
+                "well"   => puzzle.well = parse_well_size(rest).map_err(err)?,
+                "pieces" => puzzle.pieces = parse_pieces(rest).map_err(err)?,
+                "hold"   => puzzle.hold = match parse_pieces(rest).map_err(err)?.as_slice() {
+                    [hold] => Some(*hold),
+                    _ => return Err(err("the hold has room for one piece".to_string()))
+                },
+                _ => return Err(err(format!("expected puzzle, title, goal, pieces, hold, hint, well or a row of the well, not '{}'", line)))
+            }
+        }
+
+        if let Some(puzzle) = building.take() { puzzles.push(finish(puzzle)?); }
+        Ok(puzzles)
+    }
+}
+
+
+/// The letter a pack writes one of the standard seven with.
+pub(crate) fn piece_letter(kind: TetriminoKind) -> char
+{
+    PIECE_LETTERS.chars().nth(TetriminoKind::STANDARD.iter().position(|standard| *standard == kind).unwrap()).unwrap()
+}
+
+
+/// Pieces in the letters packs write them with. Spaces between them don't matter.
+fn parse_pieces(letters: &str) -> Result<Vec<TetriminoKind>, String>
+{
+    letters.chars().filter(|c| !c.is_whitespace()).map(|c| match PIECE_LETTERS.find(c) {
+        Some(index) => Ok(TetriminoKind::STANDARD[index]),
+        None => Err(format!("'{}' isn't a piece (expected I, J, L, O, S, T or Z)", c))
+    }).collect()
+}
+
+
+/// Checks a puzzle read from a pack, and puts its rows in the bottom of its well.
+fn finish((num, mut puzzle, rows): (usize, Puzzle, Vec<&str>)) -> Result<Puzzle, String>
+{
+    let name = puzzle.name.clone();
+    let err = |msg: &str| format!("puzzle {} (line {}): {}", name, num, msg);
+
+    if puzzle.pieces.is_empty() { return Err(err("it needs some pieces")); }
+    if let Goal::PerfectClear(pieces) = puzzle.goal {
+        if pieces as usize > puzzle.pieces.len() { return Err(err("it doesn't have that many pieces")); }
+    }
+    if rows.len() > puzzle.well.visible_rows() { return Err(err("it has more rows than the well")); }
+    if rows.iter().any(|row| row.len() != puzzle.well.cols()) { return Err(err(&format!("its rows have to be {} wide, like its well", puzzle.well.cols()))); }
+
+    let top = puzzle.well.rows() - rows.len();
+    for (row, squares) in rows.iter().enumerate() {
+        for (col, square) in squares.chars().enumerate() {
+            puzzle.well.set(top + row, col, match square {
+                '.' => 0,
+                'X' => GARBAGE,
+                letter => TetriminoKind::STANDARD[PIECE_LETTERS.find(letter).unwrap()].cell()
+            });
+        }
+    }
+
+    if puzzle.well.iter().any(|row| row.iter().all(|square| *square != 0)) { return Err(err("a row of the well is full already")); }
+    Ok(puzzle)
+}
+
+
+/// Where puzzle packs of the player's own go. None if the platform doesn't have a config directory.
+pub fn puzzles_dir() -> Option<PathBuf>
+{
+    dirs::config_dir().map(|dir| dir.join("rustris").join("puzzles"))
+}
+
+
+/// The built-in puzzles, then those in every .puzzles file in the given folder (see puzzles_dir()). Packs that
+/// can't be read, and puzzles named the same as one that came before, are skipped with a warning.
+pub fn load_puzzles(dir: Option<&Path>) -> Vec<Arc<Puzzle>>
+{
+    let mut puzzles: Vec<Arc<Puzzle>> = Puzzle::parse_pack(BUILTIN_PACK).expect("the built-in puzzles are fine").into_iter().map(Arc::new).collect();
+
+    let mut paths: Vec<PathBuf> = match dir.and_then(|dir| fs::read_dir(dir).ok()) {
+        Some(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                                .filter(|path| path.extension().is_some_and(|ext| ext == "puzzles")).collect(),
+        None => Vec::new()
+    };
+    paths.sort();
+
+    for path in paths
+    {
+        let pack = fs::read_to_string(&path).map_err(|err| err.to_string()).and_then(|text| Puzzle::parse_pack(&text));
+        match pack {
+            Ok(pack) => for puzzle in pack {
+                if puzzles.iter().any(|other| other.name == puzzle.name) { eprintln!("Skipping puzzle {} in {}: the name's taken", puzzle.name, path.display()); }
+                else { puzzles.push(Arc::new(puzzle)); }
+            },
+            Err(err) => eprintln!("Skipping puzzles {}: {}", path.display(), err)
+        }
+    }
+    puzzles
+}
+
+
+/// Sets a fresh game up to play the given puzzle: its well, with pieces appearing in the middle of it, and its
+/// pieces instead of the randomizer's, the held one included.
+pub fn start_puzzle(game_state: &mut GameState, puzzle: Arc<Puzzle>)
+{
+    game_state.set_mode(Mode::Puzzle);
+    game_state.set_well(puzzle.well.clone());
+    game_state.well = puzzle.well.clone();
+    game_state.curr_ttmo = puzzle.tetrimino(0);
+    game_state.next_ttmo = puzzle.tetrimino(1);
+    game_state.hold_ttmo = puzzle.hold.map(Tetrimino::new);
+    game_state.puzzle = Some(puzzle);
+}
+
+
+/// Whether the piece that just locked reached the puzzle's goal.
+pub(crate) fn goal_reached(game_state: &GameState) -> bool
+{
+    let (puzzle, lock) = match (&game_state.puzzle, game_state.last_lock) { (Some(puzzle), Some(lock)) => (puzzle, lock), _ => return false };
+    let empty = game_state.well.iter().all(|row| row.iter().all(|square| *square == 0));
+
+    match puzzle.goal
+    {
+        Goal::Lines(lines)         => game_state.lines >= lines,
+        Goal::Tetris               => lock.cleared == 4,
+        Goal::TSpin(lines)         => lock.tspin && lock.cleared == lines,
+        Goal::ClearAll             => empty,
+        Goal::PerfectClear(pieces) => empty && game_state.pieces <= pieces
+    }
+}
+
+
+/// Whether the puzzle's out of pieces to play: they've all been used, or a perfect clear's run out of them.
+pub(crate) fn out_of_pieces(game_state: &GameState) -> bool
+{
+    match &game_state.puzzle
+    {
+        Some(puzzle) => {
+            let limit = match puzzle.goal { Goal::PerfectClear(pieces) => pieces as usize, _ => puzzle.pieces.len() };
+            game_state.pieces as usize >= limit
+        }
+        None => false
+    }
+}
+
+
+/// How many of the puzzle's pieces are still to come after the one falling now. The next one is shown only if there is one.
+pub fn pieces_to_come(game_state: &GameState) -> usize
+{
+    game_state.puzzle.as_ref().map_or(0, |puzzle| puzzle.pieces.len().saturating_sub(game_state.pieces as usize + 1))
+}
+
+
+/// The game as it was when each piece of a puzzle appeared, so pieces can be taken back.
+pub struct Undo
+{
+    starts: Vec<GameState>    // The first is how the puzzle starts. The last is when the piece falling now appeared.
+}
+
+
+impl Undo
+{
+    pub fn new(game_state: &GameState) -> Self
+    {
+        Undo { starts: vec![game_state.clone()] }
+    }
+
+    /// Notes a new piece appearing, if one has since the last look. Call after every update.
+    pub fn watch(&mut self, game_state: &GameState)
+    {
+        let last = self.starts.last().unwrap();
+        if game_state.pieces != last.pieces && game_state.game_over.is_none() { self.starts.push(game_state.clone()); }
+    }
+
+    /// Takes back the last piece to lock: the game as it was when that piece appeared. With none locked yet, that's
+    /// the start of the puzzle again.
+    pub fn undo(&mut self, game_state: &GameState) -> GameState
+    {
+        // Once the puzzle's over there's no piece falling, so the last start is the last piece's.
+        if game_state.game_over.is_none() && self.starts.len() > 1 { self.starts.pop(); }
+        self.starts.last().unwrap().clone()
+    }
+
+    /// The puzzle from the start.
+    pub fn retry(&mut self) -> GameState
+    {
+        self.starts.truncate(1);
+        self.starts[0].clone()
+    }
+}
+
+
+// The built-in pack: a few of each goal, roughly easiest first, to learn the shapes from.
+
+const BUILTIN_PACK: &str = "\
+rustris-puzzles 1
+
+// Where new players start: one piece each, then a few to set things up with.
+
+puzzle tetris-1
+title FIRST TETRIS
+goal tetris
+pieces I
+hint SLIDE THE I ALL THE WAY RIGHT AND DROP IT DOWN THE GAP.
+XXXXXXXXX.
+XXXXXXXXX.
+XXXXXXXXX.
+XXXXXXXXX.
+
+puzzle lines-1
+title S AND Z
+goal lines 2
+pieces ZS
+hint KEEP THE Z OUT OF THE WAY. THE S FITS THE STEP IN THE MIDDLE, LYING DOWN.
+XXX..XXXXX
+XX..XXXXXX
+
+puzzle tss-1
+title FIRST T-SPIN
+goal tspin 1
+pieces T
+hint A T-SPIN IS A T THAT TURNS AS ITS LAST MOVE, INTO A SPOT WITH THREE OF ITS FOUR CORNERS FILLED.
+XXXX......
+XXX...XXXX
+XXXX.XXXX.
+
+puzzle tsd-1
+title FIRST T-SPIN DOUBLE
+goal tspin 2
+pieces T
+hint STAND THE T UP AND LOWER IT BESIDE THE OVERHANG, THEN TURN IT CLOCKWISE SO IT POINTS DOWN INTO THE SLOT.
+XXXX......
+XXX...XXXX
+XXXX.XXXXX
+
+puzzle tsd-2
+title BUILD THE OVERHANG
+goal tspin 2
+pieces JT
+hint PUT THE J ON THE LEFT SO IT HANGS OVER THE SLOT. THEN SPIN THE T IN UNDER IT.
+X.........
+XXX...XXXX
+XXXX.XXXXX
+
+puzzle clear-all-1
+title CLEAN SWEEP
+goal clear-all
+pieces OO
+hint NOTHING CAN BE LEFT OVER.
+XXXXXX....
+XXXXXX....
+
+puzzle pc-1
+title PERFECT CLEAR
+goal perfect-clear 4
+pieces ILJO
+hint FOUR PIECES, FOUR ROWS AND NOT A SQUARE TO SPARE.
+XXXXXX....
+XXXXXX....
+XXXXXX....
+XXXXXX....
+
+// Openers: a game's first pieces, from an empty well.
+
+puzzle pco-1
+title PERFECT CLEAR OPENER
+goal perfect-clear 10
+pieces IOLJSZTLJI
+hint TEN PIECES FILL THE BOTTOM FOUR ROWS EXACTLY. KEEP IT FLAT, AND NOTHING ABOVE THE FOURTH ROW.
+
+puzzle pco-2
+title OPENER WITH HOLD
+goal perfect-clear 10
+pieces ILZOJSZISL
+hold T
+hint THE T STARTS IN THE HOLD. SWAP IT IN WHEN THE PIECE FALLING WON'T FIT ANYWHERE.
+";
@@ -0,0 +1,437 @@
+/*
+
+Results that outlive the game: a high-score table for each mode, each entry with its replay.
+
+Everything lives in rustris' folder in the platform's data directory
+(e.g. ~/.local/share/rustris on Linux, going by $XDG_DATA_HOME), next to a replays folder:
+
+    scores.json                          the high-score tables
+    replays/sprint-40-1634567890.replay  the replay for one of their entries
+
+scores.json looks like this:
+
+    {
+      "version": 2,
+      "tables": {
+        "sprint-40": [ { "name": "BEN", "score": 0, "lines": 40, "level": 5, "frames": 6024, ... }, ... ],
+        "ultra-3": [ ... ]
+      }
+    }
+
+Tables are named after the mode they're for, as written in replays ("sprint-40", "marathon-18"...), so every
+variant of a mode gets its own. Each keeps its best TABLE_SIZE entries: fastest first for modes that are a race
+to a goal (Sprint, Cheese), highest score first for everything else. Replays of entries that drop off the
+bottom are deleted along with them.
+
+The file is written to scores.json.tmp and then renamed over scores.json, so a crash halfway through writing
+can't leave half a file behind. Version 1 files are from when most modes ran at 30 updates a second: their
+times and splits are doubled into today's updates (see Mode::updates_per_old_update()), and the file saved
+again as version 2. Anything that still can't be read is moved aside to scores.json.bad, for
+rescuing by hand, and the tables start over. A file from a newer rustris is left alone altogether.
+
+Before there were tables, finished Sprint runs went in sprint.json and Ultra games in ultra.json, every one
+of them. Those are "version 0": with no scores.json yet, their best runs are brought over into the tables.
+
+*/
+
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{GameState, GRAVITY, NORMAL_SPEED};
+use crate::modes::{GameOver, Mode};
+use crate::replay::Replay;
+
+
+/// The version of scores.json this rustris writes. Goes up whenever its layout changes.
+pub const SCORES_VERSION: u32 = 2;
+
+/// How many entries each high-score table keeps.
+pub const TABLE_SIZE: usize = 10;
+
+/// What goes in the name column when nobody said who was playing.
+pub const DEFAULT_NAME: &str = "PLAYER";
+
+
+/// One game that made a high-score table.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScoreEntry
+{
+    pub name: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,               // GameState::level() at the end, so NES levels for Marathon and 0-999 for Master.
+    pub frames: u32,              // How long it took, in updates. Mode::updates_per_second() says how long those are.
+    pub date: u64,                // Seconds since 1970.
+    pub seed: u64,
+    pub randomizer: String,
+    pub replay: Option<String>,   // File name in the replays folder, if it was saved.
+    #[serde(default)]
+    pub splits: Vec<u32>          // When 10, 20, 30... lines were reached, in updates. Sprint shows these against the best run.
+}
+
+
+/// How a mode's table is sorted.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Ranking
+{
+    Time,     // Fastest first. Only games that reached the goal count.
+    Score     // Highest first, then fastest.
+}
+
+
+impl Ranking
+{
+    pub fn of(mode: Mode) -> Self
+    {
+        match mode
+        {
+            Mode::Sprint { .. } | Mode::Cheese { .. } => Ranking::Time,
+            _ => Ranking::Score
+        }
+    }
+}
+
+
+/// All the high-score tables, keyed by mode name.
+pub struct HighScores
+{
+    pub tables: BTreeMap<String, Vec<ScoreEntry>>,
+    path: Option<PathBuf>,    // Where they're saved. None when there's nowhere to save them.
+    read_only: bool           // The file on disk is from a newer rustris, so saving would throw away whatever it added.
+}
+
+
+/// What scores.json holds.
+#[derive(Serialize, Deserialize)]
+struct ScoresFile
+{
+    version: u32,
+    tables: BTreeMap<String, Vec<ScoreEntry>>
+}
+
+
+impl HighScores
+{
+    /// The tables in the data directory. Nothing there, or a file that can't be read, just means empty tables.
+    pub fn load() -> Self
+    {
+        match data_dir() {
+            Some(dir) => HighScores::load_from(&dir.join("scores.json")),
+            None => HighScores { tables: BTreeMap::new(), path: None, read_only: true }
+        }
+    }
+
+    /// The tables in the given file, which doesn't have to exist yet.
+    pub fn load_from(path: &Path) -> Self
+    {
+        let mut scores = HighScores { tables: BTreeMap::new(), path: Some(path.to_path_buf()), read_only: false };
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                scores.import_old_records(path.parent().unwrap_or_else(|| Path::new(".")));
+                return scores;
+            }
+            Err(err) => {
+                eprintln!("Can't read high scores from {}: {}", path.display(), err);
+                scores.read_only = true;
+                return scores;
+            }
+        };
+
+        // Check the version before anything else, since a newer file might not look like this one at all.
+        let version = serde_json::from_str::<serde_json::Value>(&text).ok()
+            .and_then(|value| value.get("version").and_then(|version| version.as_u64()));
+        match version
+        {
+            Some(version) if version > u64::from(SCORES_VERSION) => {
+                eprintln!("High scores in {} are from a newer rustris (version {}), so they're being left alone.", path.display(), version);
+                scores.read_only = true;
+            }
+            Some(version) => match serde_json::from_str::<ScoresFile>(&text) {
+                Ok(file) => {
+                    scores.tables = file.tables;
+                    if version < 2 { scores.upgrade_from_30_per_second(); }
+                }
+                Err(err) => scores.set_aside(path, &err.to_string())
+            },
+            None => scores.set_aside(path, "no version")
+        }
+
+        scores
+    }
+
+    /// The table for a mode, best first.
+    pub fn table(&self, mode: Mode) -> &[ScoreEntry]
+    {
+        self.tables.get(&mode.to_string()).map_or(&[], |table| table.as_slice())
+    }
+
+    /// The top entry for a mode.
+    pub fn best(&self, mode: Mode) -> Option<&ScoreEntry>
+    {
+        self.table(mode).first()
+    }
+
+    /// Whether a just-finished game would make its mode's table. Versus games, puzzles, unfinished races and games
+    /// with the speed, gravity, well size, spawn position or pieces changed in the settings never do.
+    pub fn qualifies(&self, game_state: &GameState) -> bool
+    {
+        let standard_rules = game_state.speed == NORMAL_SPEED && game_state.gravity == GRAVITY && game_state.well.is_standard()
+                             && (game_state.spawn_row, game_state.spawn_col) == game_state.well.spawn() && game_state.piece_set.is_standard();
+
+        match (game_state.mode, Ranking::of(game_state.mode), game_state.game_over)
+        {
+            _ if !standard_rules => false,
+            (Mode::Versus, _, _) | (Mode::Puzzle, _, _) | (_, _, None) => false,
+            (_, Ranking::Time, over) if over != Some(GameOver::GoalReached) => false,
+            (mode, ranking, _) => {
+                let table = self.table(mode);
+                table.len() < TABLE_SIZE || compare(ranking, &entry_for(game_state, ""), table.last().unwrap()) == Ordering::Less
+            }
+        }
+    }
+
+    /// Puts a just-finished game in its mode's table, if it makes it, saves its replay, and writes the tables out.
+    /// Returns where in the table it went, counting from 0, or None if it didn't make it.
+    pub fn add(&mut self, game_state: &GameState, replay: &Replay, name: &str) -> io::Result<Option<usize>>
+    {
+        if !self.qualifies(game_state) { return Ok(None); }
+
+        let mut entry = entry_for(game_state, name);
+
+        // The entry still counts if its replay can't be saved. It just can't be watched.
+        match save_replay(game_state, replay) {
+            Ok(replay_name) => entry.replay = Some(replay_name),
+            Err(err) => eprintln!("Couldn't save replay: {}", err)
+        }
+
+        let ranking = Ranking::of(game_state.mode);
+        let table = self.tables.entry(game_state.mode.to_string()).or_default();
+        let place = table.iter().position(|other| compare(ranking, &entry, other) == Ordering::Less).unwrap_or(table.len());
+        table.insert(place, entry);
+
+        for dropped in table.drain(TABLE_SIZE.min(table.len())..) {
+            if let (Some(replay), Some(dir)) = (dropped.replay, data_dir()) { let _ = fs::remove_file(dir.join("replays").join(replay)); }
+        }
+
+        self.save()?;
+        Ok(Some(place))
+    }
+
+    /// Writes the tables out, to a temporary file first and then over the real one.
+    pub fn save(&self) -> io::Result<()>
+    {
+        let path = match (&self.path, self.read_only) {
+            (Some(path), false) => path,
+            _ => return Err(io::Error::new(io::ErrorKind::PermissionDenied, "high scores can't be saved here"))
+        };
+        if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+
+        let file = ScoresFile { version: SCORES_VERSION, tables: self.tables.clone() };
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(&file).unwrap())?;
+        fs::rename(&temp, path)
+    }
+
+    /// Moves an unreadable file out of the way, so the next save doesn't overwrite it.
+    fn set_aside(&mut self, path: &Path, why: &str)
+    {
+        let bad = path.with_extension("json.bad");
+        eprintln!("Can't make sense of the high scores in {} ({}), moving them to {}.", path.display(), why, bad.display());
+
+        if let Err(err) = fs::rename(path, &bad) {
+            eprintln!("Couldn't move them: {}. Leaving them alone.", err);
+            self.read_only = true;
+        }
+    }
+
+    /// Doubles the times in the tables of modes that used to run at 30 updates a second, and saves them like that.
+    fn upgrade_from_30_per_second(&mut self)
+    {
+        for (mode, table) in self.tables.iter_mut() {
+            let scale = mode.parse().map_or(1, |mode: Mode| mode.updates_per_old_update());
+            for entry in table.iter_mut() {
+                entry.frames *= scale;
+                for split in entry.splits.iter_mut() { *split *= scale; }
+            }
+        }
+
+        if let Err(err) = self.save() { eprintln!("Couldn't save the high scores at 60 updates a second: {}", err); }
+    }
+
+    /// Brings sprint.json and ultra.json, from before there were tables, into the tables. Those are from when the
+    /// game ran at 30 updates a second, so Sprint times are doubled.
+    fn import_old_records(&mut self, dir: &Path)
+    {
+        let sprints: Vec<SprintRecord> = load_old_records(&dir.join("sprint.json"));
+        let ultras: Vec<UltraRecord> = load_old_records(&dir.join("ultra.json"));
+        if sprints.is_empty() && ultras.is_empty() { return; }
+
+        for record in sprints
+        {
+            let mode = Mode::Sprint { lines: record.lines };
+            let scale = mode.updates_per_old_update();
+            let entry = ScoreEntry {
+                name: DEFAULT_NAME.to_string(), score: 0, lines: record.lines, level: record.lines / 10 + 1, frames: record.frames * scale,
+                date: record.date, seed: record.seed, randomizer: record.randomizer, replay: Some(record.replay),
+                splits: record.splits.iter().map(|split| split * scale).collect()
+            };
+            self.tables.entry(mode.to_string()).or_default().push(entry);
+        }
+
+        for record in ultras
+        {
+            let minutes = record.minutes;
+            let entry = ScoreEntry {
+                name: DEFAULT_NAME.to_string(), score: record.score, lines: record.lines, level: record.lines / 10 + 1,
+                frames: minutes * 60 * crate::UPDATES_PER_SECOND, date: record.date, seed: record.seed,
+                randomizer: record.randomizer, replay: Some(record.replay), splits: Vec::new()
+            };
+            self.tables.entry(Mode::Ultra { minutes }.to_string()).or_default().push(entry);
+        }
+
+        // Keep the best of them, same as if they'd been added one at a time.
+        for (mode, table) in self.tables.iter_mut() {
+            let ranking = mode.parse().map_or(Ranking::Score, Ranking::of);
+            table.sort_by(|a, b| compare(ranking, a, b));
+            table.truncate(TABLE_SIZE);
+        }
+
+        match self.save() {
+            Ok(()) => eprintln!("Moved the old Sprint and Ultra records into the high-score tables."),
+            Err(err) => eprintln!("Couldn't save the old Sprint and Ultra records as high scores: {}", err)
+        }
+    }
+}
+
+
+/// Where rustris keeps its files. None if the platform doesn't have a data directory.
+pub fn data_dir() -> Option<PathBuf>
+{
+    dirs::data_dir().map(|dir| dir.join("rustris"))
+}
+
+
+/// The name to put on high scores when nobody said: the user's login name, if it makes a sensible one.
+pub fn default_name() -> String
+{
+    std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok()
+        .map(|name| name.chars().filter(|c| c.is_ascii_alphanumeric()).take(8).collect::<String>().to_uppercase())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_NAME.to_string())
+}
+
+
+/// The table's order: Less if a goes above b. Ties go to whoever got there first.
+fn compare(ranking: Ranking, a: &ScoreEntry, b: &ScoreEntry) -> Ordering
+{
+    let by_time = a.frames.cmp(&b.frames);
+    match ranking {
+        Ranking::Time  => by_time,
+        Ranking::Score => b.score.cmp(&a.score).then(by_time)
+    }.then(a.date.cmp(&b.date))
+}
+
+
+/// A table entry for a finished game, with no replay yet.
+fn entry_for(game_state: &GameState, name: &str) -> ScoreEntry
+{
+    ScoreEntry {
+        name: name.to_string(),
+        score: game_state.score,
+        lines: game_state.lines,
+        level: game_state.level(),
+        frames: game_state.frames,
+        date: now(),
+        seed: game_state.seed,
+        randomizer: game_state.randomizer.to_string(),
+        replay: None,
+        splits: game_state.splits.clone()
+    }
+}
+
+
+fn now() -> u64
+{
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+
+/// Writes the replay into the replays folder. Returns its file name.
+fn save_replay(game_state: &GameState, replay: &Replay) -> io::Result<String>
+{
+    let dir = data_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory on this platform"))?;
+    fs::create_dir_all(dir.join("replays"))?;
+
+    // Two games can finish in the same second, and neither should overwrite the other's replay.
+    let date = now();
+    let replay_name = (0..).map(|copy| match copy {
+        0 => format!("{}-{}.replay", game_state.mode, date),
+        _ => format!("{}-{}-{}.replay", game_state.mode, date, copy)
+    }).find(|name| !dir.join("replays").join(name).exists()).unwrap();
+    replay.save(&dir.join("replays").join(&replay_name))?;
+    Ok(replay_name)
+}
+
+
+/// A finished Sprint run, as sprint.json had them.
+#[derive(Deserialize)]
+struct SprintRecord
+{
+    lines: u32,
+    frames: u32,
+    splits: Vec<u32>,
+    seed: u64,
+    randomizer: String,
+    date: u64,
+    replay: String
+}
+
+
+/// An Ultra game, as ultra.json had them.
+#[derive(Deserialize)]
+struct UltraRecord
+{
+    minutes: u32,
+    score: u32,
+    lines: u32,
+    seed: u64,
+    randomizer: String,
+    date: u64,
+    replay: String
+}
+
+
+fn load_old_records<T: DeserializeOwned>(path: &Path) -> Vec<T>
+{
+    fs::read_to_string(path).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+
+/// Turns seconds since 1970 into "yyyy-mm-dd", in UTC.
+pub fn format_date(date: u64) -> String
+{
+    // Days since 1970 to a date on the proleptic Gregorian calendar, counting in 400-year eras that start on March 1st.
+    let days = (date / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;    // 0 is March.
+
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{}-{:02}-{:02}", year, month, day)
+}
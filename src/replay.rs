@@ -0,0 +1,257 @@
+/*
+
+Replays: a seed, a randomizer, a mode, and which keys were pressed on which update.
+
+Since everything random in a game comes from its seed, that's all it takes to play a game back exactly.
+Replay files are plain text, so they're easy to eyeball and diff:
+
+    rustris-replay 3
+    seed 1234
+    randomizer bag
+    mode sprint-40
+    frames 10142
+    25 left,left,cw
+    33 left 1
+    41 hard
+    ...
+
+Each input line is an update number (counting from 0), then the keys pressed for that update, in the order
+they were pressed (see format_actions()). Updates with no keys pressed aren't stored. A third number says how
+many of those keys, at the end, were a held key repeating (see GameState::repeats), so finesse plays back the same.
+Left out, there were none.
+
+Versions 1 and 2 are from when most modes ran at 30 updates a second. In those modes, each of their updates is
+two of today's: the update numbers, frame count and gravity are doubled, and each update's keys go on the
+second of its two, after the piece has fallen, just where they went then. NES Marathon and Master always
+ran at their own 60, and play back as they were.
+
+Version 1 replays, from before keys were kept in order, have six flags instead, one for each Action, in
+Action::ALL order: "100001" is a move left and a hard drop. Those play back in the order the rules used
+to apply them: moves, then drops, then rotations.
+Replays from before modes existed have no mode line, and are endless games.
+Games played at another speed have a speed line, the percent of the usual falling speed they were played at.
+Games played with the gravity or spawn position changed in the settings have gravity, spawn_row and spawn_col
+lines too. Without them, those are the usual GRAVITY, and the middle of the well (Well::spawn()). Games in a well
+of another size have a line with its width, visible height and hidden rows, as in parse_well_size(): "well 20x30+4".
+Without it, it's the usual 10x20+4. Games with another piece set have a line naming it, "pieces pentominoes",
+and need the set to play back: a set of the player's own that's since changed or gone won't play back the same.
+
+*/
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{format_actions, format_well_size, parse_actions, parse_well_size, Action, GameState, Randomizer, Well, GRAVITY, NORMAL_SPEED};
+use crate::input::InputSource;
+use crate::modes::Mode;
+use crate::pieces::PieceSet;
+
+
+const HEADER: &str = "rustris-replay 3";
+const HEADER_V2: &str = "rustris-replay 2";
+const HEADER_V1: &str = "rustris-replay 1";
+
+/// The order version 1's rules applied a six-flag line's keys in.
+const V1_ORDER: [Action; 6] = [Action::MoveLeft, Action::MoveRight, Action::SoftDrop, Action::HardDrop, Action::RotateCCW, Action::RotateCW];
+
+
+#[derive(Clone)]
+pub struct Replay
+{
+    pub seed: u64,
+    pub randomizer: Randomizer,
+    pub mode: Mode,
+    pub frames: u32,                      // How many updates the recorded game lasted.
+    pub gravity: u32,
+    pub speed: u32,                       // Percent of the usual falling speed. (See GameState::speed.)
+    pub well: Well,                       // Empty, the size the game's was.
+    pub spawn: (i32, i32),                // Row and column.
+    pub pieces: Arc<PieceSet>,
+    pub inputs: Vec<(u32, Vec<Action>, usize)>   // (update number, keys pressed in order, how many are repeats), in update order.
+}
+
+
+impl Replay
+{
+    /// An empty replay for a game that's about to start.
+    pub fn new(game_state: &GameState) -> Self
+    {
+        Replay { seed: game_state.seed, randomizer: game_state.randomizer, mode: game_state.mode, frames: 0,
+                 gravity: game_state.gravity, speed: game_state.speed, well: game_state.well.empty_like(), spawn: (game_state.spawn_row, game_state.spawn_col),
+                 pieces: game_state.piece_set.clone(), inputs: Vec::new() }
+    }
+
+    /// Notes the keys about to be handed to game_update() on the given update, and how many of them are repeats.
+    pub fn record(&mut self, frame: u32, keys: &[Action], repeats: usize)
+    {
+        self.frames = frame + 1;
+        if !keys.is_empty() { self.inputs.push((frame, keys.to_vec(), repeats)); }
+    }
+
+    /// A fresh game set up exactly like the recorded one was.
+    pub fn new_game(&self) -> GameState
+    {
+        let mut game_state = GameState::with_seed(self.seed, self.randomizer);
+        game_state.set_mode(self.mode);
+        game_state.gravity = self.gravity;
+        game_state.speed = self.speed;
+        if !self.well.is_standard() { game_state.set_well(self.well.clone()); }
+        game_state.set_spawn(self.spawn.0, self.spawn.1);
+        if !self.pieces.is_standard() { game_state.set_piece_set(self.pieces.clone()); }
+        game_state
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()>
+    {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self>
+    {
+        Replay::from_text(&fs::read_to_string(path)?).map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+
+    pub fn to_text(&self) -> String
+    {
+        let mut text = format!("{}\nseed {}\nrandomizer {}\nmode {}\nframes {}\n", HEADER, self.seed, self.randomizer, self.mode, self.frames);
+        if self.gravity != GRAVITY { text.push_str(&format!("gravity {}\n", self.gravity)); }
+        if self.speed != NORMAL_SPEED { text.push_str(&format!("speed {}\n", self.speed)); }
+        if !self.well.is_standard() {
+            text.push_str(&format!("well {}\n", format_well_size(&self.well)));
+        }
+        if self.spawn != self.well.spawn() { text.push_str(&format!("spawn_row {}\nspawn_col {}\n", self.spawn.0, self.spawn.1)); }
+        if !self.pieces.is_standard() { text.push_str(&format!("pieces {}\n", self.pieces.name)); }
+
+        for (frame, keys, repeats) in self.inputs.iter() {
+            text.push_str(&format!("{} {}", frame, format_actions(keys)));
+            text.push_str(&if *repeats > 0 { format!(" {}\n", repeats) } else { "\n".to_string() });
+        }
+
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String>
+    {
+        let mut lines = text.lines().enumerate().map(|(num, line)| (num + 1, line.trim())).filter(|(_, line)| !line.is_empty());
+
+        let version = match lines.next() {
+            Some((_, line)) if line == HEADER => 3,
+            Some((_, line)) if line == HEADER_V2 => 2,
+            Some((_, line)) if line == HEADER_V1 => 1,
+            _ => return Err(format!("not a replay file (expected '{}' on the first line)", HEADER))
+        };
+
+        let mut replay = Replay { seed: 0, randomizer: Randomizer::Bag, mode: Mode::Endless, frames: 0,
+                                  gravity: GRAVITY, speed: NORMAL_SPEED, well: Well::default(), spawn: (0, 0), pieces: Arc::new(PieceSet::standard()), inputs: Vec::new() };
+        let (mut spawn_row, mut spawn_col) = (None, None);    // Defaulting to the middle of the well, once it's known.
+        let mut gravity = None;                               // Defaulting to GRAVITY, once the version's been allowed for.
+
+        for (num, line) in lines
+        {
+            let mut words = line.split_whitespace();
+            let (first, second) = match (words.next(), words.next()) {
+                (Some(first), Some(second)) => (first, second),
+                _ => return Err(format!("line {}: expected two fields, got '{}'", num, line))
+            };
+            let bad = |what: &str| format!("line {}: bad {} '{}'", num, what, line);
+
+            match first
+            {
+                "seed"       => replay.seed = second.parse().map_err(|_| bad("seed"))?,
+                "randomizer" => replay.randomizer = second.parse().map_err(|msg| format!("line {}: {}", num, msg))?,
+                "mode"       => replay.mode = second.parse().map_err(|msg| format!("line {}: {}", num, msg))?,
+                "frames"     => replay.frames = second.parse().map_err(|_| bad("frame count"))?,
+                "gravity"    => gravity = Some(second.parse().map_err(|_| bad("gravity"))?),
+                "speed"      => replay.speed = second.parse().ok().filter(|speed| *speed > 0).ok_or_else(|| bad("speed"))?,
+                "well"       => replay.well = parse_well_size(second).map_err(|msg| format!("line {}: {}", num, msg))?,
+                "spawn_row"  => spawn_row = Some(second.parse().map_err(|_| bad("spawn row"))?),
+                "spawn_col"  => spawn_col = Some(second.parse().map_err(|_| bad("spawn column"))?),
+                "pieces"     => replay.pieces = Arc::new(PieceSet::load(second).map_err(|msg| format!("line {}: {}", num, msg))?),
+                _ => {
+                    let frame: u32 = first.parse().map_err(|_| bad("update number"))?;
+                    let keys = if version == 1 { parse_v1_flags(second).ok_or_else(|| bad("key flags"))? }
+                               else { parse_actions(second).ok_or_else(|| bad("keys"))? };
+                    let repeats = match words.next() {
+                        Some(repeats) => repeats.parse().ok().filter(|repeats| *repeats <= keys.len()).ok_or_else(|| bad("repeat count"))?,
+                        None => 0
+                    };
+                    replay.inputs.push((frame, keys, repeats));
+                }
+            }
+        }
+
+        let (row, col) = replay.well.spawn();
+        replay.spawn = (spawn_row.unwrap_or(row), spawn_col.unwrap_or(col));
+
+
+        // From before every mode ran at 60 updates a second. (Left out, gravity was the old GRAVITY, which is the new one now.)
+        // Numbers too big to scale up were too big to be real ones anyway.
+        let scale = if version < 3 { replay.mode.updates_per_old_update() } else { 1 };
+        let scaled = |value: u32, what: &str| value.checked_mul(scale).ok_or_else(|| format!("bad {} '{}'", what, value));
+        replay.gravity = match gravity { Some(gravity) => scaled(gravity, "gravity")?, None => GRAVITY };
+        replay.frames = scaled(replay.frames, "frame count")?;
+        for (frame, _, _) in replay.inputs.iter_mut() {
+            *frame = frame.checked_mul(scale).and_then(|frame| frame.checked_add(scale - 1)).ok_or_else(|| format!("bad update number '{}'", frame))?;
+        }
+        Ok(replay)
+    }
+}
+
+
+
+/// A version 1 line's six flags, as the presses they amounted to.
+fn parse_v1_flags(flags: &str) -> Option<Vec<Action>>
+{
+    if flags.len() != 6 || flags.chars().any(|c| c != '0' && c != '1') { return None; }
+
+    let pressed: Vec<Action> = Action::ALL.iter().zip(flags.chars()).filter(|(_, flag)| *flag == '1').map(|(action, _)| *action).collect();
+    Some(V1_ORDER.iter().copied().filter(|action| pressed.contains(action)).collect())
+}
+
+
+/// Plays a replay's keys back into a game. The game must have been started with Replay::new_game().
+pub struct ReplayInput
+{
+    replay: Replay,
+    next: usize,      // Index of the next entry in replay.inputs to hand out.
+    repeats: usize    // How many of the keys last handed out were repeats.
+}
+
+
+impl ReplayInput
+{
+    pub fn new(replay: Replay) -> Self
+    {
+        ReplayInput { replay, next: 0, repeats: 0 }
+    }
+}
+
+
+impl InputSource for ReplayInput
+{
+    /// True once the game has run as long as the recorded one did.
+    fn finished(&self, game_state: &GameState) -> bool
+    {
+        game_state.frames >= self.replay.frames
+    }
+
+    fn next_keys(&mut self, game_state: &GameState) -> Vec<Action>
+    {
+        match self.replay.inputs.get(self.next)
+        {
+            Some((frame, keys, repeats)) if *frame == game_state.frames => {
+                self.next += 1;
+                self.repeats = *repeats;
+                keys.clone()
+            }
+            _ => { self.repeats = 0; Vec::new() }
+        }
+    }
+
+    fn repeats(&self) -> usize
+    {
+        self.repeats
+    }
+}
@@ -0,0 +1,451 @@
+/*
+
+Settings: the knobs that used to be numbers in main.rs, in a TOML file the player can edit.
+
+The file is settings.toml in rustris' folder in the platform's config directory (e.g. ~/.config/rustris on
+Linux, going by $XDG_CONFIG_HOME). It doesn't have to exist, and anything it leaves out keeps its default:
+
+    version = 2
+
+    [game]
+    speed = 100          # percent of normal falling speed
+    gravity = 42         # 60ths of a second a piece hangs in each row, in Endless, Sprint and the like
+    well_width = 10      # 4 to 40 columns
+    well_height = 20     # visible rows, 4 to 60
+    hidden_rows = 4      # rows above the visible well that pieces appear in and can stick out into, 2 to 20
+    spawn_row = 2        # where new pieces appear. Leave these out to have them in the middle of whatever well it is.
+    spawn_col = 3
+    pieces = "standard"  # or triominoes, pentominoes, mixed, or a set of your own (see pieces.rs)
+
+    [handling]
+    das = 12             # for new profiles: 60ths of a second a move key is held before it repeats
+    arr = 4              # and 60ths of a second between repeats
+
+    [visuals]
+    width = 1280
+    height = 720
+    vsync = true
+    palette = "classic"  # or deuteranopia, protanopia, tritanopia
+    patterns = false     # glyphs on the blocks
+    smooth = true        # draw the falling piece gliding between rows, rather than jumping
+
+    [audio]
+    music = true
+    volume = 0.1
+
+Times are counted in the game's updates, 60 a second. The version line says so: a file without one is from when
+the game ran at 30 a second, and its gravity, DAS and ARR are doubled when it's read.
+
+Every setting has a name, "section.key", which is what the options menu edits and what the command line's
+--set takes: "tetris --set game.gravity=5 --set audio.music=false". Values outside their limits, or of the
+wrong type, or names that aren't settings, are errors that say what would be right.
+
+Gravity, the well's size, spawn position and piece set change the rules, so games played with them changed are recorded as such in
+their replays (see replay.rs), and don't go in the high-score tables. Speed doesn't change the rules, but a slowed-down game
+is an easier one, so games at any speed but 100 stay out of the tables too.
+
+*/
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::{GameState, Palette, Well, GRAVITY, NORMAL_SPEED, MAX_HIDDEN_ROWS, MAX_WELL_COLS, MAX_WELL_ROWS, MIN_HIDDEN_ROWS, MIN_WELL_COLS,
+            MIN_WELL_ROWS, WELL_COLS, WELL_HIDDEN_ROWS, WELL_VISIBLE_ROWS};
+use crate::garbage::cheese_rows;
+use crate::pieces::{PieceSet, BUILTIN_SETS};
+
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings
+{
+    pub game: GameSettings,
+    pub handling: HandlingSettings,
+    pub visuals: VisualSettings,
+    pub audio: AudioSettings
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameSettings
+{
+    pub speed: u32,        // Percent of normal falling speed. (See GameState::speed.)
+    pub gravity: u32,      // See GameState::gravity.
+    pub well_width: usize,
+    pub well_height: usize,    // Visible rows.
+    pub hidden_rows: usize,
+    pub spawn_row: Option<i32>,    // None for the middle of the well. (See Well::spawn().)
+    pub spawn_col: Option<i32>,
+    pub pieces: String         // A PieceSet, by name.
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HandlingSettings
+{
+    pub das: u32,          // What new profiles start with. Each profile has its own after that.
+    pub arr: u32
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VisualSettings
+{
+    pub width: u32,        // Window size, in pixels. Only read when the window opens.
+    pub height: u32,
+    pub vsync: bool,
+    pub palette: String,   // A Palette, by name.
+    pub patterns: bool,
+    pub smooth: bool       // Interpolate the falling piece between steps. (See timestep.rs.)
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioSettings
+{
+    pub music: bool,
+    pub volume: f32        // 0 to 1.
+}
+
+
+impl Default for GameSettings
+{
+    fn default() -> Self
+    {
+        GameSettings { speed: NORMAL_SPEED, gravity: GRAVITY, well_width: WELL_COLS, well_height: WELL_VISIBLE_ROWS, hidden_rows: WELL_HIDDEN_ROWS,
+                       spawn_row: None, spawn_col: None, pieces: "standard".to_string() }
+    }
+}
+
+impl Default for HandlingSettings
+{
+    fn default() -> Self { HandlingSettings { das: 12, arr: 4 } }
+}
+
+impl Default for VisualSettings
+{
+    fn default() -> Self { VisualSettings { width: 1280, height: 720, vsync: true, palette: Palette::Classic.to_string(), patterns: false, smooth: true } }
+}
+
+impl Default for AudioSettings
+{
+    // The music file is loud. This is about right for it.
+    fn default() -> Self { AudioSettings { music: true, volume: 0.1 } }
+}
+
+
+/// What the settings file's version line says: 2 since the game went to 60 updates a second. (See from_toml().)
+pub const SETTINGS_VERSION: i64 = 2;
+
+/// Every setting's name, in the order the options menu lists them.
+pub const SETTING_NAMES: [&str; 18] = [
+    "game.speed", "game.gravity", "game.well_width", "game.well_height", "game.hidden_rows", "game.spawn_row", "game.spawn_col", "game.pieces",
+    "handling.das", "handling.arr",
+    "visuals.width", "visuals.height", "visuals.vsync", "visuals.palette", "visuals.patterns", "visuals.smooth",
+    "audio.music", "audio.volume"
+];
+
+/// Settings that only take effect the next time the game starts.
+pub const RESTART_SETTINGS: [&str; 3] = ["visuals.width", "visuals.height", "visuals.vsync"];
+
+
+/// A spawn position line for the settings file. Left to the well, it's commented out, showing where that puts it.
+fn spawn_line(name: &str, value: Option<i32>, auto: i32, comment: &str) -> String
+{
+    let setting = format!("{}{} = {}", if value.is_some() { "" } else { "# " }, name, value.unwrap_or(auto));
+    if comment.is_empty() { setting } else { format!("{:<21}{}", setting, comment) }
+}
+
+
+/// The lowest and highest a number setting can be, and how far the options menu moves it in one go.
+/// Where pieces can appear depends on the well they appear in.
+fn limits(name: &str, well: &Well) -> Option<(f64, f64, f64)>
+{
+    let lowest_spawn = well.rows() as f64 - f64::from(cheese_rows(well)) - 4.0;    // Any lower and Cheese's garbage would be in the way.
+
+    match name
+    {
+        "game.speed"       => Some((25.0, 400.0, 5.0)),
+        "game.gravity"     => Some((1.0, 1200.0, 1.0)),
+        "game.well_width"  => Some((MIN_WELL_COLS as f64, MAX_WELL_COLS as f64, 1.0)),
+        "game.well_height" => Some((MIN_WELL_ROWS as f64, MAX_WELL_ROWS as f64, 1.0)),
+        "game.hidden_rows" => Some((MIN_HIDDEN_ROWS as f64, MAX_HIDDEN_ROWS as f64, 1.0)),
+        "game.spawn_row"   => Some((0.0, lowest_spawn, 1.0)),
+        "game.spawn_col"   => Some((0.0, well.cols() as f64 - 4.0, 1.0)),     // The piece's 4x4 box has to fit across the well.
+        "handling.das"     => Some((0.0, 120.0, 1.0)),
+        "handling.arr"     => Some((0.0, 60.0, 1.0)),
+        "visuals.width"    => Some((640.0, 7680.0, 80.0)),
+        "visuals.height"   => Some((360.0, 4320.0, 45.0)),
+        "audio.volume"     => Some((0.0, 1.0, 0.05)),
+        _ => None
+    }
+}
+
+
+impl Settings
+{
+    /// Where the settings file lives. None if the platform doesn't have a config directory.
+    pub fn path() -> Option<PathBuf>
+    {
+        dirs::config_dir().map(|dir| dir.join("rustris").join("settings.toml"))
+    }
+
+    /// The settings file, or the defaults if there isn't one. A file that's there but wrong is an error, saying what's wrong.
+    pub fn load() -> Result<Self, String>
+    {
+        let path = match Settings::path() { Some(path) => path, None => return Ok(Settings::default()) };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => Settings::from_toml(&text).map_err(|msg| format!("{}: {}", path.display(), msg)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(err) => Err(format!("{}: {}", path.display(), err))
+        }
+    }
+
+    /// Writes the settings file, all of it, with comments. Same as the high scores: to a temporary file, then over the real one.
+    pub fn save(&self) -> io::Result<()>
+    {
+        let path = Settings::path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory on this platform"))?;
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        let temp = path.with_extension("toml.tmp");
+        fs::write(&temp, self.to_toml())?;
+        fs::rename(&temp, &path)
+    }
+
+    /// Reads a settings file. One without a version line is from when the game ran at 30 updates a second: its
+    /// gravity, DAS and ARR are doubled to keep them the same lengths of time.
+    pub fn from_toml(text: &str) -> Result<Self, String>
+    {
+        let mut value: toml::Value = text.parse().map_err(|err: toml::de::Error| err.to_string())?;
+        let table = value.as_table_mut().ok_or("expected a table")?;
+
+        let version = match table.remove("version") {
+            None => 1,
+            Some(toml::Value::Integer(version)) if (1..=SETTINGS_VERSION).contains(&version) => version,
+            Some(version) => return Err(format!("version is {}, but this rustris only knows up to {}", version, SETTINGS_VERSION))
+        };
+        if version == 1 {
+            for (section, key) in [("game", "gravity"), ("handling", "das"), ("handling", "arr")].iter() {
+                if let Some(toml::Value::Integer(updates)) = table.get_mut(*section).and_then(|section| section.get_mut(*key)) {
+                    *updates = updates.saturating_mul(2);    // Anything that big is out of range anyway.
+                }
+            }
+        }
+
+        let settings: Settings = value.try_into().map_err(|err: toml::de::Error| err.to_string())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn to_toml(&self) -> String
+    {
+        let (game, handling, visuals, audio) = (&self.game, &self.handling, &self.visuals, &self.audio);
+        let (spawn_row, spawn_col) = self.well().spawn();
+
+        format!("# rustris settings. Delete a line to have its default back.\n\n\
+                 version = {}          # which rustris wrote this; leave it be\n\n\
+                 [game]\n\
+                 speed = {}          # percent of normal falling speed, 25 to 400\n\
+                 gravity = {}         # 60ths of a second a piece hangs in each row, in Endless, Sprint and the like (42)\n\
+                 well_width = {}      # 4 to 40 columns (10)\n\
+                 well_height = {}     # visible rows, 4 to 60 (20)\n\
+                 hidden_rows = {}      # rows above those, 2 to 20 (4)\n\
+                 {}\n\
+                 {}\n\
+                 pieces = \"{}\"  # standard, triominoes, pentominoes, mixed, or a set of your own\n\n\
+                 [handling]\n\
+                 das = {}             # for new profiles: 60ths of a second a move key is held before it repeats\n\
+                 arr = {}              # and 60ths of a second between repeats\n\n\
+                 [visuals]\n\
+                 width = {}\n\
+                 height = {}\n\
+                 vsync = {}\n\
+                 palette = \"{}\"  # classic, deuteranopia, protanopia or tritanopia\n\
+                 patterns = {}     # glyphs on the blocks\n\
+                 smooth = {}        # the falling piece glides between rows\n\n\
+                 [audio]\n\
+                 music = {}\n\
+                 volume = {:<12}# 0 to 1\n",
+                SETTINGS_VERSION, game.speed, game.gravity, game.well_width, game.well_height, game.hidden_rows,
+                spawn_line("spawn_row", game.spawn_row, spawn_row, "# where new pieces appear; left out, the middle of the well"),
+                spawn_line("spawn_col", game.spawn_col, spawn_col, ""),
+                game.pieces, handling.das, handling.arr,
+                visuals.width, visuals.height, visuals.vsync, visuals.palette, visuals.patterns, visuals.smooth, audio.music, audio.volume)
+    }
+
+    /// Checks every setting is within its limits. The error names the first one that isn't.
+    pub fn validate(&self) -> Result<(), String>
+    {
+        for name in SETTING_NAMES.iter()
+        {
+            // The well's own limits come first, so there's a well to check the spawn position against.
+            let well = if name.starts_with("game.spawn") { self.well() } else { Well::default() };
+
+            if let (Some((low, high, _)), Some(value)) = (limits(name, &well), self.get(name).and_then(|value| value.parse::<f64>().ok())) {
+                if value < low || value > high {
+                    return Err(format!("{} is {}, but has to be from {} to {}", name, value, low, high));
+                }
+            }
+        }
+        PieceSet::load(&self.game.pieces).map_err(|msg| format!("game.pieces: {}", msg))?;
+        self.visuals.palette.parse::<Palette>().map(|_| ()).map_err(|msg| format!("visuals.palette: {}", msg))
+    }
+
+    /// A setting's value, as text. None if there's no setting by that name.
+    pub fn get(&self, name: &str) -> Option<String>
+    {
+        Some(match name
+        {
+            "game.speed"       => self.game.speed.to_string(),
+            "game.gravity"     => self.game.gravity.to_string(),
+            "game.well_width"  => self.game.well_width.to_string(),
+            "game.well_height" => self.game.well_height.to_string(),
+            "game.hidden_rows" => self.game.hidden_rows.to_string(),
+            "game.spawn_row"   => self.game.spawn_row.map_or("auto".to_string(), |row| row.to_string()),
+            "game.spawn_col"   => self.game.spawn_col.map_or("auto".to_string(), |col| col.to_string()),
+            "game.pieces"      => self.game.pieces.clone(),
+            "handling.das"     => self.handling.das.to_string(),
+            "handling.arr"     => self.handling.arr.to_string(),
+            "visuals.width"    => self.visuals.width.to_string(),
+            "visuals.height"   => self.visuals.height.to_string(),
+            "visuals.vsync"    => self.visuals.vsync.to_string(),
+            "visuals.palette"  => self.visuals.palette.clone(),
+            "visuals.patterns" => self.visuals.patterns.to_string(),
+            "visuals.smooth"   => self.visuals.smooth.to_string(),
+            "audio.music"      => self.audio.music.to_string(),
+            "audio.volume"     => format!("{:.2}", self.audio.volume),
+            _ => return None
+        })
+    }
+
+    /// Sets a setting from text, as in "--set name=value". Nothing changes if the value won't do.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String>
+    {
+        let mut changed = self.clone();
+        let bad = |what: &str| format!("{} has to be {}, not '{}'", name, what, value);
+        let whole = || value.parse::<u32>().map_err(|_| bad("a whole number"));
+        let size = || value.parse::<usize>().map_err(|_| bad("a whole number"));
+        let spawn = || if value == "auto" { Ok(None) } else { value.parse::<i32>().map(Some).map_err(|_| bad("a whole number or auto")) };
+        let flag = || value.parse::<bool>().map_err(|_| bad("true or false"));
+
+        match name
+        {
+            "game.speed"       => changed.game.speed = whole()?,
+            "game.gravity"     => changed.game.gravity = whole()?,
+            "game.well_width"  => changed.game.well_width = size()?,
+            "game.well_height" => changed.game.well_height = size()?,
+            "game.hidden_rows" => changed.game.hidden_rows = size()?,
+            "game.spawn_row"   => changed.game.spawn_row = spawn()?,
+            "game.spawn_col"   => changed.game.spawn_col = spawn()?,
+            "game.pieces"      => changed.game.pieces = value.to_string(),
+            "handling.das"     => changed.handling.das = whole()?,
+            "handling.arr"     => changed.handling.arr = whole()?,
+            "visuals.width"    => changed.visuals.width = whole()?,
+            "visuals.height"   => changed.visuals.height = whole()?,
+            "visuals.vsync"    => changed.visuals.vsync = flag()?,
+            "visuals.palette"  => changed.visuals.palette = value.to_string(),
+            "visuals.patterns" => changed.visuals.patterns = flag()?,
+            "visuals.smooth"   => changed.visuals.smooth = flag()?,
+            "audio.music"      => changed.audio.music = flag()?,
+            "audio.volume"     => changed.audio.volume = value.parse().map_err(|_| bad("a number"))?,
+            _ => return Err(format!("there's no setting called '{}' (there's {})", name, SETTING_NAMES.join(", ")))
+        }
+
+        changed.validate()?;
+        *self = changed;
+        Ok(())
+    }
+
+    /// Nudges a setting up or down one step, for the options menu: numbers by their step, within their limits,
+    /// flags flip, and the palette and the built-in piece sets go round. The spawn position goes from auto to where auto puts it, and
+    /// back to auto below 0.
+    pub fn step(&mut self, name: &str, up: bool)
+    {
+        let value = match self.get(name) { Some(value) => value, None => return };
+        let (spawn_row, spawn_col) = self.well().spawn();
+
+        let stepped = match (limits(name, &self.well()), value.as_str()) {
+            (Some(_), "auto") => if name == "game.spawn_row" { spawn_row.to_string() } else { spawn_col.to_string() },
+            (Some((low, _, _)), _) if !up && name.starts_with("game.spawn") && value.parse::<f64>() == Ok(low) => "auto".to_string(),
+            (Some((low, high, step)), _) => {
+                let number: f64 = value.parse().unwrap_or(low);
+                let number = number + if up { step } else { -step };
+                ((number / step).round() * step).clamp(low, high).to_string()    // Rounded, so volume doesn't drift off 0.05s.
+            }
+            (None, "true") => "false".to_string(),
+            (None, "false") => "true".to_string(),
+            (None, pieces) if name == "game.pieces" => {
+                let index = BUILTIN_SETS.iter().position(|set| *set == pieces).unwrap_or(0);    // A set of the player's own goes back to standard.
+                BUILTIN_SETS[(index + if up { 1 } else { BUILTIN_SETS.len() - 1 }) % BUILTIN_SETS.len()].to_string()
+            }
+            (None, palette) => {
+                let palette: Palette = palette.parse().unwrap_or(Palette::Classic);
+                (if up { palette.next() } else { palette.next().next().next() }).to_string()
+            }
+        };
+        let _ = self.set(name, &stepped);
+    }
+
+    /// Applies "--set name=value" from the command line, as many as there are. Errors say which one was wrong.
+    pub fn override_from_args(&mut self, args: &[String]) -> Result<(), String>
+    {
+        for index in (0..args.len()).filter(|index| args[*index] == "--set")
+        {
+            let setting = args.get(index + 1).ok_or("--set needs a name=value after it")?;
+            let (name, value) = setting.split_once('=').ok_or_else(|| format!("--set {}: expected name=value", setting))?;
+            self.set(name.trim(), value.trim()).map_err(|msg| format!("--set {}: {}", setting, msg))?;
+        }
+        Ok(())
+    }
+
+    pub fn palette(&self) -> Palette
+    {
+        self.visuals.palette.parse().unwrap_or(Palette::Classic)
+    }
+
+    /// An empty well of the size the settings say.
+    pub fn well(&self) -> Well
+    {
+        Well::new(self.game.well_width, self.game.well_height, self.game.hidden_rows)
+    }
+
+    /// Where new pieces appear: the settings' spawn_row and spawn_col, or the middle of the well for either left out.
+    pub fn spawn(&self) -> (i32, i32)
+    {
+        let (row, col) = self.well().spawn();
+        (self.game.spawn_row.unwrap_or(row), self.game.spawn_col.unwrap_or(col))
+    }
+
+    /// The piece set the settings say. The usual seven if it can't be loaded any more (validate() checked it could).
+    pub fn piece_set(&self) -> Arc<PieceSet>
+    {
+        Arc::new(PieceSet::load(&self.game.pieces).unwrap_or_else(|_| PieceSet::standard()))
+    }
+
+    /// Whether speed, gravity, the well, spawn position and pieces are as usual, so games are fit for the high-score tables.
+    pub fn standard_rules(&self) -> bool
+    {
+        self.game.speed == NORMAL_SPEED && self.game.gravity == GRAVITY && self.well().is_standard() && self.spawn() == self.well().spawn() && self.game.pieces == "standard"
+    }
+
+    /// Sets up a fresh game's speed, gravity, well, spawn position and piece set. The well and the pieces are only
+    /// swapped if they're not the usual ones, same as Replay::new_game() does, so the seed goes just as far either way.
+    pub fn apply_rules(&self, game_state: &mut GameState)
+    {
+        game_state.speed = self.game.speed;
+        game_state.gravity = self.game.gravity;
+        if !self.well().is_standard() { game_state.set_well(self.well()); }
+        let (row, col) = self.spawn();
+        game_state.set_spawn(row, col);
+        if self.game.pieces != "standard" { game_state.set_piece_set(self.piece_set()); }
+    }
+}
@@ -0,0 +1,420 @@
+/*
+
+The perfect clear solver. Given a well, the falling piece, the held one and the pieces to come, it looks for a way
+to place them that leaves the well completely empty, clearing no more than a given number of lines: four for the
+usual perfect clear opener, fewer for the ones after it.
+
+Every piece can go anywhere it can get to by moving, turning and soft dropping, so tucks and spins count, and
+each can be held instead if there's a hold. The search goes depth first, lowest placements first, and remembers
+the positions (the rows left to clear, and the pieces still to come) that have already failed so it doesn't
+try them again. Placements have to stay inside the rows left to clear: anything above them would be left over.
+
+That's a lot of placements and a lot of positions to remember, so it doesn't search in a Well. It copies the
+well's row masks for the rows left to clear (see Well::row_mask()) into a small board of its own, which is cheap
+to copy and to remember, and fits pieces in with their masks the same way would_collide() does: a piece fits if
+none of its rows ANDed with the rows under it have anything in them. A full row is one that equals the full mask.
+
+The game's hints hold if its mode has hold, starting from whatever it's holding already. Its pieces to come are
+the ones its randomizer is going to deal, as if the preview showed that far.
+
+*/
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+
+use crate::{clear_complete_rows, freeze_to_well, shift_mask, GameState, Tetrimino, TetriminoKind, Well, PIECE_SIZE};
+use crate::fumen::{self, Page, Placement};
+use crate::puzzles::pieces_to_come;
+
+
+/// Lines to clear if nobody says otherwise: a perfect clear opener's.
+pub const DEFAULT_LINES: usize = 4;
+
+/// The most lines a search can be asked to clear. More than this is too many pieces to search.
+pub const MAX_LINES: usize = 6;
+
+/// How many placements a search tries before giving up.
+const SEARCH_LIMIT: u64 = 1_000_000;
+
+
+/// What there is to work with.
+#[derive(Clone)]
+pub struct Problem
+{
+    pub well: Well,
+    pub pieces: Vec<Tetrimino>,    // The falling piece, then the ones to come.
+    pub hold: Option<Tetrimino>,   // What's held already.
+    pub can_hold: bool,
+    pub hold_used: bool,           // The falling piece has been held already, so it can't be again. (Pieces after it can.)
+    pub start: (i32, i32),         // Where the falling piece is: the row and column of its box, as turned as it is.
+    pub spawn: (i32, i32),         // Where the rest appear.
+    pub lines: usize               // The most lines to clear.
+}
+
+
+/// One piece of a solution.
+#[derive(Clone, Debug)]
+pub struct Step
+{
+    pub ttmo: Tetrimino,           // Turned as it lands.
+    pub row: i32,                  // Where its box lands, in the well as it is by then.
+    pub col: i32,
+    pub hold: bool,                // Whether it's the held piece (or the one after, with nothing held), by pressing hold first.
+    pub squares: Vec<(i32, i32)>   // Its squares, (row, column), in the well as it was before the first piece: lines that
+}                                  // clear along the way don't move them down.
+
+
+pub enum Solution
+{
+    Found(Vec<Step>),
+    Impossible,    // There's no way.
+    GaveUp         // There might be, but it took too long to look.
+}
+
+
+impl Problem
+{
+    /// A problem with its pieces appearing where they usually do in the given well, and no hold.
+    pub fn new(well: Well, pieces: Vec<Tetrimino>, lines: usize) -> Self
+    {
+        let spawn = well.spawn();
+        Problem { well, pieces, hold: None, can_hold: false, hold_used: false, start: spawn, spawn, lines }
+    }
+
+    /// The game's well, its falling piece where it is, its hold, and as many of the pieces to come as it could take to
+    /// clear the lines.
+    pub fn from_game(game_state: &GameState, lines: usize) -> Self
+    {
+        let needed = (game_state.well.cols() * lines).div_ceil(3);    // Enough for the smallest pieces there are.
+        let mut pieces = vec![game_state.curr_ttmo, game_state.next_ttmo];
+        pieces.extend(game_state.upcoming(needed.saturating_sub(1)));
+        if game_state.puzzle.is_some() { pieces.truncate(pieces_to_come(game_state) + 1); }
+
+        Problem { well: game_state.well.clone(), pieces, hold: game_state.hold_ttmo, can_hold: game_state.has_hold(),
+                  hold_used: game_state.hold_used, start: (game_state.ttmo_row, game_state.ttmo_col),
+                  spawn: (game_state.spawn_row, game_state.spawn_col), lines }
+    }
+
+    pub fn solve(&self) -> Solution
+    {
+        if self.lines == 0 || self.lines > MAX_LINES || self.pieces.is_empty() { return Solution::Impossible; }
+
+        // Anything above the lines to clear would still be there after.
+        let board = match Board::new(&self.well, self.lines) {
+            Some(board) => board,
+            None => return Solution::Impossible
+        };
+
+        // Every piece fills its own number of squares and every cleared line takes away a row's worth, so if the pieces
+        // are all the same size, the squares left to fill always have to be a multiple of it.
+        let shapes: Vec<Shapes> = self.pieces.iter().chain(self.hold.iter()).map(Shapes::new).collect();
+        let size = Some(shapes[0].size).filter(|size| shapes.iter().all(|shape| shape.size == *size));
+        if size.is_some_and(|size| !board.empty_squares().is_multiple_of(size)) { return Solution::Impossible; }
+
+        let mut origins = [0; MAX_LINES];
+        for (line, origin) in origins.iter_mut().enumerate().take(board.lines) { *origin = (board.top() + line) as i32; }
+
+        let mut search = Search { problem: self, shapes, size, failed: HashSet::new(), steps: Vec::new(), origins, tried: 0 };
+        let hold = self.hold.map(|_| self.pieces.len());
+
+        if search.search(board, 0, hold) { Solution::Found(search.steps) }
+        else if search.tried >= SEARCH_LIMIT { Solution::GaveUp }
+        else { Solution::Impossible }
+    }
+}
+
+
+/// A solution as a fumen, a page for each piece, starting from the problem's well.
+pub fn solution_fumen(well: &Well, steps: &[Step]) -> Result<String, String>
+{
+    let mut pages = Vec::new();
+    let mut well = well.clone();
+    for step in steps
+    {
+        let mut squares = Vec::new();
+        for (box_row, squares_in_row) in step.ttmo.shape.iter().enumerate() {
+            for (box_col, square) in squares_in_row.iter().enumerate() {
+                if *square != 0 { squares.push((step.row + box_row as i32, step.col + box_col as i32)); }
+            }
+        }
+        let squares = squares.try_into().map_err(|_| "fumens only have tetriminos".to_string())?;
+
+        pages.push(Page { well: well.clone(), piece: Some(Placement { kind: step.ttmo.kind, squares }), lock: true,
+                          comment: if step.hold { "hold".to_string() } else { String::new() } });
+        freeze_to_well(&step.ttmo, &mut well, &step.row, &step.col);
+        well = clear_complete_rows(&well);
+    }
+    fumen::encode(&pages)
+}
+
+
+/// The lines still to clear at the bottom of the well, as bitmasks, one per row, top to bottom. Everything above
+/// them is empty.
+#[derive(Copy, Clone)]
+struct Board
+{
+    rows: [u64; MAX_LINES],    // Only the first `lines` are used.
+    lines: usize,
+    height: usize,             // All the well's rows.
+    cols: usize,
+    full: u64                  // What a full row is.
+}
+
+
+impl Board
+{
+    /// The bottom lines of a well, or None if there's anything above them.
+    fn new(well: &Well, lines: usize) -> Option<Self>
+    {
+        let lines = lines.min(well.rows());
+        let mut board = Board { rows: [0; MAX_LINES], lines, height: well.rows(), cols: well.cols(), full: well.full_mask() };
+
+        for row in 0..well.rows() {
+            let mask = well.row_mask(row);
+            if row >= board.top() { board.rows[row - board.top()] = mask; }
+            else if mask != 0 { return None; }
+        }
+        Some(board)
+    }
+
+    /// The well row the lines start at.
+    fn top(&self) -> usize { self.height - self.lines }
+
+    fn row(&self, well_row: i32) -> u64
+    {
+        if well_row < self.top() as i32 { 0 } else { self.rows[well_row as usize - self.top()] }
+    }
+
+    fn empty_squares(&self) -> usize
+    {
+        self.rows[..self.lines].iter().map(|row| self.cols - row.count_ones() as usize).sum()
+    }
+
+    /// Whether the empty squares can still be filled by pieces of the given size, as far as walls go. Where every row
+    /// has something in one column or the one next to it, nothing can ever go across between them, so the squares to
+    /// the left have to be filled by pieces that stay on the left: a whole number of them.
+    fn splits_into(&self, size: usize) -> bool
+    {
+        let rows = &self.rows[..self.lines];
+        let walls = rows.iter().fold(self.full, |walls, row| walls & (row | row >> 1));
+        (0..self.cols - 1).filter(|col| walls & 1 << col != 0).all(|col| {
+            let left = (1u64 << (col + 1)) - 1;
+            rows.iter().map(|row| (col + 1) - (row & left).count_ones() as usize).sum::<usize>().is_multiple_of(size)
+        })
+    }
+
+    /// Where a row of a piece's box goes with the box's left edge at `col`, or None if it's through a wall.
+    fn shift(&self, mask: u64, col: i32) -> Option<u64>
+    {
+        shift_mask(mask, col, self.full)
+    }
+
+    fn collides(&self, masks: &[u64; PIECE_SIZE], row: i32, col: i32) -> bool
+    {
+        masks.iter().enumerate().filter(|(_, mask)| **mask != 0).any(|(box_row, mask)| {
+            let well_row = row + box_row as i32;
+            well_row < 0 || well_row >= self.height as i32 || self.shift(*mask, col).is_none_or(|shifted| self.row(well_row) & shifted != 0)
+        })
+    }
+
+    /// The board with a piece in it (which has to fit), and its full rows taken out. Also says which rows were kept.
+    fn place(&self, masks: &[u64; PIECE_SIZE], row: i32, col: i32) -> (Board, [usize; MAX_LINES])
+    {
+        let mut placed = *self;
+        for (box_row, mask) in masks.iter().enumerate().filter(|(_, mask)| **mask != 0) {
+            placed.rows[(row + box_row as i32) as usize - self.top()] |= self.shift(*mask, col).unwrap();
+        }
+
+        let mut after = Board { rows: [0; MAX_LINES], lines: 0, ..*self };
+        let mut kept = [0; MAX_LINES];
+        for (line, mask) in placed.rows[..self.lines].iter().enumerate().filter(|(_, mask)| **mask != self.full) {
+            after.rows[after.lines] = *mask;
+            kept[after.lines] = line;
+            after.lines += 1;
+        }
+        (after, kept)
+    }
+}
+
+
+/// A piece's rotation states as row masks, and its kicks.
+struct Shapes
+{
+    kind: TetriminoKind,
+    states: Vec<[u64; PIECE_SIZE]>,
+    tops: Vec<usize>,    // The first row of each state's box with anything in it.
+    kicks: Vec<(i32, i32)>,
+    size: usize    // Squares.
+}
+
+
+impl Shapes
+{
+    fn new(ttmo: &Tetrimino) -> Self
+    {
+        // Counted from the state it's in now, so the falling piece's can start from there.
+        let states: Vec<[u64; PIECE_SIZE]> = (0..ttmo.rotation_count).map(|turns| {
+            let mut masks = [0; PIECE_SIZE];
+            for (mask, row) in masks.iter_mut().zip(ttmo.masks[(ttmo.rotation + turns) % ttmo.rotation_count].iter()) { *mask = *row as u64; }
+            masks
+        }).collect();
+
+        let tops = states.iter().map(|masks: &[u64; PIECE_SIZE]| masks.iter().position(|mask| *mask != 0).unwrap()).collect();
+        let size = ttmo.shape.iter().flatten().filter(|square| **square != 0).count();
+        Shapes { kind: ttmo.kind, states, tops, kicks: ttmo.kicks[..ttmo.kick_count].to_vec(), size }
+    }
+}
+
+
+struct Search<'a>
+{
+    problem: &'a Problem,
+    shapes: Vec<Shapes>,    // The problem's pieces, then what's held, if anything.
+    size: Option<usize>,    // How many squares each of them has, if they all have the same.
+    failed: HashSet<([u64; MAX_LINES], usize, usize, Option<TetriminoKind>)>,    // Lines left to clear, next piece, what's held.
+    steps: Vec<Step>,
+    origins: [i32; MAX_LINES],    // Which row of the problem's well each line left to clear was.
+    tried: u64
+}
+
+
+impl Search<'_>
+{
+    /// Tries to clear the board, with the piece at `next` falling and the one at `hold` held.
+    fn search(&mut self, board: Board, next: usize, hold: Option<usize>) -> bool
+    {
+        let count = self.problem.pieces.len();
+        let key = (board.rows, board.lines, next, hold.map(|hold| self.shapes[hold].kind));
+        if self.failed.contains(&key) || self.tried >= SEARCH_LIMIT { return false; }
+
+        // Not enough pieces left to fill the gaps? Every piece placed uses up one still to come, so with one held, there's
+        // always one that can't be placed: at best the smallest.
+        let sizes = self.shapes[next..count].iter().chain(hold.map(|hold| &self.shapes[hold])).map(|shape| shape.size);
+        let left = sizes.clone().sum::<usize>() - if hold.is_some() { sizes.min().unwrap() } else { 0 };
+        if left < board.empty_squares() || self.size.is_some_and(|size| !board.splits_into(size)) {
+            self.failed.insert(key);
+            return false;
+        }
+
+        // The falling piece, or with a hold, what's held (if it's something else), or the piece after it if nothing is.
+        let mut choices = Vec::new();
+        if next < count { choices.push((next, next + 1, hold, false)); }
+        if self.problem.can_hold && next < count && !(next == 0 && self.problem.hold_used) {
+            match hold {
+                Some(held) if self.shapes[held].kind != self.shapes[next].kind => choices.push((held, next + 1, Some(next), true)),
+                None if next + 1 < count => choices.push((next + 1, next + 2, Some(next), true)),
+                _ => ()
+            }
+        }
+
+        for (piece, after, held, pressed_hold) in choices
+        {
+            let start = if piece == 0 { self.problem.start } else { self.problem.spawn };
+            for (state, row, col) in placements(&board, &self.shapes[piece], start)
+            {
+                self.tried += 1;
+                let (placed, kept) = board.place(&self.shapes[piece].states[state], row, col);
+
+                let origins = self.origins;
+                for (line, kept) in kept.iter().enumerate().take(placed.lines) { self.origins[line] = origins[*kept]; }
+                let done = placed.rows.iter().all(|row| *row == 0) || (placed.lines > 0 && self.search(placed, after, held));
+                self.origins = origins;
+
+                if done {
+                    let step = self.step(piece, state, row, col, board.top(), pressed_hold);
+                    self.steps.insert(0, step);
+                    return true;
+                }
+            }
+        }
+
+        self.failed.insert(key);
+        false
+    }
+
+    /// A placement as a step of the solution, on a board whose lines start at the given row.
+    fn step(&self, piece: usize, state: usize, row: i32, col: i32, top: usize, hold: bool) -> Step
+    {
+        let original = self.problem.pieces.get(piece).or(self.problem.hold.as_ref()).unwrap();
+        let mut ttmo = *original;
+        ttmo.rotation = (original.rotation + state) % original.rotation_count;
+        ttmo.shape = ttmo.rotations[ttmo.rotation];
+
+        let mut squares = Vec::new();
+        for (box_row, squares_in_row) in ttmo.shape.iter().enumerate() {
+            for (box_col, square) in squares_in_row.iter().enumerate() {
+                if *square != 0 { squares.push((self.origins[(row + box_row as i32) as usize - top], col + box_col as i32)); }
+            }
+        }
+        Step { ttmo, row, col, hold, squares }
+    }
+}
+
+
+/// Every spot the piece can come to rest in from `start` that's entirely inside the lines to clear: its rotation state
+/// (counted from the one it starts in), row and column. Lowest first.
+fn placements(board: &Board, shapes: &Shapes, start: (i32, i32)) -> Vec<(usize, i32, i32)>
+{
+    let (rows, cols) = (board.height as i32, board.cols as i32);
+    let area = board.top();
+    let size = PIECE_SIZE as i32;
+    let states = shapes.states.len();
+    let mut resting = Vec::new();
+
+    // Everything above the area is empty, so a piece can come straight down to just above it and do whatever it
+    // could have done higher up there instead. That saves going through all the rows in between.
+    let (mut row, col) = start;
+    if board.collides(&shapes.states[0], row, col) { return resting; }
+    row = row.max(area as i32 - size);
+
+    // Which columns each rotation state's been at in each row, as bits, from a box hanging off the left by its size.
+    let mut seen = vec![0u64; states * (rows + 2 * size) as usize];
+    let mut visit = |state: usize, row: i32, col: i32| {
+        let bits = &mut seen[state * (rows + 2 * size) as usize + (row + size) as usize];
+        let bit = 1 << (col + size);
+        let new = *bits & bit == 0;
+        *bits |= bit;
+        new
+    };
+
+    let mut landed: Vec<(i32, [u64; PIECE_SIZE])> = Vec::new();    // Rotation states with the same shape (S, Z, I) land the same.
+    let mut stack = vec![(0, row, col)];
+    visit(0, row, col);
+
+    while let Some((state, row, col)) = stack.pop()
+    {
+        let masks = &shapes.states[state];
+        let top = row + shapes.tops[state] as i32;
+        if top >= area as i32 && board.collides(masks, row + 1, col) {
+            // Its squares from its top row down, wherever that is in its box.
+            let mut squares = [0; PIECE_SIZE];
+            for (square, mask) in squares.iter_mut().zip(masks[shapes.tops[state]..].iter()) { *square = board.shift(*mask, col).unwrap(); }
+            let landing = (top, squares);
+            if !landed.contains(&landing) {
+                landed.push(landing);
+                resting.push((state, row, col));
+            }
+        }
+
+        // Left, right and down, then turns, trying kicks the same as rotate_in_well().
+        let mut moves = [(state, row, col - 1), (state, row, col + 1), (state, row + 1, col), (usize::MAX, 0, 0), (usize::MAX, 0, 0)];
+        if states > 1 {
+            for (clockwise, slot) in [(true, 3), (false, 4)] {
+                let turned = if clockwise { (state + 1) % states } else { (state + states - 1) % states };
+                let kicked = std::iter::once((0, 0)).chain(shapes.kicks.iter().copied())
+                    .map(|(right, down)| (row + down, if clockwise { col + right } else { col - right }))
+                    .find(|(row, col)| !board.collides(&shapes.states[turned], *row, *col));
+                if let Some((row, col)) = kicked { moves[slot] = (turned, row, col); }
+            }
+        }
+
+        for (state, row, col) in moves {
+            if state == usize::MAX || row < -size || row >= rows + size || col < -size || col >= cols + size { continue; }
+            if board.collides(&shapes.states[state], row, col) || !visit(state, row, col) { continue; }
+            stack.push((state, row, col));
+        }
+    }
+
+    resting.sort_by_key(|(_, row, _)| -row);
+    resting
+}
@@ -0,0 +1,116 @@
+/*
+
+Fixed-timestep game time, kept apart from however often the window system gets round to waking us up.
+
+The rules run in steps of exactly 1/N of a second, N being the mode's updates per second (see modes.rs):
+60, or NES Marathon's 60.0988. The speed setting doesn't change it; it changes how many steps a piece takes
+to fall a row. Every timing in the rules is a whole number of those steps, so a game plays the same whether the
+window redraws at 30 Hz or 240 Hz, and the same again in rustris-sim, where there's no clock at all.
+
+Real time goes into an accumulator; each time it holds a whole step, a step is due. Keys are stamped with
+the moment they arrived, and go in before the step that was under way at that moment, so two keys pressed
+during one step still land in the right one, however late the loop gets to it.
+
+Rendering happens whenever it happens. alpha() says how far real time has got through the step under way,
+for drawing things part way between where the last step left them and where the next one will.
+
+*/
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+
+/// The most steps run back to back to catch up. Fall further behind than that (a breakpoint, a window being
+/// dragged) and the rest is forgotten, so the game slows down for a moment rather than fast-forwarding.
+pub const MAX_CATCH_UP: u32 = 8;
+
+
+pub struct Timestep
+{
+    step: Duration,          // How long one step is.
+    step_start: Instant      // When the step under way began. It's due once real time reaches step_start + step.
+}
+
+
+impl Timestep
+{
+    pub fn new(updates_per_second: f64) -> Self
+    {
+        Timestep { step: Duration::from_secs_f64(1.0 / updates_per_second), step_start: Instant::now() }
+    }
+
+    /// Changes the rate, and starts counting again from now. (Whatever was in the accumulator belonged to the old rate.)
+    pub fn set_rate(&mut self, updates_per_second: f64)
+    {
+        *self = Timestep::new(updates_per_second);
+    }
+
+    /// Returns when the next step ends if real time has got past it, and moves on to the step after.
+    /// Call it until it returns None, running one update each time.
+    pub fn next_step(&mut self, now: Instant) -> Option<Instant>
+    {
+        let mut end = self.step_start + self.step;
+        if now < end { return None; }
+
+        if now - end > self.step * MAX_CATCH_UP {
+            self.step_start = now - self.step * MAX_CATCH_UP;
+            end = self.step_start + self.step;
+        }
+
+        self.step_start = end;
+        Some(end)
+    }
+
+    /// How long until the next step is due. (No time at all if it already is.)
+    pub fn due_in(&self, now: Instant) -> Duration
+    {
+        (self.step_start + self.step).saturating_duration_since(now)
+    }
+
+    /// How far through the step under way real time is, from 0 (it just started) to 1 (it's due).
+    pub fn alpha(&self, now: Instant) -> f64
+    {
+        (now.saturating_duration_since(self.step_start).as_secs_f64() / self.step.as_secs_f64()).min(1.0)
+    }
+}
+
+
+/// Events waiting for the step they arrived during. Kept in the order they arrived.
+pub struct TimedQueue<T>
+{
+    events: VecDeque<(Instant, T)>
+}
+
+
+impl<T> TimedQueue<T>
+{
+    pub fn new() -> Self
+    {
+        TimedQueue { events: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, at: Instant, event: T)
+    {
+        self.events.push_back((at, event));
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.events.clear();
+    }
+
+    /// Takes out, oldest first, every event that arrived before the given moment: the end of the step about to run.
+    pub fn take_before(&mut self, end: Instant) -> impl Iterator<Item = T> + '_
+    {
+        std::iter::from_fn(move || match self.events.front() {
+            Some((at, _)) if *at < end => self.events.pop_front().map(|(_, event)| event),
+            _ => None
+        })
+    }
+}
+
+
+impl<T> Default for TimedQueue<T>
+{
+    fn default() -> Self { TimedQueue::new() }
+}
@@ -0,0 +1,301 @@
+/*
+
+Two-player versus: two games side by side, sending garbage to each other.
+
+Both players get the same seed, so the same pieces in the same order, and it's down to who plays them
+better. Clearing lines sends garbage to the other player, as much as the attack table says: nothing for a
+single, more for doubles, triples, tetrises and T-spins, plus bonuses for back-to-backs and combos.
+
+Garbage doesn't arrive straight away. It waits in the other player's incoming meter for the garbage delay,
+and it only rises into their well when they lock a piece without clearing anything. Until then, clearing
+lines of their own cancels it out, line for line, before anything is sent back. The first player to top out
+loses.
+
+Rules come from a text file, one "name value" per line, like AI profiles:
+
+    # rustris versus rules
+    single 0
+    double 1
+    triple 2
+    tetris 4
+    tspin_single 2
+    tspin_double 4
+    tspin_triple 6
+    back_to_back 1
+    combo 0 1 1 2 2 3 3 4 4 4 5
+    garbage_delay_ms 1000
+
+The combo list is the bonus for the 1st, 2nd, 3rd... clear in a row. Past its end, the last value repeats.
+garbage_delay_ms is in milliseconds, rounded down to whole updates when it's used. Older rules files have
+garbage_delay instead, in updates of the 30 a second the game used to run at, and that's still understood.
+
+*/
+
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{game_update, GameState, Lock, Randomizer, UPDATES_PER_SECOND};
+use crate::garbage::{garbage_row, raise};
+use crate::modes::Mode;
+
+
+/// How many lines of garbage each kind of clear sends, and how long garbage takes to arrive.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Rules
+{
+    pub single: u32,
+    pub double: u32,
+    pub triple: u32,
+    pub tetris: u32,
+    pub tspin_single: u32,
+    pub tspin_double: u32,
+    pub tspin_triple: u32,
+    pub back_to_back: u32,    // Extra lines for a back-to-back tetris or T-spin.
+    pub combo: Vec<u32>,      // Extra lines for the 1st, 2nd, 3rd... clear in a row.
+    pub garbage_delay_ms: u32    // How long garbage sits in the meter before it can rise.
+}
+
+
+/// Guideline-style attacks, and a one second garbage delay.
+impl Default for Rules
+{
+    fn default() -> Self
+    {
+        Rules { single: 0, double: 1, triple: 2, tetris: 4,
+                tspin_single: 2, tspin_double: 4, tspin_triple: 6,
+                back_to_back: 1,
+                combo: vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+                garbage_delay_ms: 1000 }
+    }
+}
+
+
+impl Rules
+{
+    /// Lines of garbage a lock sends, before cancelling.
+    pub fn attack(&self, lock: &Lock) -> u32
+    {
+        if lock.cleared == 0 { return 0; }
+
+        let base = match (lock.tspin, lock.cleared) {
+            (true, 1)  => self.tspin_single,
+            (true, 2)  => self.tspin_double,
+            (true, _)  => self.tspin_triple,
+            (false, 1) => self.single,
+            (false, 2) => self.double,
+            (false, 3) => self.triple,
+            (false, _) => self.tetris
+        };
+        let back_to_back = if lock.back_to_back { self.back_to_back } else { 0 };
+        let combo = self.combo.get(lock.combo as usize - 1).or_else(|| self.combo.last()).copied().unwrap_or(0);
+
+        base + back_to_back + combo
+    }
+
+    /// The garbage delay in updates, which is what Incoming counts down.
+    pub fn garbage_delay(&self) -> u32
+    {
+        self.garbage_delay_ms * UPDATES_PER_SECOND / 1000
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()>
+    {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self>
+    {
+        Rules::from_text(&fs::read_to_string(path)?).map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+
+    pub fn to_text(&self) -> String
+    {
+        let combo: Vec<String> = self.combo.iter().map(|lines| lines.to_string()).collect();
+
+        format!("# rustris versus rules\nsingle {}\ndouble {}\ntriple {}\ntetris {}\ntspin_single {}\ntspin_double {}\ntspin_triple {}\n\
+                 back_to_back {}\ncombo {}\ngarbage_delay_ms {}\n",
+                self.single, self.double, self.triple, self.tetris, self.tspin_single, self.tspin_double, self.tspin_triple,
+                self.back_to_back, combo.join(" "), self.garbage_delay_ms)
+    }
+
+    /// Anything the text leaves out keeps its default.
+    pub fn from_text(text: &str) -> Result<Self, String>
+    {
+        let mut rules = Rules::default();
+
+        for (num, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap();
+            let values = words.map(|value| value.parse::<u32>().map_err(|_| format!("line {}: '{}' is not a number", num + 1, value)))
+                              .collect::<Result<Vec<u32>, String>>()?;
+
+            if name == "combo" {
+                if values.is_empty() { return Err(format!("line {}: combo needs at least one value", num + 1)); }
+                rules.combo = values;
+                continue;
+            }
+
+            let value = match values.as_slice() {
+                [value] => *value,
+                _ => return Err(format!("line {}: expected 'name value', got '{}'", num + 1, line))
+            };
+            match name {
+                "single"           => rules.single = value,
+                "double"           => rules.double = value,
+                "triple"           => rules.triple = value,
+                "tetris"           => rules.tetris = value,
+                "tspin_single"     => rules.tspin_single = value,
+                "tspin_double"     => rules.tspin_double = value,
+                "tspin_triple"     => rules.tspin_triple = value,
+                "back_to_back"     => rules.back_to_back = value,
+                "garbage_delay_ms" => rules.garbage_delay_ms = value,
+                "garbage_delay"    => rules.garbage_delay_ms = value.saturating_mul(1000) / 30,    // Updates, at 30 a second.
+                _ => return Err(format!("line {}: unknown rule '{}'", num + 1, name))
+            }
+        }
+
+        Ok(rules)
+    }
+}
+
+
+/// Some garbage on its way to a player.
+#[derive(Copy, Clone, Debug)]
+pub struct Incoming
+{
+    pub lines: u32,
+    pub wait: u32    // Updates before it can rise.
+}
+
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Outcome
+{
+    Winner(usize),    // Player 0 or 1.
+    Draw              // Both topped out on the same update.
+}
+
+
+/// A match between two players.
+pub struct Versus
+{
+    pub players: [GameState; 2],
+    pub incoming: [Vec<Incoming>; 2],    // Garbage on its way to each player, oldest first.
+    pub sent: [u32; 2],                  // Lines of garbage each player has sent, after cancelling.
+    pub rules: Rules,
+    pub rng: StdRng,                     // Picks garbage holes. Separate from the players', so their pieces stay the same.
+    pub outcome: Option<Outcome>
+}
+
+
+impl Versus
+{
+    /// A fresh match with a random seed.
+    pub fn new(rules: Rules) -> Self
+    {
+        Versus::with_seed(thread_rng().gen(), rules)
+    }
+
+    /// A fresh match whose pieces and garbage holes are all determined by the seed.
+    pub fn with_seed(seed: u64, rules: Rules) -> Self
+    {
+        let mut player = GameState::with_seed(seed, Randomizer::Bag);
+        player.set_mode(Mode::Versus);
+
+        Versus {
+            players: [player.clone(), player],
+            incoming: [Vec::new(), Vec::new()],
+            sent: [0; 2],
+            rules,
+            rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+            outcome: None
+        }
+    }
+
+    /// One update of the match: each player's own game_update(), then garbage going back and forth.
+    pub fn update(&mut self)
+    {
+        if self.outcome.is_some() { return; }
+
+        for player in 0..2
+        {
+            game_update(&mut self.players[player]);
+
+            count_down(&mut self.incoming[player]);
+
+            let lock = match self.players[player].last_lock { Some(lock) => lock, None => continue };
+            if lock.cleared > 0 {
+                let attack = cancel(&mut self.incoming[player], self.rules.attack(&lock));
+                if attack > 0 {
+                    self.incoming[1 - player].push(Incoming { lines: attack, wait: self.rules.garbage_delay() });
+                    self.sent[player] += attack;
+                }
+            }
+            else { receive(&mut self.players[player], &mut self.incoming[player], &mut self.rng); }
+        }
+
+        self.outcome = match (self.players[0].game_over.is_some(), self.players[1].game_over.is_some()) {
+            (true, true)  => Some(Outcome::Draw),
+            (true, false) => Some(Outcome::Winner(1)),
+            (false, true) => Some(Outcome::Winner(0)),
+            _ => None
+        };
+    }
+
+    /// Lines of garbage headed for a player: how many are ready to rise, and how many are still on their way.
+    pub fn incoming_lines(&self, player: usize) -> (u32, u32)
+    {
+        incoming_lines(&self.incoming[player])
+    }
+}
+
+
+/// Lines of garbage in a meter: how many are ready to rise, and how many are still on their way.
+pub fn incoming_lines(incoming: &[Incoming]) -> (u32, u32)
+{
+    let ready = incoming.iter().filter(|incoming| incoming.wait == 0).map(|incoming| incoming.lines).sum();
+    let waiting = incoming.iter().filter(|incoming| incoming.wait > 0).map(|incoming| incoming.lines).sum();
+    (ready, waiting)
+}
+
+
+/// One update closer to arriving, for everything in a meter.
+pub(crate) fn count_down(incoming: &mut [Incoming])
+{
+    for incoming in incoming.iter_mut() { incoming.wait = incoming.wait.saturating_sub(1); }
+}
+
+
+/// Uses an attack to knock out garbage headed for the attacker, oldest first. Returns what's left of the attack.
+pub(crate) fn cancel(incoming: &mut Vec<Incoming>, mut attack: u32) -> u32
+{
+    while attack > 0 && !incoming.is_empty()
+    {
+        let cancelled = attack.min(incoming[0].lines);
+        attack -= cancelled;
+        incoming[0].lines -= cancelled;
+        if incoming[0].lines == 0 { incoming.remove(0); }
+    }
+    attack
+}
+
+
+/// Raises all the garbage that's done waiting into a player's well. Each attack's rows share one hole.
+pub(crate) fn receive(game_state: &mut GameState, incoming: &mut Vec<Incoming>, rng: &mut StdRng)
+{
+    while incoming.first().is_some_and(|incoming| incoming.wait == 0)
+    {
+        let attack = incoming.remove(0);
+        let cols = game_state.well.cols();
+        let hole = rng.gen_range(0, cols);
+        raise(game_state, &vec![garbage_row(cols, hole); attack.lines as usize]);
+    }
+}
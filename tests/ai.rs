@@ -0,0 +1,173 @@
+/*
+
+Checks the AI's placement search finds every spot a piece can be dropped to on an empty well, once each, with
+keys that really take it there, and that the AI keeps up at 20G. Then runs the real rustris-train, straight
+through and stopped and resumed from its checkpoint, and checks both runs come out the same.
+
+*/
+
+extern crate tetris;
+
+use tetris::{game_update, landing_row, rotate_in_well, would_collide, Action, GameState, Randomizer, Tetrimino, TetriminoKind, Well, PIECE_SIZE};
+use tetris::ai::{find_placements, AiBot, Weights};
+use tetris::input::InputSource;
+use tetris::modes::Mode;
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+
+/// The squares of the well a piece covers, top to bottom and left to right.
+fn squares(ttmo: &Tetrimino, row: i32, col: i32) -> Vec<(i32, i32)>
+{
+    (0..PIECE_SIZE).flat_map(|ttmo_row| (0..PIECE_SIZE).map(move |ttmo_col| (ttmo_row, ttmo_col)))
+        .filter(|(ttmo_row, ttmo_col)| ttmo.shape[*ttmo_row][*ttmo_col] != 0)
+        .map(|(ttmo_row, ttmo_col)| (row + ttmo_row as i32, col + ttmo_col as i32))
+        .collect()
+}
+
+
+#[test]
+fn every_placement_on_an_empty_well()
+{
+    let well = Well::default();
+    let (spawn_row, spawn_col) = well.spawn();
+
+    // Flat and standing, the I has 7 and 10 spots. The T, J and L have 8 or 9 in each of four ways round.
+    let counts = [(TetriminoKind::I, 17), (TetriminoKind::O, 9), (TetriminoKind::T, 34), (TetriminoKind::J, 34),
+                  (TetriminoKind::L, 34), (TetriminoKind::S, 17), (TetriminoKind::Z, 17)];
+    for (kind, count) in counts.iter()
+    {
+        let spawned = Tetrimino::new(*kind);
+        let placements = find_placements(&spawned, &well, spawn_row, spawn_col);
+
+        // Every way round, in every column it fits in at the spawn row, dropped straight down.
+        let mut everywhere = HashSet::new();
+        for rotation in 0..spawned.rotation_count {
+            let ttmo = spawned.turned_to(&spawned.rotations[rotation]).unwrap();
+            for col in -(PIECE_SIZE as i32)..well.cols() as i32 {
+                if !would_collide(&ttmo, &well, &spawn_row, &col) { everywhere.insert(squares(&ttmo, landing_row(&ttmo, &well, spawn_row, col), col)); }
+            }
+        }
+        assert_eq!(everywhere.len(), *count, "{:?}", kind);
+
+        let found: HashSet<Vec<(i32, i32)>> = placements.iter().map(|placement| squares(&placement.ttmo, placement.row, placement.col)).collect();
+        assert_eq!(found.len(), placements.len(), "{:?} found a spot twice", kind);
+        assert_eq!(found, everywhere, "{:?}", kind);
+
+        // Each placement's keys take the piece from the spawn to over where it lands.
+        for placement in placements.iter()
+        {
+            let (mut ttmo, mut row, mut col) = (spawned, spawn_row, spawn_col);
+            for key in placement.keys.iter() {
+                match key {
+                    Action::MoveLeft | Action::MoveRight => {
+                        let shifted = col + if *key == Action::MoveLeft { -1 } else { 1 };
+                        assert!(!would_collide(&ttmo, &well, &row, &shifted));
+                        col = shifted;
+                    }
+                    _ => {
+                        let turned = rotate_in_well(&ttmo, &well, row, col, *key == Action::RotateCW).unwrap();
+                        ttmo = turned.0;
+                        row = turned.1;
+                        col = turned.2;
+                    }
+                }
+            }
+            assert_eq!(squares(&ttmo, landing_row(&ttmo, &well, row, col), col), squares(&placement.ttmo, placement.row, placement.col));
+        }
+    }
+}
+
+
+/// Lets the AI play a game to 100 pieces, and returns how many lines it cleared and how many holes it left.
+fn ai_game(mut game_state: GameState) -> (u32, usize)
+{
+    let mut bot = AiBot::new(Weights::default(), true);
+    while game_state.game_over.is_none() && game_state.pieces < 100
+    {
+        game_state.inputs = bot.next_keys(&game_state);
+        game_update(&mut game_state);
+    }
+    assert!(game_state.game_over.is_none());
+
+    // A hole is an empty square with something above it.
+    let well = &game_state.well;
+    let holes = (0..well.cols()).map(|col| (0..well.rows()).skip_while(|row| well[*row][col] == 0).filter(|row| well[*row][col] == 0).count()).sum();
+    (game_state.lines, holes)
+}
+
+
+#[test]
+fn plays_at_twenty_g()
+{
+    // At 20G every piece appears already on the stack, and falls off any ledge it's moved over. The AI has to keep
+    // planning from where the piece really is, and then it does nearly as well as in Endless with the same pieces.
+    let calm = ai_game(GameState::with_seed(7, Randomizer::Bag));
+    let mut game_state = GameState::with_seed(7, Randomizer::Bag);
+    game_state.set_mode(Mode::Master);
+    game_state.master.level = 500;
+    let twenty_g = ai_game(game_state);
+    assert!(twenty_g.0 + 6 >= calm.0, "{:?} at 20G, {:?} in Endless", twenty_g, calm);
+    assert!(twenty_g.1 <= calm.1 + 4, "{:?} at 20G, {:?} in Endless", twenty_g, calm);
+}
+
+
+/// Runs rustris-train on a tiny population with short games, writing into `dir`, and returns what it printed.
+fn train(dir: &Path, args: &[&str]) -> process::Output
+{
+    Command::new(env!("CARGO_BIN_EXE_rustris-train"))
+        .args(["--population", "6", "--games", "2", "--max-pieces", "30", "--seed", "7", "--elite", "2"])
+        .args(args)
+        .current_dir(dir)
+        .output().unwrap()
+}
+
+
+#[test]
+fn checkpoints()
+{
+    let dir: PathBuf = env::temp_dir().join(format!("rustris-train-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    // Three generations straight through.
+    let straight = train(&dir, &["--generations", "3", "--checkpoint", "straight.json", "--out", "straight.profile"]);
+    assert!(straight.status.success(), "{}", String::from_utf8_lossy(&straight.stderr));
+
+    // One generation, then resumed for two more. Everything else comes from the checkpoint.
+    assert!(train(&dir, &["--generations", "1", "--checkpoint", "resumed.json", "--out", "resumed.profile"]).status.success());
+    let first = fs::read_to_string(dir.join("resumed.json")).unwrap();
+    let resumed = Command::new(env!("CARGO_BIN_EXE_rustris-train"))
+        .args(["--resume", "resumed.json", "--generations", "3", "--checkpoint", "resumed.json", "--out", "resumed.profile"])
+        .current_dir(&dir)
+        .output().unwrap();
+    assert!(resumed.status.success(), "{}", String::from_utf8_lossy(&resumed.stderr));
+
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("resumed.json"), read("straight.json"));
+    assert_eq!(read("resumed.profile"), read("straight.profile"));
+    assert_eq!(resumed.stdout, straight.stdout);
+
+    // Resuming a finished run plays nothing new, and saves the checkpoint just as it was read.
+    fs::write(dir.join("first.json"), &first).unwrap();
+    let again = Command::new(env!("CARGO_BIN_EXE_rustris-train"))
+        .args(["--resume", "first.json", "--checkpoint", "first.json", "--out", "first.profile"])
+        .current_dir(&dir)
+        .output().unwrap();
+    assert!(again.status.success());
+    assert_eq!(read("first.json"), first);
+
+    // Checkpoints from another version, or that aren't checkpoints, are turned away.
+    fs::write(dir.join("future.json"), first.replacen("\"version\": 1", "\"version\": 2", 1)).unwrap();
+    fs::write(dir.join("junk.json"), "not json").unwrap();
+    for name in ["future.json", "junk.json", "missing.json"].iter() {
+        let refused = train(&dir, &["--resume", name]);
+        assert_eq!(refused.status.code(), Some(1), "{}", name);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,84 @@
+/*
+
+Checks Cheese's garbage: every row has exactly one hole, messiness 0 keeps the holes in one column while 100
+moves them every row, and digging rows out brings more up from below until the mode's count is used.
+
+*/
+
+extern crate tetris;
+
+use tetris::{game_update, Action, GameState, Randomizer, Tetrimino, TetriminoKind, Well, GARBAGE};
+use tetris::garbage::{cheese_left, cheese_rows, garbage_rows};
+use tetris::modes::Mode;
+
+
+/// A Cheese game with the given number of rows and messiness, and a fixed seed.
+fn cheese(seed: u64, rows: u32, messiness: u32) -> GameState
+{
+    let mut game_state = GameState::with_seed(seed, Randomizer::Bag);
+    game_state.set_mode(Mode::Cheese { rows, messiness, rise: 0 });
+    game_state
+}
+
+/// The column of the hole in each garbage row, top to bottom, checking there's exactly one.
+fn holes(well: &Well) -> Vec<usize>
+{
+    well.iter().filter(|row| row.contains(&GARBAGE)).map(|row| {
+        assert_eq!(row.iter().filter(|square| **square == 0).count(), 1);
+        row.iter().position(|square| *square == 0).unwrap()
+    }).collect()
+}
+
+
+#[test]
+fn one_hole_per_row()
+{
+    for seed in 0..20
+    {
+        let game_state = cheese(seed, 18, 100);
+        assert_eq!(holes(&game_state.well).len(), 10);
+        assert_eq!((garbage_rows(&game_state.well), game_state.cheese.pending, cheese_left(&game_state)), (10, 8, 18));
+
+        // All at the bottom, with nothing over them.
+        let rows = game_state.well.rows();
+        assert!(game_state.well.iter().skip(rows - 10).all(|row| row.contains(&GARBAGE)));
+    }
+
+    // Fewer than there'd be room for only fills what's asked for, and short wells only get half full.
+    assert_eq!(garbage_rows(&cheese(1, 4, 100).well), 4);
+    let mut game_state = cheese(1, 18, 100);
+    game_state.set_well(Well::new(10, 8, 2));
+    assert_eq!((cheese_rows(&game_state.well), garbage_rows(&game_state.well)), (4, 4));
+    assert_eq!(holes(&game_state.well).len(), 4);
+}
+
+
+#[test]
+fn messiness()
+{
+    for seed in 0..20
+    {
+        let straight = holes(&cheese(seed, 10, 0).well);
+        assert!(straight.iter().all(|hole| *hole == straight[0]), "seed {}", seed);
+
+        let messy = holes(&cheese(seed, 10, 100).well);
+        assert!(messy.windows(2).all(|pair| pair[0] != pair[1]), "seed {}", seed);
+    }
+}
+
+
+#[test]
+fn digging()
+{
+    // With messiness 0 the holes line up, so an I down them clears four rows, and four more come up in their place.
+    let mut game_state = cheese(7, 18, 0);
+    let hole = holes(&game_state.well)[0];
+    game_state.curr_ttmo = Tetrimino::new(TetriminoKind::I);
+    game_state.ttmo_col = hole as i32 - 2;    // Standing up in the third column of its box.
+    game_state.inputs.push(Action::HardDrop);
+    while game_state.pieces == 0 { game_update(&mut game_state); }
+
+    assert_eq!(game_state.lines, 4);
+    assert_eq!((garbage_rows(&game_state.well), game_state.cheese.pending, cheese_left(&game_state)), (10, 4, 14));
+    assert!(holes(&game_state.well).iter().all(|col| *col == hole));
+}
@@ -0,0 +1,129 @@
+/*
+
+Checks the board editor: what painting does, that a board plays as the puzzle it makes, to a fumen and back with
+its hold, and that saving puts new boards on the end of edited.puzzles and changed ones back where they were.
+
+*/
+
+extern crate tetris;
+
+use tetris::{GameState, TetriminoKind, Well, GARBAGE};
+use tetris::editor::{Editor, EDITED_PACK};
+use tetris::modes::Mode;
+use tetris::puzzles::{load_puzzles, pieces_to_come, start_puzzle, Goal};
+
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::Arc;
+
+
+#[test]
+fn editor()
+{
+    let mut editor = Editor::new(Well::default());
+
+    // Nothing paints up in the hidden rows or outside the well, and garbage isn't a piece for the list.
+    editor.paint(2, 0, false);
+    editor.paint(23, 10, false);
+    editor.add_piece();
+    assert_eq!(editor.well, Well::default());
+    assert!(editor.pieces.is_empty());
+    assert!(editor.to_playable().is_err());
+
+    for col in 0..8 { editor.paint(23, col, false); }
+    editor.brush = TetriminoKind::O.cell();
+    editor.paint(22, 0, false);
+    editor.paint(22, 0, true);
+    editor.add_piece();
+    editor.change_goal(false);
+    assert_eq!(editor.goal(), Goal::PerfectClear(1));
+
+    let playable = editor.to_playable().unwrap();
+    assert_eq!((playable.name.as_str(), playable.goal, playable.pieces.clone()), ("board", Goal::PerfectClear(1), vec![TetriminoKind::O]));
+    assert_eq!(playable.well, editor.well);
+    let mut game_state = GameState::with_mode(Mode::Puzzle);
+    start_puzzle(&mut game_state, Arc::new(playable));
+    assert_eq!((&game_state.well, game_state.curr_ttmo.kind, pieces_to_come(&game_state)), (&editor.well, TetriminoKind::O, 0));
+
+    // A full row can't be played.
+    editor.paint(23, 8, false);
+    editor.paint(23, 9, false);
+    assert!(editor.to_playable().is_err());
+    editor.paint(23, 9, true);
+
+    // To a fumen and back.
+    let shared = Editor::from_fumen(&editor.to_fumen().unwrap()).unwrap();
+    assert_eq!((&shared.well, &shared.pieces, shared.hold), (&editor.well, &editor.pieces, None));
+
+    // A piece in the hold goes in the quiz's brackets, and into the puzzle. Garbage empties the hold again.
+    editor.brush = TetriminoKind::I.cell();
+    editor.set_hold();
+    let shared = Editor::from_fumen(&editor.to_fumen().unwrap()).unwrap();
+    assert_eq!((&shared.pieces, shared.hold), (&editor.pieces, Some(TetriminoKind::I)));
+    assert_eq!(editor.to_playable().unwrap().hold, Some(TetriminoKind::I));
+    assert_eq!(Editor::from_puzzle(&editor.to_playable().unwrap()).hold, Some(TetriminoKind::I));
+    editor.brush = GARBAGE;
+    editor.set_hold();
+    assert_eq!(editor.hold, None);
+
+    // Goals the editor doesn't have become the nearest it does.
+    let built_in = load_puzzles(None);
+    let mut changed = (*built_in[0]).clone();
+    changed.goal = Goal::Lines(6);
+    assert_eq!(Editor::from_puzzle(&changed).goal(), Goal::Lines(4));
+    changed.goal = Goal::PerfectClear(1);
+    changed.pieces = vec![TetriminoKind::I; 3];
+    assert_eq!(Editor::from_puzzle(&changed).goal(), Goal::PerfectClear(3));
+}
+
+
+#[test]
+fn editor_saves()
+{
+    let dir = env::temp_dir().join(format!("rustris-editor-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let known = load_puzzles(None);
+    let names = |dir| load_puzzles(Some(dir)).iter().skip(known.len()).map(|puzzle| puzzle.name.clone()).collect::<Vec<String>>();
+
+    let mut editor = Editor::new(Well::default());
+    editor.brush = TetriminoKind::I.cell();
+    editor.add_piece();
+    assert_eq!(editor.save(&dir, &known).unwrap(), "board-1");
+    assert_eq!(Editor::new(Well::default()).save(&dir, &known), Err("it needs some pieces".to_string()));
+
+    // A new board goes on the end. Saved again, it replaces itself.
+    let mut second = Editor::new(Well::default());
+    second.brush = TetriminoKind::T.cell();
+    second.add_piece();
+    assert_eq!(second.save(&dir, &known).unwrap(), "board-2");
+    second.add_piece();
+    assert_eq!(second.save(&dir, &known).unwrap(), "board-2");
+    assert_eq!(names(&dir), vec!["board-1", "board-2"]);
+
+    // A built-in puzzle, changed, is saved as a new board, with its hint. The built-in one's still there as it was.
+    let mut tetris = Editor::from_puzzle(&known[0]);
+    tetris.brush = TetriminoKind::I.cell();
+    tetris.add_piece();
+    assert_eq!(tetris.save(&dir, &known).unwrap(), "board-3");
+    let loaded = load_puzzles(Some(&dir));
+    assert_eq!(loaded[0].pieces, vec![TetriminoKind::I]);
+    let saved = loaded.iter().find(|puzzle| puzzle.name == "board-3").unwrap();
+    assert_eq!((saved.title.as_str(), saved.hint.as_str(), saved.goal), ("BOARD-3", known[0].hint.as_str(), Goal::Tetris));
+    assert_eq!((saved.pieces.len(), &saved.well), (2, &known[0].well));
+
+    // One opened from the pack goes back where it was.
+    let mut first = Editor::from_puzzle(loaded.iter().find(|puzzle| puzzle.name == "board-1").unwrap());
+    first.change_goal(true);
+    assert_eq!(first.save(&dir, &known).unwrap(), "board-1");
+    let loaded = load_puzzles(Some(&dir));
+    assert_eq!(names(&dir), vec!["board-1", "board-2", "board-3"]);
+    assert_eq!(loaded.iter().find(|puzzle| puzzle.name == "board-1").unwrap().goal, Goal::Lines(2));
+
+    // A pack that can't be read is left alone.
+    fs::write(dir.join(EDITED_PACK), "rustris-puzzles 1\npuzzle\n").unwrap();
+    assert!(first.save(&dir, &known).is_err());
+    assert_eq!(fs::read_to_string(dir.join(EDITED_PACK)).unwrap(), "rustris-puzzles 1\npuzzle\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
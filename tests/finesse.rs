@@ -0,0 +1,123 @@
+/*
+
+Checks finesse against the table of fewest inputs for the I, O and T on an empty well, that a held key counts
+as one press however far DAS carries it (in replays too), and that at 20G pieces are judged by what they can
+reach along the ground.
+
+*/
+
+extern crate tetris;
+
+use tetris::{game_update, landing_row, rotate_tetrimino, would_collide, Action, GameState, Randomizer, Tetrimino, TetriminoKind, Well,
+             GARBAGE, PIECE_SIZE};
+use tetris::finesse::optimal_presses;
+use tetris::input::{AutoRepeat, InputSource};
+use tetris::master;
+use tetris::modes::Mode;
+use tetris::replay::{Replay, ReplayInput};
+
+
+/// Fewest inputs to drop a piece, turned clockwise `turns` times, with its leftmost square in each column of the well.
+fn table(kind: TetriminoKind, turns: usize, sonic: bool, well: &Well) -> Vec<Option<u32>>
+{
+    let spawned = Tetrimino::new(kind);
+    let (row, spawn_col) = well.spawn();
+    let mut ttmo = spawned;
+    for _ in 0..turns { rotate_tetrimino(&mut ttmo, true); }
+    let left = (0..PIECE_SIZE).find(|col| ttmo.shape.iter().any(|squares| squares[*col] != 0)).unwrap() as i32;
+
+    (0..well.cols() as i32).map(|column| column - left)
+        .filter(|col| !would_collide(&ttmo, well, &row, col))
+        .map(|col| optimal_presses((&spawned, row, spawn_col), well, (&ttmo, landing_row(&ttmo, well, row, col), col), sonic))
+        .collect()
+}
+
+fn all(presses: &[u32]) -> Vec<Option<u32>>
+{
+    presses.iter().map(|presses| Some(*presses)).collect()
+}
+
+
+#[test]
+fn empty_well()
+{
+    let well = Well::default();
+
+    // The I spawns standing up in the sixth column. Standing in the third, it's three taps or DAS and two back.
+    assert_eq!(table(TetriminoKind::I, 0, false, &well), all(&[1, 2, 3, 2, 1, 0, 1, 2, 2, 1]));
+    assert_eq!(table(TetriminoKind::I, 1, false, &well), all(&[2, 3, 2, 1, 2, 3, 2]));
+    assert_eq!(table(TetriminoKind::I, 2, false, &well), all(&[1, 2, 3, 2, 1, 0, 1, 2, 2, 1]));
+    assert_eq!(table(TetriminoKind::I, 3, false, &well), all(&[2, 3, 2, 1, 2, 3, 2]));
+
+    assert_eq!(table(TetriminoKind::O, 0, false, &well), all(&[1, 2, 2, 1, 0, 1, 2, 2, 1]));
+
+    // Flat side down, pointing right, pointing down (two turns, with no 180) and pointing left.
+    assert_eq!(table(TetriminoKind::T, 0, false, &well), all(&[1, 2, 1, 0, 1, 2, 2, 1]));
+    assert_eq!(table(TetriminoKind::T, 1, false, &well), all(&[2, 2, 3, 2, 1, 2, 3, 3, 2]));
+    assert_eq!(table(TetriminoKind::T, 2, false, &well), all(&[3, 4, 3, 2, 3, 4, 4, 3]));
+    assert_eq!(table(TetriminoKind::T, 3, false, &well), all(&[2, 3, 2, 1, 2, 3, 3, 2, 2]));
+}
+
+
+#[test]
+fn twenty_g()
+{
+    // A tower three high in the third column. Over it, the O goes to the wall in one go. Along the ground, it can't get there.
+    let mut well = Well::default();
+    for row in 21..24 { well.set(row, 2, GARBAGE); }
+    assert_eq!(table(TetriminoKind::O, 0, false, &well), all(&[1, 2, 2, 1, 0, 1, 2, 2, 1]));
+    assert_eq!(table(TetriminoKind::O, 0, true, &well), vec![None, None, None, Some(1), Some(0), Some(1), Some(2), Some(2), Some(1)]);
+
+    let mut game_state = GameState::with_mode(Mode::Master);
+    assert!(!master::twenty_g(&game_state));
+    game_state.master.level = 500;
+    assert!(master::twenty_g(&game_state));
+}
+
+
+/// Presses keys for the first piece through AutoRepeat, the way the window does, each held for so many updates, and
+/// drops it. Returns its faults, after checking its replay comes to the same.
+fn place(keys: &[(Action, u32)]) -> u32
+{
+    let mut game_state = GameState::with_seed(3, Randomizer::Bag);
+    let mut replay = Replay::new(&game_state);
+    let mut repeat = AutoRepeat::new(12, 4);
+    let mut step = |game_state: &mut GameState, repeat: &mut AutoRepeat| {
+        repeat.update(game_state);
+        replay.record(game_state.frames, &game_state.inputs, game_state.repeats);
+        game_update(game_state);
+    };
+
+    for (action, held) in keys.iter() {
+        repeat.press(&mut game_state.inputs, *action);
+        for _ in 0..*held { step(&mut game_state, &mut repeat); }
+        repeat.release(*action);
+    }
+    repeat.press(&mut game_state.inputs, Action::HardDrop);
+    while game_state.pieces == 0 { step(&mut game_state, &mut repeat); }
+    assert_eq!(game_state.finesse.judged_pieces, 1);
+
+    let replay = Replay::from_text(&replay.to_text()).unwrap();
+    let mut played = replay.new_game();
+    let mut input = ReplayInput::new(replay);
+    while !input.finished(&played) {
+        played.inputs = input.next_keys(&played);
+        played.repeats = input.repeats();
+        game_update(&mut played);
+    }
+    assert_eq!(played.finesse.last_faults, game_state.finesse.last_faults);
+
+    game_state.finesse.last_faults
+}
+
+
+#[test]
+fn key_downs()
+{
+    // Held to the wall, it's one press, however many times it repeats. Tapped there, every tap counts.
+    assert_eq!(place(&[(Action::MoveLeft, 40)]), 0);
+    assert_eq!(place(&[(Action::MoveLeft, 1); 5]), 4);
+
+    // Held, then tapped back one: two presses, which is as few as it takes.
+    assert_eq!(place(&[(Action::MoveLeft, 40), (Action::MoveRight, 1)]), 0);
+}
@@ -0,0 +1,185 @@
+/*
+
+Reads and writes fumens that fumen's own editor makes, and checks they come out the same both ways: the
+board, the pieces, which pages lock, comments, and the runs of pages that don't change the field.
+
+*/
+
+extern crate tetris;
+
+use tetris::{rotate_tetrimino, GameState, Randomizer, Tetrimino, TetriminoKind, Well, GARBAGE, PIECE_SIZE};
+use tetris::fumen::{decode, encode, encode_game, Page, Placement};
+use tetris::pieces::PieceSet;
+
+use std::sync::Arc;
+
+
+fn empty_page() -> Page
+{
+    Page { well: Well::default(), piece: None, lock: true, comment: String::new() }
+}
+
+/// A T flat on the floor, pointing up, in the middle of the well.
+fn t_on_floor() -> Placement
+{
+    Placement { kind: TetriminoKind::T, squares: [(23, 4), (23, 3), (23, 5), (22, 4)] }
+}
+
+fn sorted(mut squares: [(i32, i32); 4]) -> [(i32, i32); 4]
+{
+    squares.sort_unstable();
+    squares
+}
+
+
+#[test]
+fn empty_board()
+{
+    let pages = decode("v115@vhAAgH").unwrap();
+    assert_eq!(pages, vec![empty_page()]);
+    assert_eq!(encode(&pages).unwrap(), "v115@vhAAgH");
+}
+
+
+#[test]
+fn garbage()
+{
+    // Four rows of garbage, six squares wide, on the left.
+    let text = "v115@9gF8DeF8DeF8DeF8NeAgH";
+    let pages = decode(text).unwrap();
+    assert_eq!(pages.len(), 1);
+
+    let well = &pages[0].well;
+    for row in 0..well.rows() {
+        for col in 0..well.cols() {
+            assert_eq!(well[row][col], if row >= 20 && col < 6 { GARBAGE } else { 0 }, "row {}, column {}", row, col);
+        }
+    }
+    assert_eq!(encode(&pages).unwrap(), text);
+}
+
+
+#[test]
+fn pieces_and_pages()
+{
+    let pages = decode("v115@vhAVQJ").unwrap();
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].piece.map(|piece| piece.kind), Some(TetriminoKind::T));
+    assert_eq!(sorted(pages[0].piece.unwrap().squares), sorted(t_on_floor().squares));
+
+    // The T locks on the way to the second page, which has no field of its own.
+    let text = "v115@vhBVQJAgH";
+    let pages = decode(text).unwrap();
+    assert_eq!(pages.len(), 2);
+    assert!(pages[0].lock);
+    assert_eq!(pages[1].piece, None);
+    for (row, col) in t_on_floor().squares.iter() {
+        assert_eq!(pages[1].well[*row as usize][*col as usize], TetriminoKind::T.cell());
+    }
+    assert_eq!(encode(&pages).unwrap(), text);
+}
+
+
+#[test]
+fn line_clears()
+{
+    // An I finishes the bottom row, which clears before the second page.
+    let text = "v115@bhF8NexRJvhAAgH";
+    let pages = decode(text).unwrap();
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0].piece.map(|piece| sorted(piece.squares)), Some([(23, 6), (23, 7), (23, 8), (23, 9)]));
+    assert_eq!(pages[1].well, Well::default());
+    assert_eq!(encode(&pages).unwrap(), text);
+}
+
+
+#[test]
+fn comments()
+{
+    let text = "v115@vhAAgWCAouBAA";
+    let pages = decode(text).unwrap();
+    assert_eq!(pages[0].comment, "hi");
+    assert_eq!(encode(&pages).unwrap(), text);
+
+    // Pages keep the comment before theirs, and anything JavaScript's escape() can do comes back.
+    let comment = "#Q=[](T)SZ 100% ✓".to_string();
+    let pages = vec![Page { comment: comment.clone(), ..empty_page() }; 3];
+    let decoded = decode(&encode(&pages).unwrap()).unwrap();
+    assert_eq!(decoded, pages);
+}
+
+
+#[test]
+fn every_piece_every_way()
+{
+    // Each piece, in each of the four ways it can face, somewhere in the middle of the well.
+    for kind in TetriminoKind::STANDARD.iter() {
+        let mut shape = Tetrimino::new(*kind);
+        for _ in 0..4 {
+            let mut squares = Vec::new();
+            for row in 0..PIECE_SIZE {
+                for col in 0..PIECE_SIZE {
+                    if shape.shape[row][col] != 0 { squares.push((15 + row as i32, 3 + col as i32)); }
+                }
+            }
+            let piece = Placement { kind: *kind, squares: [squares[0], squares[1], squares[2], squares[3]] };
+            let pages = vec![Page { piece: Some(piece), lock: false, ..empty_page() }];
+
+            let decoded = decode(&encode(&pages).unwrap()).unwrap();
+            assert_eq!(decoded[0].piece.map(|piece| (piece.kind, sorted(piece.squares))), Some((*kind, sorted(piece.squares))));
+            rotate_tetrimino(&mut shape, true);
+        }
+    }
+}
+
+
+#[test]
+fn long_fumens()
+{
+    // Enough pages to need the "?"s, and more pages without changes than one count can hold.
+    let mut pages = vec![Page { piece: Some(t_on_floor()), lock: false, ..empty_page() }; 200];
+    pages[150].well.set(23, 0, GARBAGE);
+    let text = encode(&pages).unwrap();
+    assert!(text.contains('?'));
+    assert_eq!(text.find('?'), Some("v115@".len() + 42));
+
+    assert_eq!(decode(&text).unwrap(), pages);
+    assert_eq!(decode(&text.replace('?', "")).unwrap(), pages);
+}
+
+
+#[test]
+fn links_and_errors()
+{
+    assert_eq!(decode("https://harddrop.com/fumen/?v115@vhAAgH").unwrap(), vec![empty_page()]);
+
+    assert!(decode("hello").is_err());
+    assert!(decode("v115@").is_err());
+    assert!(decode("v115@vhAA").is_err());
+    assert!(decode("v115@vh!AgH").is_err());
+
+    let wide = Page { well: Well::new(12, 20, 4), ..empty_page() };
+    assert!(encode(&[wide]).is_err());
+
+    let mut tall = empty_page();
+    tall.well.set(0, 0, GARBAGE);
+    assert!(encode(&[tall]).is_err());
+}
+
+
+#[test]
+fn games()
+{
+    // The first piece, as it comes in at the top.
+    let game_state = GameState::with_seed(1, Randomizer::Bag);
+    let pages = decode(&encode_game(&game_state).unwrap()).unwrap();
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].well, game_state.well);
+    assert_eq!(pages[0].piece.map(|piece| piece.kind), Some(game_state.curr_ttmo.kind));
+
+    // Triominoes don't go in a fumen, so there's just the well.
+    let mut game_state = GameState::with_seed(1, Randomizer::Bag);
+    game_state.set_piece_set(Arc::new(PieceSet::load("triominoes").unwrap()));
+    let pages = decode(&encode_game(&game_state).unwrap()).unwrap();
+    assert_eq!((&pages[0].well, pages[0].piece), (&game_state.well, None));
+}
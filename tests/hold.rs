@@ -0,0 +1,83 @@
+/*
+
+Checks hold: it swaps the falling piece for the held one (or the next, the first time), once per piece, starts
+the new piece over from the top, isn't there at all in the modes that never had it, and is part of what netplay
+checks the two sides agree on.
+
+*/
+
+extern crate tetris;
+
+use tetris::{game_update, Action, GameState, Randomizer};
+use tetris::modes::Mode;
+use tetris::net::state_hash;
+
+
+fn press(game_state: &mut GameState, action: Action)
+{
+    game_state.inputs.push(action);
+    game_update(game_state);
+}
+
+
+#[test]
+fn swaps_once_per_piece()
+{
+    let mut game_state = GameState::with_seed(7, Randomizer::Bag);
+    let (first, second, third) = (game_state.curr_ttmo, game_state.next_ttmo, game_state.upcoming(1)[0]);
+
+    // Nothing held yet: the next piece comes on, and the one after it is next.
+    press(&mut game_state, Action::MoveLeft);
+    press(&mut game_state, Action::Hold);
+    assert_eq!(game_state.hold_ttmo.map(|held| held.kind), Some(first.kind));
+    assert_eq!(game_state.curr_ttmo.kind, second.kind);
+    assert_eq!(game_state.next_ttmo.kind, third.kind);
+    assert_eq!((game_state.ttmo_row, game_state.ttmo_col), (game_state.spawn_row, game_state.spawn_col));
+    assert!(game_state.hold_used);
+
+    // Not again until it locks.
+    press(&mut game_state, Action::Hold);
+    assert_eq!(game_state.curr_ttmo.kind, second.kind);
+
+    // After it locks, hold brings the first piece back, turned the way it spawns.
+    press(&mut game_state, Action::HardDrop);
+    while game_state.pieces == 0 { game_update(&mut game_state); }
+    assert!(!game_state.hold_used);
+    press(&mut game_state, Action::RotateCW);
+    press(&mut game_state, Action::Hold);
+    assert_eq!(game_state.curr_ttmo.kind, first.kind);
+    assert_eq!(game_state.curr_ttmo.rotation, 0);
+    assert_eq!(game_state.hold_ttmo.map(|held| (held.kind, held.rotation)), Some((third.kind, 0)));
+    assert_eq!(game_state.piece_presses, 0);
+}
+
+
+#[test]
+fn only_in_modes_with_hold()
+{
+    for mode in [Mode::Marathon { start_level: 0 }, Mode::Master, Mode::Puzzle].iter() {
+        assert!(!mode.has_hold());
+        let mut game_state = GameState::with_mode(*mode);
+        let falling = game_state.curr_ttmo.kind;
+        press(&mut game_state, Action::Hold);
+        assert!(game_state.hold_ttmo.is_none());
+        assert_eq!(game_state.curr_ttmo.kind, falling);
+    }
+    assert!(Mode::Sprint { lines: 40 }.has_hold());
+    assert!(Mode::Versus.has_hold());
+}
+
+
+#[test]
+fn netplay_sees_the_hold()
+{
+    // Two games the same but for what's held, or whether hold's been used, don't hash the same.
+    let game_state = GameState::with_seed(7, Randomizer::Bag);
+    let mut held = game_state.clone();
+    held.hold_ttmo = Some(game_state.next_ttmo);
+    let mut used = game_state.clone();
+    used.hold_used = true;
+
+    let hashes = [state_hash(&game_state), state_hash(&held), state_hash(&used)];
+    assert!(hashes[0] != hashes[1] && hashes[0] != hashes[2] && hashes[1] != hashes[2]);
+}
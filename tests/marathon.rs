@@ -0,0 +1,86 @@
+/*
+
+Checks NES Marathon against the NES: gravity at each level, when the first level up comes for each starting
+level, and what line clears are worth.
+
+*/
+
+extern crate tetris;
+
+use tetris::{game_update, Action, GameState, Tetrimino, TetriminoKind, GARBAGE};
+use tetris::modes::{format_time_at, nes_frames_per_row, nes_level, Mode, NES_UPDATES_PER_SECOND};
+
+
+#[test]
+fn gravity()
+{
+    let levels = [(0, 48), (8, 8), (9, 6), (10, 5), (12, 5), (13, 4), (15, 4), (16, 3), (18, 3), (19, 2), (28, 2), (29, 1), (50, 1)];
+    for (level, frames) in levels.iter() {
+        assert_eq!(nes_frames_per_row(*level), *frames, "level {}", level);
+    }
+
+    let game_state = GameState::with_mode(Mode::Marathon { start_level: 13 });
+    assert_eq!(game_state.frames_per_row(), 4);
+}
+
+
+#[test]
+fn levels()
+{
+    // Starting at 0 to 9, the first level up is after (level + 1) * 10 lines. From 10 to 15 it's after 100, and
+    // from 16, ten more for each level on.
+    let first_level_ups = [(0, 10), (8, 90), (9, 100), (10, 100), (13, 100), (15, 100), (16, 110), (19, 140), (29, 240)];
+    for (start_level, lines) in first_level_ups.iter() {
+        assert_eq!(nes_level(*start_level, lines - 1), *start_level, "start {}", start_level);
+        assert_eq!(nes_level(*start_level, *lines), start_level + 1, "start {}", start_level);
+        assert_eq!(nes_level(*start_level, lines + 9), start_level + 1, "start {}", start_level);
+        assert_eq!(nes_level(*start_level, lines + 10), start_level + 2, "start {}", start_level);
+    }
+}
+
+
+/// Clears `rows` lines at once with an I standing in the left column, at the given level and lines so far.
+fn clear(start_level: u32, lines: u32, rows: usize) -> GameState
+{
+    let mut game_state = GameState::with_mode(Mode::Marathon { start_level });
+    game_state.lines = lines;
+    let bottom = game_state.well.rows();
+    for row in bottom - rows..bottom {
+        for col in 1..game_state.well.cols() { game_state.well.set(row, col, GARBAGE); }
+    }
+
+    game_state.curr_ttmo = Tetrimino::new(TetriminoKind::I);
+    game_state.ttmo_col = -2;    // Standing up in the third column of its box.
+    game_state.inputs.push(Action::HardDrop);
+    while game_state.pieces == 0 { game_update(&mut game_state); }
+    assert_eq!(game_state.lines, lines + rows as u32);
+    game_state
+}
+
+
+#[test]
+fn line_scores()
+{
+    // 40, 100, 300 and 1200 times one more than the level the clear was made at.
+    assert_eq!(clear(0, 0, 1).score, 40);
+    assert_eq!(clear(8, 0, 2).score, 100 * 9);
+    assert_eq!(clear(9, 0, 3).score, 300 * 10);
+    assert_eq!(clear(9, 100, 4).score, 1200 * 11);
+    assert_eq!(clear(13, 0, 4).score, 1200 * 14);
+    assert_eq!(clear(16, 0, 1).score, 40 * 17);
+    assert_eq!(clear(19, 0, 4).score, 1200 * 20);
+    assert_eq!(clear(19, 190, 4).score, 1200 * 26);    // Level 25 by then.
+
+    // The level goes up with the lines that take it there, after the clear is scored.
+    let game_state = clear(8, 88, 4);
+    assert_eq!((game_state.score, game_state.level()), (1200 * 9, 9));
+}
+
+
+#[test]
+fn clock()
+{
+    // The NES runs a hair faster than 60 a second, so an hour's worth of 60ths is six seconds short of an hour.
+    assert_eq!(format_time_at(60 * 60 * 60, NES_UPDATES_PER_SECOND), "59:54.081");
+    assert_eq!(format_time_at(601, NES_UPDATES_PER_SECOND), "0:10.000");
+}
@@ -0,0 +1,82 @@
+/*
+
+Checks Master's grades: the score each one takes, and GM, which also needs the pace kept up at levels 300
+and 500 on the way to 999.
+
+*/
+
+extern crate tetris;
+
+use tetris::{game_update, Action, GameState, Tetrimino, TetriminoKind, GARBAGE, UPDATES_PER_SECOND};
+use tetris::master::{grade, MASTER_MAX_LEVEL};
+use tetris::modes::Mode;
+
+
+#[test]
+fn grades_by_score()
+{
+    let grades = [(0, "9"), (400, "8"), (800, "7"), (1400, "6"), (2000, "5"), (3500, "4"), (5500, "3"), (8000, "2"), (12000, "1"),
+                  (16000, "S1"), (22000, "S2"), (30000, "S3"), (40000, "S4"), (52000, "S5"), (66000, "S6"), (82000, "S7"),
+                  (100000, "S8"), (120000, "S9")];
+
+    let mut game_state = GameState::with_mode(Mode::Master);
+    for (index, (score, name)) in grades.iter().enumerate()
+    {
+        game_state.score = *score;
+        assert_eq!(grade(&game_state), *name);
+        if index > 0 {
+            game_state.score = score - 1;
+            assert_eq!(grade(&game_state), grades[index - 1].1);
+        }
+    }
+
+    // S9 is as far as score goes.
+    game_state.score = 999_999;
+    assert_eq!(grade(&game_state), "S9");
+}
+
+
+#[test]
+fn grand_master()
+{
+    let mut game_state = GameState::with_mode(Mode::Master);
+    game_state.score = 126000;
+    game_state.master.level = MASTER_MAX_LEVEL;
+    assert_eq!(grade(&game_state), "GM");
+
+    game_state.master.missed_gm = true;
+    assert_eq!(grade(&game_state), "S9");
+}
+
+
+/// Clears a line at level 299, with the given score and time so far, taking it past the level 300 checkpoint.
+/// Returns whether that kept GM in the running.
+fn past_300(score: u32, frames: u32) -> bool
+{
+    let mut game_state = GameState::with_mode(Mode::Master);
+    game_state.master.level = 299;
+    game_state.score = score;
+    game_state.frames = frames;
+    let bottom = game_state.well.rows() - 1;
+    for col in 1..game_state.well.cols() { game_state.well.set(bottom, col, GARBAGE); }
+
+    game_state.curr_ttmo = Tetrimino::new(TetriminoKind::I);
+    game_state.ttmo_col = -2;    // Standing up in the third column of its box.
+    game_state.inputs.push(Action::HardDrop);
+    while game_state.pieces == 0 { game_update(&mut game_state); }
+
+    assert!(game_state.master.level >= 300);
+    !game_state.master.missed_gm
+}
+
+
+#[test]
+fn checkpoints()
+{
+    // At least 12000 points within 4:15.
+    let limit = (4 * 60 + 15) * UPDATES_PER_SECOND;
+    assert!(past_300(12000, 0));
+    assert!(!past_300(10000, 0));
+    assert!(past_300(12000, limit - 2 * UPDATES_PER_SECOND));
+    assert!(!past_300(12000, limit));
+}
@@ -0,0 +1,80 @@
+/*
+
+Checks files from when most modes ran at 30 updates a second come up to today's 60: replays, settings,
+profiles and versus rules all keep the same lengths of time, and NES Marathon and Master stay as they were.
+
+*/
+
+extern crate tetris;
+
+use tetris::{Action, GRAVITY};
+use tetris::profiles::Profile;
+use tetris::replay::Replay;
+use tetris::settings::Settings;
+use tetris::versus::Rules;
+
+
+#[test]
+fn replays()
+{
+    let sprint = Replay::from_text("rustris-replay 2\nseed 5\nrandomizer bag\nmode sprint-40\nframes 100\n0 left\n12 left,hard\n").unwrap();
+    assert_eq!(sprint.frames, 200);
+    assert_eq!(sprint.gravity, GRAVITY);
+    assert_eq!(sprint.inputs, vec![(1, vec![Action::MoveLeft], 0), (25, vec![Action::MoveLeft, Action::HardDrop], 0)]);
+
+    let slow = Replay::from_text("rustris-replay 1\nseed 5\nrandomizer bag\nmode endless\nframes 10\ngravity 30\n3 100001\n").unwrap();
+    assert_eq!((slow.frames, slow.gravity), (20, 60));
+    assert_eq!(slow.inputs, vec![(7, vec![Action::MoveLeft, Action::HardDrop], 0)]);
+
+    for mode in ["marathon-5", "master"].iter() {
+        let text = format!("rustris-replay 2\nseed 5\nrandomizer nes\nmode {}\nframes 100\n12 hard\n", mode);
+        let replay = Replay::from_text(&text).unwrap();
+        assert_eq!((replay.frames, replay.inputs[0].0), (100, 12), "{}", mode);
+    }
+
+    // Numbers too big to double are turned away, rather than wrapping round or worse.
+    let huge = [("frames 3000000000\n", "frame count"), ("frames 100\ngravity 3000000000\n", "gravity"),
+                ("frames 100\n3000000000 left\n", "update number"), ("frames 100\n2147483648 left\n", "update number")];
+    for (lines, what) in huge.iter() {
+        let text = format!("rustris-replay 2\nseed 5\nrandomizer bag\nmode sprint-40\n{}", lines);
+        assert!(Replay::from_text(&text).err().unwrap().contains(what), "{}", lines);
+    }
+
+    // Today's replays are read as written, speed and all.
+    let current = Replay::from_text("rustris-replay 3\nseed 5\nrandomizer bag\nmode sprint-40\nframes 100\nspeed 50\n12 hard\n").unwrap();
+    assert_eq!((current.frames, current.speed, current.inputs[0].0), (100, 50, 12));
+    assert_eq!(Replay::from_text(&current.to_text()).unwrap().speed, 50);
+}
+
+
+#[test]
+fn settings()
+{
+    let old = Settings::from_toml("[game]\ngravity = 10\n[handling]\ndas = 8\narr = 1\n").unwrap();
+    assert_eq!((old.game.gravity, old.handling.das, old.handling.arr), (20, 16, 2));
+
+    let current = Settings::from_toml("version = 2\n[game]\ngravity = 10\n").unwrap();
+    assert_eq!(current.game.gravity, 10);
+    assert_eq!(Settings::from_toml(&Settings::default().to_toml()).unwrap(), Settings::default());
+    assert!(Settings::from_toml("version = 3\n").is_err());
+}
+
+
+#[test]
+fn profiles()
+{
+    let old = Profile::from_text("rustris-profile 1\nname BEN\ndas 6\narr 2\n").unwrap();
+    assert_eq!((old.das, old.arr), (12, 4));
+
+    let current = Profile::from_text("rustris-profile 2\nname BEN\ndas 6\narr 2\n").unwrap();
+    assert_eq!((current.das, current.arr), (6, 2));
+}
+
+
+#[test]
+fn rules()
+{
+    assert_eq!(Rules::default().garbage_delay(), 60);
+    assert_eq!(Rules::from_text("garbage_delay 15\n").unwrap().garbage_delay_ms, 500);
+    assert_eq!(Rules::from_text("garbage_delay_ms 250\n").unwrap().garbage_delay(), 15);
+}
@@ -0,0 +1,217 @@
+/*
+
+Checks piece sets: the built-in ones have the pieces they should, the 7-bag deals every piece of a set once a
+bag, set files are read as the format says, and shapes, kicks and the rest that won't do are turned away.
+Kicks move a turn that doesn't fit where it is, mirrored for the other way.
+
+*/
+
+extern crate tetris;
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::Arc;
+
+use tetris::{rotate_in_well, GameState, Randomizer, Shape, Well, PIECE_SIZE};
+use tetris::pieces::{PieceSet, BUILTIN_SETS};
+
+
+/// A shape moved up into the top left corner of its box, so shapes can be compared wherever they sit in it.
+fn normalized(shape: &Shape) -> Shape
+{
+    let filled: Vec<(usize, usize)> = (0..PIECE_SIZE).flat_map(|row| (0..PIECE_SIZE).map(move |col| (row, col)))
+        .filter(|(row, col)| shape[*row][*col] != 0).collect();
+    let top = filled.iter().map(|(row, _)| *row).min().unwrap();
+    let left = filled.iter().map(|(_, col)| *col).min().unwrap();
+
+    let mut moved: Shape = [[0; PIECE_SIZE]; PIECE_SIZE];
+    for (row, col) in filled { moved[row - top][col - left] = 1; }
+    moved
+}
+
+fn squares(shape: &Shape) -> usize
+{
+    shape.iter().flatten().filter(|square| **square != 0).count()
+}
+
+
+#[test]
+fn built_in()
+{
+    let counts: Vec<(&str, usize)> = BUILTIN_SETS.iter().map(|name| (*name, PieceSet::load(name).unwrap().pieces.len())).collect();
+    assert_eq!(counts, vec![("standard", 7), ("triominoes", 2), ("pentominoes", 18), ("mixed", 13)]);
+
+    for name in BUILTIN_SETS.iter()
+    {
+        let set = PieceSet::load(name).unwrap();
+        assert_eq!(set.name, *name);
+        assert_eq!(set.is_standard(), *name == "standard");
+
+        // Every piece is different from the others, however it's turned, and keeps its size when it turns.
+        let mut seen = HashSet::new();
+        for piece in set.pieces.iter()
+        {
+            assert!(piece.rotations.iter().all(|shape| squares(shape) == squares(&piece.rotations[0])), "{} {}", name, piece.name);
+            let shapes: HashSet<Shape> = piece.rotations.iter().map(normalized).collect();
+            assert!(shapes.is_disjoint(&seen), "{} {}", name, piece.name);
+            seen.extend(shapes);
+        }
+    }
+
+    let pentominoes = PieceSet::load("pentominoes").unwrap();
+    assert!(pentominoes.pieces.iter().all(|piece| squares(&piece.rotations[0]) == 5));
+    let states = |name: &str| pentominoes.pieces.iter().find(|piece| piece.name == name).unwrap().rotations.len();
+    assert_eq!((states("I"), states("X"), states("F'")), (2, 1, 4));
+
+    let mixed = PieceSet::load("mixed").unwrap();
+    let sizes: Vec<usize> = mixed.pieces.iter().map(|piece| squares(&piece.rotations[0])).collect();
+    assert_eq!(sizes, vec![4, 4, 4, 4, 4, 4, 4, 3, 3, 5, 5, 5, 5]);
+}
+
+
+#[test]
+fn bag()
+{
+    for name in ["triominoes", "pentominoes", "mixed"].iter()
+    {
+        let set = Arc::new(PieceSet::load(name).unwrap());
+        let mut game_state = GameState::with_seed(3, Randomizer::Bag);
+        game_state.set_piece_set(set.clone());
+
+        let count = set.pieces.len();
+        let mut dealt = vec![game_state.curr_ttmo.kind, game_state.next_ttmo.kind];
+        dealt.extend(game_state.upcoming(count * 2 - 2).iter().map(|ttmo| ttmo.kind));
+        for bag in dealt.chunks(count) {
+            let kinds: HashSet<_> = bag.iter().collect();
+            assert_eq!(kinds.len(), count, "{}", name);
+        }
+        assert!(dealt.iter().all(|kind| set.get(*kind).is_some()));
+    }
+}
+
+
+/// The example in pieces.rs' header.
+const EXAMPLE: &str = "\
+rustris-pieces 1
+name mine
+
+// Lines starting with // are comments.
+piece T standard
+
+piece U 0.9 0.5 0.1
+#.#
+###
+...
+kicks 1,0 -1,0 0,-1
+
+piece X 0.8 0.8 0.8
+.#.
+###
+.#.
+rotations 1
+
+piece S2 0.2 0.6 0.9
+.##
+##.
+...
+state
+#..
+##.
+.#.
+";
+
+
+#[test]
+fn set_files()
+{
+    let set = PieceSet::from_text(EXAMPLE).unwrap();
+    let names: Vec<&str> = set.pieces.iter().map(|piece| piece.name.as_str()).collect();
+    assert_eq!(names, vec!["T", "U", "X", "S2"]);
+
+    let standard_t = &PieceSet::standard().pieces[5];
+    assert_eq!(set.pieces[0], *standard_t);
+    assert_eq!(set.pieces[1].color, [0.9, 0.5, 0.1, 1.0]);
+    assert_eq!(set.pieces[1].kicks, vec![(1, 0), (-1, 0), (0, -1)]);
+    assert_eq!((set.pieces[1].rotations.len(), set.pieces[2].rotations.len(), set.pieces[3].rotations.len()), (4, 1, 2));
+
+    // The U turned clockwise once, about its middle square: opening to the left.
+    assert_eq!(normalized(&set.pieces[1].rotations[1])[..3], [[1, 1, 0, 0, 0], [1, 0, 0, 0, 0], [1, 1, 0, 0, 0]]);
+    assert_eq!(set.name(set.pieces[3].kind), "S2");
+
+    // A set of the player's own is a file in the config folder, named after the set inside it.
+    let dir = env::temp_dir().join(format!("rustris-pieces-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    env::set_var("XDG_CONFIG_HOME", &dir);
+    fs::create_dir_all(dir.join("rustris").join("pieces")).unwrap();
+    fs::write(PieceSet::path("mine").unwrap(), EXAMPLE).unwrap();
+    fs::write(PieceSet::path("yours").unwrap(), EXAMPLE).unwrap();
+
+    assert_eq!(PieceSet::load("mine").unwrap(), set);
+    assert!(PieceSet::load("yours").is_err());
+    assert!(PieceSet::load("theirs").is_err());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+
+#[test]
+fn bad_sets()
+{
+    let bad = [
+        ("rustris-pieces 2\nname mine\npiece T standard\n", "newer version"),
+        ("name mine\npiece T standard\n", "no header"),
+        ("rustris-pieces 1\npiece T standard\n", "no name"),
+        ("rustris-pieces 1\nname a/b\npiece T standard\n", "/ in the name"),
+        ("rustris-pieces 1\nname mine\n", "no pieces"),
+        ("rustris-pieces 1\nname mine\npiece Q standard\n", "not one of the seven"),
+        ("rustris-pieces 1\nname mine\npiece T standard\npiece T standard\n", "the same name twice"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5\n#.#\n###\n...\n", "two numbers of color"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 1.5\n#.#\n###\n...\n", "color past 1"),
+        ("rustris-pieces 1\nname mine\n#.#\n###\n...\n", "shape before any piece"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n", "no shape"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n...\n...\n...\n", "no squares"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n###\n", "not square"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n##\n...\n", "ragged"),
+        ("rustris-pieces 1\nname mine\npiece B 0.9 0.5 0.1\n######\n######\n######\n######\n######\n######\n", "6x6"),
+        ("rustris-pieces 1\nname mine\npiece S 0.9 0.5 0.1\n.##\n##.\n...\nstate\n#..\n#..\n...\n", "states of different sizes"),
+        ("rustris-pieces 1\nname mine\npiece S 0.9 0.5 0.1\n.#\n#.\nstate\n#.\n#.\nstate\n.#\n.#\n", "three states"),
+        ("rustris-pieces 1\nname mine\npiece S 0.9 0.5 0.1\n.#\n##\nstate\n#.\n##\nrotations 2\n", "states and rotations"),
+        ("rustris-pieces 1\nname mine\npiece X 0.9 0.5 0.1\n.#.\n###\n.#.\nrotations 3\n", "three rotations"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n###\n...\nkicks 3,0\n", "kick too far"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n###\n...\nkicks 1,-3\n", "kick too far up"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n###\n...\nkicks 1\n", "kick with one number"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n###\n...\nkicks 1,x\n", "kick that isn't numbers"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n###\n...\nkicks 1,0 1,0 1,0 1,0 1,0 1,0 1,0 1,0 1,0\n", "nine kicks"),
+        ("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n###\n...\nspin 1\n", "not a thing")
+    ];
+    for (text, why) in bad.iter() { assert!(PieceSet::from_text(text).is_err(), "{}", why); }
+
+    // Eight kicks are as many as there can be.
+    assert!(PieceSet::from_text("rustris-pieces 1\nname mine\npiece U 0.9 0.5 0.1\n#.#\n###\n...\nkicks 1,0 1,0 1,0 1,0 1,0 1,0 1,0 1,0\n").is_ok());
+}
+
+
+#[test]
+fn kicks()
+{
+    // A bar three long, lying in the middle row of its box. It stands up in the middle column.
+    let text = "rustris-pieces 1\nname bars\npiece K 1 1 1\n...\n###\n...\nkicks 1,0\npiece N 1 1 1\n...\n###\n...\n";
+    let set = PieceSet::from_text(text).unwrap();
+    let (kicked, plain) = (set.pieces[0].tetrimino(), set.pieces[1].tetrimino());
+    let turn = |ttmo, well: &Well, clockwise| rotate_in_well(ttmo, well, 9, 3, clockwise).map(|(_, row, col)| (row, col));
+
+    // Nothing in the way: it turns where it is, kicks or not.
+    let mut well = Well::default();
+    assert_eq!(turn(&kicked, &well, true), Some((9, 3)));
+    assert_eq!(turn(&plain, &well, true), Some((9, 3)));
+
+    // Something where it would stand: turning clockwise it kicks one to the right, and counterclockwise one to the
+    // left. The one without kicks doesn't turn at all.
+    well.set(10, 4, 1);
+    assert_eq!(turn(&kicked, &well, true), Some((9, 4)));
+    assert_eq!(turn(&kicked, &well, false), Some((9, 2)));
+    assert_eq!(turn(&plain, &well, true), None);
+    assert_eq!(turn(&plain, &well, false), None);
+}
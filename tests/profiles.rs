@@ -0,0 +1,142 @@
+/*
+
+Checks profiles: a played-in profile reads back from its file as it was written, games add up into the
+totals and per-mode records, and files that are cut off, from a newer rustris, or full of numbers that don't
+fit are turned away rather than half read.
+
+*/
+
+extern crate tetris;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use tetris::GameState;
+use tetris::modes::{GameOver, Mode};
+use tetris::profiles::{load_profiles, valid_profile_name, Profile, Tally};
+
+
+/// A finished game of the given mode.
+fn game(mode: Mode, score: u32, pieces: u32, frames: u32, over: GameOver) -> GameState
+{
+    let mut game_state = GameState::with_mode(mode);
+    game_state.score = score;
+    game_state.lines = pieces * 4 / 10;
+    game_state.pieces = pieces;
+    game_state.frames = frames;
+    game_state.clears = [1, 2, 3, 4];
+    game_state.game_over = Some(over);
+    game_state
+}
+
+/// A profile that's been played a bit.
+fn played() -> Profile
+{
+    let mut profile = Profile::new("ben");
+    profile.das = 9;
+    profile.arr = 0;
+    profile.keys[5] = vec!["Space".to_string(), "Return".to_string()];
+
+    let tally = Tally { tspins: 2, max_combo: 5, attack: 30 };
+    profile.record_game(&game(Mode::Sprint { lines: 40 }, 0, 100, 6000, GameOver::GoalReached), &tally);
+    profile.record_game(&game(Mode::Sprint { lines: 40 }, 0, 100, 4800, GameOver::GoalReached), &tally);
+    profile.record_game(&game(Mode::Ultra { minutes: 3 }, 8000, 10, 3 * 60 * 60, GameOver::TimeUp), &Tally::default());
+    profile.mark_solved("tsd-1");
+    profile
+}
+
+
+#[test]
+fn totals()
+{
+    let profile = played();
+    assert_eq!(profile.name, "BEN");
+    assert_eq!((profile.stats.games, profile.stats.pieces, profile.stats.attack, profile.stats.tspins), (3, 210, 60, 4));
+    assert_eq!((profile.stats.clears, profile.stats.max_combo), ([3, 6, 9, 12], 5));
+    assert_eq!(profile.stats.millis, 100_000 + 80_000 + 180_000);
+
+    // 100 pieces in 80 seconds is the best. Ten pieces in Ultra is too few to count (see MIN_PIECES_FOR_BEST), whatever its pace.
+    assert_eq!(profile.stats.best_pps, 1.25);
+    assert_eq!(profile.stats.best_apm, 22.5);
+
+    // The faster Sprint, and the Ultra.
+    let records: Vec<(Mode, u64)> = profile.mode_records().iter().map(|game| (game.mode, game.millis)).collect();
+    assert_eq!(records, vec![(Mode::Sprint { lines: 40 }, 80_000), (Mode::Ultra { minutes: 3 }, 180_000)]);
+
+    let mut profile = profile;
+    assert!(!profile.mark_solved("tsd-1"));
+    assert_eq!(profile.solved, vec!["tsd-1"]);
+}
+
+
+#[test]
+fn round_trip()
+{
+    let profile = played();
+    let text = profile.to_text();
+    let loaded = Profile::from_text(&text).unwrap();
+
+    assert_eq!(loaded.to_text(), text);
+    assert_eq!((loaded.name.as_str(), loaded.das, loaded.arr), ("BEN", 9, 0));
+    assert_eq!(loaded.keys, profile.keys);
+    assert_eq!(loaded.history.len(), 3);
+    assert_eq!((loaded.history[1].mode, loaded.history[1].millis, loaded.history[1].finished), (Mode::Sprint { lines: 40 }, 80_000, true));
+    assert!(!loaded.history[2].finished);
+    assert_eq!(loaded.solved, profile.solved);
+
+    // Comments and blank lines are fine, and anything left out keeps its default.
+    let short = Profile::from_text("# mine\nrustris-profile 2\n\nname BEN\n").unwrap();
+    assert_eq!((short.das, short.arr, short.stats.games), (12, 4, 0));
+}
+
+
+#[test]
+fn bad_files()
+{
+    let good = played().to_text();
+    let bad = [
+        String::new(),
+        "name BEN\n".to_string(),                                       // No header.
+        good.replace("rustris-profile 2", "rustris-profile 3"),         // From a newer rustris.
+        "rustris-profile 2\ndas 9\n".to_string(),                       // No name.
+        "rustris-profile 2\nname BEN!\n".to_string(),
+        "rustris-profile 2\nname BENJAMINS\n".to_string(),              // Too long.
+        "rustris-profile 2\nname BEN\nspeed 3\n".to_string(),
+        "rustris-profile 2\nname BEN\nkey jump Space\n".to_string(),
+        "rustris-profile 2\nname BEN\ndas -1\n".to_string(),
+        "rustris-profile 2\nname BEN\ngames 4294967296\n".to_string(),    // Doesn't fit.
+        "rustris-profile 1\nname BEN\ndas 3000000000\n".to_string(),      // Doesn't fit once it's doubled.
+        "rustris-profile 2\nname BEN\nclears 1 2 3\n".to_string(),
+        "rustris-profile 2\nname BEN\ngame 1792361985 sprint-40 1200\n".to_string(),
+        "rustris-profile 2\nname BEN\ngame 1792361985 sprint-4x 1200 40 102 20 86033 finished\n".to_string(),
+        good[..good.find("\ngame ").unwrap() + 20].to_string()           // Cut off partway through a game.
+    ];
+
+    for text in bad.iter() { assert!(Profile::from_text(text).is_err(), "{}", text); }
+
+    assert!(valid_profile_name("BEN2"));
+    assert!(!valid_profile_name(""));
+    assert!(!valid_profile_name("../BEN"));
+}
+
+
+#[test]
+fn profiles_folder()
+{
+    let dir = env::temp_dir().join(format!("rustris-profiles-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    env::set_var("XDG_DATA_HOME", &dir);
+
+    played().save().unwrap();
+    Profile::new("amy").save().unwrap();
+    fs::write(dir.join("rustris").join("profiles").join("zed.profile"), "rustris-profile 2\nname ZED\ngames lots\n").unwrap();
+
+    // In name order, skipping the one that can't be read.
+    let profiles = load_profiles();
+    let names: Vec<&str> = profiles.iter().map(|profile| profile.name.as_str()).collect();
+    assert_eq!(names, vec!["AMY", "BEN"]);
+    assert_eq!(profiles[1].to_text(), played().to_text());
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,329 @@
+/*
+
+Checks puzzle packs read and write the way puzzles.rs says, that each goal is met by what it asks for and not by
+something near it, that a piece in the hold can be swapped in, and that undo and retry put the game back as it
+was. Then solves every built-in puzzle through the game's own rules: the perfect clears with the solver, and the
+rest by trying everywhere every piece can get to.
+
+*/
+
+extern crate tetris;
+
+use tetris::{game_update, rotate_in_well, would_collide, Action, GameState, Tetrimino, TetriminoKind, GARBAGE, PIECE_SIZE};
+use tetris::modes::{GameOver, Mode};
+use tetris::puzzles::{load_puzzles, pieces_to_come, start_puzzle, Goal, Puzzle, Undo};
+use tetris::solver::{Problem, Solution};
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+
+/// The squares of the well a piece covers, sorted.
+fn squares(ttmo: &Tetrimino, row: i32, col: i32) -> Vec<(i32, i32)>
+{
+    let mut squares: Vec<(i32, i32)> = (0..PIECE_SIZE).flat_map(|ttmo_row| (0..PIECE_SIZE).map(move |ttmo_col| (ttmo_row, ttmo_col)))
+        .filter(|(ttmo_row, ttmo_col)| ttmo.shape[*ttmo_row][*ttmo_col] != 0)
+        .map(|(ttmo_row, ttmo_col)| (row + ttmo_row as i32, col + ttmo_col as i32))
+        .collect();
+    squares.sort_unstable();
+    squares
+}
+
+
+/// Somewhere the falling piece can come to rest.
+struct Rest
+{
+    keys: Vec<Action>,            // What gets it there.
+    squares: Vec<(i32, i32)>,     // What it covers.
+    turned: bool                  // Whether the last thing it did was turn (for T-spins).
+}
+
+
+/// Everywhere the falling piece can come to rest by moving, turning and soft dropping.
+fn resting(game_state: &GameState) -> Vec<Rest>
+{
+    let well = &game_state.well;
+    let fits = |ttmo: &Tetrimino, row: i32, col: i32| !would_collide(ttmo, well, &row, &col);
+    let start = (game_state.curr_ttmo, game_state.ttmo_row, game_state.ttmo_col, false);
+
+    let mut seen = HashSet::new();
+    let mut landed = HashSet::new();
+    let mut found = Vec::new();
+    let mut queue = vec![(start, Vec::new())];
+    seen.insert((start.0.rotation, start.1, start.2, start.3));
+
+    while let Some(((ttmo, row, col, turned), keys)) = queue.pop()
+    {
+        if !fits(&ttmo, row + 1, col) && landed.insert((squares(&ttmo, row, col), turned)) {
+            found.push(Rest { keys: keys.clone(), squares: squares(&ttmo, row, col), turned });
+        }
+
+        let mut next = Vec::new();
+        if fits(&ttmo, row, col - 1) { next.push((Action::MoveLeft, (ttmo, row, col - 1, false))); }
+        if fits(&ttmo, row, col + 1) { next.push((Action::MoveRight, (ttmo, row, col + 1, false))); }
+        if fits(&ttmo, row + 1, col) { next.push((Action::SoftDrop, (ttmo, row + 1, col, false))); }
+        for (key, clockwise) in [(Action::RotateCCW, false), (Action::RotateCW, true)].iter() {
+            if let Some((ttmo, row, col)) = rotate_in_well(&ttmo, well, row, col, *clockwise) { next.push((*key, (ttmo, row, col, true))); }
+        }
+
+        for (key, state) in next {
+            if !seen.insert((state.0.rotation, state.1, state.2, state.3)) { continue; }
+            let mut keys = keys.clone();
+            keys.push(key);
+            queue.push((state, keys));
+        }
+    }
+    found
+}
+
+
+/// Presses the keys, one an update, then hard drops, and returns the game once the piece has locked.
+fn play(game_state: &GameState, keys: &[Action]) -> GameState
+{
+    let mut game_state = game_state.clone();
+    let pieces = game_state.pieces;
+    for key in keys.iter().chain([Action::HardDrop].iter()) {
+        game_state.inputs.push(*key);
+        game_update(&mut game_state);
+    }
+    while game_state.pieces == pieces && game_state.game_over.is_none() { game_update(&mut game_state); }
+    game_state
+}
+
+
+/// Puts the falling piece down somewhere it covers the given squares, without turning last.
+fn play_at(game_state: &GameState, covering: &[(i32, i32)]) -> GameState
+{
+    let rest = resting(game_state).into_iter().find(|rest| rest.squares == covering && !rest.turned).unwrap();
+    play(game_state, &rest.keys)
+}
+
+
+/// Whether there's any way to place the rest of the puzzle's pieces that reaches its goal.
+fn solvable(game_state: &GameState) -> bool
+{
+    resting(game_state).iter().any(|rest| {
+        let played = play(game_state, &rest.keys);
+        match played.game_over {
+            Some(GameOver::GoalReached) => true,
+            Some(_) => false,
+            None => solvable(&played)
+        }
+    })
+}
+
+
+fn puzzle(text: &str) -> GameState
+{
+    let mut game_state = GameState::with_mode(Mode::Puzzle);
+    let pack = Puzzle::parse_pack(&format!("rustris-puzzles 1\n{}", text)).unwrap();
+    start_puzzle(&mut game_state, Arc::new(pack[0].clone()));
+    game_state
+}
+
+
+#[test]
+fn packs()
+{
+    let pack = Puzzle::parse_pack("\
+rustris-puzzles 1
+// A comment.
+
+puzzle first
+goal tspin 2
+pieces T J
+hold O
+hint  put it there.
+XXXX......
+XXX...XXXX
+XXXX.XXXXX
+
+puzzle second
+title A TITLE
+goal perfect-clear 1
+pieces O
+well 6x8+2
+XXXX..
+").unwrap();
+
+    assert_eq!(pack.len(), 2);
+    assert_eq!((pack[0].title.as_str(), pack[0].hint.as_str(), pack[0].goal), ("FIRST", "PUT IT THERE.", Goal::TSpin(2)));
+    assert_eq!(pack[0].pieces, vec![TetriminoKind::T, TetriminoKind::J]);
+    assert_eq!((pack[0].hold, pack[1].hold), (Some(TetriminoKind::O), None));
+    assert_eq!(pack[0].letters(1), "J");
+    assert_eq!((pack[0].well[23][4], pack[0].well[23][3], pack[0].well[20][9]), (0, GARBAGE, 0));
+    assert_eq!((pack[1].well.cols(), pack[1].well.visible_rows(), pack[1].well.hidden()), (6, 8, 2));
+    assert_eq!(pack[1].well[9][0], GARBAGE);
+
+    // Past the end of its list, a puzzle's piece is its last one again.
+    assert_eq!(pack[0].tetrimino(5).kind, TetriminoKind::J);
+
+    // Written out and read back, it's the same again, and so is the built-in pack.
+    let again = Puzzle::parse_pack(&Puzzle::pack_text(&pack)).unwrap();
+    for (puzzle, again) in pack.iter().zip(again.iter()) {
+        assert_eq!(again.to_text(), puzzle.to_text());
+        assert_eq!(again.well, puzzle.well);
+    }
+    let built_in: Vec<Puzzle> = load_puzzles(None).iter().map(|puzzle| (**puzzle).clone()).collect();
+    assert_eq!(Puzzle::parse_pack(&Puzzle::pack_text(&built_in)).unwrap().len(), built_in.len());
+
+    assert_eq!(Goal::TSpin(3).describe(), "T-SPIN TRIPLE");
+    assert_eq!(Goal::PerfectClear(1).describe(), "PERFECT CLEAR IN 1 PIECE");
+    for goal in ["lines 3", "tetris", "tspin 1", "clear-all", "perfect-clear 4"].iter() {
+        assert_eq!(goal.parse::<Goal>().unwrap().to_string(), *goal);
+    }
+}
+
+
+#[test]
+fn bad_packs()
+{
+    let bad = [
+        "puzzle a\ngoal tetris\npieces I\n",                               // No header.
+        "rustris-puzzles 1\ngoal tetris\n",                                // Before any puzzle.
+        "rustris-puzzles 1\npuzzle\npieces I\n",                           // No name.
+        "rustris-puzzles 1\npuzzle a b\npieces I\n",
+        "rustris-puzzles 1\npuzzle a\npieces I\npuzzle a\npieces I\n",     // The same name twice.
+        "rustris-puzzles 1\npuzzle a\npieces IQ\n",
+        "rustris-puzzles 1\npuzzle a\ngoal lines 0\npieces I\n",
+        "rustris-puzzles 1\npuzzle a\ngoal tspin 4\npieces T\n",
+        "rustris-puzzles 1\npuzzle a\ngoal perfect-clear 3\npieces IO\n",  // More pieces than it has.
+        "rustris-puzzles 1\npuzzle a\ngoal win\npieces I\n",
+        "rustris-puzzles 1\npuzzle a\ngoal tetris\n",                      // No pieces.
+        "rustris-puzzles 1\npuzzle a\npieces I\nwell 2x2+0\n",
+        "rustris-puzzles 1\npuzzle a\npieces I\nXXXX\n",                   // Too narrow.
+        "rustris-puzzles 1\npuzzle a\npieces I\nXXXXXXXXXX\n",             // Full already.
+        "rustris-puzzles 1\npuzzle a\npieces I\nwell 4x4+2\n....\n....\n....\n....\n....\n",    // Taller than the well.
+        "rustris-puzzles 1\npuzzle a\npieces I\n.........X\nhint TOO LATE\n",
+        "rustris-puzzles 1\npuzzle a\npieces I\nsize 10\n",
+        "rustris-puzzles 1\npuzzle a\npieces I\nhold IO\n",                // One piece in the hold, no more.
+        "rustris-puzzles 1\npuzzle a\npieces I\nhold\n",
+        "rustris-puzzles 1\npuzzle a\npieces I\nhold Q\n"
+    ];
+    for text in bad.iter() {
+        assert!(Puzzle::parse_pack(text).is_err(), "{}", text);
+    }
+}
+
+
+#[test]
+fn goals()
+{
+    // A T laid flat in a gap three wide clears a line, but it didn't turn in, so it isn't a T-spin.
+    let flat = [(22, 5), (23, 4), (23, 5), (23, 6)];
+    let game_state = puzzle("puzzle a\ngoal tspin 1\npieces T\nXXXX...XXX\n");
+    let played = play_at(&game_state, &flat);
+    assert_eq!((played.lines, played.game_over), (1, Some(GameOver::OutOfPieces)));
+
+    let game_state = puzzle("puzzle a\ngoal lines 1\npieces T\nXXXX...XXX\n");
+    assert_eq!(play_at(&game_state, &flat).game_over, Some(GameOver::GoalReached));
+
+    // Two O's empty the well. A perfect clear in one piece is over after the first, and in two, it's made.
+    let rows = "XXXXXXXX..\nXXXXXXXX..\nXXXXXXXX..\nXXXXXXXX..\n";
+    let bottom = [(22, 8), (22, 9), (23, 8), (23, 9)];    // Both go here: the first clears two rows, and the rest come down.
+    for (goal, first, second) in [("perfect-clear 1", Some(GameOver::OutOfPieces), None), ("perfect-clear 2", None, Some(GameOver::GoalReached)),
+                                  ("clear-all", None, Some(GameOver::GoalReached)), ("lines 4", None, Some(GameOver::GoalReached))].iter()
+    {
+        let game_state = puzzle(&format!("puzzle a\ngoal {}\npieces OO\n{}", goal, rows));
+        assert_eq!(pieces_to_come(&game_state), 1);
+        let played = play_at(&game_state, &bottom);
+        assert_eq!(played.game_over, *first, "{}", goal);
+        if first.is_some() { continue; }
+
+        assert_eq!(pieces_to_come(&played), 0);
+        assert_eq!(play_at(&played, &bottom).game_over, *second, "{}", goal);
+    }
+
+    // A tetris has to be four at once.
+    let game_state = puzzle(&format!("puzzle a\ngoal tetris\npieces OO\n{}", rows));
+    assert_eq!(play_at(&play_at(&game_state, &bottom), &bottom).game_over, Some(GameOver::OutOfPieces));
+
+    // With a piece in the hold, the falling one can be swapped for it. Without, hold does nothing.
+    let flat = [(23, 6), (23, 7), (23, 8), (23, 9)];
+    for (hold, kind) in [("hold I\n", TetriminoKind::I), ("", TetriminoKind::T)].iter()
+    {
+        let mut game_state = puzzle(&format!("puzzle a\ngoal lines 1\npieces T\n{}XXXXXX....\n", hold));
+        assert_eq!(game_state.has_hold(), !hold.is_empty());
+        game_state.inputs.push(Action::Hold);
+        game_update(&mut game_state);
+        assert_eq!(game_state.curr_ttmo.kind, *kind);
+        if hold.is_empty() { continue; }
+
+        assert_eq!(game_state.hold_ttmo.map(|held| held.kind), Some(TetriminoKind::T));
+        assert_eq!(play_at(&game_state, &flat).game_over, Some(GameOver::GoalReached));
+    }
+}
+
+
+#[test]
+fn undo_and_retry()
+{
+    let start = puzzle("puzzle a\ngoal clear-all\npieces OO\nXXXXXXXX..\nXXXXXXXX..\nXXXXXXXX..\nXXXXXXXX..\n");
+    let bottom = [(22, 8), (22, 9), (23, 8), (23, 9)];    // Both go here: the first clears two rows, and the rest come down.
+    let mut undo = Undo::new(&start);
+
+    // One piece down and taken back: the puzzle as it started.
+    let game_state = play_at(&start, &bottom);
+    undo.watch(&game_state);
+    let game_state = undo.undo(&game_state);
+    assert_eq!((game_state.pieces, &game_state.well), (0, &start.well));
+
+    // Nothing to take back, it stays at the start.
+    assert_eq!(undo.undo(&game_state).pieces, 0);
+
+    // Played to the end, undo goes back to when the last piece appeared, ready to place it again.
+    let game_state = play_at(&game_state, &bottom);
+    undo.watch(&game_state);
+    let finished = play_at(&game_state, &bottom);
+    undo.watch(&finished);
+    assert_eq!(finished.game_over, Some(GameOver::GoalReached));
+
+    let game_state = undo.undo(&finished);
+    assert_eq!((game_state.pieces, game_state.game_over, &game_state.well), (1, None, &play_at(&start, &bottom).well));
+    assert_eq!(play_at(&game_state, &bottom).game_over, Some(GameOver::GoalReached));
+
+    let game_state = undo.retry();
+    assert_eq!((game_state.pieces, &game_state.well), (0, &start.well));
+}
+
+
+#[test]
+fn built_in_puzzles_can_be_solved()
+{
+    for puzzle in load_puzzles(None)
+    {
+        let mut game_state = GameState::with_mode(Mode::Puzzle);
+        start_puzzle(&mut game_state, puzzle.clone());
+
+        match puzzle.goal
+        {
+            // The solver says where each piece goes, and the game agrees it gets there and clears the well. Openers
+            // start from an empty well, and clear four rows.
+            Goal::ClearAll | Goal::PerfectClear(_) => {
+                let rows = match game_state.well.iter().filter(|row| row.iter().any(|square| *square != 0)).count() {
+                    0 => 4,
+                    rows => rows
+                };
+                let steps = match Problem::from_game(&game_state, rows).solve() {
+                    Solution::Found(steps) => steps,
+                    _ => panic!("{} has no solution", puzzle.name)
+                };
+                for step in steps.iter() {
+                    if step.hold {
+                        game_state.inputs.push(Action::Hold);
+                        game_update(&mut game_state);
+                    }
+                    let rest = resting(&game_state).into_iter()
+                        .find(|rest| rest.squares == squares(&step.ttmo, step.row, step.col))
+                        .unwrap_or_else(|| panic!("{} can't get a piece where the solver put it", puzzle.name));
+                    game_state = play(&game_state, &rest.keys);
+                }
+                assert_eq!(game_state.game_over, Some(GameOver::GoalReached), "{}", puzzle.name);
+            }
+            _ => assert!(solvable(&game_state), "{} can't be solved", puzzle.name)
+        }
+    }
+
+    // And the search does turn down a puzzle with no way through: a T-spin triple in a slot for a double.
+    assert!(!solvable(&puzzle("puzzle a\ngoal tspin 3\npieces T\nXXXX......\nXXX...XXXX\nXXXX.XXXXX\n")));
+}
@@ -0,0 +1,184 @@
+/*
+
+Checks the high-score tables: they save and load back as they were, keep the best TABLE_SIZE in the right
+order, bring version 1 files up to 60 updates a second, leave newer files alone, and move unreadable ones
+aside rather than writing over them.
+
+*/
+
+extern crate tetris;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use tetris::GameState;
+use tetris::modes::{GameOver, Mode};
+use tetris::records::{format_date, HighScores, TABLE_SIZE};
+use tetris::replay::Replay;
+
+
+/// An empty folder of its own for one test, under the system's temporary folder.
+fn scratch(name: &str) -> PathBuf
+{
+    let dir = env::temp_dir().join(format!("rustris-records-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A Sprint 40 that reached the goal in the given number of updates.
+fn sprint(frames: u32) -> GameState
+{
+    let mut game_state = GameState::with_mode(Mode::Sprint { lines: 40 });
+    game_state.lines = 40;
+    game_state.frames = frames;
+    game_state.splits = vec![frames / 4, frames / 2, frames * 3 / 4, frames];
+    game_state.game_over = Some(GameOver::GoalReached);
+    game_state
+}
+
+
+#[test]
+fn tables()
+{
+    // Replays go in the data directory, so point that somewhere of our own.
+    let dir = scratch("tables");
+    env::set_var("XDG_DATA_HOME", &dir);
+    let path = dir.join("scores.json");
+
+    let mut scores = HighScores::load_from(&path);
+    assert!(scores.tables.is_empty());
+
+    let times = [5000, 4000, 6000, 3000, 7000, 4500, 5500, 6500, 3500, 7500, 2500];
+    for frames in times.iter() {
+        let game_state = sprint(*frames);
+        scores.add(&game_state, &Replay::new(&game_state), "BEN").unwrap();
+    }
+
+    // The best ten, fastest first. The slowest dropped off, and a game slower than all of them doesn't make it.
+    let mode = Mode::Sprint { lines: 40 };
+    let frames: Vec<u32> = scores.table(mode).iter().map(|entry| entry.frames).collect();
+    assert_eq!(frames, vec![2500, 3000, 3500, 4000, 4500, 5000, 5500, 6000, 6500, 7000]);
+    assert_eq!(scores.table(mode).len(), TABLE_SIZE);
+    assert!(!scores.qualifies(&sprint(7000)));
+    assert!(scores.qualifies(&sprint(6999)));
+
+    // Unfinished races and non-standard games don't count at all.
+    let mut quit = sprint(100);
+    quit.game_over = Some(GameOver::TopOut);
+    assert!(!scores.qualifies(&quit));
+    let mut slow = sprint(100);
+    slow.speed = 50;
+    assert!(!scores.qualifies(&slow));
+
+    // Everything comes back from the file as it went in, replays and all.
+    let loaded = HighScores::load_from(&path);
+    assert_eq!(loaded.tables.len(), 1);
+    for (saved, loaded) in scores.table(mode).iter().zip(loaded.table(mode))
+    {
+        assert_eq!((&saved.name, saved.frames, &saved.splits, saved.seed, saved.date), (&loaded.name, loaded.frames, &loaded.splits, loaded.seed, loaded.date));
+        let replay = loaded.replay.as_ref().unwrap();
+        assert!(Replay::load(&dir.join("rustris").join("replays").join(replay)).is_ok());
+    }
+    assert_eq!(loaded.best(mode).unwrap().name, "BEN");
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+
+const ENTRY: &str = r#"{ "name": "BEN", "score": 0, "lines": 40, "level": 5, "frames": 3000, "date": 1634567890, "seed": 7,
+                         "randomizer": "bag", "replay": null, "splits": [700, 1500, 2200, 3000] }"#;
+
+
+#[test]
+fn version_1()
+{
+    // Sprint ran at 30 a second then, so its times double. Marathon didn't, so its stay put.
+    let dir = scratch("version-1");
+    let path = dir.join("scores.json");
+    fs::write(&path, format!(r#"{{ "version": 1, "tables": {{ "sprint-40": [{}], "marathon-5": [{}] }} }}"#, ENTRY, ENTRY)).unwrap();
+
+    let scores = HighScores::load_from(&path);
+    let sprint = scores.best(Mode::Sprint { lines: 40 }).unwrap();
+    assert_eq!((sprint.frames, &sprint.splits), (6000, &vec![1400, 3000, 4400, 6000]));
+    assert_eq!(scores.best(Mode::Marathon { start_level: 5 }).unwrap().frames, 3000);
+
+    // And it's saved like that, so it only happens once.
+    assert!(fs::read_to_string(&path).unwrap().contains("\"version\": 2"));
+    assert_eq!(HighScores::load_from(&path).best(Mode::Sprint { lines: 40 }).unwrap().frames, 6000);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+
+#[test]
+fn version_0()
+{
+    // No scores.json yet, but a sprint.json from before there were tables.
+    let dir = scratch("version-0");
+    fs::write(dir.join("sprint.json"), r#"[ { "lines": 40, "frames": 3000, "splits": [1500], "seed": 7, "randomizer": "bag",
+                                             "date": 1634567890, "replay": "sprint-1634567890.replay" } ]"#).unwrap();
+
+    let scores = HighScores::load_from(&dir.join("scores.json"));
+    let best = scores.best(Mode::Sprint { lines: 40 }).unwrap();
+    assert_eq!((best.frames, &best.splits, best.replay.as_deref()), (6000, &vec![3000], Some("sprint-1634567890.replay")));
+    assert!(dir.join("scores.json").exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+
+#[test]
+fn newer_version()
+{
+    let dir = scratch("newer");
+    let path = dir.join("scores.json");
+    let text = r#"{ "version": 3, "tables": { "sprint-40": "something new" } }"#;
+    fs::write(&path, text).unwrap();
+
+    // Nothing's read from it, and nothing's written over it.
+    let scores = HighScores::load_from(&path);
+    assert!(scores.tables.is_empty());
+    assert!(scores.save().is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+
+#[test]
+fn unreadable()
+{
+    // Cut off halfway, not JSON at all, no version, and numbers that don't fit.
+    let truncated = format!(r#"{{ "version": 2, "tables": {{ "sprint-40": [{}"#, ENTRY);
+    let negative = format!(r#"{{ "version": 2, "tables": {{ "sprint-40": [{}] }} }}"#, ENTRY.replace("3000,", "-3000,"));
+    let files = [truncated.as_str(), "high scores", r#"{ "tables": {} }"#, negative.as_str()];
+
+    for (index, text) in files.iter().enumerate()
+    {
+        let dir = scratch(&format!("unreadable-{}", index));
+        let path = dir.join("scores.json");
+        fs::write(&path, text).unwrap();
+
+        // Moved aside for rescuing by hand, and the tables start over.
+        let scores = HighScores::load_from(&path);
+        assert!(scores.tables.is_empty(), "{}", text);
+        assert!(!path.exists(), "{}", text);
+        assert_eq!(fs::read_to_string(dir.join("scores.json.bad")).unwrap(), *text);
+        scores.save().unwrap();
+        assert!(path.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
+
+
+#[test]
+fn dates()
+{
+    assert_eq!(format_date(0), "1970-01-01");
+    assert_eq!(format_date(951_782_400), "2000-02-29");
+    assert_eq!(format_date(1_634_567_890), "2021-10-18");
+}
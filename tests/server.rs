@@ -0,0 +1,227 @@
+/*
+
+Runs a real rustris-server on localhost, and has a few bot clients play a battle royale on it while a
+spectator watches. Checks that everyone sees the same match: the same seed, the same slots, and the
+same winner at the end.
+
+*/
+
+extern crate tetris;
+
+use tetris::Action;
+use tetris::lobby::{ClientMessage, LobbyClient, ServerMessage, SERVER_PROTOCOL_VERSION};
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// Kills the server when the test is done with it, even if the test fails.
+struct Server(Child, u16);
+
+impl Drop for Server
+{
+    fn drop(&mut self) { let _ = self.0.kill(); }
+}
+
+
+fn start_server() -> Server
+{
+    // Grab a free port, then let it go for the server to have.
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let child = Command::new(env!("CARGO_BIN_EXE_rustris-server"))
+        .args(["--port", &port.to_string(), "--speed", "10"])
+        .spawn().unwrap();
+
+    let started = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err()
+    {
+        assert!(started.elapsed() < Duration::from_secs(10), "server never started listening");
+        thread::sleep(Duration::from_millis(20));
+    }
+    Server(child, port)
+}
+
+
+fn connect(server: &Server, name: &str) -> LobbyClient
+{
+    LobbyClient::connect(("127.0.0.1", server.1), name).unwrap()
+}
+
+
+/// Waits for the server to say something that `wanted` picks out, and returns what it picked.
+fn wait_for<T>(client: &mut LobbyClient, mut wanted: impl FnMut(ServerMessage) -> Option<T>) -> T
+{
+    let started = Instant::now();
+    loop
+    {
+        for message in client.poll().unwrap() {
+            if let Some(found) = wanted(message) { return found; }
+        }
+        assert!(started.elapsed() < Duration::from_secs(60), "timed out waiting on the server");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+
+/// What one client saw of a match.
+#[derive(Debug)]
+struct Seen
+{
+    seed: u64,
+    slot: Option<usize>,
+    slots: usize,
+    winner: Option<usize>,
+    places: Vec<Option<usize>>
+}
+
+
+/// Plays (or for a spectator, watches) one match to the end. Players hard drop everything, so it doesn't take long.
+/// Each slot nudges its pieces a different way first, or they'd all play the exact same game and top out together.
+fn play(mut client: LobbyClient) -> Seen
+{
+    let mut seen = Seen { seed: 0, slot: None, slots: 0, winner: None, places: Vec::new() };
+    let started = Instant::now();
+
+    loop
+    {
+        for message in client.poll().unwrap()
+        {
+            match message
+            {
+                ServerMessage::Start { seed, players } => {
+                    seen.seed = seed;
+                    seen.slots = players;
+                    seen.places = vec![None; players];
+                }
+                ServerMessage::Slot { slot, id, .. } if id == client.id => seen.slot = Some(slot),
+                ServerMessage::Out { slot, place } => seen.places[slot] = Some(place),
+                ServerMessage::Winner(winner) => {
+                    seen.winner = winner;
+                    if let Some(winner) = winner { seen.places[winner] = Some(1); }
+                    return seen;
+                }
+                ServerMessage::State(view) if Some(view.slot) == seen.slot && view.piece.is_some() => {
+                    let mut keys = Vec::new();
+                    if view.slot < 2 { keys.push(Action::ALL[view.slot]); }
+                    keys.push(Action::HardDrop);
+                    client.send(&ClientMessage::Keys(keys)).unwrap();
+                }
+                _ => ()
+            }
+        }
+        assert!(started.elapsed() < Duration::from_secs(60), "match never finished");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+
+#[test]
+fn battle_royale_with_spectator()
+{
+    let server = start_server();
+
+    let mut host = connect(&server, "host");
+    host.send(&ClientMessage::Create("arena".to_string())).unwrap();
+    wait_for(&mut host, |message| if let ServerMessage::Joined { spectating: false, .. } = message { Some(()) } else { None });
+
+    let mut guests: Vec<LobbyClient> = ["alice", "bob"].iter().map(|name| connect(&server, name)).collect();
+    for guest in guests.iter_mut()
+    {
+        guest.send(&ClientMessage::Join("arena".to_string())).unwrap();
+        wait_for(guest, |message| if let ServerMessage::Joined { spectating: false, .. } = message { Some(()) } else { None });
+    }
+
+    let mut spectator = connect(&server, "watcher");
+    spectator.send(&ClientMessage::Spectate("arena".to_string())).unwrap();
+    wait_for(&mut spectator, |message| if let ServerMessage::Joined { spectating: true, .. } = message { Some(()) } else { None });
+
+    // The room lists everyone.
+    spectator.send(&ClientMessage::List).unwrap();
+    let (players, spectators) = wait_for(&mut spectator, |message| match message {
+        ServerMessage::Room { players, spectators, .. } => Some((players, spectators)),
+        _ => None
+    });
+    assert_eq!((players, spectators), (3, 1));
+
+    let mut players = vec![host];
+    players.extend(guests);
+    for player in players.iter_mut() { player.send(&ClientMessage::Ready).unwrap(); }
+
+    let threads: Vec<_> = players.into_iter().map(|player| thread::spawn(move || play(player))).collect();
+    let watched = play(spectator);
+    let played: Vec<Seen> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+
+    assert_eq!(watched.slots, 3);
+    assert_eq!(watched.slot, None);
+
+    let mut slots: Vec<usize> = played.iter().map(|seen| seen.slot.unwrap()).collect();
+    slots.sort();
+    assert_eq!(slots, vec![0, 1, 2]);
+
+    for seen in played.iter()
+    {
+        assert_eq!(seen.seed, watched.seed);
+        assert_eq!(seen.winner, watched.winner);
+        assert_eq!(seen.places, watched.places);
+    }
+
+    // Everyone got a place, and only the winner came first. With no winner, the last players standing share first.
+    assert!(watched.places.iter().all(|place| place.is_some_and(|place| (1..=3).contains(&place))));
+    let firsts: Vec<usize> = (0..3).filter(|slot| watched.places[*slot] == Some(1)).collect();
+    match watched.winner {
+        Some(winner) => assert_eq!(firsts, vec![winner]),
+        None => assert!(firsts.len() > 1)
+    }
+}
+
+
+#[test]
+fn rejects_other_versions_and_nonsense()
+{
+    let server = start_server();
+
+    let mut stream = TcpStream::connect(("127.0.0.1", server.1)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+
+    writeln!(stream, "join arena").unwrap();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line.trim(), "error say hello first");
+
+    line.clear();
+    writeln!(stream, "hello {} tester", SERVER_PROTOCOL_VERSION + 1).unwrap();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("error this server speaks version"));
+
+    let mut client = connect(&server, "tester");
+    client.send(&ClientMessage::Join("nowhere".to_string())).unwrap();
+    let error = wait_for(&mut client, |message| if let ServerMessage::Error(msg) = message { Some(msg) } else { None });
+    assert_eq!(error, "no room called nowhere");
+}
+
+
+#[test]
+fn messages_survive_the_wire()
+{
+    let messages = [
+        "welcome 1 7", "error no room called x", "room arena 3 1 playing", "end-rooms", "joined arena spectator", "left",
+        "member 4 alice ready", "gone 4", "start 1234 3", "slot 2 4 alice", "frame 60", "out 1 3", "winner 0", "winner none"
+    ];
+    for line in messages.iter() { assert_eq!(ServerMessage::parse(line).unwrap().to_line(), *line); }
+
+    let well = "0".repeat(200) + &"8888808888".repeat(4);
+    let state = format!("state 1 - 12 4 2 30 6 3 4 4e00 1 {}", well);
+    let view = match ServerMessage::parse(&state).unwrap() { ServerMessage::State(view) => view, _ => panic!() };
+    assert_eq!(view.well[23][5], 0);
+    assert_eq!(view.piece.unwrap().0.shape[1], [1, 1, 1, 0, 0]);
+    assert_eq!(ServerMessage::State(view).to_line(), state);
+
+    assert_eq!(ClientMessage::parse("keys left,left,hard"), Some(ClientMessage::Keys(vec![Action::MoveLeft, Action::MoveLeft, Action::HardDrop])));
+    assert_eq!(ClientMessage::parse("keys -"), Some(ClientMessage::Keys(Vec::new())));
+    assert_eq!(ClientMessage::parse("keys 100001"), None);
+    assert_eq!(ClientMessage::parse("hello 1 has spaces"), None);
+    assert_eq!(ClientMessage::parse("create no/slashes"), None);
+}
@@ -0,0 +1,168 @@
+/*
+
+Checks the settings file: every setting reads back as it was written, values outside their limits or of
+the wrong type are errors rather than being quietly fixed, and so are files cut off partway or from a newer
+rustris. Also --set and the options menu's steps.
+
+*/
+
+extern crate tetris;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use tetris::settings::{Settings, SETTING_NAMES};
+
+
+/// Settings with every one changed from its default.
+fn changed() -> Settings
+{
+    let mut settings = Settings::default();
+    let values = ["150", "30", "12", "24", "3", "1", "4", "pentominoes", "9", "0", "1920", "1080", "false", "tritanopia", "true", "false", "false", "0.55"];
+    for (name, value) in SETTING_NAMES.iter().zip(values.iter()) { settings.set(name, value).unwrap(); }
+    settings
+}
+
+
+#[test]
+fn round_trip()
+{
+    let settings = changed();
+    for name in SETTING_NAMES.iter() { assert_ne!(settings.get(name), Settings::default().get(name), "{}", name); }
+    assert_eq!(Settings::from_toml(&settings.to_toml()).unwrap(), settings);
+    assert_eq!(Settings::from_toml(&Settings::default().to_toml()).unwrap(), Settings::default());
+
+    // An empty file, or one that leaves things out, is the defaults for what's missing.
+    assert_eq!(Settings::from_toml("").unwrap(), Settings::default());
+    let short = Settings::from_toml("version = 2\n[audio]\nmusic = false\n").unwrap();
+    assert_eq!((short.audio.music, short.game), (false, Settings::default().game));
+    assert!(!changed().standard_rules());
+    assert!(Settings::default().standard_rules());
+}
+
+
+#[test]
+fn out_of_range()
+{
+    // The lowest and highest of each, and one past.
+    let limits = [("game.speed", 25, 400), ("game.gravity", 1, 1200), ("game.well_width", 4, 40), ("game.well_height", 4, 60),
+                  ("game.hidden_rows", 2, 20), ("handling.das", 0, 120), ("handling.arr", 0, 60)];
+    for (name, low, high) in limits.iter()
+    {
+        let mut settings = Settings::default();
+        settings.set(name, &low.to_string()).unwrap();
+        settings.set(name, &high.to_string()).unwrap();
+        assert!(settings.set(name, &(high + 1).to_string()).is_err(), "{}", name);
+        if *low > 0 { assert!(settings.set(name, &(low - 1).to_string()).is_err(), "{}", name); }
+        assert_eq!(settings.get(name), Some(high.to_string()), "a bad value changes nothing");
+
+        let (section, key) = name.split_at(name.find('.').unwrap());
+        let text = format!("version = 2\n[{}]\n{} = {}\n", section, &key[1..], high + 1);
+        assert!(Settings::from_toml(&text).is_err(), "{}", text);
+    }
+
+    // The spawn position has to be in the well it's for.
+    let mut settings = Settings::default();
+    assert!(settings.set("game.spawn_col", "7").is_err());
+    settings.set("game.well_width", "20").unwrap();
+    settings.set("game.spawn_col", "16").unwrap();
+    assert!(settings.set("game.well_width", "10").is_err());
+
+    let mut settings = Settings::default();
+    for (name, value) in [("game.speed", "fast"), ("game.gravity", "-1"), ("visuals.vsync", "yes"), ("audio.volume", "2"),
+                          ("visuals.palette", "sepia"), ("game.pieces", "hexominoes"), ("game.colour", "1")].iter() {
+        assert!(settings.set(name, value).is_err(), "{}={}", name, value);
+    }
+    assert_eq!(settings, Settings::default());
+}
+
+
+#[test]
+fn bad_files()
+{
+    let bad = [
+        "version = 3\n",                                         // From a newer rustris.
+        "version = 0\n",
+        "version = \"2\"\n",
+        "[game]\ngravity = 9223372036854775807\n",              // Too big to double, let alone use.
+        "version = 2\n[game]\ngravity = 4294967296\n",           // Doesn't fit.
+        "version = 2\n[game]\ngravity = -5\n",
+        "version = 2\n[game]\ngravity = \"slow\"\n",
+        "version = 2\n[game]\ncolour = 1\n",                     // Not a setting.
+        "version = 2\n[keys]\nleft = \"A\"\n",
+        "version = 2\n[game\n",                                  // Cut off partway.
+        "version = 2\n[game]\ngravity =",
+        "version = 2\n[visuals]\npalette = \"sepia\"\n"
+    ];
+    for text in bad.iter() { assert!(Settings::from_toml(text).is_err(), "{}", text); }
+
+    // Cut off anywhere, a whole file is never half read: it's either an error or what was there up to the cut.
+    let text = changed().to_toml();
+    for end in (0..text.len()).filter(|end| text.is_char_boundary(*end)) {
+        if let Ok(settings) = Settings::from_toml(&text[..end]) { assert!(settings.validate().is_ok()); }
+    }
+}
+
+
+#[test]
+fn command_line()
+{
+    let args: Vec<String> = ["tetris", "--set", "game.gravity=5", "--set", " audio.music = false "].iter().map(|arg| arg.to_string()).collect();
+    let mut settings = Settings::default();
+    settings.override_from_args(&args).unwrap();
+    assert_eq!((settings.game.gravity, settings.audio.music), (5, false));
+
+    for args in [vec!["--set"], vec!["--set", "game.gravity"], vec!["--set", "game.gravity=0"]].iter() {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(Settings::default().override_from_args(&args).is_err(), "{:?}", args);
+    }
+}
+
+
+#[test]
+fn steps()
+{
+    let mut settings = Settings::default();
+
+    // Numbers stop at their limits, and volume stays on its 0.05s.
+    settings.set("handling.arr", "1").unwrap();
+    for _ in 0..3 { settings.step("handling.arr", false); }
+    assert_eq!(settings.handling.arr, 0);
+    for _ in 0..30 { settings.step("audio.volume", true); }
+    assert_eq!(settings.get("audio.volume").unwrap(), "1.00");
+    settings.step("audio.volume", false);
+    assert_eq!(settings.get("audio.volume").unwrap(), "0.95");
+
+    // The spawn position goes from auto to where auto puts it, and back.
+    settings.step("game.spawn_col", false);
+    assert_eq!(settings.game.spawn_col, Some(3));
+    for _ in 0..4 { settings.step("game.spawn_col", false); }
+    assert_eq!(settings.game.spawn_col, None);
+
+    settings.step("visuals.vsync", true);
+    assert!(!settings.visuals.vsync);
+    settings.step("game.pieces", true);
+    assert_ne!(settings.game.pieces, "standard");
+}
+
+
+#[test]
+fn settings_file()
+{
+    let dir = env::temp_dir().join(format!("rustris-settings-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    env::set_var("XDG_CONFIG_HOME", &dir);
+
+    // No file is the defaults, and a saved one loads back.
+    assert_eq!(Settings::load().unwrap(), Settings::default());
+    changed().save().unwrap();
+    assert_eq!(Settings::load().unwrap(), changed());
+
+    // A broken file is an error naming the file, not the defaults.
+    fs::write(Settings::path().unwrap(), "version = 2\n[game]\nspeed = 1\n").unwrap();
+    let err = Settings::load().unwrap_err();
+    assert!(err.contains("settings.toml") && err.contains("game.speed"), "{}", err);
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,96 @@
+/*
+
+Solves a few perfect clears, and plays each solution back through the game's own rules to check that every
+piece fits where it's put, rests there, and leaves nothing behind.
+
+*/
+
+extern crate tetris;
+
+use tetris::{clear_complete_rows, freeze_to_well, would_collide, Tetrimino, TetriminoKind, Well, GARBAGE};
+use tetris::solver::{Problem, Solution, Step};
+
+
+fn pieces(letters: &str) -> Vec<Tetrimino>
+{
+    letters.chars().map(|letter| Tetrimino::new(TetriminoKind::STANDARD["IJLOSTZ".find(letter).unwrap()])).collect()
+}
+
+fn play_back(well: &Well, steps: &[Step])
+{
+    let mut well = well.clone();
+    for step in steps {
+        assert!(!would_collide(&step.ttmo, &well, &step.row, &step.col), "{:?} doesn't fit", step);
+        assert!(would_collide(&step.ttmo, &well, &(step.row + 1), &step.col), "{:?} isn't resting on anything", step);
+        freeze_to_well(&step.ttmo, &mut well, &step.row, &step.col);
+        well = clear_complete_rows(&well);
+    }
+    assert_eq!(well, well.empty_like());
+}
+
+
+#[test]
+fn openers()
+{
+    // The first bag and a half of a game, with and without hold.
+    let problem = Problem::new(Well::default(), pieces("IOLJSZTLJT"), 4);
+    match problem.solve() {
+        Solution::Found(steps) => {
+            assert_eq!(steps.len(), 10);
+            assert!(steps.iter().all(|step| !step.hold));
+            play_back(&problem.well, &steps);
+        }
+        _ => panic!("no solution")
+    }
+
+    let mut problem = Problem::new(Well::default(), pieces("IOLJSZTLJS"), 4);
+    problem.hold = Some(Tetrimino::new(TetriminoKind::T));
+    problem.can_hold = true;
+    match problem.solve() {
+        Solution::Found(steps) => play_back(&problem.well, &steps),
+        _ => panic!("no solution with hold")
+    }
+}
+
+
+#[test]
+fn squares_before_line_clears()
+{
+    // Three rows of garbage with a gap down the side for an I, and a row on top with a gap for another. Once the first
+    // clears the three, the second goes in where the top row's gone down to.
+    let mut well = Well::default();
+    for row in 21..24 {
+        for col in 1..10 { well.set(row, col, GARBAGE); }
+    }
+    for col in 5..10 { well.set(20, col, GARBAGE); }
+
+    let problem = Problem::new(well.clone(), pieces("II"), 4);
+    match problem.solve() {
+        Solution::Found(steps) => {
+            play_back(&well, &steps);
+            let mut covered: Vec<(i32, i32)> = steps.iter().flat_map(|step| step.squares.iter().copied()).collect();
+            covered.sort_unstable();
+            let mut empty: Vec<(i32, i32)> = (20..24).flat_map(|row| (0..10).map(move |col| (row, col)))
+                .filter(|(row, col)| well[*row as usize][*col as usize] == 0).collect();
+            empty.sort_unstable();
+            assert_eq!(covered, empty);
+        }
+        _ => panic!("no solution")
+    }
+}
+
+
+#[test]
+fn impossible()
+{
+    // Not enough pieces, squares that don't come in fours, and something above the lines to clear.
+    assert!(matches!(Problem::new(Well::default(), pieces("IOLJSZTLJ"), 4).solve(), Solution::Impossible));
+
+    let mut well = Well::default();
+    well.set(23, 0, GARBAGE);
+    assert!(matches!(Problem::new(well.clone(), pieces("IOLJSZTLJT"), 4).solve(), Solution::Impossible));
+
+    well.set(23, 1, GARBAGE);
+    well.set(10, 5, GARBAGE);
+    assert!(matches!(Problem::new(well, pieces("IOLJSZTLJT"), 4).solve(), Solution::Impossible));
+}
@@ -0,0 +1,170 @@
+/*
+
+Checks the fixed timestep: a step comes due for each whole step of real time and no more, a loop that falls far
+behind only catches up so far, and keys go in the step they arrived during however late the loop gets to them.
+Then DAS and ARR: which updates a held key repeats on, and that every repeat that's due goes in.
+
+*/
+
+extern crate tetris;
+
+use std::time::{Duration, Instant};
+
+use tetris::{Action, GameState, Randomizer};
+use tetris::input::AutoRepeat;
+use tetris::timestep::{TimedQueue, Timestep, MAX_CATCH_UP};
+
+
+const STEP: f64 = 1.0 / 60.0;
+
+
+/// A Timestep at 60 a second, and a moment a shade before it started counting, to measure from.
+fn timestep() -> (Timestep, Instant, Duration)
+{
+    let before = Instant::now();
+    (Timestep::new(60.0), before, Duration::from_secs_f64(STEP))
+}
+
+
+#[test]
+fn steps_come_due()
+{
+    let (mut timestep, before, step) = timestep();
+    assert_eq!(timestep.next_step(before), None);
+
+    // Three and a half steps in, three are due, each a step after the last.
+    let now = before + step * 3 + step / 2;
+    let ends: Vec<Instant> = std::iter::from_fn(|| timestep.next_step(now)).collect();
+    assert_eq!(ends.len(), 3);
+    assert!(ends.windows(2).all(|pair| pair[1] - pair[0] == step));
+
+    // Then the next is due a step after the last one ended, and real time is half way there.
+    let last = ends[2];
+    assert_eq!(timestep.due_in(last), step);
+    assert!((timestep.alpha(last + step / 2) - 0.5).abs() < 0.001);
+    assert_eq!(timestep.alpha(last + step * 2), 1.0);
+    assert_eq!(timestep.due_in(last + step * 2), Duration::from_secs(0));
+}
+
+
+#[test]
+fn catching_up()
+{
+    // A hundred steps behind, it runs MAX_CATCH_UP of them and forgets the rest, finishing on the one due now.
+    let (mut timestep, before, step) = timestep();
+    let now = before + step * 100;
+    let ends: Vec<Instant> = std::iter::from_fn(|| timestep.next_step(now)).collect();
+    assert_eq!(ends.len(), MAX_CATCH_UP as usize);
+    assert_eq!(*ends.last().unwrap(), now);
+    assert_eq!(timestep.due_in(now), step);
+}
+
+
+#[test]
+fn keys_land_in_their_step()
+{
+    // Keys half way through the first step, and two in the second. The loop gets round to them in the middle of
+    // the fourth step, and runs the three that are due back to back.
+    let (mut timestep, before, step) = timestep();
+    let mut keys = TimedQueue::new();
+    keys.push(before + step / 2, Action::MoveLeft);
+    keys.push(before + step + step / 4, Action::RotateCW);
+    keys.push(before + step + step * 3 / 4, Action::HardDrop);
+    keys.push(before + step * 3 + step / 2, Action::MoveRight);
+
+    let now = before + step * 3 + step / 2;
+    let mut steps = Vec::new();
+    while let Some(end) = timestep.next_step(now) {
+        steps.push(keys.take_before(end).collect::<Vec<Action>>());
+    }
+    assert_eq!(steps, vec![vec![Action::MoveLeft], vec![Action::RotateCW, Action::HardDrop], vec![]]);
+
+    // The key from the step still under way waits for it.
+    let end = timestep.next_step(now + step).unwrap();
+    assert_eq!(keys.take_before(end).collect::<Vec<Action>>(), vec![Action::MoveRight]);
+    assert_eq!(keys.take_before(end + step).count(), 0);
+}
+
+
+/// Runs `updates` updates of AutoRepeat alone, with `press` going down before the first and staying held, and
+/// returns which of them had the action in their inputs.
+fn held(das: u32, arr: u32, press: Action, updates: u32) -> Vec<u32>
+{
+    let mut game_state = GameState::with_seed(3, Randomizer::Bag);
+    let mut repeat = AutoRepeat::new(das, arr);
+    repeat.press(&mut game_state.inputs, press);
+
+    let mut went_in = Vec::new();
+    for update in 0..updates {
+        repeat.update(&mut game_state);
+        let count = game_state.inputs.iter().filter(|action| **action == press).count();
+        assert!(count <= 1);
+        if count == 1 { went_in.push(update); }
+        game_state.inputs.clear();
+    }
+    went_in
+}
+
+
+#[test]
+fn das_and_arr()
+{
+    // The press, then the first repeat DAS updates later, and one every ARR updates after that.
+    assert_eq!(held(12, 4, Action::MoveLeft, 30), vec![0, 12, 16, 20, 24, 28]);
+    assert_eq!(held(5, 1, Action::MoveRight, 10), vec![0, 5, 6, 7, 8, 9]);
+
+    // An ARR of 0 goes as fast as the game does, and a DAS of 0 starts repeating the update after the press.
+    assert_eq!(held(3, 0, Action::MoveLeft, 6), vec![0, 3, 4, 5]);
+    assert_eq!(held(0, 0, Action::MoveLeft, 4), vec![0, 1, 2, 3]);
+
+    // Soft drop drops every update, whatever DAS is. Turns, hard drops and holds never repeat.
+    assert_eq!(held(12, 4, Action::SoftDrop, 4), vec![0, 1, 2, 3]);
+    for action in [Action::RotateCW, Action::RotateCCW, Action::HardDrop, Action::Hold].iter() {
+        assert_eq!(held(0, 0, *action, 5), vec![0], "{:?}", action);
+    }
+}
+
+
+#[test]
+fn every_repeat_goes_in()
+{
+    let mut game_state = GameState::with_seed(3, Randomizer::Bag);
+    let mut repeat = AutoRepeat::new(2, 1);
+
+    // Held left and right together, both repeat in the same update, and both are counted as repeats.
+    repeat.press(&mut game_state.inputs, Action::MoveLeft);
+    repeat.press(&mut game_state.inputs, Action::MoveRight);
+    repeat.update(&mut game_state);
+    game_state.inputs.clear();
+    repeat.update(&mut game_state);
+    assert!(game_state.inputs.is_empty());
+    repeat.update(&mut game_state);
+    assert_eq!(game_state.inputs, vec![Action::MoveLeft, Action::MoveRight]);
+    assert_eq!(game_state.repeats, 2);
+
+    // A repeat goes in after a turn tapped in the same update.
+    game_state.inputs.clear();
+    repeat.press(&mut game_state.inputs, Action::RotateCW);
+    repeat.update(&mut game_state);
+    assert_eq!(game_state.inputs, vec![Action::RotateCW, Action::MoveLeft, Action::MoveRight]);
+    assert_eq!(game_state.repeats, 4);
+
+    // The OS's own key repeat is ignored while a key is held. Let go and tapped again, it's a press, and DAS starts over.
+    game_state.inputs.clear();
+    repeat.press(&mut game_state.inputs, Action::MoveLeft);
+    assert!(game_state.inputs.is_empty());
+    repeat.release(Action::MoveLeft);
+    repeat.press(&mut game_state.inputs, Action::MoveLeft);
+    repeat.update(&mut game_state);
+    assert_eq!(game_state.inputs, vec![Action::MoveLeft, Action::MoveRight]);
+    assert_eq!(game_state.repeats, 5);
+
+    // Keys for a network match go out the same way, with the repeats counted for the caller.
+    let mut keys = Vec::new();
+    let mut repeat = AutoRepeat::new(1, 1);
+    repeat.press(&mut keys, Action::SoftDrop);
+    repeat.press(&mut keys, Action::MoveLeft);
+    assert_eq!(repeat.update_keys(&mut keys), 0);
+    assert_eq!(repeat.update_keys(&mut keys), 2);
+    assert_eq!(keys, vec![Action::SoftDrop, Action::MoveLeft, Action::MoveLeft, Action::SoftDrop]);
+}
@@ -0,0 +1,116 @@
+/*
+
+Checks versus: what the attack table sends for each kind of clear, garbage cancelling and waiting in the
+meter before it rises, and rules files reading back as they were written.
+
+*/
+
+extern crate tetris;
+
+use tetris::{Action, Lock, Tetrimino, TetriminoKind, GARBAGE};
+use tetris::garbage::garbage_rows;
+use tetris::versus::{Incoming, Rules, Versus};
+
+
+fn lock(cleared: u32, tspin: bool, combo: u32, back_to_back: bool) -> Lock
+{
+    Lock { cleared, tspin, combo, back_to_back }
+}
+
+
+#[test]
+fn attack_table()
+{
+    let rules = Rules::default();
+    assert_eq!(rules.attack(&lock(0, false, 0, false)), 0);
+    assert_eq!(rules.attack(&lock(0, true, 0, false)), 0);    // A T-spin with no lines sends nothing.
+
+    let plain: Vec<u32> = (1..=4).map(|cleared| rules.attack(&lock(cleared, false, 1, false))).collect();
+    assert_eq!(plain, vec![0, 1, 2, 4]);
+    let tspins: Vec<u32> = (1..=3).map(|cleared| rules.attack(&lock(cleared, true, 1, false))).collect();
+    assert_eq!(tspins, vec![2, 4, 6]);
+
+    // One extra for a back-to-back, on top of the combo bonus.
+    assert_eq!(rules.attack(&lock(4, false, 1, true)), 5);
+    assert_eq!(rules.attack(&lock(2, true, 3, true)), 4 + 1 + 1);
+
+    // The combo bonus for the nth clear in a row, and past the end of the list, the last one again.
+    let combos: Vec<u32> = (1..=13).map(|combo| rules.attack(&lock(1, false, combo, false))).collect();
+    assert_eq!(combos, vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5, 5, 5]);
+}
+
+
+/// Has player 0 clear four lines with an I standing in the left column.
+fn tetris(versus: &mut Versus)
+{
+    let player = &mut versus.players[0];
+    let pieces = player.pieces;
+    let bottom = player.well.rows();
+    for row in bottom - 4..bottom {
+        for col in 1..player.well.cols() { player.well.set(row, col, GARBAGE); }
+    }
+
+    player.curr_ttmo = Tetrimino::new(TetriminoKind::I);
+    player.ttmo_col = -2;    // Standing up in the third column of its box.
+    player.inputs.push(Action::HardDrop);
+    while versus.players[0].pieces == pieces { versus.update(); }
+}
+
+
+#[test]
+fn cancelling()
+{
+    let mut versus = Versus::with_seed(1, Rules::default());
+    versus.incoming[0] = vec![Incoming { lines: 2, wait: 0 }, Incoming { lines: 3, wait: 100 }];
+
+    // A tetris knocks out the first two lines and two of the next three, oldest first, and sends nothing.
+    tetris(&mut versus);
+    assert_eq!(versus.incoming_lines(0), (0, 1));
+    assert_eq!((versus.sent[0], versus.incoming_lines(1)), (0, (0, 0)));
+
+    // A back-to-back second tetris is worth 4 + 1 + 1 for the combo. One cancels, and the other five go across.
+    tetris(&mut versus);
+    assert!(versus.incoming[0].is_empty());
+    assert_eq!((versus.sent[0], versus.incoming_lines(1)), (5, (0, 5)));
+    // Player 1's meter has already counted down once, later in the same update.
+    assert_eq!(versus.incoming[1][0].wait, Rules::default().garbage_delay() - 1);
+}
+
+
+#[test]
+fn waiting()
+{
+    let mut versus = Versus::with_seed(1, Rules::default());
+    versus.incoming[0] = vec![Incoming { lines: 3, wait: 5 }];
+
+    for _ in 0..4 { versus.update(); }
+    assert_eq!(versus.incoming_lines(0), (0, 3));
+    versus.update();
+    assert_eq!(versus.incoming_lines(0), (3, 0));
+
+    // Ready garbage only rises when a piece locks without clearing anything, all three rows at once.
+    assert_eq!(garbage_rows(&versus.players[0].well), 0);
+    versus.players[0].inputs.push(Action::HardDrop);
+    while versus.players[0].pieces == 0 { versus.update(); }
+    assert_eq!(garbage_rows(&versus.players[0].well), 3);
+    assert!(versus.incoming[0].is_empty());
+    assert!(versus.outcome.is_none());
+}
+
+
+#[test]
+fn rules_text()
+{
+    assert_eq!(Rules::from_text(&Rules::default().to_text()).unwrap(), Rules::default());
+
+    let rules = Rules { single: 1, tspin_triple: 9, back_to_back: 2, combo: vec![0, 2], garbage_delay_ms: 250, ..Rules::default() };
+    assert_eq!(Rules::from_text(&rules.to_text()).unwrap(), rules);
+
+    // Left out means the default, and comments and blank lines don't count.
+    assert_eq!(Rules::from_text("# just one\n\ntetris 5\n").unwrap(), Rules { tetris: 5, ..Rules::default() });
+
+    assert!(Rules::from_text("combo\n").is_err());
+    assert!(Rules::from_text("tetris four\n").is_err());
+    assert!(Rules::from_text("tetris 4 5\n").is_err());
+    assert!(Rules::from_text("quad 4\n").is_err());
+}
@@ -0,0 +1,93 @@
+/*
+
+Checks the well's row masks keep up with its squares however they're changed, and that collisions and line
+clears going by the masks come out the same as going square by square.
+
+*/
+
+extern crate tetris;
+
+use tetris::{clear_complete_rows, count_complete_rows, landing_row, would_collide, Tetrimino, TetriminoKind, Well, GARBAGE};
+
+
+fn assert_masks_match(well: &Well)
+{
+    for row in 0..well.rows() {
+        let squares = well[row].iter().enumerate().filter(|(_, square)| **square != 0).fold(0, |mask, (col, _)| mask | 1 << col);
+        assert_eq!(well.row_mask(row), squares, "row {}", row);
+    }
+}
+
+
+#[test]
+fn masks()
+{
+    let mut well = Well::new(40, 20, 4);
+    assert_eq!(well.full_mask(), (1 << 40) - 1);
+
+    well.set(23, 0, GARBAGE);
+    well.set(23, 39, TetriminoKind::T.cell());
+    well.set(22, 5, GARBAGE);
+    well.set(22, 5, 0);
+    assert_masks_match(&well);
+    assert_eq!(well.row_mask(23), 1 | 1 << 39);
+
+    well.set_row(10, &[GARBAGE; 40]);
+    well.push_bottom(&[TetriminoKind::I.cell(); 40]);
+    assert_masks_match(&well);
+    assert_eq!(well.row_mask(9), well.full_mask());
+
+    well.fill(GARBAGE);
+    assert_masks_match(&well);
+    well.fill(0);
+    assert_eq!(well, Well::new(40, 20, 4));
+}
+
+
+#[test]
+fn collisions()
+{
+    // An O, which sits in the middle two columns of the bottom of its box.
+    let o = Tetrimino::new(TetriminoKind::O);
+    let mut well = Well::default();
+
+    assert!(!would_collide(&o, &well, &20, &-1));
+    assert!(would_collide(&o, &well, &20, &-2));       // Through the left wall.
+    assert!(!would_collide(&o, &well, &20, &7));
+    assert!(would_collide(&o, &well, &20, &8));        // And the right.
+    assert!(would_collide(&o, &well, &21, &3));        // Through the floor.
+    assert!(would_collide(&o, &well, &-3, &3));        // Off the top.
+    assert!(would_collide(&o, &well, &20, &-100));
+    assert!(would_collide(&o, &well, &20, &100));
+
+    well.set(23, 4, GARBAGE);
+    assert!(would_collide(&o, &well, &20, &3));
+    assert!(!would_collide(&o, &well, &20, &4));
+    assert_eq!(landing_row(&o, &well, 0, 3), 19);
+    assert_eq!(landing_row(&o, &well, 0, 4), 20);
+}
+
+
+#[test]
+fn line_clears()
+{
+    let mut well = Well::default();
+    well.set_row(23, &[GARBAGE; 10]);
+    well.set_row(21, &[GARBAGE; 10]);
+    well.set(22, 3, GARBAGE);
+    well.set(20, 7, TetriminoKind::S.cell());
+    assert_eq!(count_complete_rows(&well), 2);
+
+    let cleared = clear_complete_rows(&well);
+    assert_masks_match(&cleared);
+    assert_eq!(count_complete_rows(&cleared), 0);
+    assert_eq!(cleared.row_mask(23), 1 << 3);
+    assert_eq!(cleared.row_mask(22), 1 << 7);
+    assert_eq!(cleared[22][7], TetriminoKind::S.cell());
+    assert!((0..22).all(|row| cleared.row_mask(row) == 0));
+
+    // A well with something in every row, all the way up, and nothing to clear, stays as it was.
+    let mut full = Well::default();
+    for row in 0..full.rows() { full.set(row, row % 10, GARBAGE); }
+    assert_eq!(clear_complete_rows(&full), full);
+}
@@ -0,0 +1,108 @@
+/*
+
+Checks wells of other sizes: the limits on width, height and hidden rows, where pieces appear in them, and
+that whole games play out, and replay, in the smallest and biggest wells there can be.
+
+*/
+
+extern crate tetris;
+
+use std::panic;
+
+use tetris::{format_well_size, game_update, parse_well_size, GameState, Randomizer, Well, MAX_HIDDEN_ROWS, MAX_WELL_COLS, MAX_WELL_ROWS,
+             MIN_HIDDEN_ROWS, MIN_WELL_COLS, MIN_WELL_ROWS};
+use tetris::ai::{AiBot, Weights};
+use tetris::garbage::{cheese_rows, garbage_rows};
+use tetris::input::InputSource;
+use tetris::modes::Mode;
+use tetris::replay::{Replay, ReplayInput};
+
+
+#[test]
+fn limits()
+{
+    assert_eq!((MIN_WELL_COLS, MAX_WELL_COLS, MIN_WELL_ROWS, MAX_WELL_ROWS, MIN_HIDDEN_ROWS, MAX_HIDDEN_ROWS), (4, 40, 4, 60, 2, 20));
+
+    for text in ["4x4+2", "40x60+20", "4x60+20", "40x4+2", "10x20+4"].iter() {
+        assert_eq!(format_well_size(&parse_well_size(text).unwrap()), *text);
+    }
+    assert_eq!(parse_well_size("20x30").unwrap(), Well::new(20, 30, 4));
+    assert!(parse_well_size("10x20+4").unwrap().is_standard());
+
+    for text in ["3x20", "41x20", "10x3", "10x61", "10x20+1", "10x20+21", "10x20+", "10", "x20", "10x-20", "ten by twenty"].iter() {
+        assert!(parse_well_size(text).is_err(), "{}", text);
+    }
+
+    // Well::new() itself won't make one out of range: everything that reads a size checks first.
+    let sizes = [(3, 20, 4), (41, 20, 4), (10, 3, 4), (10, 61, 4), (10, 20, 1), (10, 20, 21)];
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| ()));
+    for (cols, rows, hidden) in sizes.iter() {
+        assert!(panic::catch_unwind(|| Well::new(*cols, *rows, *hidden)).is_err(), "{}x{}+{}", cols, rows, hidden);
+    }
+    panic::set_hook(hook);
+}
+
+
+#[test]
+fn spawn()
+{
+    // Two rows above the visible well, and in the middle, leaning left.
+    assert_eq!(Well::default().spawn(), (2, 3));
+    assert_eq!(Well::new(4, 4, 2).spawn(), (0, 0));
+    assert_eq!(Well::new(40, 60, 20).spawn(), (18, 18));
+    assert_eq!(Well::new(11, 20, 6).spawn(), (4, 3));
+
+    let mut game_state = GameState::with_seed(1, Randomizer::Bag);
+    game_state.set_well(Well::new(40, 60, 20));
+    assert_eq!((game_state.ttmo_row, game_state.ttmo_col), (18, 18));
+    assert_eq!(game_state.well.rows(), 80);
+
+    // Cheese fills at most half of a short well.
+    for (visible_rows, rows) in [(4, 2), (12, 6), (20, 10), (60, 10)].iter() {
+        let mut game_state = GameState::with_seed(1, Randomizer::Bag);
+        game_state.set_mode(Mode::Cheese { rows: 18, messiness: 100, rise: 0 });
+        game_state.set_well(Well::new(10, *visible_rows, 4));
+        assert_eq!((cheese_rows(&game_state.well), garbage_rows(&game_state.well)), (*rows, *rows));
+    }
+}
+
+
+/// The AI plays a game in the given well until it's over or 100 pieces are down, then it's played back from its
+/// replay file. Returns how many pieces it got down.
+fn play(well: Well) -> u32
+{
+    let mut game_state = GameState::with_seed(7, Randomizer::Bag);
+    game_state.set_well(well.clone());
+    let mut replay = Replay::new(&game_state);
+    let mut bot = AiBot::new(Weights::default(), false);
+
+    while game_state.game_over.is_none() && game_state.pieces < 100 {
+        game_state.inputs = bot.next_keys(&game_state);
+        replay.record(game_state.frames, &game_state.inputs, 0);
+        game_update(&mut game_state);
+    }
+
+    let replay = Replay::from_text(&replay.to_text()).unwrap();
+    assert_eq!(replay.well, well.empty_like());
+    let mut played = replay.new_game();
+    let mut input = ReplayInput::new(replay);
+    while !input.finished(&played) {
+        played.inputs = input.next_keys(&played);
+        game_update(&mut played);
+    }
+    assert_eq!((played.well, played.pieces, played.lines, played.score), (game_state.well, game_state.pieces, game_state.lines, game_state.score));
+
+    game_state.pieces
+}
+
+
+#[test]
+fn smallest_and_biggest()
+{
+    // The smallest is soon full, but it's a game. The biggest goes on as long as it's let.
+    assert!(play(Well::new(4, 4, 2)) > 0);
+    assert_eq!(play(Well::new(40, 60, 20)), 100);
+    assert_eq!(play(Well::new(4, 60, 2)), 100);
+    assert!(play(Well::new(40, 4, 20)) > 0);
+}